@@ -0,0 +1,196 @@
+use std::convert::TryInto;
+use std::path::Path;
+
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+/// Extensions of the files picked in an archive when no member is named
+pub const ROM_EXTENSIONS: [&str; 4] = ["nes", "fds", "unf", "nsf"];
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4B50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4B50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4B50;
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const SEVEN_ZIP_MAGIC: [u8; 6] = [0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C];
+
+/// Whether a file is a zip or gzip archive
+pub fn is_archive(data: &[u8]) -> bool {
+    is_zip(data) || data.starts_with(&GZIP_MAGIC)
+}
+
+fn is_zip(data: &[u8]) -> bool {
+    data.len() >= 4 && read_u32(data, 0) == ZIP_LOCAL_HEADER
+}
+
+/// Rom stored in a zip or gzip archive, files that are not archives are returned as they are.
+/// `member` names the file to take from a zip, otherwise the first rom, disk or tune is taken.
+pub fn unpack_rom(data: Vec<u8>, member: Option<&str>) -> Result<Vec<u8>, String> {
+    if is_zip(data.as_slice()) {
+        unzip(&data, member)
+    } else if data.starts_with(&GZIP_MAGIC) {
+        gunzip(&data)
+    } else if data.starts_with(&SEVEN_ZIP_MAGIC) {
+        Err("unsupported archive: 7z, only zip and gzip can be read".to_string())
+    } else {
+        Ok(data)
+    }
+}
+
+/// Entry of the central directory of a zip file
+struct ZipEntry {
+    name: String,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    offset: usize,
+}
+
+fn unzip(data: &[u8], member: Option<&str>) -> Result<Vec<u8>, String> {
+    let entries = zip_entries(data)?;
+    let entry = match member {
+        Some(member) => entries
+            .iter()
+            .find(|entry| entry.name == member || file_name(&entry.name) == member)
+            .ok_or_else(|| format!("the archive has no file named '{}'", member))?,
+        None => entries
+            .iter()
+            .find(|entry| is_rom_name(&entry.name))
+            .ok_or("the archive has no .nes, .fds, .unf or .nsf file")?,
+    };
+    if entry.flags & 0x01 != 0 {
+        return Err(format!("{} is encrypted", entry.name));
+    }
+    let header = entry.offset;
+    if data.len() < header + 30 || read_u32(data, header) != ZIP_LOCAL_HEADER {
+        return Err("the zip archive is corrupted".to_string());
+    }
+    let start =
+        header + 30 + read_u16(data, header + 26) as usize + read_u16(data, header + 28) as usize;
+    let compressed = data
+        .get(start..start + entry.compressed_size)
+        .ok_or("the zip archive is truncated")?;
+    let result = match entry.method {
+        0 => compressed.to_vec(),
+        8 => inflate(compressed, entry.size)?,
+        method => {
+            return Err(format!(
+                "{} uses the unsupported compression method {}",
+                entry.name, method
+            ))
+        }
+    };
+    check_crc(&result, entry.crc)?;
+    Ok(result)
+}
+
+/// Files listed by the central directory, found from the end of the archive
+fn zip_entries(data: &[u8]) -> Result<Vec<ZipEntry>, String> {
+    // The end record is followed by a comment of up to 64KB
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(0x10000 + 22)
+        .find(|&i| read_u32(data, i) == ZIP_END_OF_DIRECTORY)
+        .ok_or("the zip archive has no central directory")?;
+    let count = read_u16(data, end + 10) as usize;
+    let mut offset = read_u32(data, end + 16) as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if data.len() < offset + 46 || read_u32(data, offset) != ZIP_CENTRAL_HEADER {
+            return Err("the zip archive is corrupted".to_string());
+        }
+        let name_length = read_u16(data, offset + 28) as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or("the zip archive is truncated")?;
+        let entry = ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: read_u16(data, offset + 8),
+            method: read_u16(data, offset + 10),
+            crc: read_u32(data, offset + 16),
+            compressed_size: read_u32(data, offset + 20) as usize,
+            size: read_u32(data, offset + 24) as usize,
+            offset: read_u32(data, offset + 42) as usize,
+        };
+        if [entry.compressed_size, entry.size, entry.offset].contains(&0xFFFF_FFFF) {
+            return Err("ZIP64 archives are not supported".to_string());
+        }
+        entries.push(entry);
+        offset += 46
+            + name_length
+            + read_u16(data, offset + 30) as usize
+            + read_u16(data, offset + 32) as usize;
+    }
+    Ok(entries)
+}
+
+/// Content of a gzip file, a single deflate stream with its CRC32 and size at the end
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 18 || data[2] != 8 {
+        return Err("the gzip archive is corrupted".to_string());
+    }
+    let flags = data[3];
+    let mut offset = 10;
+    // Extra field, file name, comment and header CRC
+    if flags & 0x04 != 0 {
+        offset += 2 + read_u16(data, offset) as usize;
+    }
+    for flag in [0x08, 0x10].iter() {
+        if flags & flag != 0 {
+            offset += data
+                .get(offset..)
+                .and_then(|x| x.iter().position(|c| *c == 0))
+                .ok_or("the gzip archive is truncated")?
+                + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        offset += 2;
+    }
+    let end = data.len() - 8;
+    let stream = data
+        .get(offset..end)
+        .ok_or("the gzip archive is truncated")?;
+    let size = read_u32(data, end + 4) as usize;
+    let result = inflate(stream, size)?;
+    if result.len() as u32 != size as u32 {
+        return Err("the size of the gzip content does not match".to_string());
+    }
+    check_crc(&result, read_u32(data, end))?;
+    Ok(result)
+}
+
+/// Raw deflate stream of `size` bytes once decompressed
+fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    decompress_to_vec_with_limit(data, size.max(1))
+        .map_err(|e| format!("invalid compressed data: {:?}", e.status))
+}
+
+fn check_crc(data: &[u8], expected: u32) -> Result<(), String> {
+    match crc32fast::hash(data) == expected {
+        true => Ok(()),
+        false => Err("the checksum of the unpacked file does not match the archive".to_string()),
+    }
+}
+
+fn is_rom_name(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| ROM_EXTENSIONS.contains(&x.to_lowercase().as_str()))
+}
+
+/// Name of a zip entry without its directories
+fn file_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data.get(offset..offset + 2)
+        .map_or(0, |x| u16::from_le_bytes(x.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4)
+        .map_or(0, |x| u32::from_le_bytes(x.try_into().unwrap()))
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+/// Evaluates the operands of the assembler.
+///
+/// Numbers are decimal, `$` hexadecimal, `%` binary or `'c'` characters, `*` is the
+/// current address. `<x` and `>x` take the low and high byte of a value.
+/// The result is None when a label is not known yet.
+pub struct Evaluator<'a> {
+    chars: Vec<char>,
+    position: usize,
+    /// Searched in order
+    labels: [&'a BTreeMap<String, i64>; 2],
+    pc: i64,
+    /// First label that could not be found
+    pub undefined: Option<String>,
+}
+
+type Value = Result<Option<i64>, String>;
+
+impl<'a> Evaluator<'a> {
+    pub fn new(text: &str, labels: [&'a BTreeMap<String, i64>; 2], pc: i64) -> Evaluator<'a> {
+        Evaluator {
+            chars: text.chars().collect(),
+            position: 0,
+            labels,
+            pc,
+            undefined: None,
+        }
+    }
+
+    pub fn eval(&mut self) -> Value {
+        let result = self.binary(0)?;
+        self.skip_spaces();
+        match self.chars.get(self.position) {
+            None => Ok(result),
+            Some(c) => Err(format!("unexpected '{}' in expression", c)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self
+            .chars
+            .get(self.position)
+            .is_some_and(|c| c.is_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    /// Operator found at the current position, for the given precedence level
+    fn operator(&mut self, level: usize) -> Option<&'static str> {
+        const LEVELS: [&[&str]; 6] = [
+            &["|"],
+            &["^"],
+            &["&"],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        self.skip_spaces();
+        let rest: String = self.chars[self.position..].iter().take(2).collect();
+        let found = LEVELS[level].iter().find(|op| rest.starts_with(*op))?;
+        self.position += found.len();
+        Some(found)
+    }
+
+    fn binary(&mut self, level: usize) -> Value {
+        if level == 6 {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.operator(level) {
+            let right = self.binary(level + 1)?;
+            left = match (left, right) {
+                (Some(a), Some(b)) => Some(match op {
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "&" => a & b,
+                    "<<" => a << (b & 63),
+                    ">>" => a >> (b & 63),
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    _ if b == 0 => return Err("division by zero".to_string()),
+                    "/" => a / b,
+                    _ => a % b,
+                }),
+                _ => None,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Value {
+        self.skip_spaces();
+        let c = *self.chars.get(self.position).ok_or("expression expected")?;
+        let apply: fn(i64) -> i64 = match c {
+            '-' => |x| -x,
+            '~' => |x| !x,
+            '<' => |x| x & 0xFF,
+            '>' => |x| (x >> 8) & 0xFF,
+            _ => return self.value(),
+        };
+        self.position += 1;
+        Ok(self.unary()?.map(apply))
+    }
+
+    fn value(&mut self) -> Value {
+        let start = self.position;
+        let c = self.chars[start];
+        let take = |evaluator: &mut Evaluator, valid: fn(char) -> bool| -> String {
+            let begin = evaluator.position;
+            while evaluator
+                .chars
+                .get(evaluator.position)
+                .is_some_and(|x| valid(*x))
+            {
+                evaluator.position += 1;
+            }
+            evaluator.chars[begin..evaluator.position].iter().collect()
+        };
+        let number = |text: String, radix: u32| {
+            i64::from_str_radix(&text, radix)
+                .map(Some)
+                .map_err(|_| format!("bad number '{}'", text))
+        };
+        match c {
+            '(' => {
+                self.position += 1;
+                let result = self.binary(0)?;
+                self.skip_spaces();
+                match self.chars.get(self.position) {
+                    Some(')') => self.position += 1,
+                    _ => return Err("missing ')'".to_string()),
+                }
+                Ok(result)
+            }
+            '*' => {
+                self.position += 1;
+                Ok(Some(self.pc))
+            }
+            '$' => {
+                self.position += 1;
+                number(take(self, |x| x.is_ascii_hexdigit()), 16)
+            }
+            '%' => {
+                self.position += 1;
+                number(take(self, |x| x == '0' || x == '1'), 2)
+            }
+            '\'' => match (self.chars.get(start + 1), self.chars.get(start + 2)) {
+                (Some(x), Some('\'')) => {
+                    self.position += 3;
+                    Ok(Some(*x as i64))
+                }
+                _ => Err("bad character constant".to_string()),
+            },
+            _ if c.is_ascii_digit() => number(take(self, |x| x.is_ascii_digit()), 10),
+            _ if is_label_start(c) => {
+                let name = take(self, is_label_char);
+                match self.labels.iter().find_map(|x| x.get(&name)) {
+                    Some(x) => Ok(Some(*x)),
+                    None => {
+                        self.undefined.get_or_insert(name);
+                        Ok(None)
+                    }
+                }
+            }
+            _ => Err(format!("unexpected '{}' in expression", c)),
+        }
+    }
+}
+
+pub fn is_label_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '@'
+}
+
+pub fn is_label_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '@' || c == '.'
+}
@@ -0,0 +1,453 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::components::get_lookup_list;
+
+use self::expression::*;
+
+mod expression;
+
+/// Passes made before giving up on labels that keep moving
+const MAX_PASSES: usize = 16;
+/// Nested `.include` allowed, catches files including themselves
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Bytes assembled from a `.org`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+/// Header set by the `.inesprg`, `.ineschr`, `.inesmap` and `.inesmir` directives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InesHeader {
+    pub prg_banks: u8,
+    pub chr_banks: u8,
+    pub mapper: u8,
+    pub mirroring: u8,
+}
+
+/// Output of the assembler
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    /// Program bytes, in the order they appear in the source
+    pub chunks: Vec<Chunk>,
+    /// Bytes written after the `.chr` directive
+    pub chr: Vec<u8>,
+    pub labels: BTreeMap<String, i64>,
+    pub header: Option<InesHeader>,
+}
+
+impl Default for InesHeader {
+    fn default() -> Self {
+        InesHeader {
+            prg_banks: 1,
+            chr_banks: 1,
+            mapper: 0,
+            mirroring: 0,
+        }
+    }
+}
+
+impl Program {
+    /// Assembled bytes following each other, like a raw binary output
+    pub fn bytes(&self) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .flat_map(|c| c.data.iter().cloned())
+            .collect()
+    }
+
+    /// iNES image, the PRG banks end at $FFFF and unused bytes are $FF.
+    /// Without header directives, the image has 16KB of PRG and 8KB of CHR.
+    pub fn to_ines(&self) -> Vec<u8> {
+        let header = self.header.unwrap_or_default();
+        let prg_size = header.prg_banks as usize * 0x4000;
+        let prg_start = 0x10000 - prg_size.min(0x8000);
+        let mut prg = vec![0xFFu8; prg_size];
+        for chunk in self.chunks.iter() {
+            for (i, data) in chunk.data.iter().enumerate() {
+                let addr = chunk.addr as usize + i;
+                if addr >= prg_start && addr < 0x10000 {
+                    // Banks above 32KB are filled from the end, the others are left blank
+                    prg[prg_size - (0x10000 - addr)] = *data;
+                }
+            }
+        }
+        let mut chr = self.chr.clone();
+        chr.resize(header.chr_banks as usize * 0x2000, 0);
+
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, header.prg_banks, header.chr_banks];
+        rom.push((header.mapper << 4) | (header.mirroring & 0x01));
+        rom.push(header.mapper & 0xF0);
+        rom.extend_from_slice(&[0; 8]);
+        rom.extend(prg);
+        rom.extend(chr);
+        rom
+    }
+}
+
+/// Assemble a source, `.include` paths are relative to the working directory
+pub fn assemble(source: &str) -> Result<Program, String> {
+    Assembler::new().run(source, None)
+}
+
+/// Assemble a file, `.include` paths are relative to the including file
+pub fn assemble_file(path: &str) -> Result<Program, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Assembler::new().run(&source, Some(Path::new(path)))
+}
+
+/// Two pass assembler, passes are repeated while forward references move the labels
+struct Assembler {
+    opcodes: HashMap<(String, String), u8>,
+    /// Labels found by the previous pass
+    known: BTreeMap<String, i64>,
+    program: Program,
+    pc: i64,
+    in_chr: bool,
+    errors: Vec<String>,
+    /// First label used but not found during the pass
+    undefined: Option<String>,
+}
+
+/// Where a line comes from, for the error messages
+#[derive(Clone, Copy)]
+struct Location<'a> {
+    file: Option<&'a Path>,
+    line: usize,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        let mut opcodes = HashMap::new();
+        for (i, instruction) in get_lookup_list().iter().enumerate() {
+            if instruction.opcode != "???" {
+                let key = (instruction.opcode.clone(), instruction.addr_mode.clone());
+                opcodes.entry(key).or_insert(i as u8);
+            }
+        }
+        Assembler {
+            opcodes,
+            known: BTreeMap::new(),
+            program: Program::default(),
+            pc: 0,
+            in_chr: false,
+            errors: Vec::new(),
+            undefined: None,
+        }
+    }
+
+    fn run(&mut self, source: &str, file: Option<&Path>) -> Result<Program, String> {
+        for _ in 0..MAX_PASSES {
+            self.program = Program::default();
+            self.pc = 0;
+            self.in_chr = false;
+            self.errors.clear();
+            self.undefined = None;
+            self.source(source, file, 0);
+            if self.program.labels == self.known {
+                return match (self.errors.first(), &self.undefined) {
+                    (Some(e), _) => Err(e.clone()),
+                    (None, Some(name)) => Err(format!("undefined label '{}'", name)),
+                    (None, None) => Ok(self.program.clone()),
+                };
+            }
+            self.known = self.program.labels.clone();
+        }
+        Err("labels never settle, check the zero page labels defined after their use".to_string())
+    }
+
+    fn source(&mut self, source: &str, file: Option<&Path>, depth: usize) {
+        for (i, line) in source.lines().enumerate() {
+            let location = Location { file, line: i + 1 };
+            if let Err(e) = self.line(line, location, depth) {
+                let name = file.map_or("<source>".to_string(), |x| x.display().to_string());
+                self.errors
+                    .push(format!("{}:{}: {}", name, location.line, e));
+            }
+        }
+    }
+
+    /// Value of an operand, None while a label is not known
+    fn eval(&mut self, text: &str) -> Result<Option<i64>, String> {
+        let mut evaluator = Evaluator::new(text, [&self.program.labels, &self.known], self.pc);
+        let value = evaluator.eval()?;
+        if let (None, Some(name)) = (&self.undefined, evaluator.undefined) {
+            self.undefined = Some(name);
+        }
+        Ok(value)
+    }
+
+    /// Value needed right away, labels must be defined before the line
+    fn eval_now(&self, text: &str) -> Result<i64, String> {
+        let mut evaluator =
+            Evaluator::new(text, [&self.program.labels, &self.program.labels], self.pc);
+        let value = evaluator.eval()?;
+        value.ok_or_else(|| {
+            format!(
+                "'{}' must be defined before",
+                evaluator.undefined.unwrap_or_default()
+            )
+        })
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        if self
+            .program
+            .labels
+            .insert(name.to_string(), value)
+            .is_some()
+        {
+            return Err(format!("label '{}' defined twice", name));
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str, location: Location, depth: usize) -> Result<(), String> {
+        let mut line = strip_comment(line).trim();
+        // Label, either `name:` or a constant `name = value`
+        let first_len = line.find(|c: char| !is_label_char(c)).unwrap_or(line.len());
+        let (first, rest) = line.split_at(first_len);
+        if !first.is_empty() && is_label_start(first.chars().next().unwrap_or(' ')) {
+            let rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(':') {
+                self.define(first, self.pc)?;
+                line = rest.trim();
+            } else if let Some(value) = rest
+                .strip_prefix('=')
+                .or_else(|| strip_keyword(rest, "equ"))
+            {
+                let value = self.eval_now(value)?;
+                return self.define(first, value);
+            }
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (word, operand) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        match word.strip_prefix('.') {
+            Some(directive) => {
+                self.directive(&directive.to_ascii_lowercase(), operand, location, depth)
+            }
+            None => self.instruction(&word.to_ascii_uppercase(), operand),
+        }
+    }
+
+    fn emit(&mut self, data: &[u8]) {
+        if self.in_chr {
+            self.program.chr.extend_from_slice(data);
+            return;
+        }
+        if self.program.chunks.is_empty() {
+            self.program.chunks.push(Chunk {
+                addr: self.pc as u16,
+                data: Vec::new(),
+            });
+        }
+        if let Some(chunk) = self.program.chunks.last_mut() {
+            chunk.data.extend_from_slice(data);
+        }
+        self.pc += data.len() as i64;
+    }
+
+    fn directive(
+        &mut self,
+        name: &str,
+        operand: &str,
+        location: Location,
+        depth: usize,
+    ) -> Result<(), String> {
+        match name {
+            "org" => {
+                self.pc = self.eval_now(operand)?;
+                if !(0..=0xFFFF).contains(&self.pc) {
+                    return Err(format!("address ${:X} out of range", self.pc));
+                }
+                self.in_chr = false;
+                self.program.chunks.push(Chunk {
+                    addr: self.pc as u16,
+                    data: Vec::new(),
+                });
+            }
+            "byte" | "db" => {
+                for item in split_arguments(operand) {
+                    match item.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+                        Some(text) => self.emit(text.as_bytes()),
+                        None => {
+                            let value = self.eval(item)?.unwrap_or(0);
+                            if !(-128..=255).contains(&value) {
+                                return Err(format!("{} does not fit in a byte", value));
+                            }
+                            self.emit(&[value as u8]);
+                        }
+                    }
+                }
+            }
+            "word" | "dw" => {
+                for item in split_arguments(operand) {
+                    let value = self.eval(item)?.unwrap_or(0) as u16;
+                    self.emit(&value.to_le_bytes());
+                }
+            }
+            "include" | "incbin" => {
+                let path = quoted(operand)?;
+                let path = match location.file.and_then(|x| x.parent()) {
+                    Some(dir) => dir.join(path),
+                    None => PathBuf::from(path),
+                };
+                if name == "incbin" {
+                    let data = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                    self.emit(&data);
+                } else if depth >= MAX_INCLUDE_DEPTH {
+                    return Err("too many nested includes".to_string());
+                } else {
+                    let source = fs::read_to_string(&path)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?;
+                    self.source(&source, Some(&path), depth + 1);
+                }
+            }
+            "chr" => self.in_chr = true,
+            "inesprg" => self.header().prg_banks = self.eval_now(operand)? as u8,
+            "ineschr" => self.header().chr_banks = self.eval_now(operand)? as u8,
+            "inesmap" => self.header().mapper = self.eval_now(operand)? as u8,
+            "inesmir" => self.header().mirroring = self.eval_now(operand)? as u8,
+            _ => return Err(format!("unknown directive .{}", name)),
+        }
+        Ok(())
+    }
+
+    /// Header of the program, created by the first header directive
+    fn header(&mut self) -> &mut InesHeader {
+        let header = InesHeader {
+            chr_banks: 0,
+            ..InesHeader::default()
+        };
+        self.program.header.get_or_insert(header)
+    }
+
+    fn opcode(&self, mnemonic: &str, mode: &str) -> Option<u8> {
+        self.opcodes
+            .get(&(mnemonic.to_string(), mode.to_string()))
+            .cloned()
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operand: &str) -> Result<(), String> {
+        if !self.opcodes.keys().any(|(m, _)| m == mnemonic) {
+            return Err(format!("unknown instruction {}", mnemonic));
+        }
+        let upper = operand.to_ascii_uppercase().replace(' ', "");
+        // Addressing modes tried in order, with the text of their operand
+        let (modes, expression): (&[&str], &str) = if operand.is_empty() || upper == "A" {
+            (&["IMP"], "")
+        } else if let Some(x) = operand.strip_prefix('#') {
+            (&["IMM"], x)
+        } else if upper.starts_with('(') && upper.ends_with(",X)") {
+            (&["IDX"], &operand[1..operand.rfind(',').unwrap_or(1)])
+        } else if upper.starts_with('(') && upper.ends_with("),Y") {
+            (&["IDY"], &operand[1..operand.rfind(')').unwrap_or(1)])
+        } else if upper.ends_with(",X") {
+            (&["ZPX", "ABX"], &operand[..operand.rfind(',').unwrap_or(0)])
+        } else if upper.ends_with(",Y") {
+            (&["ZPY", "ABY"], &operand[..operand.rfind(',').unwrap_or(0)])
+        } else if self.opcode(mnemonic, "IND").is_some()
+            && upper.starts_with('(')
+            && upper.ends_with(')')
+        {
+            (&["IND"], &operand[1..operand.len() - 1])
+        } else {
+            (&["REL", "ZP0", "ABS"], operand)
+        };
+        let value = match expression.is_empty() {
+            true => Some(0),
+            false => self.eval(expression)?,
+        };
+        let mode = modes
+            .iter()
+            .filter(|m| self.opcode(mnemonic, m).is_some())
+            // The zero page is only used when the value is already known to fit
+            .find(|m| !m.starts_with("ZP") || value.is_some_and(|x| (0..=0xFF).contains(&x)))
+            .ok_or_else(|| format!("{} does not support this addressing mode", mnemonic))?;
+        let opcode = self.opcode(mnemonic, mode).unwrap_or(0);
+        let value = value.unwrap_or(0);
+        match *mode {
+            "IMP" => self.emit(&[opcode]),
+            "REL" => {
+                // Bytes are emitted even on errors so the following labels keep their place
+                let offset = value - (self.pc + 2);
+                self.emit(&[opcode, offset as u8]);
+                if !(-128..=127).contains(&offset) {
+                    return Err(format!("branch out of range by {} bytes", offset));
+                }
+            }
+            "IMM" | "ZP0" | "ZPX" | "ZPY" | "IDX" | "IDY" => {
+                self.emit(&[opcode, value as u8]);
+                if !(-128..=255).contains(&value) {
+                    return Err(format!("{} does not fit in a byte", value));
+                }
+            }
+            _ => {
+                let value = value as u16;
+                self.emit(&[opcode, value as u8, (value >> 8) as u8]);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// `equ` or `.equ` followed by a value
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let text = text.strip_prefix('.').unwrap_or(text);
+    match text.get(..keyword.len()) {
+        Some(x)
+            if x.eq_ignore_ascii_case(keyword)
+                && text[keyword.len()..].starts_with(char::is_whitespace) =>
+        {
+            Some(&text[keyword.len()..])
+        }
+        _ => None,
+    }
+}
+
+/// Split on the commas that are not inside a string
+fn split_arguments(text: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                result.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    result.push(text[start..].trim());
+    result.retain(|x| !x.is_empty());
+    result
+}
+
+fn quoted(text: &str) -> Result<&str, String> {
+    text.strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted path, found {}", text))
+}
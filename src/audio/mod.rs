@@ -0,0 +1,92 @@
+use super::components::*;
+
+/// Encoding of the WAV samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Signed 16 bits integers
+    #[default]
+    Pcm16,
+    /// 32 bits IEEE floats
+    Float32,
+}
+
+impl SampleFormat {
+    pub fn parse(text: &str) -> Result<SampleFormat, String> {
+        match text {
+            "s16" => Ok(SampleFormat::Pcm16),
+            "f32" => Ok(SampleFormat::Float32),
+            _ => Err(format!(
+                "unknown sample format '{}', expected s16 or f32",
+                text
+            )),
+        }
+    }
+}
+
+/// Signed 16 bits value of a sample, the APU output is between -1 and 1
+pub fn sample_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Mono WAV file of the APU output
+pub fn samples_to_wav(samples: &[f32], sample_rate: u32, format: SampleFormat) -> Vec<u8> {
+    let (tag, size): (u16, u32) = match format {
+        SampleFormat::Pcm16 => (1, 2),
+        SampleFormat::Float32 => (3, 4),
+    };
+    let data_size = samples.len() as u32 * size;
+    // Formats other than PCM need the extension size and a fact chunk
+    let extra = match format {
+        SampleFormat::Pcm16 => 0,
+        SampleFormat::Float32 => 2 + 12,
+    };
+    let mut result = Vec::with_capacity(44 + extra + data_size as usize);
+    result.extend_from_slice(b"RIFF");
+    result.extend_from_slice(&(36 + extra as u32 + data_size).to_le_bytes());
+    result.extend_from_slice(b"WAVEfmt ");
+    result.extend_from_slice(&(16 + (extra > 0) as u32 * 2).to_le_bytes());
+    result.extend_from_slice(&tag.to_le_bytes());
+    result.extend_from_slice(&1u16.to_le_bytes());
+    result.extend_from_slice(&sample_rate.to_le_bytes());
+    result.extend_from_slice(&(sample_rate * size).to_le_bytes());
+    result.extend_from_slice(&(size as u16).to_le_bytes());
+    result.extend_from_slice(&(size as u16 * 8).to_le_bytes());
+    if extra > 0 {
+        result.extend_from_slice(&0u16.to_le_bytes());
+        result.extend_from_slice(b"fact");
+        result.extend_from_slice(&4u32.to_le_bytes());
+        result.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    }
+    result.extend_from_slice(b"data");
+    result.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples.iter() {
+        match format {
+            SampleFormat::Pcm16 => result.extend_from_slice(&sample_to_i16(*sample).to_le_bytes()),
+            SampleFormat::Float32 => result.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+    result
+}
+
+/// Run `frames` frames and return the audio they produced
+pub fn record_audio(cpu: &mut CPU6502, nes: &mut NesData, frames: u64) -> Vec<f32> {
+    let mut result = Vec::new();
+    for _ in 0..frames {
+        nes.run_frame(cpu);
+        result.append(&mut nes.apu.samples);
+    }
+    result
+}
+
+/// FNV-1a hash of the samples once converted to 16 bits, so that the rounding
+/// differences of the floats do not change it
+pub fn hash_samples(samples: &[f32]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for sample in samples.iter() {
+        for byte in sample_to_i16(*sample).to_le_bytes().iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
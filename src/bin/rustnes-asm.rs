@@ -0,0 +1,36 @@
+use rustnes::assembler::*;
+use std::env;
+use std::fs;
+use std::process;
+
+/// Assembles a source file to an iNES image, or to a raw binary without header directives
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (input, output) = match args.as_slice() {
+        [input] => (
+            input.clone(),
+            format!("{}.nes", input.trim_end_matches(".asm")),
+        ),
+        [input, output] => (input.clone(), output.clone()),
+        _ => {
+            eprintln!("usage: rustnes-asm <source.asm> [output]");
+            process::exit(1);
+        }
+    };
+    let program = match assemble_file(&input) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("rustnes-asm: {}", e);
+            process::exit(1);
+        }
+    };
+    let data = match program.header {
+        Some(_) => program.to_ines(),
+        None => program.bytes(),
+    };
+    if let Err(e) = fs::write(&output, &data) {
+        eprintln!("rustnes-asm: {}: {}", output, e);
+        process::exit(1);
+    }
+    println!("{} bytes written to {}", data.len(), output);
+}
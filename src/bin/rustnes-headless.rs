@@ -0,0 +1,29 @@
+use rustnes::headless::*;
+use std::env;
+use std::process;
+
+/// Runs a rom without any terminal interaction, for scripts and CI
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match HeadlessOptions::parse(&args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("rustnes-headless: {}\n\n{}", e, USAGE);
+            process::exit(1);
+        }
+    };
+    match run(&options) {
+        Ok(result) => {
+            println!(
+                "{} frames run{}",
+                result.frames,
+                if result.condition_met { ", condition met" } else { "" }
+            );
+            process::exit(result.exit_code);
+        }
+        Err(e) => {
+            eprintln!("rustnes-headless: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use rustnes::nsf::*;
+use std::env;
+use std::process;
+
+/// Describes a NSF file and renders one of its tracks to WAV
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match NsfOptions::parse(&args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("rustnes-nsf: {}\n\n{}", e, USAGE);
+            process::exit(1);
+        }
+    };
+    match run(&options) {
+        Ok(text) => println!("{}", text),
+        Err(e) => {
+            eprintln!("rustnes-nsf: {}", e);
+            process::exit(1);
+        }
+    }
+}
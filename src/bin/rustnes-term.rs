@@ -0,0 +1,34 @@
+use rustnes::archive::*;
+use rustnes::terminal::*;
+use std::env;
+use std::fs;
+use std::process;
+
+/// Plays a rom in the terminal, handy over SSH
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (path, scale) = match args.as_slice() {
+        [path] => (path, None),
+        [path, option, scale] if option == "--scale" => match scale.parse() {
+            Ok(x) => (path, Some(x)),
+            Err(_) => exit_with_usage(),
+        },
+        _ => exit_with_usage(),
+    };
+    let rom = match fs::read(path).map_err(|e| e.to_string()).and_then(|x| unpack_rom(x, None)) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("rustnes-term: {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = run(rom, scale) {
+        eprintln!("rustnes-term: {}", e);
+        process::exit(1);
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
@@ -1,11 +1,603 @@
+use super::{Region, Resampler};
+
+/// Audio processing unit of the 2A03.
+/// It is clocked at the cpu speed, the pulse and DMC timers tick every other cycle.
+/// The mixed output goes through `resampler` and is stored in `samples` for the frontends.
 pub struct APU {
-    pub registers : [u8; 8],
+    /// Last values written from $4000 to $4017
+    pub registers : [u8; 0x18],
+    pub pulse: [Pulse; 2],
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    /// Cpu cycles since the frame counter was reset
+    pub frame_cycle: u32,
+    /// 5 steps sequence instead of 4, set by $4017
+    pub five_steps: bool,
+    pub irq_inhibit: bool,
+    pub frame_irq: bool,
+    /// Cpu cycles since power up
+    pub cycle: u64,
+    /// Rate of the output samples in Hz
+    pub sample_rate: u32,
+    /// Filtered output, centered on 0.0, waiting to be consumed by a frontend
+    pub samples: Vec<f32>,
+    /// Band-limited synthesis of the output at `sample_rate`
+    pub resampler: Resampler,
+    /// Output of the sound chip of the cartridge, added to the mix
+    pub expansion: f32,
+    /// Frame counter, noise and DMC periods, see `set_region`
+    pub region: Region,
+}
+
+/// Cpu clock of a NTSC console, in Hz
+pub const CPU_FREQUENCY: u64 = 1_789_773;
+
+pub(crate) const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+pub(crate) const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15,
+];
+const NOISE_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const DMC_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_NOISE_TABLE: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+const PAL_DMC_TABLE: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+/// Cycles of the frame counter steps: the 3 first quarter frames, then the end of the
+/// 4 and 5 steps sequences
+const FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33252, 41565];
+
+/// Volume envelope shared by the pulses and the noise
+#[derive(Default, Clone, Copy)]
+pub struct Envelope {
+    pub start: bool,
+    pub looping: bool,
+    pub constant: bool,
+    pub volume: u8,
+    pub divider: u8,
+    pub decay: u8,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Pulse {
+    pub enabled: bool,
+    /// Pulse 1 negates its sweep with one's complement
+    pub ones_complement: bool,
+    pub duty: u8,
+    pub duty_step: u8,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: u8,
+    pub envelope: Envelope,
+    pub sweep_enabled: bool,
+    pub sweep_period: u8,
+    pub sweep_negate: bool,
+    pub sweep_shift: u8,
+    pub sweep_divider: u8,
+    pub sweep_reload: bool,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Triangle {
+    pub enabled: bool,
+    pub control: bool,
+    pub linear_period: u8,
+    pub linear_counter: u8,
+    pub linear_reload: bool,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: u8,
+    pub step: u8,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Noise {
+    pub enabled: bool,
+    pub mode: bool,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: u8,
+    pub envelope: Envelope,
+    pub shift: u16,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Dmc {
+    pub enabled: bool,
+    pub irq_enabled: bool,
+    pub irq: bool,
+    pub looping: bool,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub output: u8,
+    pub sample_addr: u16,
+    pub sample_length: u16,
+    pub current_addr: u16,
+    pub bytes_remaining: u16,
+    pub buffer: Option<u8>,
+    pub shift: u8,
+    pub bits_remaining: u8,
+    pub silence: bool,
 }
 
 impl APU {
     pub fn new() -> APU {
         APU {
-            registers : [0;8] 
+            registers : [0; 0x18],
+            pulse: [
+                Pulse { ones_complement: true, ..Pulse::default() },
+                Pulse::default(),
+            ],
+            triangle: Triangle::default(),
+            noise: Noise { shift: 1, timer_period: NOISE_TABLE[0], ..Noise::default() },
+            dmc: Dmc { timer_period: DMC_TABLE[0], bits_remaining: 8, silence: true, ..Dmc::default() },
+            frame_cycle: 0,
+            five_steps: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            cycle: 0,
+            sample_rate: 44100,
+            samples: Vec::new(),
+            resampler: Resampler::new(CPU_FREQUENCY, 44100),
+            expansion: 0.0,
+            region: Region::Ntsc,
+        }
+    }
+
+    /// Switch to the timings of another console, the output keeps its sample rate
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.resampler.set_clock_rate(region.cpu_frequency());
+        self.noise.timer_period = self.noise_table()[(self.registers[0x0E] & 0x0F) as usize];
+        self.dmc.timer_period = self.dmc_table()[(self.registers[0x10] & 0x0F) as usize];
+    }
+
+    /// Only the PAL APU has its own periods, the one of Dendy is the NTSC one
+    fn noise_table(&self) -> &'static [u16; 16] {
+        match self.region {
+            Region::Pal => &PAL_NOISE_TABLE,
+            _ => &NOISE_TABLE,
+        }
+    }
+    fn dmc_table(&self) -> &'static [u16; 16] {
+        match self.region {
+            Region::Pal => &PAL_DMC_TABLE,
+            _ => &DMC_TABLE,
+        }
+    }
+
+    /// Write one of the registers from $4000 to $4017
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        let reg = (addr - 0x4000) as usize;
+        if reg >= self.registers.len() {
+            return;
+        }
+        self.registers[reg] = data;
+        match reg {
+            0x00..=0x07 => self.pulse[reg / 4].write(reg % 4, data),
+            0x08 => {
+                self.triangle.control = data & 0x80 != 0;
+                self.triangle.linear_period = data & 0x7F;
+            }
+            0x0A => self.triangle.timer_period = (self.triangle.timer_period & 0x0700) | data as u16,
+            0x0B => {
+                self.triangle.timer_period = (self.triangle.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                if self.triangle.enabled {
+                    self.triangle.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.triangle.linear_reload = true;
+            }
+            0x0C => self.noise.envelope.write(data),
+            0x0E => {
+                self.noise.mode = data & 0x80 != 0;
+                self.noise.timer_period = self.noise_table()[(data & 0x0F) as usize];
+            }
+            0x0F => {
+                if self.noise.enabled {
+                    self.noise.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.noise.envelope.start = true;
+            }
+            0x10 => {
+                self.dmc.irq_enabled = data & 0x80 != 0;
+                self.dmc.looping = data & 0x40 != 0;
+                self.dmc.timer_period = self.dmc_table()[(data & 0x0F) as usize];
+                if !self.dmc.irq_enabled {
+                    self.dmc.irq = false;
+                }
+            }
+            0x11 => self.dmc.output = data & 0x7F,
+            0x12 => self.dmc.sample_addr = 0xC000 + data as u16 * 64,
+            0x13 => self.dmc.sample_length = data as u16 * 16 + 1,
+            0x15 => {
+                self.pulse[0].set_enabled(data & 0x01 != 0);
+                self.pulse[1].set_enabled(data & 0x02 != 0);
+                self.triangle.enabled = data & 0x04 != 0;
+                if !self.triangle.enabled {
+                    self.triangle.length = 0;
+                }
+                self.noise.enabled = data & 0x08 != 0;
+                if !self.noise.enabled {
+                    self.noise.length = 0;
+                }
+                self.dmc.irq = false;
+                self.dmc.enabled = data & 0x10 != 0;
+                if !self.dmc.enabled {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+            }
+            0x17 => {
+                self.five_steps = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_steps {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Only $4015 can be read, it gives the state of the length counters and the interrupts
+    pub fn cpu_read(&mut self, addr: u16, read_only: bool) -> u8 {
+        if addr != 0x4015 {
+            return 0;
+        }
+        let data = (self.pulse[0].length > 0) as u8
+            | ((self.pulse[1].length > 0) as u8) << 1
+            | ((self.triangle.length > 0) as u8) << 2
+            | ((self.noise.length > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7;
+        if !read_only {
+            self.frame_irq = false;
+        }
+        data
+    }
+
+    /// Interrupt line of the APU
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    /// Address the DMC wants to read its next sample byte from, if its buffer is empty
+    pub fn dmc_request(&self) -> Option<u16> {
+        match self.dmc.buffer.is_none() && self.dmc.bytes_remaining > 0 {
+            true => Some(self.dmc.current_addr),
+            false => None,
+        }
+    }
+
+    /// Give the DMC the byte it asked for with `dmc_request`
+    pub fn dmc_fill(&mut self, data: u8) {
+        self.dmc.buffer = Some(data);
+        self.dmc.current_addr = match self.dmc.current_addr {
+            0xFFFF => 0x8000,
+            x => x + 1,
+        };
+        self.dmc.bytes_remaining -= 1;
+        if self.dmc.bytes_remaining == 0 {
+            if self.dmc.looping {
+                self.dmc.restart();
+            } else if self.dmc.irq_enabled {
+                self.dmc.irq = true;
+            }
+        }
+    }
+
+    /// Advance the APU by one cpu cycle
+    pub fn clock(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        if self.cycle % 2 == 1 {
+            self.pulse[0].clock_timer();
+            self.pulse[1].clock_timer();
+            self.dmc.clock_timer();
+        }
+        self.clock_frame_counter();
+
+        if self.resampler.sample_rate != self.sample_rate {
+            self.resampler.set_sample_rate(self.sample_rate);
+        }
+        let output = self.output();
+        self.resampler.clock(output, &mut self.samples);
+        self.cycle += 1;
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        let steps = match self.region {
+            Region::Pal => &PAL_FRAME_STEPS,
+            _ => &FRAME_STEPS,
+        };
+        let last = steps[if self.five_steps { 4 } else { 3 }];
+        match self.frame_cycle {
+            x if x == steps[0] || x == steps[2] => self.quarter_frame(),
+            x if x == steps[1] => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            x if x == last => {
+                self.quarter_frame();
+                self.half_frame();
+                if !self.five_steps && !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+            }
+            x if x == last + 1 => self.frame_cycle = 0,
+            _ => (),
+        }
+    }
+
+    /// Envelopes and triangle linear counter
+    fn quarter_frame(&mut self) {
+        self.pulse[0].envelope.clock();
+        self.pulse[1].envelope.clock();
+        self.noise.envelope.clock();
+        let triangle = &mut self.triangle;
+        if triangle.linear_reload {
+            triangle.linear_counter = triangle.linear_period;
+        } else if triangle.linear_counter > 0 {
+            triangle.linear_counter -= 1;
+        }
+        if !triangle.control {
+            triangle.linear_reload = false;
+        }
+    }
+
+    /// Length counters and sweeps
+    fn half_frame(&mut self) {
+        for pulse in self.pulse.iter_mut() {
+            pulse.clock_length();
+            pulse.clock_sweep();
+        }
+        if !self.triangle.control && self.triangle.length > 0 {
+            self.triangle.length -= 1;
+        }
+        if !self.noise.envelope.looping && self.noise.length > 0 {
+            self.noise.length -= 1;
+        }
+    }
+
+    /// Output level of each channel: pulse 1, pulse 2, triangle, noise and DMC
+    pub fn channels(&self) -> [u8; 5] {
+        [
+            self.pulse[0].output(),
+            self.pulse[1].output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output,
+        ]
+    }
+
+    /// Non linear mix of the channels, as done by the resistors of the console, with the
+    /// expansion audio
+    pub fn output(&self) -> f32 {
+        let [p1, p2, t, n, d] = self.channels();
+        let pulse = match p1 + p2 {
+            0 => 0.0,
+            x => 95.88 / (8128.0 / x as f32 + 100.0),
+        };
+        let tnd = t as f32 / 8227.0 + n as f32 / 12241.0 + d as f32 / 22638.0;
+        let tnd = match tnd == 0.0 {
+            true => 0.0,
+            false => 159.79 / (1.0 / tnd + 100.0),
+        };
+        pulse + tnd + self.expansion
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Envelope {
+    pub(crate) fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.volume = data & 0x0F;
+    }
+    pub(crate) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider > 0 {
+            self.divider -= 1;
+        } else {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        }
+    }
+    pub(crate) fn output(&self) -> u8 {
+        match self.constant {
+            true => self.volume,
+            false => self.decay,
+        }
+    }
+}
+
+impl Pulse {
+    pub(crate) fn write(&mut self, reg: usize, data: u8) {
+        match reg {
+            0 => {
+                self.duty = data >> 6;
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.duty_step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+    pub(crate) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_step = (self.duty_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub(crate) fn clock_length(&mut self) {
+        if !self.envelope.looping && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        match (self.sweep_negate, self.ones_complement) {
+            (false, _) => self.timer_period + change,
+            (true, true) => self.timer_period.saturating_sub(change + 1),
+            (true, false) => self.timer_period.saturating_sub(change),
+        }
+    }
+    fn clock_sweep(&mut self) {
+        let target = self.sweep_target();
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && self.timer_period >= 8 && target <= 0x7FF {
+            self.timer_period = target;
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+    pub fn output(&self) -> u8 {
+        if self.length == 0
+            || self.timer_period < 8
+            || self.sweep_target() > 0x7FF
+            || DUTY_TABLE[self.duty as usize][self.duty_step as usize] == 0
+        {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+impl Triangle {
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length > 0 && self.linear_counter > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub fn output(&self) -> u8 {
+        // Ultrasonic periods are silenced instead of popping
+        if self.timer_period < 2 {
+            return 7;
+        }
+        TRIANGLE_TABLE[self.step as usize]
+    }
+}
+
+impl Noise {
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            // The table is in cpu cycles, the timer runs every cycle
+            self.timer = self.timer_period - 1;
+            let tap = match self.mode {
+                true => 6,
+                false => 1,
+            };
+            let feedback = (self.shift & 0x01) ^ ((self.shift >> tap) & 0x01);
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
         }
     }
-}
\ No newline at end of file
+    pub fn output(&self) -> u8 {
+        if self.length == 0 || self.shift & 0x01 != 0 {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+impl Dmc {
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        // The table is in cpu cycles, the timer runs every other cycle
+        self.timer = self.timer_period / 2 - 1;
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.output <= 125 {
+                    self.output += 2;
+                }
+            } else if self.output >= 2 {
+                self.output -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(x) => {
+                    self.silence = false;
+                    self.shift = x;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::{convert::TryInto, fmt, ops::{Index, IndexMut}};
 
-use super::{Mapper, Source};
+use super::{parse_fds, parse_unif, DiskSystem, ExpansionAudio, GameDb, Mapper, Source, FDS_BIOS_SIZE};
 
 
 pub struct Cartridge{
@@ -9,10 +9,27 @@ pub struct Cartridge{
     pub header : Header,
     pub prg_memory : Vec<u8>,
     pub chr_memory : Vec<u8>,
-    pub mapper : Mapper
+    /// 8KB of work RAM mapped from 0x6000 to 0x7FFF
+    pub prg_ram : Vec<u8>,
+    pub mapper : Mapper,
+    /// Sound chip of the board, mixed with the APU
+    pub audio : ExpansionAudio,
+    /// RAM adapter and drive of the Famicom Disk System, for disk images
+    pub disk : Option<DiskSystem>,
 }
 
-#[derive(Default,Debug)]
+/// How the 4 nametables seen by the PPU are mapped on the 2KB of vram
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mirror {
+    Horizontal,
+    Vertical,
+    OneScreenLo,
+    OneScreenHi,
+    /// Vram page of each of the 4 nametables, set by the mapper
+    Pages([u8; 4]),
+}
+
+#[derive(Default,Debug,Clone)]
 pub struct Header {
     /// "NES" followed by eol
     pub nes_validator : [u8;4],
@@ -32,6 +49,10 @@ pub struct Header {
     pub flags_10 : u8,
     /// Empty padding, used to store names of hackers
     pub padding : [u8;5],
+    /// Name of the game, not part of iNES
+    pub title : Option<String>,
+    /// Name of the board, not part of iNES
+    pub board : Option<String>,
 }
 
 impl Cartridge {
@@ -40,17 +61,36 @@ impl Cartridge {
             rom : Vec::new(),
             header: Header::default(),
             mapper: Mapper::default(),
+            audio: ExpansionAudio::default(),
+            disk: None,
             prg_memory : Vec::new(),
-            chr_memory : Vec::new()
+            chr_memory : Vec::new(),
+            prg_ram : vec![0u8; 0x2000]
         }
     }
-    pub fn load(&mut self, data : Vec<u8>){
-        self.rom = data;
-        self.header = Header::new(&self.rom[0..16]);
+    /// iNES or NES 2.0 image, the header and the size of the banks are checked first
+    pub fn load(&mut self, data : Vec<u8>) -> Result<(), String> {
+        self.load_with_db(data, GameDb::embedded())
+    }
+    /// Same as `load`, with the headers fixed by another game database
+    pub fn load_with_db(&mut self, data : Vec<u8>, db : &GameDb) -> Result<(), String> {
+        if data.len() < 16 || data[0..4] != *b"NES\x1A" {
+            return Err("not an iNES rom, the header is missing".to_string());
+        }
+        let header = Header::new(&data[0..16]);
         let mut offset = 16;
-        if self.header.has_trainer() {offset += 512};
-        let nb_bank = self.header.nb_prg_banks as usize;
-        let nb_bank2 = self.header.nb_chr_banks as usize;
+        if header.has_trainer() {offset += 512};
+        let size = offset
+            + header.nb_prg_banks as usize * 0x4000
+            + header.nb_chr_banks as usize * 0x2000;
+        if header.nb_prg_banks == 0 {
+            return Err("the rom has no PRG bank".to_string());
+        }
+        if data.len() < size {
+            return Err(format!("the rom is truncated, {} bytes instead of {}", data.len(), size));
+        }
+        self.rom = data;
+        self.header = header;
         self.prg_memory = self.rom[
                 offset .. offset + self.header.nb_prg_banks as usize * 0x4000
         ].to_vec();
@@ -59,9 +99,74 @@ impl Cartridge {
             self.chr_memory = self.rom[
                 offset .. offset + self.header.nb_chr_banks as usize * 0x2000
             ].to_vec();
+        } else {
+            // Boards without CHR ROM have 8KB of CHR RAM instead
+            self.chr_memory = vec![0u8; 0x2000];
+        }
+        // Many iNES dumps have a wrong header, the database knows the right one
+        let chr = &self.rom[offset..offset + self.header.nb_chr_banks as usize * 0x2000];
+        if let Some(game) = db.find(&self.prg_memory, chr) {
+            game.apply(&mut self.header);
+        }
+        self.mapper = Mapper::new(self.header.clone());
+        self.prg_ram = vec![0u8; self.mapper.prg_ram_size()];
+        // The 512 bytes of trainer were loaded at $7000 by the copiers
+        if self.header.has_trainer() {
+            if let Some(at) = self.mapper.map_prg_ram(0x7000).filter(|at| at + 512 <= self.prg_ram.len()) {
+                self.prg_ram[at..at + 512].copy_from_slice(&self.rom[16..16 + 512]);
+            }
+        }
+        self.audio = ExpansionAudio::for_mapper(self.header.mapper_id());
+        self.disk = None;
+        Ok(())
+    }
+    /// UNIF image, converted to the NES 2.0 header of its board
+    pub fn load_unif(&mut self, data: &[u8]) -> Result<(), String> {
+        let unif = parse_unif(data)?;
+        self.load(unif.to_ines()?)?;
+        self.header.title = unif.name.or(self.header.title.take());
+        self.header.board = Some(unif.board);
+        Ok(())
+    }
+    /// Tune data of a NSF file, `prg` is a multiple of 4KB mapped by `banks` from $8000
+    pub fn load_nsf(&mut self, prg: Vec<u8>, banks: [u8; 8]) {
+        self.rom = Vec::new();
+        self.header = Header::default();
+        self.mapper = Mapper::nsf(prg.len(), banks);
+        self.prg_memory = prg;
+        self.chr_memory = vec![0u8; 0x2000];
+        self.prg_ram = vec![0u8; 0x2000];
+        self.disk = None;
+    }
+    /// Disk image of the Famicom Disk System, `bios` is the 8KB ROM of the RAM adapter
+    pub fn load_fds(&mut self, image: &[u8], bios: Vec<u8>) -> Result<(), String> {
+        if bios.len() != FDS_BIOS_SIZE {
+            return Err(format!("the FDS BIOS is {} bytes long, not {}", FDS_BIOS_SIZE, bios.len()));
+        }
+        let sides = parse_fds(image)?;
+        self.rom = image.to_vec();
+        self.header = Header::default();
+        self.mapper = Mapper::fds();
+        self.prg_memory = vec![0u8; 0x6000];
+        self.prg_memory.extend(bios);
+        self.chr_memory = vec![0u8; 0x2000];
+        self.prg_ram = vec![0u8; 0x2000];
+        self.audio = ExpansionAudio::from_nsf_flags(0x04);
+        self.disk = Some(DiskSystem::new(&sides));
+        Ok(())
+    }
+    /// Nametable arrangement wired on the board
+    pub fn mirror(&self) -> Mirror {
+        if let Some(disk) = &self.disk {
+            return disk.mirror;
+        }
+        if let Some(mirror) = self.mapper.mirror() {
+            return mirror;
+        }
+        match self.header.flags_6 & 0x01 {
+            1 => Mirror::Vertical,
+            _ => Mirror::Horizontal,
         }
-                
-
     }
     pub fn to_vec(&self) -> Vec<u8>{
         self.rom.to_vec()
@@ -75,6 +180,18 @@ impl Cartridge {
         
     }
     pub fn cpu_read(&self, addr : u16) -> u8 {
+        if let Some(data) = self.disk.as_ref().and_then(|disk| disk.read(addr)) {
+            return data;
+        }
+        if let Some(data) = self.audio.cpu_read(addr) {
+            return data;
+        }
+        if let Some(data) = self.mapper.cpu_read(addr) {
+            return data;
+        }
+        if let Some(offset) = self.mapper.map_prg_ram(addr) {
+            return self.prg_ram[offset];
+        }
         match self.mapper.map(Source::CPU,addr) {
             Some(a) => self.prg_memory[a],
             None => 0
@@ -88,9 +205,66 @@ impl Cartridge {
         
     }
     pub fn cpu_write(&mut self, addr : u16, data : u8) {
+        if self.disk.as_mut().is_some_and(|disk| disk.write(addr, data)) {
+            return;
+        }
+        // The ROM answers the write too, a 0 from either side wins on the bus
+        let data = match self.mapper.bus_conflicts() && addr >= 0x8000 {
+            true => self.mapper.map(Source::CPU,addr).map_or(data, |a| data & self.prg_memory[a]),
+            false => data,
+        };
+        // The sound chips share some registers with the mappers
+        let audio = self.audio.cpu_write(addr, data);
+        if self.mapper.cpu_write(addr, data) || audio {
+            return;
+        }
+        if let Some(offset) = self.mapper.map_prg_ram(addr) {
+            self.prg_ram[offset] = data;
+            return;
+        }
+        // The ROM is read only, the boards ignore the writes to addresses without registers
         match self.mapper.map(Source::CPU,addr) {
-            Some(a) => self.prg_memory[a] = data,
-            None => ()
+            Some(a) if a < self.mapper.prg_memory_ram() => self.prg_memory[a] = data,
+            _ => ()
+        }
+    }
+    /// A read made by the cpu, some registers are acknowledged by it
+    pub fn observe_read(&mut self, addr : u16, data : u8) {
+        self.audio.observe_read(addr, data);
+        self.mapper.observe_read(addr);
+        if let Some(disk) = self.disk.as_mut() {
+            disk.acknowledge(addr);
+        }
+    }
+    /// A read made by the PPU on the pattern tables, some mappers switch banks on it
+    pub fn observe_ppu_read(&mut self, addr : u16) {
+        self.mapper.observe_ppu_read(addr);
+    }
+    /// A write of the cpu to the PPU registers, some mappers follow PPUCTRL and PPUMASK
+    pub fn observe_ppu_register(&mut self, addr : u16, data : u8) {
+        self.mapper.observe_ppu_register(addr, data);
+    }
+    /// Nametable fetch answered by the board instead of the console vram
+    pub fn nametable_read(&self, addr : u16) -> Option<u8> {
+        match self.mapper.map_nametable(addr) {
+            Some(offset) => Some(self.chr_memory[offset]),
+            None => self.mapper.nametable_read(addr),
+        }
+    }
+    /// Nametable write taken by the board, returns false when it goes to the console vram
+    pub fn nametable_write(&mut self, addr : u16, data : u8) -> bool {
+        // The nametables in CHR ROM cannot be written
+        self.mapper.map_nametable(addr).is_some() || self.mapper.nametable_write(addr, data)
+    }
+    /// Interrupt request of the board
+    pub fn irq(&self) -> bool {
+        self.disk.as_ref().is_some_and(|disk| disk.irq()) || self.mapper.irq()
+    }
+    /// Advance the parts of the board running on their own by one cpu cycle
+    pub fn clock(&mut self) {
+        self.mapper.clock();
+        if let Some(disk) = self.disk.as_mut() {
+            disk.clock();
         }
     }
 }
@@ -108,16 +282,41 @@ impl Header {
             flags_9: data[9],
             flags_10: data[10],
             padding: [data[11],data[12],data[13],data[14],data[15]],
+            title: None,
+            board: None,
         }
     }
+    /// 8 bits on iNES, NES 2.0 adds 4 more in the low nibble of the flags 8
     pub fn mapper_id(&self) -> u16 {
-        ((self.flags_7 >> 4) << 4 | self.flags_6 >> 4) as u16
+        let id = ((self.flags_7 >> 4) << 4 | self.flags_6 >> 4) as u16;
+        match self.is_nes2() {
+            true => (self.flags_8 as u16 & 0x0F) << 8 | id,
+            false => id,
+        }
+    }
+    /// Board variant of the mapper on NES 2.0, 0 when unknown
+    pub fn submapper(&self) -> u8 {
+        match self.is_nes2() {
+            true => self.flags_8 >> 4,
+            false => 0,
+        }
+    }
+    /// PRG RAM and battery backed RAM given by NES 2.0, 64 bytes shifted by each nibble
+    pub fn prg_ram_size(&self) -> Option<usize> {
+        if !self.is_nes2() {
+            return None;
+        }
+        let size = |shift : u8| match shift {
+            0 => 0,
+            _ => 64usize << shift,
+        };
+        Some(size(self.flags_10 & 0x0F) + size(self.flags_10 >> 4))
     }
     pub fn is_nes2(&self) -> bool {
         self.flags_7 & 0b00001100 == 0x8
     }
     pub fn has_trainer(&self) -> bool{
-        self.flags_6 & 0x4 == 1
+        self.flags_6 & 0x04 != 0
     }
 }
 
@@ -140,7 +339,14 @@ impl fmt::Display for Header {
             self.nb_prg_banks,
             self.nb_chr_banks,
             self.mapper_id()
-        )
+        )?;
+        if let Some(title) = &self.title {
+            writeln!(f, "                title : {}", title)?;
+        }
+        if let Some(board) = &self.board {
+            writeln!(f, "                board : {}", board)?;
+        }
+        Ok(())
     }
 }
 
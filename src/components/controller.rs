@@ -0,0 +1,54 @@
+/// Standard NES joypad, read through $4016 (port 1) and $4017 (port 2).
+/// The buttons are stored in the order the shift register sends them,
+/// which is also the FM2 `RLDUTSBA` order read from the low bit up.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Controller {
+    /// Buttons currently held, set by the frontend or a movie
+    pub buttons: u8,
+    /// Shift register latched from `buttons` while the strobe is high
+    pub shift: u8,
+    /// Strobe bit written to $4016
+    pub strobe: bool,
+}
+
+/// Bit masks of the joypad buttons
+pub enum Button {
+    A = 1 << 0,
+    B = 1 << 1,
+    Select = 1 << 2,
+    Start = 1 << 3,
+    Up = 1 << 4,
+    Down = 1 << 5,
+    Left = 1 << 6,
+    Right = 1 << 7,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller::default()
+    }
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        match pressed {
+            true => self.buttons |= button as u8,
+            false => self.buttons &= !(button as u8),
+        }
+    }
+    /// Write to $4016, only the lowest bit is wired to the strobe line
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 == 1;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+    /// Read the next button, once the 8 buttons are sent the pad returns 1
+    pub fn read(&mut self, read_only: bool) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+        let bit = self.shift & 0x01;
+        if !read_only {
+            self.shift = (self.shift >> 1) | 0x80;
+        }
+        bit
+    }
+}
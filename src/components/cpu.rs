@@ -693,11 +693,12 @@ impl OperationCodes for CPU6502 {
     }
     /// Jump to sub routine, push current program counter to stack
     fn JSR(&mut self, nes: &mut NesData) -> u8 {
+        // The address of the last byte of the instruction is pushed, RTS adds 1 to it
         self.pc -= 1;
         nes.cpu_write( 0x0100 + self.stkp as u16, self.pc.get_high_byte());
-        self.stkp -= 1;
-        nes.cpu_write( 0x0100 + self.stkp as u16, self.pc.get_high_byte());
-        self.stkp -= 1;
+        self.stkp = self.stkp.wrapping_sub(1);
+        nes.cpu_write( 0x0100 + self.stkp as u16, self.pc.get_low_byte());
+        self.stkp = self.stkp.wrapping_sub(1);
         self.pc = self.addr_abs;
         0u8
     }
@@ -833,10 +834,11 @@ impl OperationCodes for CPU6502 {
     }
     /// Return from subroutine, Pop the program counter from the stack
     fn RTS(&mut self, nes: &mut NesData) -> u8 {
-        self.stkp += 1;
+        self.stkp = self.stkp.wrapping_add(1);
+        let lo = self.read(nes, 0x0100 + self.stkp as u16, true) as u16;
+        self.stkp = self.stkp.wrapping_add(1);
         let hi = (self.read(nes, 0x0100 + self.stkp as u16, true) as u16) << 8;
-        let lo = self.read(nes, 0x0100 + (self.stkp + 1) as u16, true) as u16;
-        self.pc = hi + lo;
+        self.pc = (hi | lo).wrapping_add(1);
 
         0u8
     }
@@ -966,7 +968,7 @@ impl CPUFunctions for CPU6502 {
         self.x = 0;
         self.y = 0;
         self.stkp = 0xFD;
-        self.status = 0x00 | FLAGS6502::U as u8;
+        self.status = FLAGS6502::U as u8 | FLAGS6502::I as u8;
         self.addr_abs = 0xFFFC;
         let lo = self.read(nes, self.addr_abs, true) as u16;
         let hi = self.read(nes, self.addr_abs + 1, true) as u16;
@@ -980,8 +982,8 @@ impl CPUFunctions for CPU6502 {
         self.a = 0;
         self.x = 0;
         self.y = 0;
-        self.stkp = 0x00;
-        self.status = 0x00 | FLAGS6502::U as u8;
+        self.stkp = 0xFD;
+        self.status = FLAGS6502::U as u8 | FLAGS6502::I as u8;
         self.addr_abs = 0xFFFC;
         let lo = self.read(nes, self.addr_abs, true) as u16;
         let hi = self.read(nes, self.addr_abs + 1, true) as u16;
@@ -993,7 +995,7 @@ impl CPUFunctions for CPU6502 {
         self.cycles = 3;
     }
     fn interupt_req(&mut self, nes: &mut NesData) {
-        if self.get_flag(FLAGS6502::I) != 0 {
+        if self.get_flag(FLAGS6502::I) == 0 {
             self.write(
                 nes,
                 0x0100 + self.stkp as u16,
@@ -1016,7 +1018,7 @@ impl CPUFunctions for CPU6502 {
     }
     fn fetch_data(&mut self, nes: &mut NesData) -> u8 {
         if self.lookup[self.curr_opcode as usize].addr_mode != "IMP" {
-            self.fetched_data = self.read(nes, self.addr_abs, true);
+            self.fetched_data = self.read(nes, self.addr_abs, false);
         }
         self.fetched_data
     }
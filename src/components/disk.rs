@@ -0,0 +1,480 @@
+use super::Mirror;
+
+/// Size of a disk side in a `.fds` file
+pub const FDS_SIDE_SIZE: usize = 65500;
+/// Size of the BIOS of the RAM adapter, mapped from $E000
+pub const FDS_BIOS_SIZE: usize = 0x2000;
+/// Header of the files made by fwNES, followed by the number of sides
+const FWNES_MAGIC: &[u8; 4] = b"FDS\x1A";
+/// Start of every disk side
+const DISK_VERIFICATION: &[u8; 15] = b"\x01*NINTENDO-HVC*";
+/// Gap before the first block and after each block, in bytes
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+/// Cpu cycles to read or write a byte, the drive transfers about 96400 bits per second
+const BYTE_CYCLES: u32 = 150;
+/// Cpu cycles for the head to go back to the start of the disk
+const REWIND_CYCLES: u32 = 50000;
+/// Cpu cycles the drive stays empty when changing the side, for the BIOS to notice it
+const INSERT_CYCLES: u32 = 1_000_000;
+
+/// Famicom Disk System: the RAM adapter and its disk drive.
+/// The sides are stored as the drive sees them, with the gaps, the block marks and the
+/// CRCs that `.fds` files leave out.
+pub struct DiskSystem {
+    /// Raw content of every side
+    pub sides: Vec<Vec<u8>>,
+    /// Sides written to since they were loaded
+    pub modified: Vec<bool>,
+    /// Side in the drive
+    pub inserted: Option<usize>,
+    /// $4023 bit 0, the other disk registers are ignored when clear
+    pub disk_registers_enabled: bool,
+    /// $4023 bit 1
+    pub sound_enabled: bool,
+    pub timer_reload: u16,
+    pub timer_counter: u16,
+    pub timer_repeat: bool,
+    pub timer_enabled: bool,
+    pub timer_irq: bool,
+    /// Control register $4025
+    pub motor_on: bool,
+    pub transfer_reset: bool,
+    pub read_mode: bool,
+    pub mirror: Mirror,
+    pub crc_control: bool,
+    pub transfer_enabled: bool,
+    pub disk_irq_enabled: bool,
+    /// A byte was read or has to be written, flag of $4030
+    pub transfer_complete: bool,
+    pub disk_irq: bool,
+    /// Position of the head in the raw side
+    pub position: usize,
+    pub end_of_head: bool,
+    /// The head is moving over the disk
+    pub scanning: bool,
+    pub(crate) read_data: u8,
+    pub(crate) write_data: u8,
+    pub(crate) gap_ended: bool,
+    pub(crate) crc: u32,
+    pub(crate) previous_crc_control: bool,
+    pub(crate) delay: u32,
+    pub(crate) insert_delay: u32,
+}
+
+impl DiskSystem {
+    /// Sides of a `.fds` file, `sides` comes from `parse_fds`
+    pub fn new(sides: &[Vec<u8>]) -> DiskSystem {
+        DiskSystem {
+            sides: sides.iter().map(|side| side_to_raw(side)).collect(),
+            modified: vec![false; sides.len()],
+            inserted: Some(0),
+            disk_registers_enabled: false,
+            sound_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            transfer_reset: false,
+            read_mode: true,
+            mirror: Mirror::Horizontal,
+            crc_control: false,
+            transfer_enabled: false,
+            disk_irq_enabled: false,
+            transfer_complete: false,
+            disk_irq: false,
+            position: 0,
+            end_of_head: true,
+            scanning: false,
+            read_data: 0,
+            write_data: 0,
+            gap_ended: false,
+            crc: 0,
+            previous_crc_control: false,
+            delay: 0,
+            insert_delay: 0,
+        }
+    }
+
+    /// Put a side in the drive, `None` ejects the disk.
+    /// The drive stays empty for a moment so that the BIOS sees the change.
+    pub fn insert(&mut self, side: Option<usize>) -> Result<(), String> {
+        if let Some(x) = side {
+            if x >= self.sides.len() {
+                return Err(format!(
+                    "side {} does not exist, the disk has {} sides",
+                    x + 1,
+                    self.sides.len()
+                ));
+            }
+            self.insert_delay = INSERT_CYCLES;
+        }
+        self.inserted = side;
+        Ok(())
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    fn disk_present(&self) -> bool {
+        self.inserted.is_some() && self.insert_delay == 0
+    }
+
+    pub fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    /// Registers from $4020 to $4026, returns false for any other address
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        if !(0x4020..=0x4026).contains(&addr) {
+            return false;
+        }
+        if !self.disk_registers_enabled && addr != 0x4023 {
+            return true;
+        }
+        match addr {
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | data as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | (data as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = data & 0x01 != 0;
+                self.timer_enabled = data & 0x02 != 0;
+                match self.timer_enabled {
+                    true => self.timer_counter = self.timer_reload,
+                    false => self.timer_irq = false,
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = data & 0x01 != 0;
+                self.sound_enabled = data & 0x02 != 0;
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.motor_on = data & 0x01 != 0;
+                self.transfer_reset = data & 0x02 != 0;
+                self.read_mode = data & 0x04 != 0;
+                self.mirror = match data & 0x08 {
+                    0 => Mirror::Vertical,
+                    _ => Mirror::Horizontal,
+                };
+                self.crc_control = data & 0x10 != 0;
+                self.transfer_enabled = data & 0x40 != 0;
+                self.disk_irq_enabled = data & 0x80 != 0;
+                self.disk_irq = false;
+            }
+            _ => (),
+        }
+        true
+    }
+
+    /// Registers from $4030 to $4033, `acknowledge` has to follow a read made by the cpu
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        if !self.disk_registers_enabled || !(0x4030..=0x4033).contains(&addr) {
+            return None;
+        }
+        let result = match addr {
+            0x4030 => {
+                self.timer_irq as u8
+                    | (self.transfer_complete as u8) << 1
+                    | ((self.mirror == Mirror::Horizontal) as u8) << 3
+                    | (self.end_of_head as u8) << 6
+            }
+            0x4031 => self.read_data,
+            // Not inserted, not ready and write protected
+            0x4032 => {
+                let present = self.disk_present();
+                (!present as u8 * 0x05) | ((!present || !self.scanning) as u8) << 1 | 0x40
+            }
+            // Battery good
+            _ => 0x80,
+        };
+        Some(result)
+    }
+
+    /// Reading the status or the data clears the flags and the IRQs
+    pub fn acknowledge(&mut self, addr: u16) {
+        if !self.disk_registers_enabled {
+            return;
+        }
+        match addr {
+            0x4030 => {
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => (),
+        }
+    }
+
+    /// Advance the timer and the drive by one cpu cycle
+    pub fn clock(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled || !self.disk_registers_enabled {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+            return;
+        }
+        let side = match self.inserted {
+            Some(x) if self.motor_on => x,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        if self.transfer_reset && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut need_irq = self.disk_irq_enabled;
+        if self.read_mode {
+            let data = self.sides[side].get(self.position).copied().unwrap_or(0);
+            if !self.previous_crc_control {
+                self.update_crc(data);
+            }
+            if !self.transfer_enabled {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if data != 0 && !self.gap_ended {
+                // The mark at the end of the gap does not raise the IRQ
+                self.gap_ended = true;
+                need_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                if need_irq {
+                    self.disk_irq = true;
+                }
+            }
+            if !self.transfer_enabled {
+                data = 0;
+            }
+            if !self.crc_control {
+                self.update_crc(data);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            let raw = &mut self.sides[side];
+            if self.position >= raw.len() {
+                raw.resize(self.position + 1, 0);
+            }
+            raw[self.position] = data;
+            self.modified[side] = true;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn update_crc(&mut self, data: u8) {
+        for bit in 0..8 {
+            let carry = self.crc & 0x01 != 0;
+            self.crc >>= 1;
+            if carry {
+                self.crc ^= 0x8408;
+            }
+            if data & (1 << bit) != 0 {
+                self.crc ^= 0x10000;
+            }
+        }
+    }
+
+    /// Whether a side was written to since it was loaded
+    pub fn is_modified(&self) -> bool {
+        self.modified.contains(&true)
+    }
+
+    /// The disk as a `.fds` file with a fwNES header, including what was written to it
+    pub fn to_fds(&self) -> Vec<u8> {
+        let sides: Vec<Vec<u8>> = self.sides.iter().map(|raw| raw_to_side(raw)).collect();
+        sides_to_fds(&sides)
+    }
+
+    /// Replace the sides by the ones of a `.fds` file written by `to_fds`
+    pub fn load_save(&mut self, data: &[u8]) -> Result<(), String> {
+        let sides = parse_fds(data)?;
+        if sides.len() != self.sides.len() {
+            return Err(format!(
+                "the save has {} sides, the disk has {}",
+                sides.len(),
+                self.sides.len()
+            ));
+        }
+        self.sides = sides.iter().map(|side| side_to_raw(side)).collect();
+        Ok(())
+    }
+}
+
+/// Whether a file looks like a disk image, with or without the fwNES header
+pub fn is_fds_image(data: &[u8]) -> bool {
+    data.starts_with(FWNES_MAGIC) || data.starts_with(DISK_VERIFICATION)
+}
+
+/// Sides of a `.fds` file, with or without the fwNES header
+pub fn parse_fds(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let data = match data.starts_with(FWNES_MAGIC) {
+        true => &data[16.min(data.len())..],
+        false => data,
+    };
+    if data.is_empty() || data.len() % FDS_SIDE_SIZE != 0 {
+        return Err(format!(
+            "a disk image is made of sides of {} bytes, the file has {} bytes",
+            FDS_SIDE_SIZE,
+            data.len()
+        ));
+    }
+    let sides: Vec<Vec<u8>> = data
+        .chunks(FDS_SIDE_SIZE)
+        .map(|side| side.to_vec())
+        .collect();
+    for (i, side) in sides.iter().enumerate() {
+        if !side.starts_with(DISK_VERIFICATION) {
+            return Err(format!("side {} is not a Famicom disk", i + 1));
+        }
+    }
+    Ok(sides)
+}
+
+/// `.fds` file with a fwNES header
+pub fn sides_to_fds(sides: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = FWNES_MAGIC.to_vec();
+    result.push(sides.len() as u8);
+    result.resize(16, 0);
+    for side in sides.iter() {
+        let start = result.len();
+        result.extend_from_slice(&side[..side.len().min(FDS_SIDE_SIZE)]);
+        result.resize(start + FDS_SIDE_SIZE, 0);
+    }
+    result
+}
+
+/// Length of the block starting at `data`, `file_size` is the size given by the last
+/// file header. None at the end of the disk.
+fn block_length(data: &[u8], file_size: &mut usize) -> Option<usize> {
+    let length = match data.first()? {
+        1 => 56,
+        2 => 2,
+        3 => {
+            *file_size = *data.get(13)? as usize | (*data.get(14)? as usize) << 8;
+            16
+        }
+        4 => 1 + *file_size,
+        _ => return None,
+    };
+    match length <= data.len() {
+        true => Some(length),
+        false => None,
+    }
+}
+
+/// Add the gaps, the block marks and the CRCs around the blocks of a side
+fn side_to_raw(side: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; LEADING_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+    while let Some(length) = block_length(&side[position..], &mut file_size) {
+        result.push(0x80);
+        result.extend_from_slice(&side[position..position + length]);
+        // The CRC is not checked, any value does
+        result.extend_from_slice(&[0x4D, 0x62]);
+        result.resize(result.len() + BLOCK_GAP, 0);
+        position += length;
+    }
+    // Room for the files written after the last one
+    let size = result.len().max(FDS_SIDE_SIZE + LEADING_GAP);
+    result.resize(size, 0);
+    result
+}
+
+/// Blocks of a raw side, without what `side_to_raw` added
+fn raw_to_side(raw: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(FDS_SIDE_SIZE);
+    let mut position = 0;
+    let mut file_size = 0;
+    loop {
+        // Skip the gap up to the block mark
+        while position < raw.len() && raw[position] == 0 {
+            position += 1;
+        }
+        if position >= raw.len() || raw[position] != 0x80 {
+            break;
+        }
+        position += 1;
+        match block_length(&raw[position..], &mut file_size) {
+            Some(length) => {
+                result.extend_from_slice(&raw[position..position + length]);
+                position += length + 2;
+            }
+            None => break,
+        }
+    }
+    result.resize(FDS_SIDE_SIZE.max(result.len()), 0);
+    result
+}
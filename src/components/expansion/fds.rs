@@ -0,0 +1,244 @@
+use super::PULSE_FULL;
+
+/// Level of the full scale wave at the maximum volume
+const FDS_FULL: f32 = 2.4 * PULSE_FULL;
+/// Changes of the modulation counter for each entry of the modulation table, 4 resets it
+const MODULATION_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+/// Master volume of $4089, 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+
+/// Sound of the Famicom Disk System: a 64 steps wavetable with a volume envelope, whose
+/// pitch is modulated by a second table with its own envelope
+pub struct FdsAudio {
+    /// 64 samples of 6 bits, from $4040 to $407F
+    pub wave: [u8; 64],
+    /// 64 entries of 3 bits, filled 2 at a time through $4088 at the position the
+    /// modulation reads
+    pub modulation_table: [u8; 64],
+    pub volume: FdsEnvelope,
+    pub modulation: FdsEnvelope,
+    pub frequency: u16,
+    /// $4083 bit 7, the wave is stopped and restarted from its start
+    pub wave_halted: bool,
+    /// $4083 bit 6
+    pub envelopes_halted: bool,
+    /// $4089 bit 7, the wave can be written and the output is held
+    pub wave_writable: bool,
+    pub master_volume: u8,
+    /// $408A, multiplies the period of both envelopes
+    pub envelope_speed: u8,
+    pub modulation_frequency: u16,
+    /// $4087 bit 7
+    pub modulation_halted: bool,
+    /// 7 bits signed counter of the modulation
+    pub modulation_counter: i8,
+    pub(crate) wave_accumulator: u32,
+    pub(crate) modulation_accumulator: u32,
+    pub(crate) modulation_position: u8,
+    /// Last sample output, kept while the wave is written
+    pub(crate) sample: u8,
+}
+
+/// Envelope of the volume or of the modulation depth
+#[derive(Default, Clone, Copy)]
+pub struct FdsEnvelope {
+    /// Bit 7 of the register, the gain is set directly
+    pub disabled: bool,
+    pub increase: bool,
+    pub speed: u8,
+    /// From 0 to 63, the volume is limited to 32 on output
+    pub gain: u8,
+    pub(crate) timer: u32,
+}
+
+impl FdsAudio {
+    pub fn new() -> FdsAudio {
+        FdsAudio {
+            wave: [0; 64],
+            modulation_table: [0; 64],
+            volume: FdsEnvelope::default(),
+            modulation: FdsEnvelope::default(),
+            frequency: 0,
+            wave_halted: true,
+            envelopes_halted: false,
+            wave_writable: false,
+            master_volume: 0,
+            envelope_speed: 0xE8,
+            modulation_frequency: 0,
+            modulation_halted: true,
+            modulation_counter: 0,
+            wave_accumulator: 0,
+            modulation_accumulator: 0,
+            modulation_position: 0,
+            sample: 0,
+        }
+    }
+
+    /// Registers from $4040 to $408A
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x4040..=0x407F => {
+                if self.wave_writable {
+                    self.wave[addr as usize - 0x4040] = data & 0x3F;
+                }
+            }
+            0x4080 => self.volume.write(data),
+            0x4082 => self.frequency = (self.frequency & 0x0F00) | data as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.wave_halted = data & 0x80 != 0;
+                self.envelopes_halted = data & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(data),
+            0x4085 => {
+                // Sign extension of the 7 bits
+                self.modulation_counter = ((data << 1) as i8) >> 1;
+            }
+            0x4086 => {
+                self.modulation_frequency = (self.modulation_frequency & 0x0F00) | data as u16
+            }
+            0x4087 => {
+                self.modulation_frequency =
+                    (self.modulation_frequency & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.modulation_halted = data & 0x80 != 0;
+                if self.modulation_halted {
+                    self.modulation_accumulator = 0;
+                }
+            }
+            0x4088 => {
+                // The table can only be written while the modulation is halted
+                if self.modulation_halted {
+                    let position = self.modulation_position as usize;
+                    self.modulation_table[position] = data & 0x07;
+                    self.modulation_table[(position + 1) & 0x3F] = data & 0x07;
+                    self.modulation_position = (self.modulation_position + 2) & 0x3F;
+                }
+            }
+            0x4089 => {
+                self.wave_writable = data & 0x80 != 0;
+                self.master_volume = data & 0x03;
+            }
+            0x408A => self.envelope_speed = data,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407F => Some(self.wave[addr as usize - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.envelopes_halted && !self.wave_halted && self.envelope_speed > 0 {
+            let period = 8 * self.envelope_speed as u32;
+            self.volume.clock(period);
+            self.modulation.clock(period);
+        }
+
+        if !self.modulation_halted && self.modulation_frequency > 0 {
+            // A step of the table every time the accumulator overflows its 16 bits
+            self.modulation_accumulator += self.modulation_frequency as u32;
+            if self.modulation_accumulator > 0xFFFF {
+                self.modulation_accumulator &= 0xFFFF;
+                self.step_modulation();
+            }
+        }
+
+        if !self.wave_halted {
+            self.wave_accumulator = (self.wave_accumulator + self.pitch() as u32) & 0x3FFFFF;
+        }
+        if !self.wave_writable {
+            self.sample = self.wave[((self.wave_accumulator >> 16) & 0x3F) as usize];
+        }
+    }
+
+    fn step_modulation(&mut self) {
+        let entry = self.modulation_table[self.modulation_position as usize];
+        self.modulation_position = (self.modulation_position + 1) & 0x3F;
+        self.modulation_counter = match entry {
+            4 => 0,
+            x => {
+                let counter = self.modulation_counter + MODULATION_STEPS[x as usize];
+                // Wraps around on 7 bits
+                (((counter as u8) << 1) as i8) >> 1
+            }
+        };
+    }
+
+    /// Frequency of the wave once modulated, as computed by the hardware
+    pub fn pitch(&self) -> i32 {
+        let frequency = self.frequency as i32;
+        if self.modulation_halted {
+            return frequency;
+        }
+        let counter = self.modulation_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += match counter < 0 {
+                true => -1,
+                false => 2,
+            };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= frequency;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (frequency + temp).max(0)
+    }
+
+    pub fn output(&self) -> f32 {
+        let gain = self.volume.gain.min(32) as f32;
+        self.sample as f32 * gain * MASTER_VOLUMES[self.master_volume as usize] * FDS_FULL
+            / (63.0 * 32.0)
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FdsEnvelope {
+    fn write(&mut self, data: u8) {
+        self.disabled = data & 0x80 != 0;
+        self.increase = data & 0x40 != 0;
+        self.speed = data & 0x3F;
+        if self.disabled {
+            self.gain = data & 0x3F;
+        }
+    }
+    /// `period` is the period of the speed multiplier, in cpu cycles
+    fn clock(&mut self, period: u32) {
+        if self.disabled {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < period * (self.speed as u32 + 1) {
+            return;
+        }
+        self.timer = 0;
+        match self.increase {
+            true if self.gain < 32 => self.gain += 1,
+            false if self.gain > 0 => self.gain -= 1,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::super::{Pulse, DUTY_TABLE};
+use super::PULSE_FULL;
+
+/// Full scale of the DMC of the APU, the PCM channel is as loud
+const PCM_FULL: f32 = 0.574;
+/// Cpu cycles between two clocks of the envelopes and length counters, 240Hz
+const FRAME_PERIOD: u16 = 7457;
+
+/// Audio of the MMC5: two pulses like the APU ones, without sweep, and a PCM channel
+pub struct Mmc5Audio {
+    pub pulse: [Pulse; 2],
+    /// Last value written to $5011, or read from $8000-$BFFF in read mode
+    pub pcm: u8,
+    /// $5010 bit 0, the PCM channel samples the reads of the cpu
+    pub pcm_read_mode: bool,
+    pub pcm_irq_enabled: bool,
+    pub pcm_irq: bool,
+    pub(crate) cycle: u64,
+    pub(crate) frame_timer: u16,
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Mmc5Audio {
+        Mmc5Audio {
+            pulse: [Pulse::default(); 2],
+            pcm: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            cycle: 0,
+            frame_timer: 0,
+        }
+    }
+
+    /// Registers from $5000 to $5015
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            // There is no sweep unit, $5001 and $5005 do nothing
+            0x5000..=0x5007 => {
+                let reg = (addr & 0x03) as usize;
+                if reg != 1 {
+                    self.pulse[(addr as usize >> 2) & 0x01].write(reg, data);
+                }
+            }
+            0x5010 => {
+                self.pcm_read_mode = data & 0x01 != 0;
+                self.pcm_irq_enabled = data & 0x80 != 0;
+            }
+            0x5011 => {
+                // A write of 0 is ignored, it raises the IRQ in read mode
+                if !self.pcm_read_mode && data != 0 {
+                    self.pcm = data;
+                }
+            }
+            0x5015 => {
+                self.pulse[0].set_enabled(data & 0x01 != 0);
+                self.pulse[1].set_enabled(data & 0x02 != 0);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5010 => Some((self.pcm_irq as u8) << 7 | self.pcm_read_mode as u8),
+            0x5015 => {
+                Some((self.pulse[0].length > 0) as u8 | ((self.pulse[1].length > 0) as u8) << 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// In read mode, the reads from $8000 to $BFFF go to the PCM channel
+    pub fn observe_read(&mut self, addr: u16, data: u8) {
+        if !self.pcm_read_mode || !(0x8000..=0xBFFF).contains(&addr) {
+            return;
+        }
+        match data {
+            0 => self.pcm_irq = self.pcm_irq_enabled,
+            x => self.pcm = x,
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.cycle % 2 == 1 {
+            self.pulse[0].clock_timer();
+            self.pulse[1].clock_timer();
+        }
+        self.cycle += 1;
+        // The envelopes and the length counters are both clocked at 240Hz
+        self.frame_timer += 1;
+        if self.frame_timer == FRAME_PERIOD {
+            self.frame_timer = 0;
+            for pulse in self.pulse.iter_mut() {
+                pulse.envelope.clock();
+                pulse.clock_length();
+            }
+        }
+    }
+
+    /// The pulses are mixed linearly, at the level of the APU pulses
+    pub fn output(&self) -> f32 {
+        let pulses = self
+            .pulse
+            .iter()
+            .map(|pulse| pulse_output(pulse) as f32)
+            .sum::<f32>();
+        pulses * PULSE_FULL / 15.0 + self.pcm as f32 * PCM_FULL / 255.0
+    }
+}
+
+impl Default for Mmc5Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Like `Pulse::output`, without the muting of the sweep unit
+fn pulse_output(pulse: &Pulse) -> u8 {
+    match pulse.length == 0 || DUTY_TABLE[pulse.duty as usize][pulse.duty_step as usize] == 0 {
+        true => 0,
+        false => pulse.envelope.output(),
+    }
+}
@@ -0,0 +1,142 @@
+pub use self::fds::*;
+pub use self::mmc5::*;
+pub use self::n163::*;
+pub use self::sunsoft5b::*;
+pub use self::vrc6::*;
+pub use self::vrc7::*;
+
+mod fds;
+mod mmc5;
+mod n163;
+mod sunsoft5b;
+mod vrc6;
+mod vrc7;
+
+/// Output of an APU pulse at volume 15 alone, the levels of the chips are relative to it
+pub const PULSE_FULL: f32 = 0.1494;
+
+/// Sound chips of the cartridges, their output is added to the APU mix.
+/// Each chip is present only when the board or the NSF file has it.
+#[derive(Default)]
+pub struct ExpansionAudio {
+    pub vrc6: Option<Vrc6>,
+    pub vrc7: Option<Vrc7>,
+    pub fds: Option<FdsAudio>,
+    pub mmc5: Option<Mmc5Audio>,
+    pub n163: Option<N163>,
+    pub sunsoft5b: Option<Sunsoft5b>,
+}
+
+impl ExpansionAudio {
+    /// Chips enabled by the expansion byte of a NSF header
+    pub fn from_nsf_flags(flags: u8) -> ExpansionAudio {
+        ExpansionAudio {
+            vrc6: (flags & 0x01 != 0).then(|| Vrc6::new(false)),
+            vrc7: (flags & 0x02 != 0).then(Vrc7::new),
+            fds: (flags & 0x04 != 0).then(FdsAudio::new),
+            mmc5: (flags & 0x08 != 0).then(Mmc5Audio::new),
+            n163: (flags & 0x10 != 0).then(N163::new),
+            sunsoft5b: (flags & 0x20 != 0).then(Sunsoft5b::new),
+        }
+    }
+
+    /// Chip of an iNES mapper, if it has one
+    pub fn for_mapper(mapper_id: u16) -> ExpansionAudio {
+        let mut result = ExpansionAudio::default();
+        match mapper_id {
+            5 => result.mmc5 = Some(Mmc5Audio::new()),
+            19 => result.n163 = Some(N163::new()),
+            24 => result.vrc6 = Some(Vrc6::new(false)),
+            // VRC6b has the address lines A0 and A1 swapped
+            26 => result.vrc6 = Some(Vrc6::new(true)),
+            69 => result.sunsoft5b = Some(Sunsoft5b::new()),
+            85 => result.vrc7 = Some(Vrc7::new()),
+            _ => (),
+        }
+        result
+    }
+
+    /// Write to the registers of the chips, returns false when no chip has this address
+    pub fn cpu_write(&mut self, addr: u16, data: u8) -> bool {
+        let mut result = false;
+        if let Some(chip) = self.vrc6.as_mut() {
+            result |= chip.write(addr, data);
+        }
+        if let Some(chip) = self.vrc7.as_mut() {
+            result |= chip.write(addr, data);
+        }
+        if let Some(chip) = self.fds.as_mut() {
+            result |= chip.write(addr, data);
+        }
+        if let Some(chip) = self.mmc5.as_mut() {
+            result |= chip.write(addr, data);
+        }
+        if let Some(chip) = self.n163.as_mut() {
+            result |= chip.write(addr, data);
+        }
+        if let Some(chip) = self.sunsoft5b.as_mut() {
+            result |= chip.write(addr, data);
+        }
+        result
+    }
+
+    /// Readable registers of the chips
+    pub fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.fds
+            .as_ref()
+            .and_then(|chip| chip.read(addr))
+            .or_else(|| self.mmc5.as_ref().and_then(|chip| chip.read(addr)))
+            .or_else(|| self.n163.as_ref().and_then(|chip| chip.read(addr)))
+    }
+
+    /// A read made by the cpu on the cartridge, some chips react to it
+    pub fn observe_read(&mut self, addr: u16, data: u8) {
+        if let Some(chip) = self.mmc5.as_mut() {
+            chip.observe_read(addr, data);
+        }
+        if let Some(chip) = self.n163.as_mut() {
+            chip.observe_read(addr);
+        }
+    }
+
+    /// Advance the chips by one cpu cycle
+    pub fn clock(&mut self) {
+        if let Some(chip) = self.vrc6.as_mut() {
+            chip.clock();
+        }
+        if let Some(chip) = self.vrc7.as_mut() {
+            chip.clock();
+        }
+        if let Some(chip) = self.fds.as_mut() {
+            chip.clock();
+        }
+        if let Some(chip) = self.mmc5.as_mut() {
+            chip.clock();
+        }
+        if let Some(chip) = self.n163.as_mut() {
+            chip.clock();
+        }
+        if let Some(chip) = self.sunsoft5b.as_mut() {
+            chip.clock();
+        }
+    }
+
+    /// Sum of the chips, in the units of `APU::output`
+    pub fn output(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |chip| chip.output())
+            + self.vrc7.as_ref().map_or(0.0, |chip| chip.output())
+            + self.fds.as_ref().map_or(0.0, |chip| chip.output())
+            + self.mmc5.as_ref().map_or(0.0, |chip| chip.output())
+            + self.n163.as_ref().map_or(0.0, |chip| chip.output())
+            + self.sunsoft5b.as_ref().map_or(0.0, |chip| chip.output())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vrc6.is_none()
+            && self.vrc7.is_none()
+            && self.fds.is_none()
+            && self.mmc5.is_none()
+            && self.n163.is_none()
+            && self.sunsoft5b.is_none()
+    }
+}
@@ -0,0 +1,141 @@
+use super::PULSE_FULL;
+
+/// Cpu cycles spent on each channel
+const CHANNEL_PERIOD: u8 = 15;
+/// Level of a channel playing a full scale wave at volume 15
+const CHANNEL_FULL: f32 = 1.5 * PULSE_FULL;
+
+/// Namco 163: up to 8 wavetable channels, stored with their waves in 128 bytes of RAM.
+/// The chip updates one channel at a time and outputs it until the next one, like most
+/// emulators the active channels are averaged instead.
+pub struct N163 {
+    pub ram: [u8; 0x80],
+    /// $F800, bits 0 to 6
+    pub address: u8,
+    /// $F800 bit 7, the address is incremented after every access of $4800
+    pub auto_increment: bool,
+    /// $E000 bit 6
+    pub disabled: bool,
+    /// Channel updated next, the last one is 7
+    pub current: u8,
+    /// Last output of every channel, from -120 to 105
+    pub outputs: [i16; 8],
+    pub(crate) timer: u8,
+}
+
+impl N163 {
+    pub fn new() -> N163 {
+        N163 {
+            ram: [0; 0x80],
+            address: 0,
+            auto_increment: false,
+            disabled: false,
+            current: 7,
+            outputs: [0; 8],
+            timer: 0,
+        }
+    }
+
+    /// Data port at $4800, address port at $F800 and the disable bit at $E000
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x4800..=0x4FFF => {
+                self.ram[self.address as usize] = data;
+                self.increment();
+            }
+            0xF800..=0xFFFF => {
+                self.address = data & 0x7F;
+                self.auto_increment = data & 0x80 != 0;
+            }
+            // Also selects a bank of the mapper
+            0xE000..=0xE7FF => {
+                self.disabled = data & 0x40 != 0;
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.ram[self.address as usize]),
+            _ => None,
+        }
+    }
+
+    /// Reading the data port increments the address too
+    pub fn observe_read(&mut self, addr: u16) {
+        if (0x4800..=0x4FFF).contains(&addr) {
+            self.increment();
+        }
+    }
+
+    fn increment(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+    }
+
+    /// Number of channels updated, from 1 to 8
+    pub fn active_channels(&self) -> u8 {
+        ((self.ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    pub fn clock(&mut self) {
+        if self.disabled {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < CHANNEL_PERIOD {
+            return;
+        }
+        self.timer = 0;
+        self.update_channel(self.current);
+        self.current = match self.current <= 8 - self.active_channels() {
+            true => 7,
+            false => self.current - 1,
+        };
+    }
+
+    /// Advance the phase of a channel and sample its wave
+    fn update_channel(&mut self, channel: u8) {
+        let base = 0x40 + channel as usize * 8;
+        let frequency = self.ram[base] as u32
+            | (self.ram[base + 2] as u32) << 8
+            | (self.ram[base + 4] as u32 & 0x03) << 16;
+        let length = 256 - (self.ram[base + 4] as u32 & 0xFC);
+        let mut phase = self.ram[base + 1] as u32
+            | (self.ram[base + 3] as u32) << 8
+            | (self.ram[base + 5] as u32) << 16;
+        phase = (phase + frequency) % (length << 16);
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+
+        // The wave is made of 4 bits samples, the low nibble first
+        let position = (self.ram[base + 6] as u32 + (phase >> 16)) & 0xFF;
+        let byte = self.ram[(position >> 1) as usize];
+        let sample = match position & 0x01 {
+            0 => byte & 0x0F,
+            _ => byte >> 4,
+        };
+        let volume = self.ram[base + 7] & 0x0F;
+        self.outputs[channel as usize] = (sample as i16 - 8) * volume as i16;
+    }
+
+    pub fn output(&self) -> f32 {
+        if self.disabled {
+            return 0.0;
+        }
+        let active = self.active_channels();
+        let sum: i16 = self.outputs[(8 - active) as usize..].iter().sum();
+        sum as f32 / active as f32 * CHANNEL_FULL / 120.0
+    }
+}
+
+impl Default for N163 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,172 @@
+use super::PULSE_FULL;
+
+/// Level of a tone channel at volume 15
+const CHANNEL_FULL: f32 = 1.5 * PULSE_FULL;
+/// Cpu cycles per clock of the tone and noise counters
+const PRESCALER: u8 = 16;
+
+/// Sunsoft 5B, a YM2149F (AY-3-8910 clone): three square channels, a noise generator
+/// and an envelope, with a logarithmic volume
+pub struct Sunsoft5b {
+    /// Selected by $C000, written by $E000
+    pub registers: [u8; 16],
+    pub selected: u8,
+    pub(crate) tone_timers: [u16; 3],
+    pub(crate) tone_outputs: [bool; 3],
+    pub(crate) noise_timer: u8,
+    /// 17 bits LFSR
+    pub(crate) noise_shift: u32,
+    pub(crate) envelope_timer: u32,
+    /// 0 to 31, counted from the start of the shape
+    pub(crate) envelope_step: u8,
+    pub(crate) envelope_attack: bool,
+    pub(crate) envelope_holding: bool,
+    pub(crate) prescaler: u8,
+    /// Amplitude of the 32 levels, 1.5dB apart
+    levels: [f32; 32],
+}
+
+impl Sunsoft5b {
+    pub fn new() -> Sunsoft5b {
+        let mut levels = [0f32; 32];
+        for (i, level) in levels.iter_mut().enumerate().skip(1) {
+            *level = 10f32.powf((i as f32 - 31.0) * 1.5 / 20.0);
+        }
+        Sunsoft5b {
+            registers: [0; 16],
+            selected: 0,
+            tone_timers: [0; 3],
+            tone_outputs: [false; 3],
+            noise_timer: 0,
+            noise_shift: 1,
+            envelope_timer: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+            prescaler: 0,
+            levels,
+        }
+    }
+
+    /// Register select at $C000, data at $E000
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr & 0xE000 {
+            0xC000 => self.selected = data & 0x0F,
+            0xE000 => {
+                self.registers[self.selected as usize] = data;
+                if self.selected == 13 {
+                    self.envelope_step = 0;
+                    self.envelope_attack = data & 0x04 != 0;
+                    self.envelope_holding = false;
+                    self.envelope_timer = 0;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let period = self.registers[channel * 2] as u16
+            | (self.registers[channel * 2 + 1] as u16 & 0x0F) << 8;
+        period.max(1)
+    }
+
+    fn envelope_period(&self) -> u32 {
+        (self.registers[11] as u32 | (self.registers[12] as u32) << 8).max(1)
+    }
+
+    pub fn clock(&mut self) {
+        // The envelope has 32 steps instead of 16, twice as fast as the one of the AY
+        self.envelope_timer += 1;
+        if self.envelope_timer >= self.envelope_period() * PRESCALER as u32 / 2 {
+            self.envelope_timer = 0;
+            self.clock_envelope();
+        }
+
+        self.prescaler += 1;
+        if self.prescaler < PRESCALER {
+            return;
+        }
+        self.prescaler = 0;
+        for channel in 0..3 {
+            self.tone_timers[channel] += 1;
+            if self.tone_timers[channel] >= self.tone_period(channel) {
+                self.tone_timers[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+        self.noise_timer += 1;
+        if self.noise_timer >= (self.registers[6] & 0x1F).max(1) * 2 {
+            self.noise_timer = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < 31 {
+            self.envelope_step += 1;
+            return;
+        }
+        // End of the shape: continue, attack, alternate and hold bits
+        let shape = self.registers[13];
+        if shape & 0x08 == 0 {
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+            self.envelope_step = 31;
+        } else if shape & 0x01 != 0 {
+            self.envelope_holding = true;
+            // Held at the end of the shape, or at its other end when alternating
+            if shape & 0x02 != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+        } else {
+            if shape & 0x02 != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    /// Level of the envelope, from 0 to 31
+    pub fn envelope_level(&self) -> u8 {
+        match self.envelope_attack {
+            true => self.envelope_step,
+            false => 31 - self.envelope_step,
+        }
+    }
+
+    /// Level of a channel, from 0 to 31, before the tone and noise gating
+    fn channel_level(&self, channel: usize) -> u8 {
+        let volume = self.registers[8 + channel];
+        match (volume & 0x10 != 0, volume & 0x0F) {
+            (true, _) => self.envelope_level(),
+            (false, 0) => 0,
+            (false, x) => x * 2 + 1,
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        let mixer = self.registers[7];
+        let noise = self.noise_shift & 0x01 != 0;
+        let mut result = 0.0;
+        for channel in 0..3 {
+            let tone = self.tone_outputs[channel] || mixer & (1 << channel) != 0;
+            let noise = noise || mixer & (8 << channel) != 0;
+            if tone && noise {
+                result += self.levels[self.channel_level(channel) as usize];
+            }
+        }
+        result * CHANNEL_FULL
+    }
+}
+
+impl Default for Sunsoft5b {
+    fn default() -> Self {
+        Self::new()
+    }
+}
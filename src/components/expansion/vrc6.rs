@@ -0,0 +1,165 @@
+use super::PULSE_FULL;
+
+/// Konami VRC6: two pulses with 8 duty cycles and a sawtooth
+pub struct Vrc6 {
+    /// VRC6b (mapper 26) swaps the address lines A0 and A1
+    pub swapped_lines: bool,
+    pub pulse: [Vrc6Pulse; 2],
+    pub saw: Vrc6Saw,
+    /// $9003 bit 0, stops every channel
+    pub halt: bool,
+    /// $9003 bits 1 and 2, the periods are shifted right by 4 or 8
+    pub frequency_shift: u8,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Vrc6Pulse {
+    pub enabled: bool,
+    /// Ignore the duty, the volume is output constantly
+    pub digitized: bool,
+    /// The output is high for `duty + 1` of 16 steps
+    pub duty: u8,
+    pub volume: u8,
+    pub period: u16,
+    pub timer: u16,
+    pub step: u8,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Vrc6Saw {
+    pub enabled: bool,
+    /// Added to the accumulator every other step
+    pub rate: u8,
+    pub period: u16,
+    pub timer: u16,
+    pub step: u8,
+    pub accumulator: u8,
+}
+
+impl Vrc6 {
+    pub fn new(swapped_lines: bool) -> Vrc6 {
+        Vrc6 {
+            swapped_lines,
+            pulse: [Vrc6Pulse::default(); 2],
+            saw: Vrc6Saw::default(),
+            halt: false,
+            frequency_shift: 0,
+        }
+    }
+
+    /// Registers from $9000 to $B002
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        let addr = match self.swapped_lines {
+            true => (addr & 0xF000) | ((addr & 0x01) << 1) | ((addr & 0x02) >> 1),
+            false => addr & 0xF003,
+        };
+        match addr {
+            0x9000..=0x9002 => self.pulse[0].write(addr & 0x03, data),
+            0xA000..=0xA002 => self.pulse[1].write(addr & 0x03, data),
+            0xB000..=0xB002 => self.saw.write(addr & 0x03, data),
+            0x9003 => {
+                self.halt = data & 0x01 != 0;
+                self.frequency_shift = match data & 0x06 {
+                    0x00 => 0,
+                    0x02 => 4,
+                    _ => 8,
+                };
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        let shift = self.frequency_shift;
+        self.pulse[0].clock(shift);
+        self.pulse[1].clock(shift);
+        self.saw.clock(shift);
+    }
+
+    /// The three channels are summed by a 6 bits DAC, a pulse at volume 15 is as loud as
+    /// one of the APU
+    pub fn output(&self) -> f32 {
+        let sum = self.pulse[0].output() + self.pulse[1].output() + self.saw.output();
+        sum as f32 * PULSE_FULL / 15.0
+    }
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.digitized = data & 0x80 != 0;
+                self.duty = (data >> 4) & 0x07;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub fn output(&self) -> u8 {
+        match self.enabled && (self.digitized || self.step <= self.duty) {
+            true => self.volume,
+            false => 0,
+        }
+    }
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        // The rate is added on every other step, the accumulator is reset after 7 additions
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 0x01 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+    /// The 5 high bits of the accumulator
+    pub fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
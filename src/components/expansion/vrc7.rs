@@ -0,0 +1,353 @@
+use std::f64::consts::PI;
+
+use super::PULSE_FULL;
+
+/// Level of a channel at full volume
+const CHANNEL_FULL: f32 = PULSE_FULL;
+/// The FM synthesis runs at 49716Hz, one sample every 36 cpu cycles
+const SAMPLE_PERIOD: u8 = 36;
+const SAMPLE_RATE: f64 = 49716.0;
+/// Range of the envelopes, in dB
+const MAX_ATTENUATION: f64 = 48.0;
+/// Seconds to cover `MAX_ATTENUATION` at the lowest rates, each rate is twice as fast
+const ATTACK_TIME: f64 = 1.4;
+const DECAY_TIME: f64 = 10.0;
+/// Tremolo at 3.7Hz and vibrato at 6.4Hz
+const TREMOLO_FREQUENCY: f64 = 3.7;
+const TREMOLO_DEPTH: f64 = 4.8;
+const VIBRATO_FREQUENCY: f64 = 6.4;
+/// In cents
+const VIBRATO_DEPTH: f64 = 7.0;
+/// Phase shift of the carrier for a modulator at full level, in cycles
+const MODULATION_INDEX: f64 = 2.0;
+/// Frequency multipliers of the operators, halved
+const MULTIPLIERS: [f64; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+/// Key scaling of the level for the high bits of the frequency, in dB at 3dB/octave
+const KEY_SCALE_LEVELS: [f64; 16] = [
+    0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625, 18.0, 18.75, 19.125, 19.5, 19.875, 20.25,
+    20.625, 21.0,
+];
+
+/// The 15 instruments built in the VRC7, in the layout of the custom instrument registers
+pub const VRC7_PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+/// Konami VRC7, a subset of the YM2413 (OPLL): 6 FM channels of two operators, with
+/// 15 fixed instruments and a custom one. The rhythm mode is not available.
+/// The synthesis is done in floating point, the envelopes are linear in dB.
+pub struct Vrc7 {
+    /// Selected by $9010, written by $9030
+    pub registers: [u8; 0x40],
+    pub selected: u8,
+    /// $E000 bit 6 silences and resets the sound
+    pub muted: bool,
+    pub channels: [Vrc7Channel; 6],
+    pub(crate) timer: u8,
+    /// Samples since power up, for the LFOs
+    pub(crate) lfo_time: u64,
+    pub(crate) sample: f32,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Vrc7Channel {
+    pub modulator: Operator,
+    pub carrier: Operator,
+    pub key_on: bool,
+    /// Last two outputs of the modulator, for its feedback
+    pub(crate) feedback: [f64; 2],
+    pub(crate) output: f64,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Operator {
+    /// In cycles, from 0 to 1
+    pub phase: f64,
+    /// Attenuation of the envelope, in dB
+    pub attenuation: f64,
+    pub stage: EnvelopeStage,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvelopeStage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    #[default]
+    Off,
+}
+
+/// Settings of a channel shared by its two operators
+struct ChannelSettings {
+    frequency: u16,
+    block: u8,
+    sustain: bool,
+    vibrato: f64,
+}
+
+impl Vrc7 {
+    pub fn new() -> Vrc7 {
+        Vrc7 {
+            registers: [0; 0x40],
+            selected: 0,
+            muted: false,
+            channels: [Vrc7Channel::default(); 6],
+            timer: 0,
+            lfo_time: 0,
+            sample: 0.0,
+        }
+    }
+
+    /// Register select at $9010, data at $9030 and the mute bit at $E000
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x9010 => self.selected = data & 0x3F,
+            0x9030 => self.write_register(self.selected, data),
+            // Also the mirroring and the RAM enable of the mapper
+            0xE000 => {
+                self.muted = data & 0x40 != 0;
+                if self.muted {
+                    self.channels = [Vrc7Channel::default(); 6];
+                    self.sample = 0.0;
+                }
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn write_register(&mut self, reg: u8, data: u8) {
+        self.registers[reg as usize] = data;
+        if let 0x20..=0x25 = reg {
+            let channel = &mut self.channels[reg as usize - 0x20];
+            let key_on = data & 0x10 != 0;
+            if key_on && !channel.key_on {
+                for operator in [&mut channel.modulator, &mut channel.carrier] {
+                    operator.phase = 0.0;
+                    operator.stage = EnvelopeStage::Attack;
+                }
+            } else if !key_on && channel.key_on {
+                channel.modulator.stage = EnvelopeStage::Release;
+                channel.carrier.stage = EnvelopeStage::Release;
+            }
+            channel.key_on = key_on;
+        }
+    }
+
+    /// Instrument of a channel, 0 is the custom one of the registers 0 to 7
+    pub fn patch(&self, channel: usize) -> [u8; 8] {
+        match self.registers[0x30 + channel] >> 4 {
+            0 => {
+                let mut result = [0u8; 8];
+                result.copy_from_slice(&self.registers[0..8]);
+                result
+            }
+            x => VRC7_PATCHES[x as usize - 1],
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.muted {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < SAMPLE_PERIOD {
+            return;
+        }
+        self.timer = 0;
+        self.lfo_time += 1;
+        let time = self.lfo_time as f64 / SAMPLE_RATE;
+        let tremolo = (1.0 - (2.0 * PI * TREMOLO_FREQUENCY * time).cos()) / 2.0 * TREMOLO_DEPTH;
+        let vibrato =
+            2f64.powf((2.0 * PI * VIBRATO_FREQUENCY * time).sin() * VIBRATO_DEPTH / 1200.0);
+
+        let mut sum = 0.0;
+        for index in 0..6 {
+            let patch = self.patch(index);
+            let settings = ChannelSettings {
+                frequency: self.registers[0x10 + index] as u16
+                    | (self.registers[0x20 + index] as u16 & 0x01) << 8,
+                block: (self.registers[0x20 + index] >> 1) & 0x07,
+                sustain: self.registers[0x20 + index] & 0x20 != 0,
+                vibrato,
+            };
+            let volume = self.registers[0x30 + index] & 0x0F;
+            sum += self.channels[index].sample(&patch, &settings, volume, tremolo);
+        }
+        self.sample = sum as f32;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.sample * CHANNEL_FULL
+    }
+}
+
+impl Default for Vrc7 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vrc7Channel {
+    /// Next output of the channel, from -1 to 1
+    fn sample(
+        &mut self,
+        patch: &[u8; 8],
+        settings: &ChannelSettings,
+        volume: u8,
+        tremolo: f64,
+    ) -> f64 {
+        // Modulator, with its feedback
+        self.modulator.advance(patch, 0, settings);
+        let feedback = match patch[3] & 0x07 {
+            0 => 0.0,
+            x => (self.feedback[0] + self.feedback[1]) / 2.0 * 2f64.powi(x as i32 - 6),
+        };
+        let level = (patch[2] & 0x3F) as f64 * 0.75;
+        let modulator = self
+            .modulator
+            .output(patch, 0, settings, level, tremolo, feedback);
+        self.feedback = [modulator, self.feedback[0]];
+
+        self.carrier.advance(patch, 1, settings);
+        let level = volume as f64 * 3.0;
+        self.output = self.carrier.output(
+            patch,
+            1,
+            settings,
+            level,
+            tremolo,
+            modulator * MODULATION_INDEX,
+        );
+        self.output
+    }
+}
+
+impl Operator {
+    /// Advance the phase and the envelope by one sample, `index` is 0 for the modulator
+    /// and 1 for the carrier
+    fn advance(&mut self, patch: &[u8; 8], index: usize, settings: &ChannelSettings) {
+        let flags = patch[index];
+        let mut increment = settings.frequency as f64
+            * (1 << settings.block) as f64
+            * MULTIPLIERS[(flags & 0x0F) as usize]
+            / (1 << 19) as f64;
+        if flags & 0x40 != 0 {
+            increment *= settings.vibrato;
+        }
+        self.phase = (self.phase + increment).fract();
+
+        // Key scaling of the rates
+        let key = (settings.block << 1 | (settings.frequency >> 8) as u8)
+            >> match flags & 0x10 {
+                0 => 2,
+                _ => 0,
+            };
+        let attack = patch[4 + index] >> 4;
+        let decay = patch[4 + index] & 0x0F;
+        let sustain_level = (patch[6 + index] >> 4) as f64 * 3.0;
+        let release = patch[6 + index] & 0x0F;
+        let sustained = flags & 0x20 != 0;
+        match self.stage {
+            EnvelopeStage::Attack => {
+                self.attenuation -= rate_step(attack, key, ATTACK_TIME);
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.stage = EnvelopeStage::Decay;
+                }
+            }
+            EnvelopeStage::Decay => {
+                self.attenuation += rate_step(decay, key, DECAY_TIME);
+                if self.attenuation >= sustain_level {
+                    self.attenuation = sustain_level;
+                    self.stage = EnvelopeStage::Sustain;
+                }
+            }
+            // Percussive instruments keep decaying
+            EnvelopeStage::Sustain if !sustained => {
+                self.attenuation += rate_step(release, key, DECAY_TIME)
+            }
+            EnvelopeStage::Sustain => (),
+            EnvelopeStage::Release => {
+                let rate = match (settings.sustain, sustained) {
+                    (true, _) => 5,
+                    (false, true) => release,
+                    (false, false) => 7,
+                };
+                self.attenuation += rate_step(rate, key, DECAY_TIME);
+            }
+            EnvelopeStage::Off => self.attenuation = MAX_ATTENUATION,
+        }
+        if self.attenuation >= MAX_ATTENUATION {
+            self.attenuation = MAX_ATTENUATION;
+            self.stage = EnvelopeStage::Off;
+        }
+    }
+
+    /// Output from -1 to 1, `level` is the attenuation of the total level or of the
+    /// volume and `modulation` the phase shift in cycles
+    fn output(
+        &self,
+        patch: &[u8; 8],
+        index: usize,
+        settings: &ChannelSettings,
+        level: f64,
+        tremolo: f64,
+        modulation: f64,
+    ) -> f64 {
+        if self.stage == EnvelopeStage::Off {
+            return 0.0;
+        }
+        let key_scale = match patch[2 + index] >> 6 {
+            0 => 0.0,
+            x => {
+                let base = KEY_SCALE_LEVELS[(settings.frequency >> 5) as usize]
+                    - 3.0 * (7 - settings.block) as f64;
+                base.max(0.0) * [0.0, 0.5, 1.0, 2.0][x as usize]
+            }
+        };
+        let tremolo = match patch[index] & 0x80 {
+            0 => 0.0,
+            _ => tremolo,
+        };
+        let attenuation = self.attenuation + level + key_scale + tremolo;
+        let mut wave = (2.0 * PI * (self.phase + modulation)).sin();
+        // Half-wave rectified waveform
+        let rectified = patch[3] & (0x08 << index) != 0;
+        if rectified && wave < 0.0 {
+            wave = 0.0;
+        }
+        wave * 10f64.powf(-attenuation / 20.0)
+    }
+}
+
+/// Change of the attenuation in one sample for a rate from 0 to 15, 0 stops the envelope
+fn rate_step(rate: u8, key: u8, time: f64) -> f64 {
+    if rate == 0 {
+        return 0.0;
+    }
+    let rate = (rate as u32 * 4 + key as u32).min(63);
+    if rate >= 60 {
+        return MAX_ATTENUATION;
+    }
+    let seconds = time / 2f64.powf((rate as f64 - 4.0) / 4.0);
+    MAX_ATTENUATION / (seconds * SAMPLE_RATE)
+}
@@ -0,0 +1,97 @@
+use super::Mirror;
+
+/// Sunsoft FME-7 (mapper 69). A command register at $8000 selects which of the 16 internal
+/// registers the parameter written at $A000 goes to.
+#[derive(Default)]
+pub struct Fme7 {
+    /// $8000, register written by the next parameter
+    pub command: u8,
+    /// Commands 0 to 7, 1KB CHR banks
+    pub chr_banks: [u8; 8],
+    /// Command 8, 8KB bank at $6000. Bit 6 selects the RAM instead of the ROM, bit 7 enables it.
+    pub bank_6000: u8,
+    /// Commands 9 to B, 8KB PRG banks at $8000, $A000 and $C000
+    pub prg_banks: [u8; 3],
+    /// Command C: vertical, horizontal, single screen lo and hi
+    pub mirroring: u8,
+    /// Command D bit 0
+    pub irq_enabled: bool,
+    /// Command D bit 7, the counter is decremented every cpu cycle
+    pub counter_enabled: bool,
+    /// Commands E and F
+    pub counter: u16,
+    pub irq_pending: bool,
+}
+
+impl Fme7 {
+    pub fn new() -> Fme7 {
+        Fme7::default()
+    }
+
+    /// Write to the registers from $8000 to $BFFF, the sound chip uses $C000 and $E000.
+    /// The writes from $6000 are taken unless they reach the RAM.
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x6000..=0x7FFF => self.bank_6000 & 0xC0 != 0xC0,
+            0x8000..=0x9FFF => {
+                self.command = data & 0x0F;
+                true
+            }
+            0xA000..=0xBFFF => {
+                match self.command {
+                    0..=7 => self.chr_banks[self.command as usize] = data,
+                    8 => self.bank_6000 = data,
+                    9..=0xB => self.prg_banks[self.command as usize - 9] = data & 0x3F,
+                    0xC => self.mirroring = data & 0x03,
+                    0xD => {
+                        self.irq_enabled = data & 0x01 != 0;
+                        self.counter_enabled = data & 0x80 != 0;
+                        self.irq_pending = false;
+                    }
+                    0xE => self.counter = self.counter & 0xFF00 | data as u16,
+                    _ => self.counter = self.counter & 0x00FF | (data as u16) << 8,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// RAM bank mapped at $6000, None when it is the ROM or the RAM is disabled
+    pub fn prg_ram_bank(&self) -> Option<usize> {
+        match self.bank_6000 & 0xC0 {
+            0xC0 => Some((self.bank_6000 & 0x3F) as usize),
+            _ => None,
+        }
+    }
+
+    /// 8KB ROM bank mapped at a cpu address from $6000, negative banks count from the last one
+    pub fn prg_rom_bank(&self, addr: u16) -> Option<isize> {
+        match addr {
+            0x6000..=0x7FFF if self.bank_6000 & 0x40 == 0 => Some((self.bank_6000 & 0x3F) as isize),
+            0x6000..=0x7FFF => None,
+            0x8000..=0xDFFF => Some(self.prg_banks[(addr as usize >> 13) - 4] as isize),
+            _ => Some(-1),
+        }
+    }
+
+    pub fn mirror(&self) -> Mirror {
+        match self.mirroring {
+            0 => Mirror::Vertical,
+            1 => Mirror::Horizontal,
+            2 => Mirror::OneScreenLo,
+            _ => Mirror::OneScreenHi,
+        }
+    }
+
+    /// The IRQ is sent when the counter wraps from 0 to $FFFF
+    pub fn clock(&mut self) {
+        if !self.counter_enabled {
+            return;
+        }
+        self.counter = self.counter.wrapping_sub(1);
+        if self.counter == 0xFFFF && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
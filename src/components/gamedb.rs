@@ -0,0 +1,183 @@
+use std::sync::OnceLock;
+
+use super::{mapper_name, Header};
+use crate::utils::Sha1;
+
+/// Database built in the emulator
+const EMBEDDED_DB: &str = include_str!("nes20db.xml");
+
+/// Game of the database, with the header it should have
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameInfo {
+    pub title: String,
+    /// Checksums of the PRG followed by the CHR
+    pub crc32: u32,
+    pub sha1: Option<[u8; 20]>,
+    pub mapper: u16,
+    pub submapper: u8,
+    /// H, V or 4 for four screens
+    pub mirroring: Option<char>,
+    pub battery: bool,
+    /// Bytes of PRG RAM and of battery backed PRG RAM
+    pub prg_ram: usize,
+    pub prg_nvram: usize,
+    /// Timing of NES 2.0: NTSC, PAL, multiple regions or Dendy
+    pub region: u8,
+}
+
+/// Games of a database in the NES 2.0 XML format
+#[derive(Clone, Debug, Default)]
+pub struct GameDb {
+    pub games: Vec<GameInfo>,
+}
+
+impl GameDb {
+    /// Games of the XML, the ones without a rom checksum are skipped
+    pub fn parse(xml: &str) -> GameDb {
+        let mut games = Vec::new();
+        let mut rest = xml;
+        while let Some(start) = rest.find("<game>") {
+            rest = &rest[start + 6..];
+            let end = rest.find("</game>").unwrap_or(rest.len());
+            if let Some(game) = parse_game(&rest[..end]) {
+                games.push(game);
+            }
+            rest = &rest[end..];
+        }
+        GameDb { games }
+    }
+
+    /// Database built in the emulator, parsed on first use
+    pub fn embedded() -> &'static GameDb {
+        static DB: OnceLock<GameDb> = OnceLock::new();
+        DB.get_or_init(|| GameDb::parse(EMBEDDED_DB))
+    }
+
+    /// Game made of this PRG and CHR ROM. The SHA-1 is compared when the database has it.
+    pub fn find(&self, prg: &[u8], chr: &[u8]) -> Option<&GameInfo> {
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(prg);
+        crc.update(chr);
+        let crc = crc.finalize();
+        let mut sha1 = None;
+        self.games
+            .iter()
+            .filter(|game| game.crc32 == crc)
+            .find(|game| match game.sha1 {
+                Some(expected) => {
+                    *sha1.get_or_insert_with(|| {
+                        let mut hasher = Sha1::new();
+                        hasher.update(prg);
+                        hasher.update(chr);
+                        hasher.finalize()
+                    }) == expected
+                }
+                None => true,
+            })
+    }
+}
+
+impl GameInfo {
+    /// Give the title and the board to the header. An iNES 1.0 header is also replaced by
+    /// the NES 2.0 one of the database, NES 2.0 headers are trusted.
+    pub fn apply(&self, header: &mut Header) {
+        header.title = Some(self.title.clone());
+        header.board = mapper_name(self.mapper).map(|name| name.to_string());
+        if header.is_nes2() {
+            return;
+        }
+        let mirroring = match self.mirroring {
+            Some('V') => 0x01,
+            Some('4') => 0x08,
+            _ => 0x00,
+        };
+        header.flags_6 = (self.mapper as u8 & 0x0F) << 4
+            | header.flags_6 & 0x04
+            | (self.battery as u8) << 1
+            | mirroring;
+        header.flags_7 = self.mapper as u8 & 0xF0 | 0x08;
+        header.flags_8 = self.submapper << 4 | (self.mapper >> 8) as u8 & 0x0F;
+        header.flags_9 = 0;
+        header.flags_10 = size_shift(self.prg_ram) | size_shift(self.prg_nvram) << 4;
+        // Boards without CHR ROM keep their 8KB of CHR RAM
+        let chr_ram = match header.nb_chr_banks {
+            0 => 0x07,
+            _ => 0x00,
+        };
+        header.padding = [chr_ram, self.region & 0x03, 0, 0, 0];
+    }
+}
+
+/// Size of NES 2.0, 64 bytes shifted left
+fn size_shift(size: usize) -> u8 {
+    match size {
+        0 => 0,
+        _ => (size / 64).max(1).trailing_zeros() as u8,
+    }
+}
+
+fn parse_game(xml: &str) -> Option<GameInfo> {
+    let rom = tag(xml, "rom")?;
+    let pcb = tag(xml, "pcb");
+    let number = |tag: Option<&str>, name: &str| {
+        tag.and_then(|tag| attribute(tag, name))
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0)
+    };
+    Some(GameInfo {
+        title: comment(xml).map(title).unwrap_or_default(),
+        crc32: u32::from_str_radix(attribute(rom, "crc32")?, 16).ok()?,
+        sha1: attribute(rom, "sha1").and_then(parse_sha1),
+        mapper: number(pcb, "mapper") as u16,
+        submapper: number(pcb, "submapper") as u8,
+        mirroring: pcb
+            .and_then(|pcb| attribute(pcb, "mirroring"))
+            .and_then(|x| x.chars().next()),
+        battery: number(pcb, "battery") != 0,
+        prg_ram: number(tag(xml, "prgram"), "size"),
+        prg_nvram: number(tag(xml, "prgnvram"), "size"),
+        region: number(tag(xml, "console"), "region") as u8,
+    })
+}
+
+/// Attributes of the first `<name .../>` element
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{} ", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find('>')?;
+    Some(&xml[start..start + end])
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{}=\"", name);
+    let start = tag
+        .match_indices(&key)
+        .map(|(i, _)| i)
+        .find(|&i| i == 0 || tag.as_bytes()[i - 1].is_ascii_whitespace())?
+        + key.len();
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+fn comment(xml: &str) -> Option<&str> {
+    let start = xml.find("<!--")? + 4;
+    let end = xml[start..].find("-->")?;
+    Some(xml[start..start + end].trim())
+}
+
+/// Title of a game from its comment, which can be the path of the dump
+fn title(comment: &str) -> String {
+    let name = comment.rsplit(['\\', '/']).next().unwrap_or(comment);
+    name.strip_suffix(".nes").unwrap_or(name).to_string()
+}
+
+fn parse_sha1(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 {
+        return None;
+    }
+    let mut result = [0; 20];
+    for (i, byte) in result.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(result)
+}
@@ -1,10 +1,11 @@
 use super::cpu::*;
-use std::fs;
 use std::str::FromStr;
 
+/// Opcode table, embedded so the binaries can run from any directory
+const INSTRUCTIONS: &str = include_str!("instructions.txt");
+
 pub fn get_lookup_list() -> Vec<INSTRUCTION> {
-    let data = fs::read_to_string("src/components/instructions.txt").expect("unable to read file");
-    let data_lines = data.lines();
+    let data_lines = INSTRUCTIONS.lines();
 
     let mut result: Vec<INSTRUCTION> = Vec::new();
 
@@ -1,9 +1,61 @@
 use super::*;
+
+/// Not an iNES mapper, the 4KB banks of a NSF file are switched by $5FF8-$5FFF
+pub const NSF_MAPPER: u16 = 0x1000;
+/// iNES number reserved for the Famicom Disk System
+pub const FDS_MAPPER: u16 = 20;
+
+/// Usual name of the boards of a mapper
+pub fn mapper_name(mapper_id: u16) -> Option<&'static str> {
+    Some(match mapper_id {
+        0 => "NROM",
+        1 => "SxROM (MMC1)",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "TxROM (MMC3)",
+        5 => "ExROM (MMC5)",
+        7 => "AxROM",
+        9 => "PxROM (MMC2)",
+        10 => "FxROM (MMC4)",
+        11 => "Color Dreams",
+        19 => "Namco 163",
+        20 => "Famicom Disk System",
+        21 | 23 | 25 => "Konami VRC2/VRC4",
+        22 => "Konami VRC2a",
+        24 => "Konami VRC6a",
+        26 => "Konami VRC6b",
+        34 => "BNROM/NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica BF909x",
+        79 => "AVE NINA-03/06",
+        85 => "Konami VRC7",
+        87 => "Jaleco JF-xx",
+        140 => "Jaleco JF-11/14",
+        180 => "UNROM (74HC08)",
+        _ => return None,
+    })
+}
+
 #[derive(Default)]
 pub struct Mapper {
     pub mapper_id: u16,
+    /// Board variant given by NES 2.0, 0 when unknown
+    pub submapper: u8,
     pub nb_prg_banks: usize,
     pub nb_chr_banks: usize,
+    /// PRG RAM given by a NES 2.0 header
+    pub header_prg_ram: Option<usize>,
+    /// Bank registers of the mappers switching banks
+    pub banks: [u8; 8],
+    /// Registers and ExRAM of the boards with a MMC5
+    pub mmc5: Option<Mmc5>,
+    /// Registers and IRQ counter of the Konami VRC2, VRC4, VRC6 and VRC7 boards
+    pub vrc: Option<Vrc>,
+    /// Registers and IRQ counter of the Sunsoft FME-7
+    pub fme7: Option<Fme7>,
+    /// Registers and IRQ counter of the Namco 163
+    pub namco163: Option<Namco163>,
 }
 
 pub enum Source {
@@ -15,8 +67,305 @@ impl Mapper {
     pub fn new(header: Header) -> Self {
         Mapper {
             mapper_id: header.mapper_id(),
+            submapper: header.submapper(),
             nb_prg_banks: header.nb_prg_banks.into(),
             nb_chr_banks: header.nb_chr_banks.into(),
+            header_prg_ram: header.prg_ram_size(),
+            banks: [0; 8],
+            mmc5: match header.mapper_id() {
+                5 => Some(Mmc5::new()),
+                _ => None,
+            },
+            vrc: Vrc::for_mapper(header.mapper_id(), header.submapper()),
+            fme7: (header.mapper_id() == 69).then(Fme7::new),
+            namco163: (header.mapper_id() == 19).then(Namco163::new),
+        }
+    }
+
+    /// NSF bankswitching over `prg_size` bytes of tune data, starting with `banks`
+    pub fn nsf(prg_size: usize, banks: [u8; 8]) -> Self {
+        Mapper {
+            mapper_id: NSF_MAPPER,
+            submapper: 0,
+            nb_prg_banks: prg_size / 0x1000,
+            nb_chr_banks: 0,
+            header_prg_ram: None,
+            banks,
+            mmc5: None,
+            vrc: None,
+            fme7: None,
+            namco163: None,
+        }
+    }
+
+    /// RAM adapter of the disk system: 32KB of RAM from $6000 and the BIOS from $E000.
+    /// The RAM from $8000 and the BIOS are in the PRG memory, the rest is the PRG RAM.
+    pub fn fds() -> Self {
+        Mapper {
+            mapper_id: FDS_MAPPER,
+            submapper: 0,
+            nb_prg_banks: 2,
+            nb_chr_banks: 0,
+            header_prg_ram: None,
+            banks: [0; 8],
+            mmc5: None,
+            vrc: None,
+            fme7: None,
+            namco163: None,
+        }
+    }
+
+    /// Whether the ROM drives the data bus along with the cpu when a register is written,
+    /// only the bits set in both reach the mapper. Submapper 1 marks the boards without it.
+    pub fn bus_conflicts(&self) -> bool {
+        match (self.mapper_id, self.submapper) {
+            (2 | 3, 1) => false,
+            (2 | 3, _) | (7, 2) | (11 | 66 | 180, _) => true,
+            (34, _) => !self.nina_001(),
+            _ => false,
+        }
+    }
+
+    /// Mapper 34 is both BNROM and NINA-001, the NINA boards have CHR ROM to switch
+    fn nina_001(&self) -> bool {
+        match self.submapper {
+            1 => true,
+            2 => false,
+            _ => self.nb_chr_banks > 1,
+        }
+    }
+
+    /// Bytes of RAM at the start of the PRG memory, the rest of it is ROM
+    pub fn prg_memory_ram(&self) -> usize {
+        match self.mapper_id {
+            FDS_MAPPER => 0x6000,
+            _ => 0,
+        }
+    }
+
+    /// Whether the CHR memory is RAM, the boards without CHR ROM have 8KB of it
+    pub fn chr_ram(&self) -> bool {
+        self.nb_chr_banks == 0
+    }
+
+    /// Bytes of PRG RAM on the board
+    pub fn prg_ram_size(&self) -> usize {
+        match self.mapper_id {
+            5 => 0x10000,
+            // Banked by the FME-7, the boards have up to 512KB
+            69 => self.header_prg_ram.unwrap_or(0).clamp(0x2000, 0x80000),
+            _ => 0x2000,
+        }
+    }
+
+    /// Offset in the PRG RAM of a cpu address, $6000 to $7FFF unless the mapper banks it
+    pub fn map_prg_ram(&self, addr: u16) -> Option<usize> {
+        match &self.mmc5 {
+            Some(mmc5) if addr >= 0x6000 => match mmc5.map_prg(addr) {
+                (false, offset) => Some(offset),
+                (true, _) => None,
+            },
+            Some(_) => None,
+            None if !(0x6000..=0x7FFF).contains(&addr) => None,
+            None => match &self.fme7 {
+                Some(fme7) => {
+                    let bank = fme7.prg_ram_bank()? % (self.prg_ram_size() / 0x2000);
+                    Some(bank * 0x2000 + (addr as usize & 0x1FFF))
+                }
+                None => Some(addr as usize & 0x1FFF),
+            },
+        }
+    }
+
+    /// Registers of the mapper that can be read
+    pub fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.mmc5
+            .as_ref()
+            .and_then(|mmc5| mmc5.read(addr))
+            .or_else(|| self.namco163.as_ref().and_then(|chip| chip.read(addr)))
+    }
+
+    /// A read made by the cpu, some registers are acknowledged by it
+    pub fn observe_read(&mut self, addr: u16) {
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            mmc5.acknowledge(addr);
+        }
+    }
+
+    /// A write of the cpu to the PPU registers, from $2000 to $3FFF
+    pub fn observe_ppu_register(&mut self, addr: u16, data: u8) {
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            mmc5.observe_ppu_register(addr, data);
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mmc5.as_ref().is_some_and(|mmc5| mmc5.irq())
+            || self.vrc.as_ref().is_some_and(|vrc| vrc.irq.pending)
+            || self.fme7.as_ref().is_some_and(|fme7| fme7.irq_pending)
+            || self.namco163.as_ref().is_some_and(|chip| chip.irq_pending)
+    }
+
+    /// Advance the counters of the mapper by one cpu cycle
+    pub fn clock(&mut self) {
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            mmc5.clock();
+        }
+        if let Some(vrc) = self.vrc.as_mut() {
+            vrc.irq.clock();
+        }
+        if let Some(fme7) = self.fme7.as_mut() {
+            fme7.clock();
+        }
+        if let Some(chip) = self.namco163.as_mut() {
+            chip.clock();
+        }
+    }
+
+    /// Nametable fetch answered by the mapper instead of the console vram
+    pub fn nametable_read(&self, addr: u16) -> Option<u8> {
+        self.mmc5.as_ref().and_then(|mmc5| mmc5.nametable_read(addr))
+    }
+
+    /// Offset in the CHR of a nametable taken from the cartridge instead of the vram
+    pub fn map_nametable(&self, addr: u16) -> Option<usize> {
+        let bank = self.namco163.as_ref()?.nametable_bank(addr)?;
+        Some(self.chr_offset(bank as isize, 0x400, addr))
+    }
+
+    /// Nametable write taken by the mapper, returns false when it goes to the console vram
+    pub fn nametable_write(&mut self, addr: u16, data: u8) -> bool {
+        self.mmc5.as_mut().is_some_and(|mmc5| mmc5.nametable_write(addr, data))
+    }
+
+    /// Write to the registers of the mapper, returns false when the address is not one
+    pub fn cpu_write(&mut self, addr: u16, data: u8) -> bool {
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            // The ROM and the protected RAM ignore the writes
+            return mmc5.write(addr, data)
+                || (addr >= 0x6000 && (mmc5.map_prg(addr).0 || !mmc5.prg_ram_writable()));
+        }
+        if let Some(vrc) = self.vrc.as_mut() {
+            return vrc.write(addr, data);
+        }
+        if let Some(fme7) = self.fme7.as_mut() {
+            return fme7.write(addr, data);
+        }
+        if let Some(chip) = self.namco163.as_mut() {
+            return chip.write(addr, data);
+        }
+        match (self.mapper_id, addr) {
+            (NSF_MAPPER, 0x5FF8..=0x5FFF) => {
+                self.banks[addr as usize - 0x5FF8] = data;
+                true
+            }
+            // The BIOS is read only
+            (FDS_MAPPER, 0xE000..=0xFFFF) => true,
+            // PRG bank, the 4 CHR banks and the mirroring, one register every 4KB.
+            // Nothing is mapped at $8000-$9FFF, the writes are ignored.
+            (9 | 10, 0x8000..=0xFFFF) => {
+                if addr >= 0xA000 {
+                    self.banks[(addr as usize >> 12) - 0xA] = data & 0x1F;
+                }
+                true
+            }
+            // Discrete boards: PRG bank in `banks[0]`, CHR banks from `banks[1]`
+            (2 | 180, 0x8000..=0xFFFF) | (71, 0xC000..=0xFFFF) => {
+                self.banks[0] = data;
+                true
+            }
+            (3, 0x8000..=0xFFFF) => {
+                self.banks[1] = data;
+                true
+            }
+            // One screen mirroring in `banks[2]`
+            (7, 0x8000..=0xFFFF) => {
+                self.banks[0] = data & 0x0F;
+                self.banks[2] = data >> 4 & 0x01;
+                true
+            }
+            (71, 0x8000..=0xBFFF) => {
+                if addr & 0xF000 == 0x9000 {
+                    self.banks[2] = data >> 4 & 0x01;
+                }
+                true
+            }
+            (11, 0x8000..=0xFFFF) => {
+                self.banks[0] = data & 0x03;
+                self.banks[1] = data >> 4;
+                true
+            }
+            (34, 0x8000..=0xFFFF) if !self.nina_001() => {
+                self.banks[0] = data;
+                true
+            }
+            // The registers of NINA-001 are also written to the PRG RAM below them
+            (34, 0x7FFD..=0x7FFF) if self.nina_001() => {
+                self.banks[addr as usize - 0x7FFD] = data;
+                false
+            }
+            (66, 0x8000..=0xFFFF) => {
+                self.banks[0] = data >> 4 & 0x03;
+                self.banks[1] = data & 0x03;
+                true
+            }
+            (79, 0x4100..=0x5FFF) if addr & 0xE100 == 0x4100 => {
+                self.banks[0] = data >> 3 & 0x01;
+                self.banks[1] = data & 0x07;
+                true
+            }
+            // The 2 bits of the CHR bank are wired in reverse
+            (87, 0x6000..=0x7FFF) => {
+                self.banks[1] = (data & 0x01) << 1 | (data >> 1 & 0x01);
+                true
+            }
+            (140, 0x6000..=0x7FFF) => {
+                self.banks[0] = data >> 4 & 0x03;
+                self.banks[1] = data & 0x0F;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A pattern fetched by the PPU. MMC2 and MMC4 latch the CHR banks to use when the
+    /// tiles $FD or $FE are fetched, the new bank is used from the next fetch.
+    pub fn observe_ppu_read(&mut self, addr: u16) {
+        if let Some(mmc5) = self.mmc5.as_mut() {
+            mmc5.observe_ppu_read(addr);
+        }
+        match (self.mapper_id, addr) {
+            // MMC2 only watches the first row of the tiles in the first pattern table
+            (9, 0x0FD8) | (10, 0x0FD8..=0x0FDF) => self.banks[6] = 0,
+            (9, 0x0FE8) | (10, 0x0FE8..=0x0FEF) => self.banks[6] = 1,
+            (9 | 10, 0x1FD8..=0x1FDF) => self.banks[7] = 0,
+            (9 | 10, 0x1FE8..=0x1FEF) => self.banks[7] = 1,
+            _ => (),
+        }
+    }
+
+    /// Nametable arrangement selected by the mapper, None when it is wired on the board
+    pub fn mirror(&self) -> Option<Mirror> {
+        match self.mapper_id {
+            5 => self.mmc5.as_ref().map(|mmc5| Mirror::Pages(mmc5.vram_pages())),
+            9 | 10 => Some(match self.banks[5] & 0x01 {
+                0 => Mirror::Vertical,
+                _ => Mirror::Horizontal,
+            }),
+            21..=26 | 85 => self.vrc.as_ref().map(|vrc| vrc.mirror()),
+            19 => self.namco163.as_ref().map(|chip| Mirror::Pages(chip.vram_pages())),
+            69 => self.fme7.as_ref().map(|fme7| fme7.mirror()),
+            // Only the Fire Hawk board of mapper 71 controls the mirroring
+            7 => Some(self.one_screen()),
+            71 if self.submapper == 1 => Some(self.one_screen()),
+            _ => None,
+        }
+    }
+
+    fn one_screen(&self) -> Mirror {
+        match self.banks[2] {
+            0 => Mirror::OneScreenLo,
+            _ => Mirror::OneScreenHi,
         }
     }
 
@@ -25,6 +374,14 @@ impl Mapper {
         match self.mapper_id {
             0 => self.mapper_000(src, addr),
             1 => self.mapper_001(src, addr),
+            5 => self.mapper_005(src, addr),
+            9 | 10 => self.mapper_009(src, addr),
+            19 => self.mapper_019(src, addr),
+            69 => self.mapper_069(src, addr),
+            21..=26 | 85 => self.mapper_vrc(src, addr),
+            2 | 3 | 7 | 11 | 34 | 66 | 71 | 79 | 87 | 140 | 180 => self.mapper_discrete(src, addr),
+            NSF_MAPPER => self.mapper_nsf(src, addr),
+            FDS_MAPPER => self.mapper_fds(src, addr),
             _ => None,
         }
     }
@@ -43,7 +400,7 @@ impl Mapper {
                 }
             }
             Source::PPU => {
-                if addr <= 0x0FFF {
+                if addr <= 0x1FFF {
                     Some(addr.into())
                 } else {
                     None
@@ -54,4 +411,127 @@ impl Mapper {
     fn mapper_001(&self, src: Source, addr: u16) -> Option<usize> {
         None
     }
+    /// The RAM mapped by MMC5 is handled by `map_prg_ram`
+    fn mapper_005(&self, src: Source, addr: u16) -> Option<usize> {
+        let mmc5 = self.mmc5.as_ref()?;
+        match src {
+            Source::CPU if addr >= 0x8000 => match mmc5.map_prg(addr) {
+                (true, offset) => Some(offset % (self.nb_prg_banks * 0x4000).max(1)),
+                (false, _) => None,
+            },
+            Source::PPU if addr <= 0x1FFF => {
+                let (bank, size, addr) = mmc5.chr_bank(addr);
+                Some(self.chr_offset(bank, size, addr))
+            }
+            _ => None,
+        }
+    }
+    /// MMC2 switches 8KB of PRG at $8000 and MMC4 16KB, the rest is the last banks.
+    /// Each pattern table has two CHR banks, `banks[1..5]`, picked by its latch in `banks[6..8]`.
+    fn mapper_009(&self, src: Source, addr: u16) -> Option<usize> {
+        match src {
+            Source::CPU if addr >= 0x8000 => Some(match (self.mapper_id, addr) {
+                (9, 0x8000..=0x9FFF) => self.prg_offset(self.banks[0] as isize, 0x2000, addr),
+                (9, _) => self.prg_offset((addr as isize >> 13) - 8, 0x2000, addr),
+                (_, 0x8000..=0xBFFF) => self.prg_offset(self.banks[0] as isize, 0x4000, addr),
+                _ => self.prg_offset(-1, 0x4000, addr),
+            }),
+            Source::PPU if addr <= 0x1FFF => {
+                let table = (addr >> 12) as usize;
+                let bank = self.banks[1 + table * 2 + self.banks[6 + table] as usize];
+                Some(self.chr_offset(bank as isize, 0x1000, addr))
+            }
+            _ => None,
+        }
+    }
+    /// Namco 163, the nametables from the CHR are handled by `map_nametable`
+    fn mapper_019(&self, src: Source, addr: u16) -> Option<usize> {
+        let chip = self.namco163.as_ref()?;
+        match src {
+            Source::CPU if addr >= 0x8000 => Some(self.prg_offset(chip.prg_bank(addr), 0x2000, addr)),
+            Source::PPU if addr <= 0x1FFF => {
+                let bank = chip.chr_banks[addr as usize >> 10];
+                Some(self.chr_offset(bank as isize, 0x400, addr))
+            }
+            _ => None,
+        }
+    }
+    /// Sunsoft FME-7, the ROM can also be mapped at $6000 in place of the RAM
+    fn mapper_069(&self, src: Source, addr: u16) -> Option<usize> {
+        let fme7 = self.fme7.as_ref()?;
+        match src {
+            Source::CPU if addr >= 0x6000 => {
+                Some(self.prg_offset(fme7.prg_rom_bank(addr)?, 0x2000, addr))
+            }
+            Source::PPU if addr <= 0x1FFF => {
+                let bank = fme7.chr_banks[addr as usize >> 10];
+                Some(self.chr_offset(bank as isize, 0x400, addr))
+            }
+            _ => None,
+        }
+    }
+    /// Boards made of latches: UxROM, CNROM, AxROM, Color Dreams, BNROM, NINA-001, GxROM,
+    /// Camerica, NINA-03/06, Jaleco and UNROM with a 74HC08. CHR RAM boards have a single 8KB bank.
+    fn mapper_discrete(&self, src: Source, addr: u16) -> Option<usize> {
+        match src {
+            Source::CPU if addr >= 0x8000 => Some(match (self.mapper_id, addr) {
+                (2 | 71, 0x8000..=0xBFFF) => self.prg_offset(self.banks[0] as isize, 0x4000, addr),
+                (2 | 71, _) => self.prg_offset(-1, 0x4000, addr),
+                (180, 0x8000..=0xBFFF) => self.prg_offset(0, 0x4000, addr),
+                (180, _) => self.prg_offset(self.banks[0] as isize, 0x4000, addr),
+                (3 | 87, _) => self.prg_offset((addr as isize >> 14) - 2, 0x4000, addr),
+                _ => self.prg_offset(self.banks[0] as isize, 0x8000, addr),
+            }),
+            Source::PPU if addr <= 0x1FFF => Some(match self.mapper_id {
+                34 if self.nina_001() => {
+                    let bank = self.banks[1 + (addr as usize >> 12)];
+                    self.chr_offset(bank as isize, 0x1000, addr)
+                }
+                2 | 7 | 34 | 71 | 180 => self.chr_offset(0, 0x2000, addr),
+                _ => self.chr_offset(self.banks[1] as isize, 0x2000, addr),
+            }),
+            _ => None,
+        }
+    }
+    /// Konami VRC2, VRC4, VRC6 and VRC7, the banks are kept by `Vrc`
+    fn mapper_vrc(&self, src: Source, addr: u16) -> Option<usize> {
+        let vrc = self.vrc.as_ref()?;
+        match src {
+            Source::CPU if addr >= 0x8000 => {
+                let (bank, size) = vrc.prg_bank(addr);
+                Some(self.prg_offset(bank, size, addr))
+            }
+            Source::PPU if addr <= 0x1FFF => {
+                let (bank, size) = vrc.chr_bank(addr);
+                Some(self.chr_offset(bank, size, addr))
+            }
+            _ => None,
+        }
+    }
+    fn mapper_fds(&self, src: Source, addr: u16) -> Option<usize> {
+        match src {
+            Source::CPU if addr >= 0x8000 => Some(addr as usize - 0x8000),
+            Source::PPU if addr <= 0x1FFF => Some(addr as usize),
+            _ => None,
+        }
+    }
+    /// Offset of `addr` in a PRG bank of `size` bytes, negative banks count from the last one
+    fn prg_offset(&self, bank: isize, size: usize, addr: u16) -> usize {
+        let count = (self.nb_prg_banks * 0x4000 / size).max(1) as isize;
+        bank.rem_euclid(count) as usize * size + (addr as usize & (size - 1))
+    }
+    /// Offset of `addr` in a CHR bank of `size` bytes, boards without CHR ROM have 8KB of RAM
+    fn chr_offset(&self, bank: isize, size: usize, addr: u16) -> usize {
+        let count = (self.nb_chr_banks.max(1) * 0x2000 / size).max(1) as isize;
+        bank.rem_euclid(count) as usize * size + (addr as usize & (size - 1))
+    }
+    fn mapper_nsf(&self, src: Source, addr: u16) -> Option<usize> {
+        match src {
+            Source::CPU if addr >= 0x8000 => {
+                let bank = self.banks[(addr as usize - 0x8000) >> 12] as usize % self.nb_prg_banks.max(1);
+                Some(bank * 0x1000 + (addr as usize & 0x0FFF))
+            }
+            _ => None,
+        }
+    }
 }
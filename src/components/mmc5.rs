@@ -0,0 +1,366 @@
+/// Cpu cycles without PPU fetches after which the MMC5 sees the frame as ended. The chip
+/// waits 3 cycles, the PPU of the emulator fetches the sprites all at once and is quiet longer.
+const IDLE_CYCLES: u8 = 32;
+/// Nametable fetches on each scanline: 32 tiles, 2 for the next line and 2 dummy ones
+const LINE_FETCHES: u8 = 36;
+
+/// Nintendo MMC5 (mapper 5), the registers from $5100 to $5206 and the 1KB of ExRAM.
+/// It follows the fetches of the PPU to count the scanlines and to know which tile is drawn.
+pub struct Mmc5 {
+    /// $5100, 32KB, 16KB, 16KB + 8KB or 8KB PRG banks
+    pub prg_mode: u8,
+    /// $5101, 8KB, 4KB, 2KB or 1KB CHR banks
+    pub chr_mode: u8,
+    /// $5102 and $5103, the PRG RAM can only be written when they hold 2 and 1
+    pub prg_ram_protect: [u8; 2],
+    /// $5104: ExRAM as a nametable, as extended attributes, as RAM, as ROM
+    pub exram_mode: u8,
+    /// $5105, 2 bits per nametable: vram page 0 or 1, ExRAM, fill mode
+    pub nametables: u8,
+    /// $5106 and $5107, the tile and palette of the fill mode nametable
+    pub fill_tile: u8,
+    pub fill_attribute: u8,
+    /// $5113 to $5117, in 8KB units. Bit 7 selects ROM instead of RAM from $8000 to $DFFF.
+    pub prg_banks: [u8; 5],
+    /// $5120 to $512B with the 2 bits of $5130 on top, in units of the CHR mode.
+    /// The 8 first ones are used by the sprites, the 4 last by the background of 8x16 sprites.
+    pub chr_banks: [u16; 12],
+    /// $5130, the upper bits of the next CHR bank written
+    pub chr_upper: u8,
+    /// Whether $5128-$512B were written after $5120-$5127
+    pub background_banks_last: bool,
+    /// $5200: enabled, right side, tile where the split starts
+    pub split_control: u8,
+    /// $5201, vertical scroll of the split
+    pub split_scroll: u8,
+    /// $5202, 4KB CHR bank of the split
+    pub split_bank: u8,
+    /// $5203, the IRQ is sent at the start of this scanline
+    pub irq_scanline: u8,
+    pub irq_enabled: bool,
+    pub irq_pending: bool,
+    /// The PPU is rendering a frame, `scanline` is the visible line being drawn
+    pub in_frame: bool,
+    pub scanline: u8,
+    /// $5205 and $5206, unsigned 8x8 multiplication
+    pub multiplicand: u8,
+    pub multiplier: u8,
+    pub exram: [u8; 0x400],
+    /// Sprite size and rendering, taken from the writes to PPUCTRL and PPUMASK
+    pub large_sprites: bool,
+    pub rendering: bool,
+    last_fetch: u16,
+    repeated_fetches: u8,
+    /// Nametable fetches since the start of the scanline
+    line_fetches: u8,
+    idle_cycles: u8,
+    /// ExRAM byte of the tile being fetched, for the extended attributes
+    extended_attribute: u8,
+    /// Vertical position in the split of the tile being fetched
+    split_y: Option<u8>,
+}
+
+impl Mmc5 {
+    pub fn new() -> Mmc5 {
+        Mmc5 {
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            background_banks_last: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_scanline: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            exram: [0; 0x400],
+            large_sprites: false,
+            rendering: false,
+            last_fetch: 0,
+            repeated_fetches: 0,
+            line_fetches: 0,
+            idle_cycles: 0,
+            extended_attribute: 0,
+            split_y: None,
+        }
+    }
+
+    /// Registers from $5100 to $5206 and ExRAM, returns false for the other addresses
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x5100 => self.prg_mode = data & 0x03,
+            0x5101 => self.chr_mode = data & 0x03,
+            0x5102 | 0x5103 => self.prg_ram_protect[addr as usize - 0x5102] = data & 0x03,
+            0x5104 => self.exram_mode = data & 0x03,
+            0x5105 => self.nametables = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0x03,
+            0x5113..=0x5117 => self.prg_banks[addr as usize - 0x5113] = data,
+            0x5120..=0x512B => {
+                self.chr_banks[addr as usize - 0x5120] = (self.chr_upper as u16) << 8 | data as u16;
+                self.background_banks_last = addr >= 0x5128;
+            }
+            0x5130 => self.chr_upper = data & 0x03,
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_scanline = data,
+            0x5204 => self.irq_enabled = data & 0x80 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5C00..=0x5FFF => match self.exram_mode {
+                // Outside of the frame, the nametable modes write zeros
+                0 | 1 => self.exram[addr as usize - 0x5C00] = if self.in_frame { data } else { 0 },
+                2 => self.exram[addr as usize - 0x5C00] = data,
+                _ => (),
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        let product = self.multiplicand as u16 * self.multiplier as u16;
+        match addr {
+            0x5204 => Some((self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6),
+            0x5205 => Some(product as u8),
+            0x5206 => Some((product >> 8) as u8),
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => Some(self.exram[addr as usize - 0x5C00]),
+            _ => None,
+        }
+    }
+
+    /// Reading the status acknowledges the IRQ
+    pub fn acknowledge(&mut self, addr: u16) {
+        if addr == 0x5204 {
+            self.irq_pending = false;
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+
+    pub fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    /// Bank register and size of the PRG mapped at a cpu address from $6000
+    fn prg_bank(&self, addr: u16) -> (u8, usize) {
+        let register = |x: usize| self.prg_banks[x];
+        // $5117 is always ROM
+        match (self.prg_mode, addr) {
+            (_, 0x6000..=0x7FFF) => (register(0), 0x2000),
+            (0, _) => (register(4) | 0x80, 0x8000),
+            (1, 0xC000..=0xFFFF) => (register(4) | 0x80, 0x4000),
+            (1, _) | (2, 0x8000..=0xBFFF) => (register(2), 0x4000),
+            (2, 0xC000..=0xDFFF) | (3, 0xC000..=0xDFFF) => (register(3), 0x2000),
+            (3, 0x8000..=0x9FFF) => (register(1), 0x2000),
+            (3, 0xA000..=0xBFFF) => (register(2), 0x2000),
+            _ => (register(4) | 0x80, 0x2000),
+        }
+    }
+
+    /// PRG mapped at a cpu address from $6000: ROM or RAM, offset in it.
+    /// The ROM offset is wrapped by the caller, the RAM is 64KB.
+    pub fn map_prg(&self, addr: u16) -> (bool, usize) {
+        let (bank, size) = self.prg_bank(addr);
+        let rom = addr >= 0x8000 && bank & 0x80 != 0;
+        // The banks are in 8KB units, the low bits are ignored for the larger sizes
+        let bank = (bank & 0x7F) as usize & !(size / 0x2000 - 1);
+        let offset = bank * 0x2000 + (addr as usize & (size - 1));
+        match rom {
+            true => (true, offset),
+            false => (false, offset & 0xFFFF),
+        }
+    }
+
+    /// CHR bank, its size and the address in it for a pattern fetch. The sprites and the
+    /// background of 8x16 sprites have their own banks, the cpu and 8x8 sprites use the last
+    /// ones written.
+    pub fn chr_bank(&self, addr: u16) -> (isize, usize, u16) {
+        if let Some(y) = self.split_y.filter(|_| !self.sprite_fetch()) {
+            // The row of the tile comes from the scroll of the split
+            return (
+                self.split_bank as isize,
+                0x1000,
+                (addr & 0x0FF8) | (y & 0x07) as u16,
+            );
+        }
+        if self.exram_mode == 1 && self.in_frame && !self.sprite_fetch() {
+            let bank = (self.chr_upper as isize) << 6 | (self.extended_attribute & 0x3F) as isize;
+            return (bank, 0x1000, addr);
+        }
+        let background = match self.large_sprites && self.in_frame {
+            true => !self.sprite_fetch(),
+            false => self.background_banks_last,
+        };
+        let size = 0x2000 >> self.chr_mode;
+        let slot = addr as usize / size;
+        let register = match background {
+            true => 8 + (slot * (size / 0x400)) % 4 + (size / 0x400).min(4) - 1,
+            false => slot * (size / 0x400) + size / 0x400 - 1,
+        };
+        (self.chr_banks[register] as isize, size, addr)
+    }
+
+    /// Nametable fetch replaced by ExRAM, the fill mode, the split or the extended attributes
+    pub fn nametable_read(&self, addr: u16) -> Option<u8> {
+        let offset = addr as usize & 0x03FF;
+        let attribute = offset >= 0x03C0;
+        if let Some(y) = self.split_y.filter(|_| attribute) {
+            let column = self.column(self.line_fetches).unwrap_or(0);
+            let data = self.exram[0x03C0 + (y as usize / 32) * 8 + column / 4];
+            let shift = ((y as usize / 16) & 0x01) * 4 + ((column / 2) & 0x01) * 2;
+            return Some(((data >> shift) & 0x03) * 0x55);
+        }
+        if !attribute && self.in_frame {
+            // The fetch is not counted yet, a new line starts after the 36th one
+            let next = (self.line_fetches + 1) % LINE_FETCHES;
+            let scanline = self.scanline as u16 + (next == 0 || next >= 32) as u16;
+            if let Some(y) = self.split_line(next, scanline) {
+                let column = self.column(next).unwrap_or(0);
+                return Some(self.exram[(y as usize / 8) * 32 + column]);
+            }
+        }
+        if attribute && self.exram_mode == 1 && self.in_frame {
+            return Some((self.extended_attribute >> 6) * 0x55);
+        }
+        match (self.nametables >> ((addr >> 9) & 0x06)) & 0x03 {
+            2 if self.exram_mode <= 1 => Some(self.exram[offset]),
+            2 => Some(0),
+            3 if attribute => Some(self.fill_attribute * 0x55),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    /// Writes to the nametables mapped to ExRAM or to the fill mode
+    pub fn nametable_write(&mut self, addr: u16, data: u8) -> bool {
+        match (self.nametables >> ((addr >> 9) & 0x06)) & 0x03 {
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[addr as usize & 0x03FF] = data;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    /// Vram page of each nametable mapped to the console vram
+    pub fn vram_pages(&self) -> [u8; 4] {
+        let page = |i: u8| (self.nametables >> (i * 2)) & 0x01;
+        [page(0), page(1), page(2), page(3)]
+    }
+
+    /// A fetch of the PPU. Three fetches of the same nametable byte in a row are only
+    /// seen at the start of a scanline.
+    pub fn observe_ppu_read(&mut self, addr: u16) {
+        self.idle_cycles = 0;
+        match addr == self.last_fetch {
+            true => self.repeated_fetches += 1,
+            false => self.repeated_fetches = 0,
+        }
+        self.last_fetch = addr;
+        if !(0x2000..=0x2FFF).contains(&addr) || addr & 0x03FF >= 0x03C0 {
+            return;
+        }
+        if self.repeated_fetches == 2 {
+            if self.in_frame {
+                self.scanline = self.scanline.wrapping_add(1);
+                if self.scanline == self.irq_scanline {
+                    self.irq_pending = true;
+                }
+            } else {
+                self.in_frame = true;
+                self.scanline = 0;
+            }
+            self.line_fetches = 0;
+        } else {
+            self.line_fetches = (self.line_fetches + 1) % LINE_FETCHES;
+        }
+        self.extended_attribute = self.exram[addr as usize & 0x03FF];
+        let scanline = self.scanline as u16 + (self.line_fetches >= 32) as u16;
+        self.split_y = match self.in_frame {
+            true => self.split_line(self.line_fetches, scanline),
+            false => None,
+        };
+    }
+
+    /// Writes of the cpu to PPUCTRL and PPUMASK
+    pub fn observe_ppu_register(&mut self, addr: u16, data: u8) {
+        match addr & 0x2007 {
+            0x2000 => self.large_sprites = data & 0x20 != 0,
+            0x2001 => {
+                self.rendering = data & 0x18 != 0;
+                if !self.rendering {
+                    self.in_frame = false;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// One cpu cycle, the frame ends when the PPU stops fetching
+    pub fn clock(&mut self) {
+        if self.idle_cycles < IDLE_CYCLES {
+            self.idle_cycles += 1;
+        } else {
+            self.in_frame = false;
+            self.split_y = None;
+            self.repeated_fetches = 0;
+        }
+    }
+
+    /// Sprite patterns are fetched between the last tile of the line and the first of the next one
+    fn sprite_fetch(&self) -> bool {
+        self.in_frame && self.line_fetches == 31
+    }
+
+    /// Tile column of a nametable fetch, the line starts on the third tile as the two first
+    /// were fetched at the end of the previous line
+    fn column(&self, fetch: u8) -> Option<usize> {
+        match fetch {
+            0..=31 => Some((fetch as usize + 2) & 0x1F),
+            32 | 33 => Some(fetch as usize - 32),
+            _ => None,
+        }
+    }
+
+    /// Line of the split drawn by a nametable fetch for `scanline`, None outside of the split
+    fn split_line(&self, fetch: u8, scanline: u16) -> Option<u8> {
+        let column = self.column(fetch)?;
+        if self.split_control & 0x80 == 0 || self.exram_mode > 1 {
+            return None;
+        }
+        let threshold = (self.split_control & 0x1F) as usize;
+        let inside = match self.split_control & 0x40 != 0 {
+            true => column >= threshold,
+            false => column < threshold,
+        };
+        let line = scanline + self.split_scroll as u16;
+        match inside {
+            true => Some((line % 240) as u8),
+            false => None,
+        }
+    }
+}
+
+impl Default for Mmc5 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,14 +2,40 @@ pub use self::apu::*;
 pub use self::cpu::*;
 pub use self::instruction_generator::get_lookup_list;
 pub use self::nes::*;
+pub use self::palette::*;
 pub use self::ppu::*;
+pub use self::region::*;
+pub use self::resampler::*;
 pub use self::mapper::*;
+pub use self::mmc5::*;
+pub use self::fme7::*;
+pub use self::namco163::*;
 pub use self::cartridge::*;
+pub use self::controller::*;
+pub use self::disk::*;
+pub use self::expansion::*;
+pub use self::gamedb::*;
+pub use self::state::*;
+pub use self::unif::*;
+pub use self::vrc::*;
 
 mod apu;
 mod cpu;
 mod instruction_generator;
 mod nes;
+mod palette;
 mod ppu;
+mod region;
+mod resampler;
 mod mapper;
+mod mmc5;
+mod fme7;
+mod namco163;
 mod cartridge;
+mod controller;
+mod disk;
+mod expansion;
+mod gamedb;
+mod state;
+mod unif;
+mod vrc;
@@ -0,0 +1,97 @@
+/// Namco 163 (mapper 19), the banking and IRQ part of the chip. The sound channels and their
+/// 128 bytes of RAM are in the expansion audio, they share $E000 and $F800 with the mapper.
+/// The CHR registers can also select the pages of the console vram, from $E0. The pattern
+/// tables always come from the CHR here, only the nametables use them.
+#[derive(Default)]
+pub struct Namco163 {
+    /// $8000 to $BFFF, 1KB CHR banks, then $C000 to $DFFF for the 4 nametables
+    pub chr_banks: [u8; 12],
+    /// $E000, $E800 and $F000, 8KB PRG banks at $8000, $A000 and $C000
+    pub prg_banks: [u8; 3],
+    /// $F800, the PRG RAM can be written when the high nibble is 4 and the bit of its 2KB is clear
+    pub write_protect: u8,
+    /// $5000 and $5800, 15 bits counting up every cpu cycle
+    pub irq_counter: u16,
+    pub irq_enabled: bool,
+    pub irq_pending: bool,
+}
+
+impl Namco163 {
+    /// The nametables start on the vram, with a vertical mirroring
+    pub fn new() -> Namco163 {
+        let mut chip = Namco163::default();
+        chip.chr_banks[8..].copy_from_slice(&[0xE0, 0xE1, 0xE0, 0xE1]);
+        chip
+    }
+
+    /// Write to the registers, the writes to the protected PRG RAM are dropped
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            0x5000..=0x57FF => {
+                self.irq_counter = self.irq_counter & 0x7F00 | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = self.irq_counter & 0x00FF | (data as u16 & 0x7F) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF => return !self.prg_ram_writable(addr),
+            0x8000..=0xDFFF => self.chr_banks[(addr as usize - 0x8000) >> 11] = data,
+            0xE000..=0xF7FF => self.prg_banks[(addr as usize - 0xE000) >> 11] = data & 0x3F,
+            0xF800..=0xFFFF => self.write_protect = data,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The IRQ counter can be read back
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => Some((self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        self.write_protect & 0xF0 == 0x40
+            && self.write_protect & (1 << ((addr - 0x6000) >> 11)) == 0
+    }
+
+    /// 8KB PRG bank at a cpu address from $8000, negative banks count from the last one
+    pub fn prg_bank(&self, addr: u16) -> isize {
+        match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize >> 13) - 4] as isize,
+            _ => -1,
+        }
+    }
+
+    /// CHR bank of a nametable, None when it is a page of the console vram
+    pub fn nametable_bank(&self, addr: u16) -> Option<u8> {
+        let bank = self.chr_banks[8 + (addr as usize >> 10 & 0x03)];
+        match bank {
+            0xE0..=0xFF => None,
+            _ => Some(bank),
+        }
+    }
+
+    /// Vram page of each nametable, used for the ones not taken from the CHR
+    pub fn vram_pages(&self) -> [u8; 4] {
+        let mut pages = [0; 4];
+        for (i, page) in pages.iter_mut().enumerate() {
+            *page = self.chr_banks[8 + i] & 0x01;
+        }
+        pages
+    }
+
+    /// The counter stops once it reaches $7FFF and sends the IRQ
+    pub fn clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+    }
+}
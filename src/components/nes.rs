@@ -1,19 +1,50 @@
 // use super::super::components::*;
-use super::{super::utils::*, APU, Cartridge, PPU, PPUComponents};
+use super::{super::utils::*, APU, CPU6502, CPUFunctions, Cartridge, Controller, PPU, PPUComponents, Region};
 
 pub struct NesData {
     /// Ram data, from 0x0000 to 0x1FFF
     pub ram: [u8; 0x2000],
     /// Cartridge data, from 0x4020 to 0xFFFF
     pub cartridge: Cartridge,
-    /// PPU Registers
-    pub ppu_registers : [u8; 0x0007],
     /// APU Registers
     pub apu : APU,
-    /// PPU struct containing the ppu data
+    /// PPU struct containing the ppu data, its registers are mapped from 0x2000 to 0x3FFF
     pub ppu : PPU,
+    /// Joypads plugged in the two controller ports
+    pub controllers : [Controller; 2],
+    /// Cpu cycles left before the end of an OAM DMA, the cpu is halted meanwhile
+    pub dma_cycles : u16,
     /// Clock counter
     pub clock_counter : u128,
+    /// Television standard, taken from the header of the cartridge, see `set_region`
+    pub region : Region,
+    /// Accesses made by the cpu, only recorded when set, for the debugger watchpoints
+    pub access_log : Option<Vec<BusAccess>>,
+}
+
+/// Address space an access is made on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    Cpu,
+    /// Video memory, only the accesses made by the cpu through $2007 are recorded
+    Ppu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// Opcode fetch, only reported by the debugger
+    Execute,
+}
+
+/// A read or a write seen on one of the buses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusAccess {
+    pub space: AddressSpace,
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub data: u8,
 }
 
 pub trait DataActions {
@@ -28,67 +59,176 @@ impl NesData {
         NesData {
             ram: [0u8; 0x2000],
             cartridge: Cartridge::new(),
-            ppu_registers : [0u8; 0x0007],
             apu : APU::new(),
             ppu : PPU::new(),
+            controllers : [Controller::new(); 2],
+            dma_cycles: 0,
             clock_counter: 0,
+            region: Region::Ntsc,
+            access_log: None,
         }
     }
-    pub fn insert_cartridge(&mut self, cartridge: Vec<u8>) {
-        self.cartridge.load(cartridge);
+    pub fn insert_cartridge(&mut self, cartridge: Vec<u8>) -> Result<(), String> {
+        self.cartridge.load(cartridge)?;
+        self.set_region(Region::from_header(&self.cartridge.header));
+        Ok(())
+    }
+    /// Cartridge from a UNIF image
+    pub fn insert_unif(&mut self, image: &[u8]) -> Result<(), String> {
+        self.cartridge.load_unif(image)?;
+        self.set_region(Region::from_header(&self.cartridge.header));
+        Ok(())
+    }
+    /// Run with the clocks and frame timings of a console region
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.region = region;
+        self.apu.set_region(region);
+    }
+    /// Famicom Disk System with a disk image, `bios` is the 8KB ROM of the RAM adapter.
+    /// The Disk System was only sold in Japan, it always runs as NTSC.
+    pub fn insert_disk(&mut self, image: &[u8], bios: Vec<u8>) -> Result<(), String> {
+        self.cartridge.load_fds(image, bios)?;
+        self.set_region(Region::Ntsc);
+        Ok(())
+    }
+    /// Put the console back in its power up state, the cartridge is kept inserted
+    pub fn reset_memory(&mut self) {
+        self.ram = [0u8; 0x2000];
+        let sample_rate = self.apu.sample_rate;
+        self.apu = APU::new();
+        self.apu.sample_rate = sample_rate;
+        self.ppu = PPU::new();
+        for controller in self.controllers.iter_mut() {
+            *controller = Controller { buttons: controller.buttons, ..Controller::new() };
+        }
+        self.dma_cycles = 0;
+        self.clock_counter = 0;
+        self.set_region(self.region);
+    }
+    /// System clock, runs at the PPU speed.
+    /// The cpu and the APU are clocked every 3 PPU dots, or every 3.2 dots on PAL,
+    /// the cpu receives the NMI at the start of the vblank and the IRQs of the APU and the cartridge.
+    pub fn clock(&mut self, cpu: &mut CPU6502) {
+        self.ppu.clock(&mut self.cartridge);
+        if self.region.cpu_clocked(self.clock_counter) {
+            self.clock_apu();
+            self.cartridge.clock();
+            if self.dma_cycles > 0 {
+                self.dma_cycles -= 1;
+            } else {
+                if cpu.cycles == 0 && (self.apu.irq() || self.cartridge.irq()) {
+                    cpu.interupt_req(self);
+                }
+                cpu.clock(self);
+            }
+        }
+        if self.ppu.nmi {
+            self.ppu.nmi = false;
+            cpu.non_maskable_interupt_req(self);
+        }
+        self.clock_counter += 1;
+    }
+    /// Advance the APU and the sound chip of the cartridge by one cpu cycle, and feed the
+    /// DMC when it needs a sample
+    pub fn clock_apu(&mut self) {
+        self.cartridge.audio.clock();
+        self.apu.expansion = self.cartridge.audio.output();
+        self.apu.clock();
+        if let Some(addr) = self.apu.dmc_request() {
+            let data = self.cpu_read(addr, true);
+            self.apu.dmc_fill(data);
+        }
+    }
+    /// Clock the system until the PPU finishes the current frame
+    pub fn run_frame(&mut self, cpu: &mut CPU6502) {
+        while !self.ppu.frame_complete {
+            self.clock(cpu);
+        }
+        self.ppu.frame_complete = false;
+    }
+    /// Copy a page of cpu memory to OAM, the cpu is halted for 513 or 514 cycles
+    fn oam_dma(&mut self, page: u8) {
+        for i in 0..256u16 {
+            let data = self.cpu_read((page as u16) << 8 | i, true);
+            let addr = self.ppu.oam_addr.wrapping_add(i as u8);
+            self.ppu.oam[addr as usize] = data;
+        }
+        // The APU was already clocked on this cpu cycle
+        self.dma_cycles = 513 + ((self.apu.cycle + 1) % 2) as u16;
+    }
+    fn log_access(&mut self, space: AddressSpace, kind: AccessKind, addr: u16, data: u8) {
+        if let Some(log) = self.access_log.as_mut() {
+            log.push(BusAccess { space, kind, addr, data });
+        }
+    }
+    /// Log the vram access made through $2007, `vram_addr` is taken before the access
+    fn log_ppu_data(&mut self, addr: u16, vram_addr: u16, kind: AccessKind, data: u8) {
+        if addr & 0x0007 == 0x0007 {
+            self.log_access(AddressSpace::Ppu, kind, vram_addr & 0x3FFF, data);
+        }
     }
-    pub fn reset_memory(&mut self) {}
-    pub fn clock(&mut self) {}
 }
 
 impl DataActions for NesData {
     fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.log_access(AddressSpace::Cpu, AccessKind::Write, addr, data);
         match addr.to_where() {
-            NESComponents::RAM => self.ram[(addr % 0x07ff) as usize] = data,
-            NESComponents::APU => self.apu.registers[((addr - 0x4000) & 0x0017) as usize] = data,
-            NESComponents::PPU => self.ppu_registers[((addr - 0x2000) & 0x0007) as usize] = data,
+            NESComponents::RAM => self.ram[(addr & 0x07ff) as usize] = data,
+            NESComponents::APU => self.apu.cpu_write(addr, data),
+            NESComponents::PPU => {
+                let vram_addr = self.ppu.vram_addr;
+                self.ppu.cpu_write(addr, data, &mut self.cartridge);
+                self.cartridge.observe_ppu_register(addr, data);
+                self.log_ppu_data(addr, vram_addr, AccessKind::Write, data);
+            }
             NESComponents::CARTRIDGE => self.cartridge.cpu_write(addr, data),
+            NESComponents::DMA => self.oam_dma(data),
+            NESComponents::CONTROLLER => match addr {
+                0x4016 => self.controllers.iter_mut().for_each(|c| c.write(data)),
+                // $4017 is the frame counter of the APU on writes
+                _ => self.apu.cpu_write(addr, data),
+            },
             _ => (),
         }
     }
     fn cpu_read(&mut self, addr: u16, read_only: bool) -> u8 {
-        match addr.to_where() {
+        let vram_addr = self.ppu.vram_addr;
+        let data = match addr.to_where() {
             NESComponents::RAM => match read_only {
                 true => self.ram[(addr & 0x07ff) as usize],
                 false => self.ram[(addr & 0x07ff) as usize],
             },
-            NESComponents::CARTRIDGE =>  self.cartridge.cpu_read(addr),
-            NESComponents::PPU => match read_only {
-                true => self.ppu_registers[((addr - 0x2000) & 0x0007) as usize],
-                false => self.ppu_registers[((addr - 0x2000) & 0x0007) as usize],
-            },
-            NESComponents::APU => match read_only {
-                true => self.apu.registers[((addr - 0x4000) & 0x0017) as usize],
-                false => self.apu.registers[((addr - 0x4000) & 0x0017) as usize],
-            },
+            NESComponents::CARTRIDGE => {
+                let data = self.cartridge.cpu_read(addr);
+                if !read_only {
+                    self.cartridge.observe_read(addr, data);
+                }
+                data
+            }
+            NESComponents::PPU => self.ppu.cpu_read(addr, read_only, &mut self.cartridge),
+            NESComponents::APU => self.apu.cpu_read(addr, read_only),
+            NESComponents::CONTROLLER => self.controllers[(addr - 0x4016) as usize].read(read_only),
             _ => 0u8,
+        };
+        if !read_only {
+            self.log_access(AddressSpace::Cpu, AccessKind::Read, addr, data);
+            if let NESComponents::PPU = addr.to_where() {
+                self.log_ppu_data(addr, vram_addr, AccessKind::Read, data);
+            }
         }
+        data
     }
 
     fn ppu_write(&mut self, addr: u16, data: u8) {
-        match addr.to_where() {
-            PPUComponents::PALLETTE => self.ppu.pallette[addr as usize] = data,
-            PPUComponents::PATTERN => self.ppu.pattern[addr as usize] = data, // TODO: Should it write on the ROM?
-            PPUComponents::NAMETABLES => {
-                let index = if (addr - 0x2000) & 0x0800 < 0x400 {0} else {1};
-                self.ppu.names[index][addr as usize] = data;
-            },
-        }
+        self.ppu.ppu_write(addr, data, &mut self.cartridge);
     }
 
     fn ppu_read(&mut self, addr: u16, read_only: bool) -> u8 {
-        match addr.to_where() {
-            PPUComponents::PALLETTE => self.ppu.pallette[addr as usize],
-            PPUComponents::PATTERN => self.cartridge.ppu_read(addr),
-            PPUComponents::NAMETABLES => {
-                let index = if (addr - 0x2000) & 0x0800 < 0x400 {0} else {1};
-                self.ppu.names[index][((addr - 0x2000) & 0x0400) as usize]
-            },
+        // Peeking must not disturb the mappers following the fetches
+        match read_only {
+            true => self.ppu.peek(addr, &self.cartridge),
+            false => self.ppu.ppu_read(addr, &mut self.cartridge),
         }
     }
 }
@@ -98,6 +238,8 @@ enum NESComponents {
     APU,
     CARTRIDGE,
     PPU,
+    CONTROLLER,
+    DMA,
     NOCOMP,
 }
 
@@ -106,11 +248,15 @@ impl AddrConvert<NESComponents> for u16 {
         let x = *self;
         if x < 0x2000 {
             NESComponents::RAM
-        } else if x >= 0x2000 && x < 0x3FFF {
+        } else if x >= 0x2000 && x <= 0x3FFF {
             NESComponents::PPU
-        } else if x >= 0x4000 && x < 0x4017 {
+        } else if x == 0x4014 {
+            NESComponents::DMA
+        } else if x == 0x4016 || x == 0x4017 {
+            NESComponents::CONTROLLER
+        } else if x >= 0x4000 && x < 0x4016 {
             NESComponents::APU 
-        } else if x >= 0x4020 && x < 0xFFFF {
+        } else if x >= 0x4020 {
             NESComponents::CARTRIDGE
         } else {
             NESComponents::NOCOMP
@@ -0,0 +1,213 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+
+use super::PpuMask;
+
+/// Colors of the 2C02, in RGB
+pub const NTSC_PALLETTE: [(u8, u8, u8); 64] = [
+    (84, 84, 84), (0, 30, 116), (8, 16, 144), (48, 0, 136),
+    (68, 0, 100), (92, 0, 48), (84, 4, 0), (60, 24, 0),
+    (32, 42, 0), (8, 58, 0), (0, 64, 0), (0, 60, 0),
+    (0, 50, 60), (0, 0, 0), (0, 0, 0), (0, 0, 0),
+    (152, 150, 152), (8, 76, 196), (48, 50, 236), (92, 30, 228),
+    (136, 20, 176), (160, 20, 100), (152, 34, 32), (120, 60, 0),
+    (84, 90, 0), (40, 114, 0), (8, 124, 0), (0, 118, 40),
+    (0, 102, 120), (0, 0, 0), (0, 0, 0), (0, 0, 0),
+    (236, 238, 236), (76, 154, 236), (120, 124, 236), (176, 98, 236),
+    (228, 84, 236), (236, 88, 180), (236, 106, 100), (212, 136, 32),
+    (160, 170, 0), (116, 196, 0), (76, 208, 32), (56, 204, 108),
+    (56, 180, 204), (60, 60, 60), (0, 0, 0), (0, 0, 0),
+    (236, 238, 236), (168, 204, 236), (188, 188, 236), (212, 178, 236),
+    (236, 174, 236), (236, 174, 212), (236, 180, 176), (228, 196, 144),
+    (204, 210, 120), (180, 222, 120), (168, 226, 144), (152, 226, 180),
+    (160, 214, 228), (160, 162, 160), (0, 0, 0), (0, 0, 0),
+];
+
+/// Names accepted by `Palette::builtin`
+pub const BUILTIN_PALETTES: [&str; 3] = ["2c02", "ntsc", "grayscale"];
+
+/// The emphasized channels are kept, the two others are multiplied by this
+const EMPHASIS_ATTENUATION: f64 = 0.746;
+
+/// Maps the 64 colors of the PPU, under the 8 combinations of the PPUMASK emphasis
+/// bits, to RGB
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// Indexed by the emphasis on the bits 6 to 8 and the color on the low 6 bits,
+    /// like the pixels of `PPU::frame_buffer`
+    pub colors: [(u8, u8, u8); 512],
+}
+
+/// Settings of the NTSC signal decoding of `Palette::generate`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscParameters {
+    /// Rotation of the hues, in degrees
+    pub hue: f64,
+    /// 1 is the nominal saturation, 0 removes the colors
+    pub saturation: f64,
+    /// 1 is the nominal contrast
+    pub contrast: f64,
+    /// Added to the luma, 0 is the nominal brightness
+    pub brightness: f64,
+    /// The channels are raised to `1 / gamma`, 1 leaves them unchanged
+    pub gamma: f64,
+}
+
+impl Default for NtscParameters {
+    fn default() -> Self {
+        NtscParameters {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::from_colors(&NTSC_PALLETTE)
+    }
+}
+
+impl Palette {
+    /// The 64 base colors, the emphasized ones are derived from them
+    pub fn from_colors(base: &[(u8, u8, u8); 64]) -> Palette {
+        let mut colors = [(0u8, 0u8, 0u8); 512];
+        for (i, color) in colors.iter_mut().enumerate() {
+            let (r, g, b) = base[i & 0x3F];
+            let emphasis = i >> 6;
+            // Bit 0 emphasizes the red, bit 1 the green and bit 2 the blue
+            let channel = |value: u8, bit: usize| match emphasis != 0 && emphasis & (1 << bit) == 0 {
+                true => (value as f64 * EMPHASIS_ATTENUATION) as u8,
+                false => value,
+            };
+            *color = (channel(r, 0), channel(g, 1), channel(b, 2));
+        }
+        Palette { colors }
+    }
+
+    /// One of `BUILTIN_PALETTES`
+    pub fn builtin(name: &str) -> Option<Palette> {
+        match name {
+            "2c02" => Some(Palette::default()),
+            "ntsc" => Some(Palette::generate(&NtscParameters::default())),
+            "grayscale" => Some(Palette::generate(&NtscParameters {
+                saturation: 0.0,
+                ..NtscParameters::default()
+            })),
+            _ => None,
+        }
+    }
+
+    /// Read a `.pal` file of 64 colors (192 bytes) or of 512 colors with the emphasis
+    /// (1536 bytes)
+    pub fn from_pal(data: &[u8]) -> Result<Palette, String> {
+        let rgb = |i: usize| (data[i * 3], data[i * 3 + 1], data[i * 3 + 2]);
+        match data.len() {
+            192 => {
+                let mut base = [(0u8, 0u8, 0u8); 64];
+                for (i, color) in base.iter_mut().enumerate() {
+                    *color = rgb(i);
+                }
+                Ok(Palette::from_colors(&base))
+            }
+            1536 => {
+                let mut colors = [(0u8, 0u8, 0u8); 512];
+                for (i, color) in colors.iter_mut().enumerate() {
+                    *color = rgb(i);
+                }
+                Ok(Palette { colors })
+            }
+            x => Err(format!(
+                "a .pal file is 192 or 1536 bytes long, not {}",
+                x
+            )),
+        }
+    }
+
+    /// A `.pal` file, or the name of a built-in palette
+    pub fn load(path: &str) -> io::Result<Palette> {
+        if let Some(palette) = Palette::builtin(path) {
+            return Ok(palette);
+        }
+        Palette::from_pal(&fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The 1536 bytes `.pal` file of the palette
+    pub fn to_pal(&self) -> Vec<u8> {
+        self.colors.iter().flat_map(|&(r, g, b)| vec![r, g, b]).collect()
+    }
+
+    /// Decode the composite video signal of every color, as a television would
+    pub fn generate(parameters: &NtscParameters) -> Palette {
+        // Voltages of the signal, relative to the sync level
+        const LOW: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+        const HIGH: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+        const BLACK: f64 = 0.518;
+        const WHITE: f64 = 1.962;
+
+        let mut colors = [(0u8, 0u8, 0u8); 512];
+        for (index, color) in colors.iter_mut().enumerate() {
+            let hue = (index & 0x0F) as i32;
+            let level = (index >> 4) & 0x03;
+            let emphasis = index >> 6;
+            let (low, high) = match hue {
+                0 => (HIGH[level], HIGH[level]),
+                13 => (LOW[level], LOW[level]),
+                14 | 15 => (0.0, 0.0),
+                _ => (LOW[level], HIGH[level]),
+            };
+
+            // The signal is a square wave of 12 phases, the hue selects its phase
+            let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+            for phase in 0..12 {
+                let in_phase = |hue: i32| (hue + phase) % 12 < 6;
+                let mut signal = if in_phase(hue) { high } else { low };
+                if (emphasis & 1 != 0 && in_phase(0))
+                    || (emphasis & 2 != 0 && in_phase(4))
+                    || (emphasis & 4 != 0 && in_phase(8))
+                {
+                    signal *= EMPHASIS_ATTENUATION;
+                }
+                let signal = (signal - BLACK) / (WHITE - BLACK) / 12.0;
+                let angle = PI * (phase as f64 + 3.9 + parameters.hue / 30.0) / 6.0;
+                y += signal;
+                i += signal * angle.cos();
+                q += signal * angle.sin();
+            }
+            if hue >= 14 {
+                y = 0.0;
+            }
+            let y = y * parameters.contrast + parameters.brightness;
+            let i = i * parameters.saturation * 2.0;
+            let q = q * parameters.saturation * 2.0;
+
+            let channel = |value: f64| {
+                (value.clamp(0.0, 1.0).powf(1.0 / parameters.gamma) * 255.0).round() as u8
+            };
+            *color = (
+                channel(y + 0.946882 * i + 0.623557 * q),
+                channel(y - 0.274788 * i - 0.635691 * q),
+                channel(y - 1.108545 * i + 1.709007 * q),
+            );
+        }
+        Palette { colors }
+    }
+
+    /// Color of a pixel of `PPU::frame_buffer`
+    pub fn rgb(&self, pixel: u16) -> (u8, u8, u8) {
+        self.colors[(pixel & 0x1FF) as usize]
+    }
+
+    /// Color of an entry of `PPU::pallette` once PPUMASK is applied
+    pub fn color(&self, entry: u8, mask: u8) -> (u8, u8, u8) {
+        let entry = match mask & PpuMask::Grayscale as u8 != 0 {
+            true => entry & 0x30,
+            false => entry & 0x3F,
+        };
+        self.rgb(entry as u16 | ((mask as u16 >> 5) << 6))
+    }
+}
@@ -1,5 +1,13 @@
-// use super::super::utils::*;
-// use super::*;
+use super::super::utils::*;
+use std::io;
+
+use super::{Cartridge, Mirror, Palette, Region, StateReader, StateWriter};
+
+/// Width of the picture drawn by the PPU
+pub const SCREEN_WIDTH: usize = 256;
+/// Height of the picture drawn by the PPU
+pub const SCREEN_HEIGHT: usize = 240;
+
 /// The Picture processing unit.
 /// It should probably be handled by the computer itself depending the design.
 /// This component handles the pictures drawn on the screen,
@@ -7,22 +15,654 @@
 pub struct PPU {
     /// Ram data, from 0x2000 to 0x2FFF
     pub names: [[u8; 0x0400];2],
-    /// Pattern data, from 0x0000 to 0x1FFF
-    pub pattern: [u8; 0x2000],
-    /// Pallette data, from 0x3000 to 0x3FFF
+    /// Pallette data, from 0x3F00 to 0x3FFF
     pub pallette: [u8; 0x0020],
     /// Object attribute memory, should be shared with the cpu
     pub oam: [u8; 256],
+    /// PPUCTRL ($2000)
+    pub ctrl: u8,
+    /// PPUMASK ($2001)
+    pub mask: u8,
+    /// PPUSTATUS ($2002), bit 7 is the vertical blank
+    pub status: u8,
+    /// OAMADDR ($2003)
+    pub oam_addr: u8,
+    /// Current vram address, the "loopy v" register
+    pub vram_addr: u16,
+    /// Temporary vram address, the "loopy t" register
+    pub tram_addr: u16,
+    /// Fine horizontal scroll, 3 bits
+    pub fine_x: u8,
+    /// Write toggle shared by PPUSCROLL and PPUADDR
+    pub address_latch: bool,
+    /// PPUDATA reads are delayed by one read
+    pub data_buffer: u8,
+    /// Dot currently drawn on the scanline, from 0 to 340
+    pub cycle: u16,
+    /// Scanline currently drawn, -1 being the pre-render line and 240 to 260 the vblank,
+    /// up to 310 on PAL and Dendy
+    pub scanline: i16,
+    /// Set when the last dot of a frame is reached, the caller clears it
+    pub frame_complete: bool,
+    /// Set when a NMI must be sent to the cpu, the caller clears it
+    pub nmi: bool,
+    /// Number of frames drawn since power up
+    pub frame_count: u64,
+    /// Picture of the last frame, one entry per pixel holding
+    /// the pallette color on the low 6 bits and the PPUMASK emphasis on the bits 6 to 8
+    pub frame_buffer: Vec<u16>,
+    /// Colors of the frame buffer pixels, it is not part of the save states
+    pub palette: Palette,
+    /// Number of scanlines and start of the vblank, see `NesData::set_region`
+    pub region: Region,
+    bg: Background,
+    sprites: Sprites,
+}
+
+/// Background fetches and shifters
+#[derive(Default, Clone)]
+struct Background {
+    next_tile_id: u8,
+    next_tile_attrib: u8,
+    next_tile_lsb: u8,
+    next_tile_msb: u8,
+    shifter_pattern_lo: u16,
+    shifter_pattern_hi: u16,
+    shifter_attrib_lo: u16,
+    shifter_attrib_hi: u16,
+}
+
+/// Sprites found during the evaluation of the next scanline
+#[derive(Default, Clone)]
+struct Sprites {
+    /// Up to 8 sprites, 4 bytes each as in OAM
+    scanline: [u8; 32],
+    count: usize,
+    shifter_lo: [u8; 8],
+    shifter_hi: [u8; 8],
+    zero_hit_possible: bool,
+    zero_being_rendered: bool,
+}
+
+pub enum PPUComponents {
+    NAMETABLES,
+    PATTERN,
+    PALLETTE,
+}
+
+/// Bits of PPUCTRL
+pub enum PpuCtrl {
+    IncrementMode = 1 << 2,
+    PatternSprite = 1 << 3,
+    PatternBackground = 1 << 4,
+    SpriteSize = 1 << 5,
+    EnableNmi = 1 << 7,
+}
+
+/// Bits of PPUMASK
+pub enum PpuMask {
+    Grayscale = 1 << 0,
+    RenderBackgroundLeft = 1 << 1,
+    RenderSpritesLeft = 1 << 2,
+    RenderBackground = 1 << 3,
+    RenderSprites = 1 << 4,
+}
+
+/// Bits of PPUSTATUS
+pub enum PpuStatus {
+    SpriteOverflow = 1 << 5,
+    SpriteZeroHit = 1 << 6,
+    VerticalBlank = 1 << 7,
 }
 
 impl PPU {
     pub fn new() -> PPU {
         PPU {
-            pattern: [0u8; 0x2000],
             pallette: [0u8; 0x0020],
             oam: [0u8; 256],
-            names : [[0u8; 0x0400];2]
+            names : [[0u8; 0x0400];2],
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            vram_addr: 0,
+            tram_addr: 0,
+            fine_x: 0,
+            address_latch: false,
+            data_buffer: 0,
+            cycle: 0,
+            scanline: 0,
+            frame_complete: false,
+            nmi: false,
+            frame_count: 0,
+            frame_buffer: vec![0u16; SCREEN_WIDTH * SCREEN_HEIGHT],
+            palette: Palette::default(),
+            region: Region::Ntsc,
+            bg: Background::default(),
+            sprites: Sprites::default(),
+        }
+    }
+
+    pub fn vblank(&self) -> bool {
+        self.status & PpuStatus::VerticalBlank as u8 != 0
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & (PpuMask::RenderBackground as u8 | PpuMask::RenderSprites as u8) != 0
+    }
+
+    /// Read one of the 8 registers seen by the cpu from $2000 to $2007
+    pub fn cpu_read(&mut self, addr: u16, read_only: bool, cartridge: &mut Cartridge) -> u8 {
+        match (addr & 0x0007, read_only) {
+            (0x0002, true) => self.status,
+            (0x0002, false) => {
+                let data = (self.status & 0xE0) | (self.data_buffer & 0x1F);
+                self.status &= !(PpuStatus::VerticalBlank as u8);
+                self.address_latch = false;
+                data
+            }
+            (0x0004, _) => self.oam[self.oam_addr as usize],
+            (0x0007, true) => self.data_buffer,
+            (0x0007, false) => {
+                let mut data = self.data_buffer;
+                self.data_buffer = self.ppu_read(self.vram_addr, cartridge);
+                // The pallette is not behind the buffer
+                if self.vram_addr >= 0x3F00 {
+                    data = self.data_buffer;
+                }
+                self.increment_vram_addr();
+                data
+            }
+            _ => 0u8,
+        }
+    }
+
+    /// Write one of the 8 registers seen by the cpu from $2000 to $2007
+    pub fn cpu_write(&mut self, addr: u16, data: u8, cartridge: &mut Cartridge) {
+        match addr & 0x0007 {
+            0x0000 => {
+                // Enabling the NMI during the vblank sends it right away
+                if data & PpuCtrl::EnableNmi as u8 != 0 && self.ctrl & PpuCtrl::EnableNmi as u8 == 0 && self.vblank() {
+                    self.nmi = true;
+                }
+                self.ctrl = data;
+                self.tram_addr = (self.tram_addr & !0x0C00) | ((data as u16 & 0x03) << 10);
+            }
+            0x0001 => self.mask = data,
+            0x0003 => self.oam_addr = data,
+            0x0004 => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x0005 => {
+                if !self.address_latch {
+                    self.fine_x = data & 0x07;
+                    self.tram_addr = (self.tram_addr & !0x001F) | (data as u16 >> 3);
+                } else {
+                    self.tram_addr = (self.tram_addr & !0x73E0)
+                        | ((data as u16 & 0x07) << 12)
+                        | ((data as u16 >> 3) << 5);
+                }
+                self.address_latch = !self.address_latch;
+            }
+            0x0006 => {
+                if !self.address_latch {
+                    self.tram_addr = (self.tram_addr & 0x00FF) | ((data as u16 & 0x3F) << 8);
+                } else {
+                    self.tram_addr = (self.tram_addr & 0xFF00) | data as u16;
+                    self.vram_addr = self.tram_addr;
+                }
+                self.address_latch = !self.address_latch;
+            }
+            0x0007 => {
+                self.ppu_write(self.vram_addr, data, cartridge);
+                self.increment_vram_addr();
+            }
+            _ => (),
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let step = match self.ctrl & PpuCtrl::IncrementMode as u8 != 0 {
+            true => 32,
+            false => 1,
+        };
+        self.vram_addr = self.vram_addr.wrapping_add(step) & 0x7FFF;
+    }
+
+    /// Read on the PPU bus, from 0x0000 to 0x3FFF, the mapper sees the fetches
+    pub fn ppu_read(&mut self, addr: u16, cartridge: &mut Cartridge) -> u8 {
+        let data = self.peek(addr, cartridge);
+        if addr & 0x3FFF < 0x3F00 {
+            cartridge.observe_ppu_read(addr & 0x3FFF);
+        }
+        data
+    }
+
+    /// Read on the PPU bus without side effects, for the debugger
+    pub fn peek(&self, addr: u16, cartridge: &Cartridge) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr.to_where() {
+            PPUComponents::PATTERN => cartridge.ppu_read(addr),
+            PPUComponents::NAMETABLES => match cartridge.nametable_read(addr) {
+                Some(data) => data,
+                None => {
+                    let (table, offset) = nametable_index(addr, cartridge.mirror());
+                    self.names[table][offset]
+                }
+            },
+            PPUComponents::PALLETTE => {
+                let mask = match self.mask & PpuMask::Grayscale as u8 != 0 {
+                    true => 0x30,
+                    false => 0x3F,
+                };
+                self.pallette[pallette_index(addr)] & mask
+            }
+        }
+    }
+
+    /// Write on the PPU bus, from 0x0000 to 0x3FFF
+    pub fn ppu_write(&mut self, addr: u16, data: u8, cartridge: &mut Cartridge) {
+        let addr = addr & 0x3FFF;
+        match addr.to_where() {
+            PPUComponents::PATTERN => cartridge.ppu_write(addr, data),
+            PPUComponents::NAMETABLES => {
+                if !cartridge.nametable_write(addr, data) {
+                    let (table, offset) = nametable_index(addr, cartridge.mirror());
+                    self.names[table][offset] = data;
+                }
+            }
+            PPUComponents::PALLETTE => self.pallette[pallette_index(addr)] = data,
+        }
+    }
+
+    /// Advance the PPU by one dot.
+    /// A NTSC frame is 341 dots on 262 scanlines, PAL and Dendy have 312 scanlines.
+    pub fn clock(&mut self, cartridge: &mut Cartridge) {
+        if self.scanline >= -1 && self.scanline < 240 {
+            self.render_dot(cartridge);
+        }
+
+        if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
+            self.status |= PpuStatus::VerticalBlank as u8;
+            if self.ctrl & PpuCtrl::EnableNmi as u8 != 0 {
+                self.nmi = true;
+            }
+        }
+
+        if self.cycle >= 1 && self.cycle <= 256 && self.scanline >= 0 && self.scanline < 240 {
+            let color = self.compose_pixel();
+            let x = self.cycle as usize - 1;
+            let y = self.scanline as usize;
+            self.frame_buffer[y * SCREEN_WIDTH + x] = color;
+        }
+
+        self.cycle += 1;
+        if self.cycle > 340 {
+            self.cycle = 0;
+            self.scanline += 1;
+            if self.scanline > self.region.last_scanline() {
+                self.scanline = -1;
+                self.frame_complete = true;
+                self.frame_count += 1;
+            }
+        }
+    }
+
+    /// Background fetches, scrolling and sprite evaluation of the visible and pre-render lines
+    fn render_dot(&mut self, cartridge: &mut Cartridge) {
+        // The first dot of the first line is skipped on odd frames, only on NTSC
+        if self.scanline == 0
+            && self.cycle == 0
+            && self.frame_count % 2 == 1
+            && self.rendering()
+            && self.region == Region::Ntsc
+        {
+            self.cycle = 1;
+        }
+
+        if self.scanline == -1 && self.cycle == 1 {
+            self.status &= !(PpuStatus::VerticalBlank as u8
+                | PpuStatus::SpriteZeroHit as u8
+                | PpuStatus::SpriteOverflow as u8);
+            self.sprites.shifter_lo = [0; 8];
+            self.sprites.shifter_hi = [0; 8];
+        }
+
+        if (self.cycle >= 2 && self.cycle < 258) || (self.cycle >= 321 && self.cycle < 338) {
+            self.update_shifters();
+            match (self.cycle - 1) % 8 {
+                0 => {
+                    self.load_background_shifters();
+                    self.bg.next_tile_id = self.ppu_read(0x2000 | (self.vram_addr & 0x0FFF), cartridge);
+                }
+                2 => {
+                    let v = self.vram_addr;
+                    let attrib_addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    let mut attrib = self.ppu_read(attrib_addr, cartridge);
+                    if (v >> 5) & 0x02 != 0 {
+                        attrib >>= 4;
+                    }
+                    if v & 0x02 != 0 {
+                        attrib >>= 2;
+                    }
+                    self.bg.next_tile_attrib = attrib & 0x03;
+                }
+                4 => {
+                    let addr = self.background_tile_addr();
+                    self.bg.next_tile_lsb = self.ppu_read(addr, cartridge);
+                }
+                6 => {
+                    let addr = self.background_tile_addr() + 8;
+                    self.bg.next_tile_msb = self.ppu_read(addr, cartridge);
+                }
+                7 => self.increment_scroll_x(),
+                _ => (),
+            }
+        }
+
+        if self.cycle == 256 {
+            self.increment_scroll_y();
+        }
+        if self.cycle == 257 {
+            self.load_background_shifters();
+            self.transfer_address_x();
+        }
+        // Dummy nametable fetches at the end of the line
+        if self.cycle == 338 || self.cycle == 340 {
+            self.bg.next_tile_id = self.ppu_read(0x2000 | (self.vram_addr & 0x0FFF), cartridge);
+        }
+        if self.scanline == -1 && self.cycle >= 280 && self.cycle < 305 {
+            self.transfer_address_y();
+        }
+
+        if self.cycle == 257 && self.scanline >= 0 {
+            self.evaluate_sprites();
+        }
+        // Done at the end of the sprite fetches, before the first tiles of the next line
+        if self.cycle == 320 {
+            self.fetch_sprites(cartridge);
+        }
+    }
+
+    fn background_tile_addr(&self) -> u16 {
+        let table = (self.ctrl & PpuCtrl::PatternBackground as u8 != 0) as u16;
+        (table << 12) + ((self.bg.next_tile_id as u16) << 4) + ((self.vram_addr >> 12) & 0x07)
+    }
+
+    fn increment_scroll_x(&mut self) {
+        if !self.rendering() {
+            return;
+        }
+        if self.vram_addr & 0x001F == 31 {
+            self.vram_addr &= !0x001F;
+            self.vram_addr ^= 0x0400;
+        } else {
+            self.vram_addr += 1;
+        }
+    }
+
+    fn increment_scroll_y(&mut self) {
+        if !self.rendering() {
+            return;
+        }
+        if self.vram_addr & 0x7000 != 0x7000 {
+            self.vram_addr += 0x1000;
+            return;
+        }
+        self.vram_addr &= !0x7000;
+        let mut coarse_y = (self.vram_addr >> 5) & 0x1F;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.vram_addr ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.vram_addr = (self.vram_addr & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn transfer_address_x(&mut self) {
+        if self.rendering() {
+            self.vram_addr = (self.vram_addr & !0x041F) | (self.tram_addr & 0x041F);
+        }
+    }
+
+    fn transfer_address_y(&mut self) {
+        if self.rendering() {
+            self.vram_addr = (self.vram_addr & !0x7BE0) | (self.tram_addr & 0x7BE0);
+        }
+    }
+
+    fn load_background_shifters(&mut self) {
+        let bg = &mut self.bg;
+        bg.shifter_pattern_lo = (bg.shifter_pattern_lo & 0xFF00) | bg.next_tile_lsb as u16;
+        bg.shifter_pattern_hi = (bg.shifter_pattern_hi & 0xFF00) | bg.next_tile_msb as u16;
+        let attrib_lo = if bg.next_tile_attrib & 0x01 != 0 { 0xFF } else { 0x00 };
+        let attrib_hi = if bg.next_tile_attrib & 0x02 != 0 { 0xFF } else { 0x00 };
+        bg.shifter_attrib_lo = (bg.shifter_attrib_lo & 0xFF00) | attrib_lo;
+        bg.shifter_attrib_hi = (bg.shifter_attrib_hi & 0xFF00) | attrib_hi;
+    }
+
+    fn update_shifters(&mut self) {
+        if self.mask & PpuMask::RenderBackground as u8 != 0 {
+            self.bg.shifter_pattern_lo <<= 1;
+            self.bg.shifter_pattern_hi <<= 1;
+            self.bg.shifter_attrib_lo <<= 1;
+            self.bg.shifter_attrib_hi <<= 1;
+        }
+        if self.mask & PpuMask::RenderSprites as u8 != 0 && self.cycle >= 1 && self.cycle < 258 {
+            for i in 0..self.sprites.count {
+                let x = &mut self.sprites.scanline[i * 4 + 3];
+                if *x > 0 {
+                    *x -= 1;
+                } else {
+                    self.sprites.shifter_lo[i] <<= 1;
+                    self.sprites.shifter_hi[i] <<= 1;
+                }
+            }
+        }
+    }
+
+    fn sprite_height(&self) -> i16 {
+        match self.ctrl & PpuCtrl::SpriteSize as u8 != 0 {
+            true => 16,
+            false => 8,
+        }
+    }
+
+    /// Find the first 8 sprites of OAM on the next scanline
+    fn evaluate_sprites(&mut self) {
+        self.sprites.scanline = [0xFF; 32];
+        self.sprites.count = 0;
+        self.sprites.shifter_lo = [0; 8];
+        self.sprites.shifter_hi = [0; 8];
+        self.sprites.zero_hit_possible = false;
+
+        let height = self.sprite_height();
+        for entry in 0..64 {
+            let diff = self.scanline - self.oam[entry * 4] as i16;
+            if diff < 0 || diff >= height {
+                continue;
+            }
+            if self.sprites.count == 8 {
+                self.status |= PpuStatus::SpriteOverflow as u8;
+                break;
+            }
+            if entry == 0 {
+                self.sprites.zero_hit_possible = true;
+            }
+            let slot = self.sprites.count * 4;
+            self.sprites.scanline[slot..slot + 4].copy_from_slice(&self.oam[entry * 4..entry * 4 + 4]);
+            self.sprites.count += 1;
+        }
+    }
+
+    /// Load the pattern shifters of the sprites found for the next scanline
+    fn fetch_sprites(&mut self, cartridge: &mut Cartridge) {
+        let height = self.sprite_height();
+        for i in 0..self.sprites.count {
+            let y = self.sprites.scanline[i * 4] as i16;
+            let tile = self.sprites.scanline[i * 4 + 1] as u16;
+            let attrib = self.sprites.scanline[i * 4 + 2];
+            let mut row = (self.scanline - y) as u16;
+            if attrib & 0x80 != 0 {
+                row = (height as u16 - 1) - row;
+            }
+            let addr = match height {
+                8 => {
+                    let table = (self.ctrl & PpuCtrl::PatternSprite as u8 != 0) as u16;
+                    (table << 12) | (tile << 4) | row
+                }
+                _ => {
+                    let table = tile & 0x01;
+                    let tile = (tile & 0xFE) + (row >> 3);
+                    (table << 12) | (tile << 4) | (row & 0x07)
+                }
+            };
+            let mut lo = self.ppu_read(addr, cartridge);
+            let mut hi = self.ppu_read(addr + 8, cartridge);
+            if attrib & 0x40 != 0 {
+                lo = lo.reverse_bits();
+                hi = hi.reverse_bits();
+            }
+            self.sprites.shifter_lo[i] = lo;
+            self.sprites.shifter_hi[i] = hi;
+        }
+        // The empty slots fetch the tile $FF, the mappers watching the fetches see them
+        for _ in self.sprites.count..8 {
+            let addr = match height {
+                8 => ((self.ctrl & PpuCtrl::PatternSprite as u8 != 0) as u16) << 12 | 0x0FF0,
+                _ => 0x1FE0,
+            };
+            self.ppu_read(addr, cartridge);
+            self.ppu_read(addr + 8, cartridge);
+        }
+    }
+
+    /// Mix the background and the sprites of the current dot into a pallette color
+    fn compose_pixel(&mut self) -> u16 {
+        let x = self.cycle - 1;
+        let mut bg_pixel = 0u8;
+        let mut bg_pallette = 0u8;
+        let show_bg_left = self.mask & PpuMask::RenderBackgroundLeft as u8 != 0;
+        if self.mask & PpuMask::RenderBackground as u8 != 0 && (x >= 8 || show_bg_left) {
+            let bit = 0x8000 >> self.fine_x;
+            bg_pixel = ((self.bg.shifter_pattern_hi & bit != 0) as u8) << 1
+                | (self.bg.shifter_pattern_lo & bit != 0) as u8;
+            bg_pallette = ((self.bg.shifter_attrib_hi & bit != 0) as u8) << 1
+                | (self.bg.shifter_attrib_lo & bit != 0) as u8;
         }
+
+        let mut fg_pixel = 0u8;
+        let mut fg_pallette = 0u8;
+        let mut fg_priority = false;
+        let show_fg_left = self.mask & PpuMask::RenderSpritesLeft as u8 != 0;
+        self.sprites.zero_being_rendered = false;
+        if self.mask & PpuMask::RenderSprites as u8 != 0 && (x >= 8 || show_fg_left) {
+            for i in 0..self.sprites.count {
+                if self.sprites.scanline[i * 4 + 3] != 0 {
+                    continue;
+                }
+                let pixel = ((self.sprites.shifter_hi[i] & 0x80 != 0) as u8) << 1
+                    | (self.sprites.shifter_lo[i] & 0x80 != 0) as u8;
+                if pixel != 0 {
+                    let attrib = self.sprites.scanline[i * 4 + 2];
+                    fg_pixel = pixel;
+                    fg_pallette = (attrib & 0x03) + 0x04;
+                    fg_priority = attrib & 0x20 == 0;
+                    self.sprites.zero_being_rendered = i == 0;
+                    break;
+                }
+            }
+        }
+
+        let (pixel, pallette) = match (bg_pixel, fg_pixel) {
+            (0, 0) => (0, 0),
+            (0, _) => (fg_pixel, fg_pallette),
+            (_, 0) => (bg_pixel, bg_pallette),
+            _ => {
+                if self.sprites.zero_hit_possible && self.sprites.zero_being_rendered && x != 255 {
+                    self.status |= PpuStatus::SpriteZeroHit as u8;
+                }
+                match fg_priority {
+                    true => (fg_pixel, fg_pallette),
+                    false => (bg_pixel, bg_pallette),
+                }
+            }
+        };
+
+        let mut color = self.pallette[pallette_index(0x3F00 + ((pallette as u16) << 2) + pixel as u16)];
+        if self.mask & PpuMask::Grayscale as u8 != 0 {
+            color &= 0x30;
+        }
+        // The PAL and Dendy PPUs swap the red and green emphasis bits, the frame buffer
+        // keeps the order of NTSC
+        let mut emphasis = self.mask as u16 >> 5;
+        if self.region != Region::Ntsc {
+            emphasis = (emphasis & 0x04) | ((emphasis & 0x01) << 1) | ((emphasis & 0x02) >> 1);
+        }
+        (color as u16 & 0x3F) | (emphasis << 6)
+    }
+}
+
+impl PPU {
+    /// Shifters and latches of the frame being drawn, part of the save states
+    pub fn save_rendering(&self, state: &mut StateWriter) {
+        let bg = &self.bg;
+        state.bytes(&[bg.next_tile_id, bg.next_tile_attrib, bg.next_tile_lsb, bg.next_tile_msb]);
+        for x in [bg.shifter_pattern_lo, bg.shifter_pattern_hi, bg.shifter_attrib_lo, bg.shifter_attrib_hi].iter() {
+            state.u16(*x);
+        }
+        let sprites = &self.sprites;
+        state.bytes(&sprites.scanline);
+        state.u8(sprites.count as u8);
+        state.bytes(&sprites.shifter_lo);
+        state.bytes(&sprites.shifter_hi);
+        state.bool(sprites.zero_hit_possible);
+        state.bool(sprites.zero_being_rendered);
+    }
+    pub fn load_rendering(&mut self, state: &mut StateReader) -> io::Result<()> {
+        let bg = &mut self.bg;
+        bg.next_tile_id = state.u8()?;
+        bg.next_tile_attrib = state.u8()?;
+        bg.next_tile_lsb = state.u8()?;
+        bg.next_tile_msb = state.u8()?;
+        bg.shifter_pattern_lo = state.u16()?;
+        bg.shifter_pattern_hi = state.u16()?;
+        bg.shifter_attrib_lo = state.u16()?;
+        bg.shifter_attrib_hi = state.u16()?;
+        let sprites = &mut self.sprites;
+        state.fill(&mut sprites.scanline)?;
+        sprites.count = (state.u8()? as usize).min(8);
+        state.fill(&mut sprites.shifter_lo)?;
+        state.fill(&mut sprites.shifter_hi)?;
+        sprites.zero_hit_possible = state.bool()?;
+        sprites.zero_being_rendered = state.bool()?;
+        Ok(())
+    }
+}
+
+/// Table and offset of a nametable address once the mirroring is applied
+fn nametable_index(addr: u16, mirror: Mirror) -> (usize, usize) {
+    let table = ((addr >> 10) & 0x03) as usize;
+    let offset = (addr & 0x03FF) as usize;
+    match mirror {
+        Mirror::Vertical => (table & 0x01, offset),
+        Mirror::Horizontal => (table >> 1, offset),
+        Mirror::OneScreenLo => (0, offset),
+        Mirror::OneScreenHi => (1, offset),
+        Mirror::Pages(pages) => (pages[table] as usize & 0x01, offset),
+    }
+}
+
+/// Index in the pallette ram, the backdrop colors of the sprites are mirrors of the background ones
+fn pallette_index(addr: u16) -> usize {
+    let index = (addr & 0x001F) as usize;
+    match index {
+        0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+        _ => index,
     }
 }
 
@@ -31,9 +671,3 @@ impl Default for PPU {
         Self::new()
     }
 }
-
-pub enum PPUComponents {
-    NAMETABLES,
-    PATTERN,
-    PALLETTE,
-}
\ No newline at end of file
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use super::Header;
+
+/// Television standard of the console, it sets the clocks and the length of the frames
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclones sold in Russia: the frames of PAL with the cpu and APU timings of NTSC
+    Dendy,
+}
+
+/// Names accepted by `Region::parse`
+pub const REGION_NAMES: [&str; 3] = ["ntsc", "pal", "dendy"];
+
+impl Region {
+    /// TV system given by the header: the timing byte of NES 2.0, or the flags 9 and 10
+    /// of iNES. Multi-region games run as NTSC.
+    pub fn from_header(header: &Header) -> Region {
+        if header.is_nes2() {
+            return match header.padding[1] & 0x03 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            };
+        }
+        match header.flags_9 & 0x01 != 0 || header.flags_10 & 0x03 == 0x02 {
+            true => Region::Pal,
+            false => Region::Ntsc,
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Region, String> {
+        match name.to_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!(
+                "unknown region '{}', expected one of {}",
+                name,
+                REGION_NAMES.join(", ")
+            )),
+        }
+    }
+
+    /// Cpu clock in Hz
+    pub fn cpu_frequency(&self) -> u64 {
+        match self {
+            Region::Ntsc => 1_789_773,
+            Region::Pal => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    /// Whether the cpu is clocked on a PPU dot: every 3 dots, or 5 times every 16 dots on PAL
+    pub fn cpu_clocked(&self, dot: u128) -> bool {
+        match self {
+            Region::Pal => dot * 5 % 16 < 5,
+            _ => dot.is_multiple_of(3),
+        }
+    }
+
+    /// Last scanline of the frame, after the vblank. The pre-render line is -1.
+    pub fn last_scanline(&self) -> i16 {
+        match self {
+            Region::Ntsc => 260,
+            _ => 310,
+        }
+    }
+
+    /// Scanline starting the vblank, Dendy waits 50 lines after the picture to keep the
+    /// vblank as long as on NTSC
+    pub fn vblank_scanline(&self) -> i16 {
+        match self {
+            Region::Dendy => 291,
+            _ => 241,
+        }
+    }
+
+    /// Duration of a frame in real time
+    pub fn frame_duration(&self) -> Duration {
+        match self {
+            Region::Ntsc => Duration::from_nanos(16_639_267),
+            Region::Pal => Duration::from_nanos(19_997_209),
+            Region::Dendy => Duration::from_nanos(19_997_203),
+        }
+    }
+}
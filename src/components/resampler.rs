@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// Output samples touched by a change of amplitude
+const KERNEL_WIDTH: usize = 16;
+/// Positions of a change between two output samples with their own kernel
+const KERNEL_PHASES: usize = 64;
+/// Cutoff of the kernel, relative to the output sample rate
+const KERNEL_CUTOFF: f64 = 0.45;
+/// The rate can only be adjusted by this much, to avoid audible pitch changes
+const MAX_RATE_ADJUSTMENT: f64 = 0.05;
+
+/// Band-limited step synthesis, in the style of blip_buf.
+///
+/// The input is an amplitude sampled at the clock rate, only its changes matter: each
+/// one adds a band-limited step to the output, which is then sampled at `sample_rate`.
+/// The output goes through the filters of the console, high-passes at 90Hz and
+/// 440Hz and a low-pass at 14kHz.
+pub struct Resampler {
+    pub clock_rate: u64,
+    pub sample_rate: u32,
+    /// Multiplies the output rate, frontends syncing to the audio use it to keep their
+    /// buffer half full. See `adjust_rate`.
+    pub rate_adjustment: f64,
+    /// Output samples per clock
+    step: f64,
+    /// Position of the current clock, in output samples since the start of `deltas`
+    pub(super) time: f64,
+    /// Derivative of the output, the first entry is the next output sample
+    pub(super) deltas: VecDeque<f32>,
+    /// Last input amplitude
+    pub(super) amplitude: f32,
+    /// Sum of the deltas already output
+    pub(super) level: f32,
+    /// `KERNEL_PHASES` kernels of `KERNEL_WIDTH` taps, each summing to 1
+    kernels: Vec<[f32; KERNEL_WIDTH]>,
+    pub(super) filters: [Filter; 3],
+}
+
+/// First order filter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub cutoff: f64,
+    coefficient: f32,
+    pub(super) previous_input: f32,
+    pub(super) previous_output: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, cutoff: f64, sample_rate: u32) -> Filter {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f64;
+        let coefficient = match kind {
+            FilterKind::HighPass => rc / (rc + dt),
+            FilterKind::LowPass => dt / (rc + dt),
+        };
+        Filter {
+            kind,
+            cutoff,
+            coefficient: coefficient as f32,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    pub fn apply(&mut self, input: f32) -> f32 {
+        let output = match self.kind {
+            FilterKind::HighPass => {
+                self.coefficient * (self.previous_output + input - self.previous_input)
+            }
+            FilterKind::LowPass => {
+                self.previous_output + self.coefficient * (input - self.previous_output)
+            }
+        };
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+impl Resampler {
+    pub fn new(clock_rate: u64, sample_rate: u32) -> Resampler {
+        let mut kernels = vec![[0f32; KERNEL_WIDTH]; KERNEL_PHASES];
+        for (phase, kernel) in kernels.iter_mut().enumerate() {
+            // Windowed sinc centered between the taps `KERNEL_WIDTH / 2 - 1` and
+            // `KERNEL_WIDTH / 2`, shifted by the phase
+            let offset = phase as f64 / KERNEL_PHASES as f64;
+            let mut taps = [0f64; KERNEL_WIDTH];
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let x = i as f64 - (KERNEL_WIDTH / 2) as f64 + 1.0 - offset;
+                let sinc = match x == 0.0 {
+                    true => 1.0,
+                    false => (2.0 * PI * KERNEL_CUTOFF * x).sin() / (2.0 * PI * KERNEL_CUTOFF * x),
+                };
+                let window = 0.5 + 0.5 * (PI * x / (KERNEL_WIDTH / 2) as f64).cos();
+                *tap = sinc * window.max(0.0);
+            }
+            let sum: f64 = taps.iter().sum();
+            for (value, tap) in kernel.iter_mut().zip(taps.iter()) {
+                *value = (tap / sum) as f32;
+            }
+        }
+        let mut result = Resampler {
+            clock_rate,
+            sample_rate,
+            rate_adjustment: 1.0,
+            step: 0.0,
+            time: 0.0,
+            deltas: VecDeque::from(vec![0f32; KERNEL_WIDTH + 2]),
+            amplitude: 0.0,
+            level: 0.0,
+            kernels,
+            filters: console_filters(sample_rate),
+        };
+        result.update_step();
+        result
+    }
+
+    /// Change the output rate, the filters are restarted
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.filters = console_filters(sample_rate);
+        self.update_step();
+    }
+
+    /// Rate of the input, set when the console region changes
+    pub fn set_clock_rate(&mut self, clock_rate: u64) {
+        self.clock_rate = clock_rate;
+        self.update_step();
+    }
+
+    /// Dynamic rate control: `ratio` above 1 produces more samples for the same
+    /// emulated time. It is limited to `MAX_RATE_ADJUSTMENT` around 1.
+    pub fn adjust_rate(&mut self, ratio: f64) {
+        self.rate_adjustment = ratio.clamp(1.0 - MAX_RATE_ADJUSTMENT, 1.0 + MAX_RATE_ADJUSTMENT);
+        self.update_step();
+    }
+
+    fn update_step(&mut self) {
+        self.step = self.sample_rate as f64 * self.rate_adjustment / self.clock_rate as f64;
+    }
+
+    /// Advance by one clock with the given input amplitude, completed samples are
+    /// pushed to `output`
+    pub fn clock(&mut self, amplitude: f32, output: &mut Vec<f32>) {
+        if amplitude != self.amplitude {
+            self.add_delta(amplitude - self.amplitude);
+            self.amplitude = amplitude;
+        }
+        self.time += self.step;
+        while self.time >= 1.0 {
+            self.time -= 1.0;
+            self.level += self.deltas.pop_front().unwrap_or(0.0);
+            self.deltas.push_back(0.0);
+            let mut sample = self.level;
+            for filter in self.filters.iter_mut() {
+                sample = filter.apply(sample);
+            }
+            output.push(sample);
+        }
+    }
+
+    fn add_delta(&mut self, delta: f32) {
+        let phase = ((self.time * KERNEL_PHASES as f64) as usize).min(KERNEL_PHASES - 1);
+        for (i, tap) in self.kernels[phase].iter().enumerate() {
+            self.deltas[i] += delta * tap;
+        }
+    }
+}
+
+/// Analog filters between the APU and the audio output of the console
+fn console_filters(sample_rate: u32) -> [Filter; 3] {
+    [
+        Filter::new(FilterKind::HighPass, 90.0, sample_rate),
+        Filter::new(FilterKind::HighPass, 440.0, sample_rate),
+        Filter::new(FilterKind::LowPass, 14000.0, sample_rate),
+    ]
+}
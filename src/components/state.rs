@@ -0,0 +1,1008 @@
+use std::io;
+
+use super::{
+    Cartridge, Controller, DiskSystem, Envelope, EnvelopeStage, ExpansionAudio, FdsAudio,
+    FdsEnvelope, Fme7, Mirror, Mmc5, Mmc5Audio, Namco163, NesData, Operator, Pulse, Resampler,
+    Region, Sunsoft5b, Vrc, Vrc6, Vrc7, APU, CPU6502, N163, PPU,
+};
+
+/// Magic bytes at the start of every save state
+const STATE_MAGIC: &[u8; 4] = b"RNSS";
+/// Bumped every time the layout of a component changes
+const STATE_VERSION: u8 = 12;
+
+/// Snapshot of the whole console, used by the movies and the frontends.
+/// The cartridge rom itself is not saved, only what the program can modify.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveState {
+    pub data: Vec<u8>,
+}
+
+/// Components that can be written to and restored from a save state.
+/// Fields must be read back in the same order they are written.
+pub trait Snapshot {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()>;
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl SaveState {
+    pub fn capture(cpu: &CPU6502, nes: &NesData) -> SaveState {
+        let mut state = StateWriter { data: Vec::new() };
+        state.bytes(STATE_MAGIC);
+        state.u8(STATE_VERSION);
+        cpu.save_state(&mut state);
+        nes.save_state(&mut state);
+        SaveState { data: state.data }
+    }
+
+    /// Restore the console, nothing is modified if the state is invalid
+    pub fn restore(&self, cpu: &mut CPU6502, nes: &mut NesData) -> io::Result<()> {
+        let backup = SaveState::capture(cpu, nes);
+        match self.load_into(cpu, nes) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                backup
+                    .load_into(cpu, nes)
+                    .expect("failed to roll back to the previous state");
+                Err(e)
+            }
+        }
+    }
+
+    fn load_into(&self, cpu: &mut CPU6502, nes: &mut NesData) -> io::Result<()> {
+        let mut state = StateReader { data: &self.data, position: 0 };
+        if state.bytes(4)? != STATE_MAGIC {
+            return Err(invalid_state("not a save state"));
+        }
+        if state.u8()? != STATE_VERSION {
+            return Err(invalid_state("unsupported save state version"));
+        }
+        cpu.load_state(&mut state)?;
+        nes.load_state(&mut state)
+    }
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+    pub fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+    pub fn u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.data.extend_from_slice(value);
+    }
+}
+
+impl<'a> StateReader<'a> {
+    pub fn bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        match self.data.get(self.position..self.position + length) {
+            Some(x) => {
+                self.position += length;
+                Ok(x)
+            }
+            None => Err(invalid_state("save state is truncated")),
+        }
+    }
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+    pub fn u16(&mut self) -> io::Result<u16> {
+        let mut x = [0u8; 2];
+        x.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(x))
+    }
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let mut x = [0u8; 8];
+        x.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(x))
+    }
+    pub fn f32(&mut self) -> io::Result<f32> {
+        let mut x = [0u8; 4];
+        x.copy_from_slice(self.bytes(4)?);
+        Ok(f32::from_bits(u32::from_le_bytes(x)))
+    }
+    pub fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+    pub fn u128(&mut self) -> io::Result<u128> {
+        let mut x = [0u8; 16];
+        x.copy_from_slice(self.bytes(16)?);
+        Ok(u128::from_le_bytes(x))
+    }
+    /// Fill a buffer whose size is known by the component
+    pub fn fill(&mut self, target: &mut [u8]) -> io::Result<()> {
+        target.copy_from_slice(self.bytes(target.len())?);
+        Ok(())
+    }
+}
+
+fn invalid_state(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Snapshot for CPU6502 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.a);
+        state.u8(self.x);
+        state.u8(self.y);
+        state.u8(self.stkp);
+        state.u16(self.pc);
+        state.u8(self.status);
+        state.u8(self.fetched_data);
+        state.u16(self.addr_abs);
+        state.u16(self.addr_rel);
+        state.u8(self.curr_opcode);
+        state.u8(self.cycles);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.a = state.u8()?;
+        self.x = state.u8()?;
+        self.y = state.u8()?;
+        self.stkp = state.u8()?;
+        self.pc = state.u16()?;
+        self.status = state.u8()?;
+        self.fetched_data = state.u8()?;
+        self.addr_abs = state.u16()?;
+        self.addr_rel = state.u16()?;
+        self.curr_opcode = state.u8()?;
+        self.cycles = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for PPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.names[0]);
+        state.bytes(&self.names[1]);
+        state.bytes(&self.pallette);
+        state.bytes(&self.oam);
+        state.bytes(&[self.ctrl, self.mask, self.status, self.oam_addr, self.fine_x, self.data_buffer]);
+        state.bool(self.address_latch);
+        state.u16(self.vram_addr);
+        state.u16(self.tram_addr);
+        state.u16(self.cycle);
+        state.u16(self.scanline as u16);
+        state.bool(self.frame_complete);
+        state.bool(self.nmi);
+        state.u64(self.frame_count);
+        self.save_rendering(state);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.names[0])?;
+        state.fill(&mut self.names[1])?;
+        state.fill(&mut self.pallette)?;
+        state.fill(&mut self.oam)?;
+        self.ctrl = state.u8()?;
+        self.mask = state.u8()?;
+        self.status = state.u8()?;
+        self.oam_addr = state.u8()?;
+        self.fine_x = state.u8()?;
+        self.data_buffer = state.u8()?;
+        self.address_latch = state.bool()?;
+        self.vram_addr = state.u16()?;
+        self.tram_addr = state.u16()?;
+        self.cycle = state.u16()?;
+        self.scanline = state.u16()? as i16;
+        self.frame_complete = state.bool()?;
+        self.nmi = state.bool()?;
+        self.frame_count = state.u64()?;
+        self.load_rendering(state)
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.start);
+        state.bool(self.looping);
+        state.bool(self.constant);
+        state.bytes(&[self.volume, self.divider, self.decay]);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.start = state.bool()?;
+        self.looping = state.bool()?;
+        self.constant = state.bool()?;
+        self.volume = state.u8()?;
+        self.divider = state.u8()?;
+        self.decay = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Pulse {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.bytes(&[self.duty, self.duty_step, self.length]);
+        state.u16(self.timer_period);
+        state.u16(self.timer);
+        self.envelope.save_state(state);
+        state.bool(self.sweep_enabled);
+        state.bool(self.sweep_negate);
+        state.bool(self.sweep_reload);
+        state.bytes(&[self.sweep_period, self.sweep_shift, self.sweep_divider]);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.enabled = state.bool()?;
+        self.duty = state.u8()?;
+        self.duty_step = state.u8()?;
+        self.length = state.u8()?;
+        self.timer_period = state.u16()?;
+        self.timer = state.u16()?;
+        self.envelope.load_state(state)?;
+        self.sweep_enabled = state.bool()?;
+        self.sweep_negate = state.bool()?;
+        self.sweep_reload = state.bool()?;
+        self.sweep_period = state.u8()?;
+        self.sweep_shift = state.u8()?;
+        self.sweep_divider = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for APU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.registers);
+        self.pulse[0].save_state(state);
+        self.pulse[1].save_state(state);
+
+        let t = &self.triangle;
+        state.bool(t.enabled);
+        state.bool(t.control);
+        state.bool(t.linear_reload);
+        state.bytes(&[t.linear_period, t.linear_counter, t.length, t.step]);
+        state.u16(t.timer_period);
+        state.u16(t.timer);
+
+        let n = &self.noise;
+        state.bool(n.enabled);
+        state.bool(n.mode);
+        state.u8(n.length);
+        state.u16(n.timer_period);
+        state.u16(n.timer);
+        state.u16(n.shift);
+        n.envelope.save_state(state);
+
+        let d = &self.dmc;
+        state.bool(d.enabled);
+        state.bool(d.irq_enabled);
+        state.bool(d.irq);
+        state.bool(d.looping);
+        state.bool(d.silence);
+        state.bool(d.buffer.is_some());
+        state.bytes(&[d.buffer.unwrap_or(0), d.output, d.shift, d.bits_remaining]);
+        for x in [d.timer_period, d.timer, d.sample_addr, d.sample_length, d.current_addr, d.bytes_remaining].iter() {
+            state.u16(*x);
+        }
+
+        state.u64(self.frame_cycle as u64);
+        state.bool(self.five_steps);
+        state.bool(self.irq_inhibit);
+        state.bool(self.frame_irq);
+        state.u64(self.cycle);
+        self.resampler.save_state(state);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.registers)?;
+        self.pulse[0].load_state(state)?;
+        self.pulse[1].load_state(state)?;
+
+        let t = &mut self.triangle;
+        t.enabled = state.bool()?;
+        t.control = state.bool()?;
+        t.linear_reload = state.bool()?;
+        t.linear_period = state.u8()?;
+        t.linear_counter = state.u8()?;
+        t.length = state.u8()?;
+        t.step = state.u8()?;
+        t.timer_period = state.u16()?;
+        t.timer = state.u16()?;
+
+        let n = &mut self.noise;
+        n.enabled = state.bool()?;
+        n.mode = state.bool()?;
+        n.length = state.u8()?;
+        n.timer_period = state.u16()?;
+        n.timer = state.u16()?;
+        n.shift = state.u16()?;
+        n.envelope.load_state(state)?;
+
+        let d = &mut self.dmc;
+        d.enabled = state.bool()?;
+        d.irq_enabled = state.bool()?;
+        d.irq = state.bool()?;
+        d.looping = state.bool()?;
+        d.silence = state.bool()?;
+        let has_buffer = state.bool()?;
+        let buffer = state.u8()?;
+        d.buffer = if has_buffer { Some(buffer) } else { None };
+        d.output = state.u8()?;
+        d.shift = state.u8()?;
+        d.bits_remaining = state.u8()?;
+        d.timer_period = state.u16()?;
+        d.timer = state.u16()?;
+        d.sample_addr = state.u16()?;
+        d.sample_length = state.u16()?;
+        d.current_addr = state.u16()?;
+        d.bytes_remaining = state.u16()?;
+
+        self.frame_cycle = state.u64()? as u32;
+        self.five_steps = state.bool()?;
+        self.irq_inhibit = state.bool()?;
+        self.frame_irq = state.bool()?;
+        self.cycle = state.u64()?;
+        self.resampler.load_state(state)
+    }
+}
+
+/// The rates are settings of the frontend, only the signal in flight is saved
+impl Snapshot for Resampler {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.f64(self.time);
+        state.u16(self.deltas.len() as u16);
+        for delta in self.deltas.iter() {
+            state.f32(*delta);
+        }
+        state.f32(self.amplitude);
+        state.f32(self.level);
+        for filter in self.filters.iter() {
+            state.f32(filter.previous_input);
+            state.f32(filter.previous_output);
+        }
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.time = state.f64()?;
+        let length = state.u16()? as usize;
+        self.deltas.clear();
+        for _ in 0..length {
+            self.deltas.push_back(state.f32()?);
+        }
+        self.amplitude = state.f32()?;
+        self.level = state.f32()?;
+        for filter in self.filters.iter_mut() {
+            filter.previous_input = state.f32()?;
+            filter.previous_output = state.f32()?;
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for Controller {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.buttons);
+        state.u8(self.shift);
+        state.bool(self.strobe);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.buttons = state.u8()?;
+        self.shift = state.u8()?;
+        self.strobe = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Cartridge {
+    fn save_state(&self, state: &mut StateWriter) {
+        // The sizes tell apart the cartridges, only the RAM parts are written
+        state.u64(self.prg_memory.len() as u64);
+        state.bytes(&self.prg_memory[..self.mapper.prg_memory_ram()]);
+        state.u64(self.chr_memory.len() as u64);
+        if self.mapper.chr_ram() {
+            state.bytes(&self.chr_memory);
+        }
+        state.bytes(&self.prg_ram);
+        state.bytes(&self.mapper.banks);
+        if let Some(mmc5) = &self.mapper.mmc5 {
+            mmc5.save_state(state);
+        }
+        if let Some(vrc) = &self.mapper.vrc {
+            vrc.save_state(state);
+        }
+        if let Some(fme7) = &self.mapper.fme7 {
+            fme7.save_state(state);
+        }
+        if let Some(chip) = &self.mapper.namco163 {
+            chip.save_state(state);
+        }
+        if let Some(disk) = &self.disk {
+            disk.save_state(state);
+        }
+        self.audio.save_state(state);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        if state.u64()? as usize != self.prg_memory.len() {
+            return Err(invalid_state("save state was made with another cartridge"));
+        }
+        let ram = self.mapper.prg_memory_ram();
+        state.fill(&mut self.prg_memory[..ram])?;
+        if state.u64()? as usize != self.chr_memory.len() {
+            return Err(invalid_state("save state was made with another cartridge"));
+        }
+        if self.mapper.chr_ram() {
+            state.fill(&mut self.chr_memory)?;
+        }
+        state.fill(&mut self.prg_ram)?;
+        state.fill(&mut self.mapper.banks)?;
+        if let Some(mmc5) = self.mapper.mmc5.as_mut() {
+            mmc5.load_state(state)?;
+        }
+        if let Some(vrc) = self.mapper.vrc.as_mut() {
+            vrc.load_state(state)?;
+        }
+        if let Some(fme7) = self.mapper.fme7.as_mut() {
+            fme7.load_state(state)?;
+        }
+        if let Some(chip) = self.mapper.namco163.as_mut() {
+            chip.load_state(state)?;
+        }
+        if let Some(disk) = self.disk.as_mut() {
+            disk.load_state(state)?;
+        }
+        self.audio.load_state(state)
+    }
+}
+
+impl Snapshot for Vrc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.prg_banks);
+        for bank in self.chr_banks.iter() {
+            state.u16(*bank);
+        }
+        state.bytes(&[self.mirroring, self.ppu_mode]);
+        state.bool(self.prg_swap);
+        state.bytes(&[self.irq.latch, self.irq.counter]);
+        state.u16(self.irq.prescaler as u16);
+        state.bool(self.irq.enabled);
+        state.bool(self.irq.enable_after_ack);
+        state.bool(self.irq.cycle_mode);
+        state.bool(self.irq.pending);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.prg_banks)?;
+        for bank in self.chr_banks.iter_mut() {
+            *bank = state.u16()?;
+        }
+        let modes = state.bytes(2)?;
+        self.mirroring = modes[0];
+        self.ppu_mode = modes[1];
+        self.prg_swap = state.bool()?;
+        let counter = state.bytes(2)?;
+        self.irq.latch = counter[0];
+        self.irq.counter = counter[1];
+        self.irq.prescaler = state.u16()? as i16;
+        self.irq.enabled = state.bool()?;
+        self.irq.enable_after_ack = state.bool()?;
+        self.irq.cycle_mode = state.bool()?;
+        self.irq.pending = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Fme7 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.command);
+        state.bytes(&self.chr_banks);
+        state.u8(self.bank_6000);
+        state.bytes(&self.prg_banks);
+        state.u8(self.mirroring);
+        state.bool(self.irq_enabled);
+        state.bool(self.counter_enabled);
+        state.u16(self.counter);
+        state.bool(self.irq_pending);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.command = state.u8()?;
+        state.fill(&mut self.chr_banks)?;
+        self.bank_6000 = state.u8()?;
+        state.fill(&mut self.prg_banks)?;
+        self.mirroring = state.u8()?;
+        self.irq_enabled = state.bool()?;
+        self.counter_enabled = state.bool()?;
+        self.counter = state.u16()?;
+        self.irq_pending = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Namco163 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.chr_banks);
+        state.bytes(&self.prg_banks);
+        state.u8(self.write_protect);
+        state.u16(self.irq_counter);
+        state.bool(self.irq_enabled);
+        state.bool(self.irq_pending);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.chr_banks)?;
+        state.fill(&mut self.prg_banks)?;
+        self.write_protect = state.u8()?;
+        self.irq_counter = state.u16()?;
+        self.irq_enabled = state.bool()?;
+        self.irq_pending = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Mmc5 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[self.prg_mode, self.chr_mode, self.exram_mode, self.nametables]);
+        state.bytes(&self.prg_ram_protect);
+        state.bytes(&[self.fill_tile, self.fill_attribute, self.chr_upper]);
+        state.bytes(&self.prg_banks);
+        for bank in self.chr_banks.iter() {
+            state.u16(*bank);
+        }
+        state.bool(self.background_banks_last);
+        state.bytes(&[self.split_control, self.split_scroll, self.split_bank, self.irq_scanline]);
+        state.bool(self.irq_enabled);
+        state.bool(self.irq_pending);
+        state.bool(self.in_frame);
+        state.u8(self.scanline);
+        state.bytes(&[self.multiplicand, self.multiplier]);
+        state.bytes(&self.exram);
+        state.bool(self.large_sprites);
+        state.bool(self.rendering);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        let modes = state.bytes(4)?;
+        self.prg_mode = modes[0];
+        self.chr_mode = modes[1];
+        self.exram_mode = modes[2];
+        self.nametables = modes[3];
+        state.fill(&mut self.prg_ram_protect)?;
+        let fill = state.bytes(3)?;
+        self.fill_tile = fill[0];
+        self.fill_attribute = fill[1];
+        self.chr_upper = fill[2];
+        state.fill(&mut self.prg_banks)?;
+        for bank in self.chr_banks.iter_mut() {
+            *bank = state.u16()?;
+        }
+        self.background_banks_last = state.bool()?;
+        let split = state.bytes(4)?;
+        self.split_control = split[0];
+        self.split_scroll = split[1];
+        self.split_bank = split[2];
+        self.irq_scanline = split[3];
+        self.irq_enabled = state.bool()?;
+        self.irq_pending = state.bool()?;
+        self.in_frame = state.bool()?;
+        self.scanline = state.u8()?;
+        self.multiplicand = state.u8()?;
+        self.multiplier = state.u8()?;
+        state.fill(&mut self.exram)?;
+        self.large_sprites = state.bool()?;
+        self.rendering = state.bool()?;
+        Ok(())
+    }
+}
+
+/// Only the sides written to are saved, the others are still the ones of the image
+impl Snapshot for DiskSystem {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.sides.len() as u8);
+        for (side, modified) in self.sides.iter().zip(self.modified.iter()) {
+            state.bool(*modified);
+            if *modified {
+                state.bytes(side);
+            }
+        }
+        state.u8(self.inserted.map_or(0xFF, |x| x as u8));
+        state.bool(self.disk_registers_enabled);
+        state.bool(self.sound_enabled);
+        state.u16(self.timer_reload);
+        state.u16(self.timer_counter);
+        state.bool(self.timer_repeat);
+        state.bool(self.timer_enabled);
+        state.bool(self.timer_irq);
+        state.bool(self.motor_on);
+        state.bool(self.transfer_reset);
+        state.bool(self.read_mode);
+        state.bool(self.mirror == Mirror::Horizontal);
+        state.bool(self.crc_control);
+        state.bool(self.transfer_enabled);
+        state.bool(self.disk_irq_enabled);
+        state.bool(self.transfer_complete);
+        state.bool(self.disk_irq);
+        state.u64(self.position as u64);
+        state.bool(self.end_of_head);
+        state.bool(self.scanning);
+        state.bytes(&[self.read_data, self.write_data]);
+        state.bool(self.gap_ended);
+        state.u64(self.crc as u64);
+        state.bool(self.previous_crc_control);
+        state.u64(self.delay as u64);
+        state.u64(self.insert_delay as u64);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        if state.u8()? as usize != self.sides.len() {
+            return Err(invalid_state("save state was made with another disk"));
+        }
+        for (side, modified) in self.sides.iter_mut().zip(self.modified.iter_mut()) {
+            if state.bool()? {
+                state.fill(side)?;
+                *modified = true;
+            }
+        }
+        self.inserted = match state.u8()? {
+            0xFF => None,
+            x if (x as usize) < self.sides.len() => Some(x as usize),
+            _ => return Err(invalid_state("save state was made with another disk")),
+        };
+        self.disk_registers_enabled = state.bool()?;
+        self.sound_enabled = state.bool()?;
+        self.timer_reload = state.u16()?;
+        self.timer_counter = state.u16()?;
+        self.timer_repeat = state.bool()?;
+        self.timer_enabled = state.bool()?;
+        self.timer_irq = state.bool()?;
+        self.motor_on = state.bool()?;
+        self.transfer_reset = state.bool()?;
+        self.read_mode = state.bool()?;
+        self.mirror = match state.bool()? {
+            true => Mirror::Horizontal,
+            false => Mirror::Vertical,
+        };
+        self.crc_control = state.bool()?;
+        self.transfer_enabled = state.bool()?;
+        self.disk_irq_enabled = state.bool()?;
+        self.transfer_complete = state.bool()?;
+        self.disk_irq = state.bool()?;
+        self.position = state.u64()? as usize;
+        self.end_of_head = state.bool()?;
+        self.scanning = state.bool()?;
+        self.read_data = state.u8()?;
+        self.write_data = state.u8()?;
+        self.gap_ended = state.bool()?;
+        self.crc = state.u64()? as u32;
+        self.previous_crc_control = state.bool()?;
+        self.delay = state.u64()? as u32;
+        self.insert_delay = state.u64()? as u32;
+        Ok(())
+    }
+}
+
+/// Only the chips of the cartridge are present, and saved
+impl Snapshot for ExpansionAudio {
+    fn save_state(&self, state: &mut StateWriter) {
+        if let Some(chip) = &self.vrc6 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.vrc7 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.fds {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.mmc5 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.n163 {
+            chip.save_state(state);
+        }
+        if let Some(chip) = &self.sunsoft5b {
+            chip.save_state(state);
+        }
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        if let Some(chip) = self.vrc6.as_mut() {
+            chip.load_state(state)?;
+        }
+        if let Some(chip) = self.vrc7.as_mut() {
+            chip.load_state(state)?;
+        }
+        if let Some(chip) = self.fds.as_mut() {
+            chip.load_state(state)?;
+        }
+        if let Some(chip) = self.mmc5.as_mut() {
+            chip.load_state(state)?;
+        }
+        if let Some(chip) = self.n163.as_mut() {
+            chip.load_state(state)?;
+        }
+        match self.sunsoft5b.as_mut() {
+            Some(chip) => chip.load_state(state),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Snapshot for Vrc6 {
+    fn save_state(&self, state: &mut StateWriter) {
+        for pulse in self.pulse.iter() {
+            state.bool(pulse.enabled);
+            state.bool(pulse.digitized);
+            state.bytes(&[pulse.duty, pulse.volume, pulse.step]);
+            state.u16(pulse.period);
+            state.u16(pulse.timer);
+        }
+        let saw = &self.saw;
+        state.bool(saw.enabled);
+        state.bytes(&[saw.rate, saw.step, saw.accumulator]);
+        state.u16(saw.period);
+        state.u16(saw.timer);
+        state.bool(self.halt);
+        state.u8(self.frequency_shift);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        for pulse in self.pulse.iter_mut() {
+            pulse.enabled = state.bool()?;
+            pulse.digitized = state.bool()?;
+            pulse.duty = state.u8()?;
+            pulse.volume = state.u8()?;
+            pulse.step = state.u8()?;
+            pulse.period = state.u16()?;
+            pulse.timer = state.u16()?;
+        }
+        let saw = &mut self.saw;
+        saw.enabled = state.bool()?;
+        saw.rate = state.u8()?;
+        saw.step = state.u8()?;
+        saw.accumulator = state.u8()?;
+        saw.period = state.u16()?;
+        saw.timer = state.u16()?;
+        self.halt = state.bool()?;
+        self.frequency_shift = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Operator {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.f64(self.phase);
+        state.f64(self.attenuation);
+        state.u8(self.stage as u8);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.phase = state.f64()?;
+        self.attenuation = state.f64()?;
+        self.stage = match state.u8()? {
+            0 => EnvelopeStage::Attack,
+            1 => EnvelopeStage::Decay,
+            2 => EnvelopeStage::Sustain,
+            3 => EnvelopeStage::Release,
+            _ => EnvelopeStage::Off,
+        };
+        Ok(())
+    }
+}
+
+impl Snapshot for Vrc7 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.registers);
+        state.u8(self.selected);
+        state.bool(self.muted);
+        for channel in self.channels.iter() {
+            channel.modulator.save_state(state);
+            channel.carrier.save_state(state);
+            state.bool(channel.key_on);
+            state.f64(channel.feedback[0]);
+            state.f64(channel.feedback[1]);
+            state.f64(channel.output);
+        }
+        state.u8(self.timer);
+        state.u64(self.lfo_time);
+        state.f32(self.sample);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.registers)?;
+        self.selected = state.u8()?;
+        self.muted = state.bool()?;
+        for channel in self.channels.iter_mut() {
+            channel.modulator.load_state(state)?;
+            channel.carrier.load_state(state)?;
+            channel.key_on = state.bool()?;
+            channel.feedback = [state.f64()?, state.f64()?];
+            channel.output = state.f64()?;
+        }
+        self.timer = state.u8()?;
+        self.lfo_time = state.u64()?;
+        self.sample = state.f32()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for FdsEnvelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.disabled);
+        state.bool(self.increase);
+        state.bytes(&[self.speed, self.gain]);
+        state.u64(self.timer as u64);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.disabled = state.bool()?;
+        self.increase = state.bool()?;
+        self.speed = state.u8()?;
+        self.gain = state.u8()?;
+        self.timer = state.u64()? as u32;
+        Ok(())
+    }
+}
+
+impl Snapshot for FdsAudio {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.wave);
+        state.bytes(&self.modulation_table);
+        self.volume.save_state(state);
+        self.modulation.save_state(state);
+        state.u16(self.frequency);
+        state.bool(self.wave_halted);
+        state.bool(self.envelopes_halted);
+        state.bool(self.wave_writable);
+        state.bytes(&[self.master_volume, self.envelope_speed]);
+        state.u16(self.modulation_frequency);
+        state.bool(self.modulation_halted);
+        state.u8(self.modulation_counter as u8);
+        state.u64(self.wave_accumulator as u64);
+        state.u64(self.modulation_accumulator as u64);
+        state.bytes(&[self.modulation_position, self.sample]);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.wave)?;
+        state.fill(&mut self.modulation_table)?;
+        self.volume.load_state(state)?;
+        self.modulation.load_state(state)?;
+        self.frequency = state.u16()?;
+        self.wave_halted = state.bool()?;
+        self.envelopes_halted = state.bool()?;
+        self.wave_writable = state.bool()?;
+        self.master_volume = state.u8()?;
+        self.envelope_speed = state.u8()?;
+        self.modulation_frequency = state.u16()?;
+        self.modulation_halted = state.bool()?;
+        self.modulation_counter = state.u8()? as i8;
+        self.wave_accumulator = state.u64()? as u32;
+        self.modulation_accumulator = state.u64()? as u32;
+        self.modulation_position = state.u8()?;
+        self.sample = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Mmc5Audio {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.pulse[0].save_state(state);
+        self.pulse[1].save_state(state);
+        state.u8(self.pcm);
+        state.bool(self.pcm_read_mode);
+        state.bool(self.pcm_irq_enabled);
+        state.bool(self.pcm_irq);
+        state.u64(self.cycle);
+        state.u16(self.frame_timer);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.pulse[0].load_state(state)?;
+        self.pulse[1].load_state(state)?;
+        self.pcm = state.u8()?;
+        self.pcm_read_mode = state.bool()?;
+        self.pcm_irq_enabled = state.bool()?;
+        self.pcm_irq = state.bool()?;
+        self.cycle = state.u64()?;
+        self.frame_timer = state.u16()?;
+        Ok(())
+    }
+}
+
+/// The RAM holds the waves and the channel settings, some games also keep data in it
+impl Snapshot for N163 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        state.u8(self.address);
+        state.bool(self.auto_increment);
+        state.bool(self.disabled);
+        state.u8(self.current);
+        for output in self.outputs.iter() {
+            state.u16(*output as u16);
+        }
+        state.u8(self.timer);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.ram)?;
+        self.address = state.u8()?;
+        self.auto_increment = state.bool()?;
+        self.disabled = state.bool()?;
+        self.current = state.u8()?;
+        for output in self.outputs.iter_mut() {
+            *output = state.u16()? as i16;
+        }
+        self.timer = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Sunsoft5b {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.registers);
+        state.u8(self.selected);
+        for (timer, output) in self.tone_timers.iter().zip(self.tone_outputs.iter()) {
+            state.u16(*timer);
+            state.bool(*output);
+        }
+        state.u8(self.noise_timer);
+        state.u64(self.noise_shift as u64);
+        state.u64(self.envelope_timer as u64);
+        state.u8(self.envelope_step);
+        state.bool(self.envelope_attack);
+        state.bool(self.envelope_holding);
+        state.u8(self.prescaler);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.fill(&mut self.registers)?;
+        self.selected = state.u8()?;
+        for i in 0..3 {
+            self.tone_timers[i] = state.u16()?;
+            self.tone_outputs[i] = state.bool()?;
+        }
+        self.noise_timer = state.u8()?;
+        self.noise_shift = state.u64()? as u32;
+        self.envelope_timer = state.u64()? as u32;
+        self.envelope_step = state.u8()?;
+        self.envelope_attack = state.bool()?;
+        self.envelope_holding = state.bool()?;
+        self.prescaler = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for NesData {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(match self.region {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Dendy => 2,
+        });
+        state.bytes(&self.ram);
+        self.apu.save_state(state);
+        self.ppu.save_state(state);
+        self.controllers[0].save_state(state);
+        self.controllers[1].save_state(state);
+        state.u16(self.dma_cycles);
+        state.u128(self.clock_counter);
+        self.cartridge.save_state(state);
+    }
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        // Restored first, the APU and the PPU then load their own state over its timings
+        let region = match state.u8()? {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::Dendy,
+            _ => return Err(invalid_state("unknown region")),
+        };
+        self.set_region(region);
+        state.fill(&mut self.ram)?;
+        self.apu.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.controllers[0].load_state(state)?;
+        self.controllers[1].load_state(state)?;
+        self.dma_cycles = state.u16()?;
+        self.clock_counter = state.u128()?;
+        self.cartridge.load_state(state)
+    }
+}
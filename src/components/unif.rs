@@ -0,0 +1,178 @@
+use std::convert::TryInto;
+
+/// Magic bytes at the start of a `.unf` file
+pub const UNIF_MAGIC: &[u8; 4] = b"UNIF";
+/// Size of the UNIF header, the chunks follow it
+const UNIF_HEADER_SIZE: usize = 32;
+
+/// Content of a UNIF file. The board is named instead of numbered like on iNES,
+/// and the ROM is split in chunks.
+#[derive(Debug, Default)]
+pub struct Unif {
+    /// MAPR, without the prefix of the maker like "NES-" or "UNL-"
+    pub board: String,
+    /// NAME
+    pub name: Option<String>,
+    /// PRG0 to PRGF, in order
+    pub prg: Vec<u8>,
+    /// CHR0 to CHRF, the board has CHR RAM when empty
+    pub chr: Vec<u8>,
+    /// MIRR: horizontal, vertical, single screen lo and hi, four screens, by the mapper
+    pub mirroring: Option<u8>,
+    /// BATR, the PRG RAM is kept
+    pub battery: bool,
+    /// TVCI: NTSC, PAL or both
+    pub tv_system: u8,
+}
+
+/// Whether a file is a UNIF image
+pub fn is_unif_image(data: &[u8]) -> bool {
+    data.starts_with(UNIF_MAGIC)
+}
+
+/// Chunks of a UNIF file, the unknown ones are skipped
+pub fn parse_unif(data: &[u8]) -> Result<Unif, String> {
+    if !is_unif_image(data) || data.len() < UNIF_HEADER_SIZE {
+        return Err("not a UNIF file".to_string());
+    }
+    let mut unif = Unif::default();
+    let mut prg: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr: [Option<&[u8]>; 16] = [None; 16];
+    let mut offset = UNIF_HEADER_SIZE;
+    while offset < data.len() {
+        if offset + 8 > data.len() {
+            return Err(format!("truncated chunk header at {:#X}", offset));
+        }
+        let id = &data[offset..offset + 4];
+        let length = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let start = offset + 8;
+        let chunk = data
+            .get(start..start.saturating_add(length))
+            .ok_or_else(|| format!("chunk {} is truncated", String::from_utf8_lossy(id)))?;
+        match id {
+            b"MAPR" => unif.board = board_name(&chunk_string(chunk)).to_string(),
+            b"NAME" => unif.name = Some(chunk_string(chunk)),
+            b"MIRR" => unif.mirroring = chunk.first().copied(),
+            b"BATR" => unif.battery = chunk.first().is_some_and(|x| *x != 0),
+            b"TVCI" => unif.tv_system = chunk.first().copied().unwrap_or(0),
+            [b'P', b'R', b'G', digit] | [b'C', b'H', b'R', digit] => {
+                let index = (*digit as char)
+                    .to_digit(16)
+                    .ok_or_else(|| format!("unknown chunk {}", String::from_utf8_lossy(id)))?;
+                match id[0] {
+                    b'P' => prg[index as usize] = Some(chunk),
+                    _ => chr[index as usize] = Some(chunk),
+                }
+            }
+            _ => (),
+        }
+        offset = start + length;
+    }
+    if unif.board.is_empty() {
+        return Err("the UNIF file has no MAPR chunk".to_string());
+    }
+    unif.prg = prg
+        .iter()
+        .flatten()
+        .flat_map(|chunk| chunk.iter())
+        .copied()
+        .collect();
+    unif.chr = chr
+        .iter()
+        .flatten()
+        .flat_map(|chunk| chunk.iter())
+        .copied()
+        .collect();
+    if unif.prg.is_empty() {
+        return Err("the UNIF file has no PRG chunk".to_string());
+    }
+    Ok(unif)
+}
+
+/// Text of a chunk, up to its terminating 0
+fn chunk_string(chunk: &[u8]) -> String {
+    let end = chunk.iter().position(|x| *x == 0).unwrap_or(chunk.len());
+    String::from_utf8_lossy(&chunk[..end]).trim().to_string()
+}
+
+/// Board of a MAPR chunk without the prefix of its maker
+fn board_name(mapr: &str) -> &str {
+    for prefix in ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-"].iter() {
+        if let Some(board) = mapr.strip_prefix(prefix) {
+            return board;
+        }
+    }
+    mapr
+}
+
+/// iNES mapper and NES 2.0 submapper of a board, only the boards of the emulated mappers
+pub fn board_mapper(board: &str) -> Option<(u16, u8)> {
+    Some(match board {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, 0),
+        "UNROM" | "UOROM" => (2, 2),
+        "CNROM" => (3, 2),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, 0),
+        "ANROM" | "AN1ROM" | "AOROM" => (7, 1),
+        "AMROM" => (7, 2),
+        "PNROM" | "PEEOROM" => (9, 0),
+        "FJROM" | "FKROM" => (10, 0),
+        "BNROM" => (34, 2),
+        "AVE-NINA-01" | "AVE-NINA-02" => (34, 1),
+        "GNROM" | "MHROM" => (66, 0),
+        "NINA-03" | "NINA-06" | "AVE-NINA-03" | "AVE-NINA-06" => (79, 0),
+        "JLROM" | "JSROM" | "BTR" => (69, 0),
+        _ => return None,
+    })
+}
+
+impl Unif {
+    /// NES 2.0 image of the same cartridge, the banks are mirrored up to the iNES units
+    pub fn to_ines(&self) -> Result<Vec<u8>, String> {
+        let (mapper, submapper) = board_mapper(&self.board)
+            .ok_or_else(|| format!("unsupported UNIF board '{}'", self.board))?;
+        let prg = mirrored(&self.prg, 0x4000);
+        let chr = mirrored(&self.chr, 0x2000);
+        if prg.len() / 0x4000 > 0xFF || chr.len() / 0x2000 > 0xFF {
+            return Err("the UNIF rom is too large".to_string());
+        }
+        let mut flags_6 = (mapper as u8 & 0x0F) << 4;
+        match self.mirroring {
+            Some(1) => flags_6 |= 0x01,
+            Some(4) => flags_6 |= 0x08,
+            _ => (),
+        }
+        if self.battery {
+            flags_6 |= 0x02;
+        }
+        // 8KB of PRG RAM, battery backed or not
+        let prg_ram = match self.battery {
+            true => 0x70,
+            false => 0x07,
+        };
+        let mut rom = vec![
+            0x4E,
+            0x45,
+            0x53,
+            0x1A,
+            (prg.len() / 0x4000) as u8,
+            (chr.len() / 0x2000) as u8,
+            flags_6,
+            mapper as u8 & 0xF0 | 0x08,
+            submapper << 4 | (mapper >> 8) as u8,
+            0,
+            prg_ram,
+            0,
+            self.tv_system.min(2),
+        ];
+        rom.resize(16, 0);
+        rom.extend(prg);
+        rom.extend(chr);
+        Ok(rom)
+    }
+}
+
+/// Data repeated up to a multiple of `unit` bytes
+fn mirrored(data: &[u8], unit: usize) -> Vec<u8> {
+    let length = data.len().div_ceil(unit) * unit;
+    data.iter().cycle().take(length).copied().collect()
+}
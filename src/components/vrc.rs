@@ -0,0 +1,257 @@
+use super::Mirror;
+
+/// Chips of the Konami boards, VRC2 has no IRQ and no PRG mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VrcKind {
+    Vrc2,
+    Vrc4,
+    Vrc6,
+    Vrc7,
+}
+
+/// IRQ counter shared by VRC4, VRC6 and VRC7. It counts cpu cycles, or scanlines with a
+/// prescaler dividing the cpu clock by 113.67, and reloads from the latch when it overflows.
+#[derive(Clone, Copy, Default)]
+pub struct VrcIrq {
+    pub latch: u8,
+    pub counter: u8,
+    /// Decremented by 3 every cycle, the counter is clocked when it reaches 0
+    pub prescaler: i16,
+    pub enabled: bool,
+    /// Enable bit restored when the IRQ is acknowledged
+    pub enable_after_ack: bool,
+    pub cycle_mode: bool,
+    pub pending: bool,
+}
+
+impl VrcIrq {
+    pub fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 0x01 != 0;
+        self.enabled = data & 0x02 != 0;
+        self.cycle_mode = data & 0x04 != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+        self.pending = false;
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if !self.cycle_mode {
+            self.prescaler -= 3;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += 341;
+        }
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+/// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25), VRC6 (mappers 24 and 26) and VRC7 (mapper 85).
+/// The boards connect different cpu address lines to the 2 register select pins of the chip,
+/// VRC7 has a single pin.
+pub struct Vrc {
+    pub kind: VrcKind,
+    /// Cpu address lines wired to the pins A0 and A1, several lines when the board is unknown
+    pub a0_lines: u16,
+    pub a1_lines: u16,
+    /// VRC2a ignores the low bit of the CHR banks
+    pub chr_shift: u8,
+    /// 8KB banks at $8000 and $A000, the 16KB bank at $8000 and 8KB at $C000 on VRC6.
+    /// VRC7 has a third 8KB bank at $C000.
+    pub prg_banks: [u8; 3],
+    /// 1KB CHR banks, VRC6 uses them as 2KB banks in some modes
+    pub chr_banks: [u16; 8],
+    /// $9000 on VRC2 and VRC4, $E000 on VRC7: vertical, horizontal, single screen lo and hi
+    pub mirroring: u8,
+    /// VRC4 $9002 bit 1, $8000 and $C000 are swapped
+    pub prg_swap: bool,
+    /// VRC6 $B003, the CHR mode in the bits 0-1 and the mirroring in the bits 2-3
+    pub ppu_mode: u8,
+    pub irq: VrcIrq,
+}
+
+impl Vrc {
+    /// Chip and wiring of a board, None for the other mappers.
+    /// Submapper 0 is an unknown board, the pins are connected to the lines of every variant.
+    pub fn for_mapper(mapper_id: u16, submapper: u8) -> Option<Vrc> {
+        let (kind, a0_lines, a1_lines) = match (mapper_id, submapper) {
+            // VRC4a, VRC4c
+            (21, 1) => (VrcKind::Vrc4, 0x02, 0x04),
+            (21, 2) => (VrcKind::Vrc4, 0x40, 0x80),
+            (21, _) => (VrcKind::Vrc4, 0x42, 0x84),
+            // VRC2a
+            (22, _) => (VrcKind::Vrc2, 0x02, 0x01),
+            // VRC4f, VRC4e, VRC2b
+            (23, 1) => (VrcKind::Vrc4, 0x01, 0x02),
+            (23, 2) => (VrcKind::Vrc4, 0x04, 0x08),
+            (23, 3) => (VrcKind::Vrc2, 0x01, 0x02),
+            (23, _) => (VrcKind::Vrc4, 0x05, 0x0A),
+            // VRC6a, VRC6b
+            (24, _) => (VrcKind::Vrc6, 0x01, 0x02),
+            // VRC4b, VRC4d, VRC2c
+            (25, 1) => (VrcKind::Vrc4, 0x02, 0x01),
+            (25, 2) => (VrcKind::Vrc4, 0x08, 0x04),
+            (25, 3) => (VrcKind::Vrc2, 0x02, 0x01),
+            (25, _) => (VrcKind::Vrc4, 0x0A, 0x05),
+            (26, _) => (VrcKind::Vrc6, 0x02, 0x01),
+            // VRC7b, VRC7a
+            (85, 1) => (VrcKind::Vrc7, 0x08, 0x00),
+            (85, 2) => (VrcKind::Vrc7, 0x10, 0x00),
+            (85, _) => (VrcKind::Vrc7, 0x18, 0x00),
+            _ => return None,
+        };
+        Some(Vrc {
+            kind,
+            a0_lines,
+            a1_lines,
+            chr_shift: (mapper_id == 22) as u8,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            mirroring: 0,
+            prg_swap: false,
+            ppu_mode: 0,
+            irq: VrcIrq::default(),
+        })
+    }
+
+    /// Register selected by a cpu address: $x000 to $x003
+    fn register(&self, addr: u16) -> u16 {
+        let a0 = (addr & self.a0_lines != 0) as u16;
+        let a1 = (addr & self.a1_lines != 0) as u16;
+        addr & 0xF000 | a1 << 1 | a0
+    }
+
+    /// Write to the registers from $8000, returns false below
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        if addr < 0x8000 {
+            return false;
+        }
+        let register = self.register(addr);
+        match self.kind {
+            VrcKind::Vrc6 => self.write_vrc6(register, data),
+            VrcKind::Vrc7 => self.write_vrc7(addr, register, data),
+            _ => self.write_vrc4(register, data),
+        }
+        true
+    }
+
+    fn write_vrc4(&mut self, register: u16, data: u8) {
+        let vrc4 = self.kind == VrcKind::Vrc4;
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x1F,
+            0x9000 | 0x9001 if vrc4 => self.mirroring = data & 0x03,
+            0x9002 if vrc4 => self.prg_swap = data & 0x02 != 0,
+            0x9003 if vrc4 => (),
+            0x9000..=0x9003 => self.mirroring = data & 0x01,
+            0xA000..=0xA003 => self.prg_banks[1] = data & 0x1F,
+            // Two registers for the low and high nibbles of each CHR bank
+            0xB000..=0xEFFF => {
+                let index = ((register as usize >> 12) - 0xB) * 2 + (register as usize >> 1 & 0x01);
+                let bank = &mut self.chr_banks[index];
+                *bank = match register & 0x01 {
+                    0 => *bank & 0x1F0 | (data as u16 & 0x0F),
+                    _ => *bank & 0x0F | (data as u16 & 0x1F) << 4,
+                };
+            }
+            0xF000 if vrc4 => self.irq.latch = self.irq.latch & 0xF0 | data & 0x0F,
+            0xF001 if vrc4 => self.irq.latch = self.irq.latch & 0x0F | data << 4,
+            0xF002 if vrc4 => self.irq.write_control(data),
+            0xF003 if vrc4 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    /// The sound registers from $9000 to $B002 are handled by the expansion audio
+    fn write_vrc6(&mut self, register: u16, data: u8) {
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x0F,
+            0xB003 => self.ppu_mode = data,
+            0xC000..=0xC003 => self.prg_banks[1] = data & 0x1F,
+            0xD000..=0xEFFF => {
+                self.chr_banks[((register as usize >> 12) - 0xD) * 4 + (register as usize & 0x03)] =
+                    data as u16
+            }
+            0xF000 => self.irq.latch = data,
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    /// The sound registers $9010 and $9030 are handled by the expansion audio,
+    /// they are decoded from A4 and A5 whatever the wiring of the board
+    fn write_vrc7(&mut self, addr: u16, register: u16, data: u8) {
+        match register {
+            0x8000 => self.prg_banks[0] = data & 0x3F,
+            0x8001 => self.prg_banks[1] = data & 0x3F,
+            0x9000 if addr & 0x30 == 0 => self.prg_banks[2] = data & 0x3F,
+            0xA000..=0xDFFF => {
+                self.chr_banks[((register as usize >> 12) - 0xA) * 2 + (register as usize & 0x01)] =
+                    data as u16
+            }
+            0xE000 => self.mirroring = data & 0x03,
+            0xE001 => self.irq.latch = data,
+            0xF000 => self.irq.write_control(data),
+            0xF001 => self.irq.acknowledge(),
+            _ => (),
+        }
+    }
+
+    /// PRG bank mapped at a cpu address from $8000, as `(bank, size)`.
+    /// Negative banks count from the last one.
+    pub fn prg_bank(&self, addr: u16) -> (isize, usize) {
+        match (self.kind, addr) {
+            (VrcKind::Vrc6, 0x8000..=0xBFFF) => (self.prg_banks[0] as isize, 0x4000),
+            (VrcKind::Vrc6, 0xC000..=0xDFFF) => (self.prg_banks[1] as isize, 0x2000),
+            (VrcKind::Vrc7, 0x8000..=0x9FFF) => (self.prg_banks[0] as isize, 0x2000),
+            (VrcKind::Vrc7, 0xC000..=0xDFFF) => (self.prg_banks[2] as isize, 0x2000),
+            (_, 0xA000..=0xBFFF) => (self.prg_banks[1] as isize, 0x2000),
+            (_, 0x8000..=0x9FFF) if !self.prg_swap => (self.prg_banks[0] as isize, 0x2000),
+            (_, 0xC000..=0xDFFF) if self.prg_swap => (self.prg_banks[0] as isize, 0x2000),
+            (_, 0x8000..=0xDFFF) => (-2, 0x2000),
+            _ => (-1, 0x2000),
+        }
+    }
+
+    /// CHR bank mapped at a PPU address below $2000, as `(bank, size)`
+    pub fn chr_bank(&self, addr: u16) -> (isize, usize) {
+        let slot = addr as usize >> 10;
+        if self.kind != VrcKind::Vrc6 {
+            return ((self.chr_banks[slot] >> self.chr_shift) as isize, 0x400);
+        }
+        match (self.ppu_mode & 0x03, slot) {
+            (0, _) => (self.chr_banks[slot] as isize, 0x400),
+            (1, _) => (self.chr_banks[slot >> 1] as isize, 0x800),
+            (_, 0..=3) => (self.chr_banks[slot] as isize, 0x400),
+            _ => (self.chr_banks[4 + ((slot - 4) >> 1)] as isize, 0x800),
+        }
+    }
+
+    pub fn mirror(&self) -> Mirror {
+        let mirroring = match self.kind {
+            VrcKind::Vrc6 => self.ppu_mode >> 2 & 0x03,
+            _ => self.mirroring,
+        };
+        match mirroring {
+            0 => Mirror::Vertical,
+            1 => Mirror::Horizontal,
+            2 => Mirror::OneScreenLo,
+            _ => Mirror::OneScreenHi,
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use super::super::components::*;
+use super::super::utils::parse_number;
+
+/// Condition of a breakpoint or a watchpoint, for example `A == $10 && [$0300] != 0`.
+///
+/// Values are the registers `A`, `X`, `Y`, `S`, `P` and `PC`, the position of the PPU
+/// with `SCANLINE`, `DOT` and `FRAME`, the accessed `ADDR` and `VALUE` for watchpoints,
+/// numbers and bytes of cpu memory written `[addr]`.
+/// A value different from 0 is true.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    Pc,
+    Scanline,
+    Dot,
+    Frame,
+    Addr,
+    Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Lower,
+    LowerEqual,
+    Greater,
+    GreaterEqual,
+    BitAnd,
+    BitOr,
+    Add,
+    Sub,
+}
+
+/// Values only known while checking a watchpoint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AccessContext {
+    pub addr: u16,
+    pub data: u8,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let result = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(result),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+        }
+    }
+
+    pub fn eval(&self, cpu: &CPU6502, nes: &mut NesData, access: AccessContext) -> i64 {
+        match self {
+            Expression::Number(x) => *x,
+            Expression::Register(r) => match r {
+                Register::A => cpu.a as i64,
+                Register::X => cpu.x as i64,
+                Register::Y => cpu.y as i64,
+                Register::S => cpu.stkp as i64,
+                Register::P => cpu.status as i64,
+                Register::Pc => cpu.pc as i64,
+                Register::Scanline => nes.ppu.scanline as i64,
+                Register::Dot => nes.ppu.cycle as i64,
+                Register::Frame => nes.ppu.frame_count as i64,
+                Register::Addr => access.addr as i64,
+                Register::Value => access.data as i64,
+            },
+            Expression::Memory(addr) => {
+                let addr = addr.eval(cpu, nes, access) as u16;
+                nes.cpu_read(addr, true) as i64
+            }
+            Expression::Binary(op, left, right) => {
+                let left = left.eval(cpu, nes, access);
+                // Evaluated lazily so a memory read is only made when needed
+                match op {
+                    Operator::Or if left != 0 => return 1,
+                    Operator::And if left == 0 => return 0,
+                    _ => (),
+                }
+                let right = right.eval(cpu, nes, access);
+                match op {
+                    Operator::Or | Operator::And => (right != 0) as i64,
+                    Operator::Equal => (left == right) as i64,
+                    Operator::NotEqual => (left != right) as i64,
+                    Operator::Lower => (left < right) as i64,
+                    Operator::LowerEqual => (left <= right) as i64,
+                    Operator::Greater => (left > right) as i64,
+                    Operator::GreaterEqual => (left >= right) as i64,
+                    Operator::BitAnd => left & right,
+                    Operator::BitOr => left | right,
+                    Operator::Add => left.wrapping_add(right),
+                    Operator::Sub => left.wrapping_sub(right),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, cpu: &CPU6502, nes: &mut NesData, access: AccessContext) -> bool {
+        self.eval(cpu, nes, access) != 0
+    }
+}
+
+/// Split the text in numbers, names and symbols
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '$' || c == '_' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let pair: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            match pair.as_str() {
+                "==" | "!=" | "<=" | ">=" | "&&" | "||" => {
+                    tokens.push(pair);
+                    i += 2;
+                }
+                _ if "<>&|+-()[]".contains(c) => {
+                    tokens.push(c.to_string());
+                    i += 1;
+                }
+                _ => return Err(format!("unexpected character '{}'", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|x| x.as_str())
+    }
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token.ok_or_else(|| "unexpected end of expression".to_string())
+    }
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            ref x if x == expected => Ok(()),
+            x => Err(format!("expected '{}' but found '{}'", expected, x)),
+        }
+    }
+    /// Parse a chain of binary operators of the same precedence
+    fn binary(
+        &mut self,
+        operators: &[(&str, Operator)],
+        operand: fn(&mut Parser) -> Result<Expression, String>,
+    ) -> Result<Expression, String> {
+        let mut left = operand(self)?;
+        while let Some(op) = self
+            .peek()
+            .and_then(|t| operators.iter().find(|(s, _)| *s == t))
+        {
+            let op = op.1;
+            self.position += 1;
+            left = Expression::Binary(op, Box::new(left), Box::new(operand(self)?));
+        }
+        Ok(left)
+    }
+    fn or(&mut self) -> Result<Expression, String> {
+        self.binary(&[("||", Operator::Or)], Parser::and)
+    }
+    fn and(&mut self) -> Result<Expression, String> {
+        self.binary(&[("&&", Operator::And)], Parser::comparison)
+    }
+    fn comparison(&mut self) -> Result<Expression, String> {
+        self.binary(
+            &[
+                ("==", Operator::Equal),
+                ("!=", Operator::NotEqual),
+                ("<=", Operator::LowerEqual),
+                (">=", Operator::GreaterEqual),
+                ("<", Operator::Lower),
+                (">", Operator::Greater),
+            ],
+            Parser::bitwise,
+        )
+    }
+    fn bitwise(&mut self) -> Result<Expression, String> {
+        self.binary(
+            &[("&", Operator::BitAnd), ("|", Operator::BitOr)],
+            Parser::sum,
+        )
+    }
+    fn sum(&mut self) -> Result<Expression, String> {
+        self.binary(&[("+", Operator::Add), ("-", Operator::Sub)], Parser::value)
+    }
+    fn value(&mut self) -> Result<Expression, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let result = self.or()?;
+                self.expect(")")?;
+                Ok(result)
+            }
+            "[" => {
+                let result = self.or()?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(result)))
+            }
+            _ => match register(&token) {
+                Some(r) => Ok(Expression::Register(r)),
+                None => parse_number(&token).map(|x| Expression::Number(x as i64)),
+            },
+        }
+    }
+}
+
+fn register(name: &str) -> Option<Register> {
+    match name.to_ascii_uppercase().as_str() {
+        "A" => Some(Register::A),
+        "X" => Some(Register::X),
+        "Y" => Some(Register::Y),
+        "S" | "SP" => Some(Register::S),
+        "P" => Some(Register::P),
+        "PC" => Some(Register::Pc),
+        "SCANLINE" => Some(Register::Scanline),
+        "DOT" => Some(Register::Dot),
+        "FRAME" => Some(Register::Frame),
+        "ADDR" => Some(Register::Addr),
+        "VALUE" => Some(Register::Value),
+        _ => None,
+    }
+}
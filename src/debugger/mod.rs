@@ -0,0 +1,464 @@
+use std::fmt;
+use std::mem;
+
+use super::assembler::assemble_file;
+use super::components::*;
+use super::utils::parse_number;
+
+pub use self::expression::*;
+pub use self::tui::*;
+
+mod expression;
+mod tui;
+
+/// Command line help of the debugger
+pub const DEBUGGER_HELP: &str = "commands:
+    s, step                  run one instruction
+    n, next                  run one instruction, subroutines are run until they return
+    o, out                   run until the current subroutine returns
+    c, continue              run until a breakpoint or a watchpoint is hit
+    scanline N               run until the PPU starts the scanline N
+    nmi                      run until the NMI handler is entered
+    b ADDR [if EXPR]         break when the cpu is about to execute ADDR
+    w MODE [cpu|ppu] ADDR[-END] [if EXPR]
+                             watch the reads (r), writes (w) and executions (x) of a range
+    d N, dw N                delete the breakpoint or the watchpoint N
+    l, list                  list the breakpoints and the watchpoints";
+
+pub fn test_cpu(cpu: &mut CPU6502, nes: &mut NesData, depth: Option<usize>) {
+    // Set the reset vector to the cartridge memory address
+    nes.cpu_write(0xFFFC, 0x20);
+    nes.cpu_write(0xFFFD, 0x40);
+
+    // get the Assembly code
+    let code: Vec<u8> = test_code();
+    // Writes the code in the ram with offset 0x8000
+    for i in 0..code.len() {
+        nes.cpu_write(0x4020 + i as u16, code[i]);
+    }
+    cpu.power(nes);
+    let mut ui = DebuggerUi::new();
+    ui.code_rows = depth.unwrap_or(ui.code_rows);
+    ui.run(cpu, nes).unwrap();
+}
+
+/// This functions returns a compiled assembly code  that
+/// loads some data in the ram and executes some shift left
+pub(crate) fn test_code() -> Vec<u8> {
+    assemble_file("src/test/test.asm").unwrap().bytes()
+}
+
+/// Stop the cpu when it is about to execute an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Expression>,
+    pub enabled: bool,
+}
+
+/// Stop the cpu when it reads, writes or executes an address of a range
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: AddressSpace,
+    /// First and last address watched, both included
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    /// Only checked on the cpu address space
+    pub execute: bool,
+    pub condition: Option<Expression>,
+    pub enabled: bool,
+}
+
+/// Why the debugger gave the hand back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step is done
+    Step,
+    Breakpoint(usize),
+    Watchpoint(usize, BusAccess),
+    Scanline(i16),
+    Nmi,
+    /// `clock_limit` clocks were run without stopping
+    ClockLimit,
+}
+
+/// Where a run should end, besides the breakpoints and watchpoints
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Instruction,
+    Return { pc: u16, stkp: u8 },
+    Out { stkp: u8 },
+    Scanline(i16),
+    Nmi(u16),
+    Never,
+}
+
+/// Debugger core, the cpu and the bus are given on every call so the frontends keep them
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Maximum number of PPU clocks run by a single command
+    pub clock_limit: u64,
+}
+
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+
+impl Breakpoint {
+    pub fn new(addr: u16) -> Breakpoint {
+        Breakpoint {
+            addr,
+            condition: None,
+            enabled: true,
+        }
+    }
+}
+
+impl Watchpoint {
+    pub fn new(space: AddressSpace, start: u16, end: u16) -> Watchpoint {
+        Watchpoint {
+            space,
+            start,
+            end,
+            read: false,
+            write: false,
+            execute: false,
+            condition: None,
+            enabled: true,
+        }
+    }
+    fn matches(&self, access: &BusAccess) -> bool {
+        let kind = match access.kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        };
+        self.enabled
+            && kind
+            && self.space == access.space
+            && (self.start..=self.end).contains(&access.addr)
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            // One second of NTSC video
+            clock_limit: 341 * 262 * 60,
+        }
+    }
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    /// Run a single instruction
+    pub fn step_into(&mut self, cpu: &mut CPU6502, nes: &mut NesData) -> StopReason {
+        self.run(cpu, nes, Target::Instruction)
+    }
+    /// Run a single instruction, a JSR is run until its subroutine returns
+    pub fn step_over(&mut self, cpu: &mut CPU6502, nes: &mut NesData) -> StopReason {
+        match nes.cpu_read(cpu.pc, true) {
+            JSR => self.run(
+                cpu,
+                nes,
+                Target::Return {
+                    pc: cpu.pc.wrapping_add(3),
+                    stkp: cpu.stkp,
+                },
+            ),
+            _ => self.step_into(cpu, nes),
+        }
+    }
+    /// Run until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self, cpu: &mut CPU6502, nes: &mut NesData) -> StopReason {
+        self.run(cpu, nes, Target::Out { stkp: cpu.stkp })
+    }
+    /// Run until the PPU starts rendering `scanline`, from -1 (pre-render) to 260
+    pub fn run_to_scanline(
+        &mut self,
+        cpu: &mut CPU6502,
+        nes: &mut NesData,
+        scanline: i16,
+    ) -> StopReason {
+        self.run(cpu, nes, Target::Scanline(scanline))
+    }
+    /// Run until the cpu is about to execute the first instruction of the NMI handler
+    pub fn run_to_nmi(&mut self, cpu: &mut CPU6502, nes: &mut NesData) -> StopReason {
+        let vector = nes.cpu_read(0xFFFA, true) as u16 | (nes.cpu_read(0xFFFB, true) as u16) << 8;
+        self.run(cpu, nes, Target::Nmi(vector))
+    }
+    /// Run until a breakpoint or a watchpoint is hit
+    pub fn resume(&mut self, cpu: &mut CPU6502, nes: &mut NesData) -> StopReason {
+        self.run(cpu, nes, Target::Never)
+    }
+
+    fn run(&mut self, cpu: &mut CPU6502, nes: &mut NesData, target: Target) -> StopReason {
+        let watching = self
+            .watchpoints
+            .iter()
+            .any(|w| w.enabled && (w.read || w.write));
+        nes.access_log = if watching { Some(Vec::new()) } else { None };
+
+        let mut previous_cycles = cpu.cycles;
+        for _ in 0..self.clock_limit {
+            let previous_scanline = nes.ppu.scanline;
+            nes.clock(cpu);
+            if let Some(stop) = self.check_accesses(cpu, nes) {
+                return stop;
+            }
+            if let Target::Scanline(line) = target {
+                if nes.ppu.scanline == line && previous_scanline != line {
+                    return StopReason::Scanline(line);
+                }
+            }
+            // The cpu runs a whole instruction on its first cycle, then waits for the others
+            let boundary = cpu.cycles == 0 && previous_cycles != 0;
+            previous_cycles = cpu.cycles;
+            if !boundary {
+                continue;
+            }
+            match target {
+                Target::Instruction => return StopReason::Step,
+                Target::Return { pc, stkp } if cpu.pc == pc && cpu.stkp >= stkp => {
+                    return StopReason::Step
+                }
+                Target::Out { stkp }
+                    if (cpu.curr_opcode == RTS || cpu.curr_opcode == RTI) && cpu.stkp > stkp =>
+                {
+                    return StopReason::Step
+                }
+                Target::Nmi(vector) if cpu.pc == vector => return StopReason::Nmi,
+                _ => (),
+            }
+            if let Some(stop) = self.check_instruction(cpu, nes) {
+                return stop;
+            }
+        }
+        StopReason::ClockLimit
+    }
+
+    fn check_accesses(&self, cpu: &CPU6502, nes: &mut NesData) -> Option<StopReason> {
+        let accesses = match nes.access_log.as_mut() {
+            Some(log) if !log.is_empty() => mem::take(log),
+            _ => return None,
+        };
+        for access in accesses.iter() {
+            if let Some(i) = self.watchpoint_hit(access, cpu, nes) {
+                return Some(StopReason::Watchpoint(i, *access));
+            }
+        }
+        None
+    }
+
+    /// Breakpoints and execute watchpoints on the instruction about to run
+    fn check_instruction(&self, cpu: &CPU6502, nes: &mut NesData) -> Option<StopReason> {
+        for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+            if breakpoint.enabled
+                && breakpoint.addr == cpu.pc
+                && condition(&breakpoint.condition, cpu, nes, AccessContext::default())
+            {
+                return Some(StopReason::Breakpoint(i));
+            }
+        }
+        let access = BusAccess {
+            space: AddressSpace::Cpu,
+            kind: AccessKind::Execute,
+            addr: cpu.pc,
+            data: nes.cpu_read(cpu.pc, true),
+        };
+        self.watchpoint_hit(&access, cpu, nes)
+            .map(|i| StopReason::Watchpoint(i, access))
+    }
+
+    fn watchpoint_hit(
+        &self,
+        access: &BusAccess,
+        cpu: &CPU6502,
+        nes: &mut NesData,
+    ) -> Option<usize> {
+        let context = AccessContext {
+            addr: access.addr,
+            data: access.data,
+        };
+        self.watchpoints
+            .iter()
+            .position(|w| w.matches(access) && condition(&w.condition, cpu, nes, context))
+    }
+
+    /// Run a command of `DEBUGGER_HELP` and describe its result
+    pub fn command(
+        &mut self,
+        line: &str,
+        cpu: &mut CPU6502,
+        nes: &mut NesData,
+    ) -> Result<String, String> {
+        let (line, condition) = match line.find(" if ") {
+            Some(i) => (&line[..i], Some(Expression::parse(&line[i + 4..])?)),
+            None => (line, None),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let stop = match words.as_slice() {
+            ["s"] | ["step"] => self.step_into(cpu, nes),
+            ["n"] | ["next"] => self.step_over(cpu, nes),
+            ["o"] | ["out"] => self.step_out(cpu, nes),
+            ["c"] | ["continue"] => self.resume(cpu, nes),
+            ["scanline", line] => self.run_to_scanline(cpu, nes, parse_number(line)? as i16),
+            ["nmi"] => self.run_to_nmi(cpu, nes),
+            ["b", addr] => {
+                let breakpoint = Breakpoint {
+                    condition,
+                    ..Breakpoint::new(parse_number(addr)? as u16)
+                };
+                return Ok(format!(
+                    "breakpoint {} set",
+                    self.add_breakpoint(breakpoint)
+                ));
+            }
+            ["w", mode, args @ ..] => {
+                let watchpoint = parse_watchpoint(mode, args, condition)?;
+                return Ok(format!(
+                    "watchpoint {} set",
+                    self.add_watchpoint(watchpoint)
+                ));
+            }
+            ["d", index] => {
+                remove(&mut self.breakpoints, index)?;
+                return Ok(format!("breakpoint {} deleted", index));
+            }
+            ["dw", index] => {
+                remove(&mut self.watchpoints, index)?;
+                return Ok(format!("watchpoint {} deleted", index));
+            }
+            ["l"] | ["list"] => return Ok(self.list()),
+            _ => {
+                return Err(format!(
+                    "unknown command '{}'\n{}",
+                    line.trim(),
+                    DEBUGGER_HELP
+                ))
+            }
+        };
+        Ok(format!("{} at ${:04X}", stop, cpu.pc))
+    }
+
+    fn list(&self) -> String {
+        let mut result = String::new();
+        for (i, b) in self.breakpoints.iter().enumerate() {
+            result += &format!(
+                "breakpoint {} : ${:04X}{}\n",
+                i,
+                b.addr,
+                if b.condition.is_some() {
+                    " (conditional)"
+                } else {
+                    ""
+                }
+            );
+        }
+        for (i, w) in self.watchpoints.iter().enumerate() {
+            let mode: String = [(w.read, 'r'), (w.write, 'w'), (w.execute, 'x')]
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, c)| *c)
+                .collect();
+            result += &format!(
+                "watchpoint {} : {} {:?} ${:04X}-${:04X}{}\n",
+                i,
+                mode,
+                w.space,
+                w.start,
+                w.end,
+                if w.condition.is_some() {
+                    " (conditional)"
+                } else {
+                    ""
+                }
+            );
+        }
+        result
+    }
+}
+
+fn condition(
+    condition: &Option<Expression>,
+    cpu: &CPU6502,
+    nes: &mut NesData,
+    access: AccessContext,
+) -> bool {
+    match condition {
+        Some(expression) => expression.is_true(cpu, nes, access),
+        None => true,
+    }
+}
+
+/// Parse `MODE [cpu|ppu] ADDR[-END]`
+fn parse_watchpoint(
+    mode: &str,
+    args: &[&str],
+    condition: Option<Expression>,
+) -> Result<Watchpoint, String> {
+    let (space, range) = match args {
+        ["cpu", range] => (AddressSpace::Cpu, range),
+        ["ppu", range] => (AddressSpace::Ppu, range),
+        [range] => (AddressSpace::Cpu, range),
+        _ => return Err("expected [cpu|ppu] ADDR[-END]".to_string()),
+    };
+    let mut bounds = range.splitn(2, '-');
+    let start = parse_number(bounds.next().unwrap_or(""))? as u16;
+    let end = match bounds.next() {
+        Some(x) => parse_number(x)? as u16,
+        None => start,
+    };
+    if mode.is_empty() || !mode.chars().all(|c| "rwx".contains(c)) {
+        return Err(format!("'{}' is not a mix of r, w and x", mode));
+    }
+    Ok(Watchpoint {
+        read: mode.contains('r'),
+        write: mode.contains('w'),
+        execute: mode.contains('x'),
+        condition,
+        ..Watchpoint::new(space, start, end)
+    })
+}
+
+fn remove<T>(list: &mut Vec<T>, index: &str) -> Result<(), String> {
+    let index = parse_number(index)? as usize;
+    if index >= list.len() {
+        return Err(format!("there is no entry {}", index));
+    }
+    list.remove(index);
+    Ok(())
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint(i) => write!(f, "breakpoint {} hit", i),
+            StopReason::Watchpoint(i, access) => write!(
+                f,
+                "watchpoint {} hit by {:?} of ${:02X} at {:?} ${:04X}",
+                i, access.kind, access.data, access.space, access.addr
+            ),
+            StopReason::Scanline(line) => write!(f, "scanline {} reached", line),
+            StopReason::Nmi => write!(f, "NMI entered"),
+            StopReason::ClockLimit => write!(f, "still running"),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::io;
+
+use console::Term;
+
+use super::super::components::*;
+use super::super::disassembler::*;
+use super::super::utils::parse_number;
+use super::*;
+
+/// Width of the disassembly pane, the other panes are drawn on its right
+const LEFT_WIDTH: usize = 48;
+/// Bytes shown on each line of the memory pane
+const MEMORY_COLUMNS: usize = 16;
+/// Most recent stack entries shown
+const STACK_ROWS: usize = 8;
+/// Lines of command output kept under the panes
+const OUTPUT_ROWS: usize = 6;
+
+/// Commands handled by the panel on top of `DEBUGGER_HELP`
+pub const TUI_HELP: &str = "    x/N [cpu|ppu|oam] ADDR   print N bytes from ADDR
+    m [cpu|ppu|oam] ADDR     show ADDR in the memory pane, `m +` and `m -` scroll it
+    e [cpu|ppu|oam] ADDR B.. write the bytes B.. from ADDR
+    sym FILE                 load the labels of a .nl or .dbg file
+    q, quit                  leave the debugger
+    an empty line runs the last command again";
+
+/// Memory shown by the hex editor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemorySpace {
+    Cpu,
+    Ppu,
+    Oam,
+}
+
+/// Split pane debugger: disassembly on the left, registers, stack and breakpoints on
+/// the right, memory and command output below.
+/// Only the lines that changed since the last step are redrawn.
+pub struct DebuggerUi {
+    pub debugger: Debugger,
+    pub disassembler: Disassembler,
+    pub memory_space: MemorySpace,
+    pub memory_addr: u16,
+    /// Lines of the memory pane
+    pub memory_rows: usize,
+    /// Instructions shown before and after the program counter
+    pub code_rows: usize,
+    output: Vec<String>,
+    last_command: String,
+    /// Screen as it was last drawn
+    drawn: Vec<String>,
+}
+
+impl MemorySpace {
+    fn parse(text: &str) -> Option<MemorySpace> {
+        match text {
+            "cpu" => Some(MemorySpace::Cpu),
+            "ppu" => Some(MemorySpace::Ppu),
+            "oam" => Some(MemorySpace::Oam),
+            _ => None,
+        }
+    }
+    fn size(&self) -> usize {
+        match self {
+            MemorySpace::Cpu => 0x10000,
+            MemorySpace::Ppu => 0x4000,
+            MemorySpace::Oam => 0x100,
+        }
+    }
+    /// Read without side effects
+    pub fn read(&self, nes: &mut NesData, addr: u16) -> u8 {
+        match self {
+            MemorySpace::Cpu => nes.cpu_read(addr, true),
+            MemorySpace::Ppu => nes.ppu_read(addr & 0x3FFF, true),
+            MemorySpace::Oam => nes.ppu.oam[addr as usize & 0xFF],
+        }
+    }
+    pub fn write(&self, nes: &mut NesData, addr: u16, data: u8) {
+        match self {
+            MemorySpace::Cpu => nes.cpu_write(addr, data),
+            MemorySpace::Ppu => nes.ppu_write(addr & 0x3FFF, data),
+            MemorySpace::Oam => nes.ppu.oam[addr as usize & 0xFF] = data,
+        }
+    }
+}
+
+impl Default for DebuggerUi {
+    fn default() -> Self {
+        DebuggerUi::new()
+    }
+}
+
+impl DebuggerUi {
+    pub fn new() -> DebuggerUi {
+        DebuggerUi {
+            debugger: Debugger::new(),
+            disassembler: Disassembler::new(),
+            memory_space: MemorySpace::Cpu,
+            memory_addr: 0,
+            memory_rows: 8,
+            code_rows: 8,
+            output: Vec::new(),
+            last_command: "s".to_string(),
+            drawn: Vec::new(),
+        }
+    }
+
+    /// Read commands from the terminal until `quit`
+    pub fn run(&mut self, cpu: &mut CPU6502, nes: &mut NesData) -> io::Result<()> {
+        let term = Term::stdout();
+        term.clear_screen()?;
+        self.drawn.clear();
+        loop {
+            let screen = self.render(cpu, nes);
+            self.draw(&term, &screen)?;
+            let line = term.read_line()?;
+            if !self.execute(&line, cpu, nes) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run a command, returns false when the user wants to leave
+    pub fn execute(&mut self, line: &str, cpu: &mut CPU6502, nes: &mut NesData) -> bool {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            x => x.to_string(),
+        };
+        self.last_command = line.clone();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            ["q"] | ["quit"] => return false,
+            [x, args @ ..] if x.starts_with("x/") => self.examine(&x[2..], args, nes),
+            ["m", "+"] => self.scroll(1),
+            ["m", "-"] => self.scroll(-1),
+            ["m", args @ ..] => self.parse_location(args).map(|(space, addr)| {
+                self.memory_space = space;
+                self.memory_addr = addr;
+                Vec::new()
+            }),
+            ["e", args @ ..] => self.edit(args, nes),
+            ["sym", path] => match self.disassembler.symbols.load(path) {
+                Ok(()) => Ok(vec![format!(
+                    "{} labels known",
+                    self.disassembler.symbols.labels.len()
+                )]),
+                Err(e) => Err(e.to_string()),
+            },
+            _ => self
+                .debugger
+                .command(&line, cpu, nes)
+                .map(|x| x.lines().map(String::from).collect()),
+        };
+        self.output = match result {
+            Ok(lines) => lines,
+            Err(e) if e.starts_with("unknown command") => e
+                .lines()
+                .chain(TUI_HELP.lines())
+                .map(String::from)
+                .collect(),
+            Err(e) => vec![e],
+        };
+        true
+    }
+
+    /// `[cpu|ppu|oam] ADDR`, the space defaults to the one of the memory pane
+    fn parse_location(&self, args: &[&str]) -> Result<(MemorySpace, u16), String> {
+        let (space, addr) = match args {
+            [space, addr] => (
+                MemorySpace::parse(space).ok_or("expected cpu, ppu or oam")?,
+                addr,
+            ),
+            [addr] => (self.memory_space, addr),
+            _ => return Err("expected [cpu|ppu|oam] ADDR".to_string()),
+        };
+        Ok((space, parse_number(addr)? as u16))
+    }
+
+    fn examine(
+        &self,
+        count: &str,
+        args: &[&str],
+        nes: &mut NesData,
+    ) -> Result<Vec<String>, String> {
+        let count = parse_number(count)? as usize;
+        let (space, addr) = self.parse_location(args)?;
+        Ok(hex_dump(space, addr, count, nes))
+    }
+
+    fn scroll(&mut self, direction: i64) -> Result<Vec<String>, String> {
+        let page = (self.memory_rows * MEMORY_COLUMNS) as i64;
+        let size = self.memory_space.size() as i64;
+        self.memory_addr = (self.memory_addr as i64 + direction * page).rem_euclid(size) as u16;
+        Ok(Vec::new())
+    }
+
+    fn edit(&mut self, args: &[&str], nes: &mut NesData) -> Result<Vec<String>, String> {
+        let split = match args.first().and_then(|x| MemorySpace::parse(x)) {
+            Some(_) => 2,
+            None => 1,
+        };
+        if args.len() <= split {
+            return Err("expected [cpu|ppu|oam] ADDR BYTE..".to_string());
+        }
+        let (space, addr) = self.parse_location(&args[..split])?;
+        for (i, data) in args[split..].iter().enumerate() {
+            space.write(nes, addr.wrapping_add(i as u16), parse_number(data)? as u8);
+        }
+        Ok(vec![format!("{} bytes written", args.len() - split)])
+    }
+
+    /// Lines of the whole panel
+    pub fn render(&self, cpu: &CPU6502, nes: &mut NesData) -> Vec<String> {
+        let left = self.code_pane(cpu, nes);
+        let mut right = registers_pane(cpu);
+        right.push(String::new());
+        right.extend(stack_pane(cpu, nes));
+        right.push(String::new());
+        right.extend(self.breakpoints_pane());
+
+        let mut screen = Vec::new();
+        for i in 0..left.len().max(right.len()) {
+            let code = left.get(i).map_or("", |x| x.as_str());
+            let other = right.get(i).map_or("", |x| x.as_str());
+            screen.push(format!("{:<2$} | {}", code, other, LEFT_WIDTH));
+        }
+        screen.push("-".repeat(LEFT_WIDTH + 32));
+        screen.push(format!("Memory {:?}", self.memory_space));
+        screen.extend(hex_dump(
+            self.memory_space,
+            self.memory_addr,
+            self.memory_rows * MEMORY_COLUMNS,
+            nes,
+        ));
+        screen.push("-".repeat(LEFT_WIDTH + 32));
+        for i in 0..OUTPUT_ROWS {
+            screen.push(self.output.get(i).cloned().unwrap_or_default());
+        }
+        screen.push("> ".to_string());
+        screen
+    }
+
+    fn code_pane(&self, cpu: &CPU6502, nes: &mut NesData) -> Vec<String> {
+        let mut result = vec!["Code".to_string()];
+        for decoded in disassemble_around(
+            &self.disassembler,
+            nes,
+            cpu.pc,
+            self.code_rows / 2,
+            self.code_rows,
+        ) {
+            let marker = if decoded.addr == cpu.pc { ">" } else { " " };
+            let line = format!("{} {}", marker, self.disassembler.format_line(&decoded));
+            result.push(line.chars().take(LEFT_WIDTH).collect());
+        }
+        result
+    }
+
+    fn breakpoints_pane(&self) -> Vec<String> {
+        let mut result = vec!["Breakpoints".to_string()];
+        result.extend(self.debugger.list().lines().map(String::from));
+        result
+    }
+
+    /// Rewrite the lines that changed, the cursor is left on the command line
+    fn draw(&mut self, term: &Term, screen: &[String]) -> io::Result<()> {
+        for (i, line) in screen.iter().enumerate() {
+            if self.drawn.get(i) != Some(line) {
+                term.move_cursor_to(0, i)?;
+                term.clear_line()?;
+                term.write_str(line)?;
+            }
+        }
+        for i in screen.len()..self.drawn.len() {
+            term.move_cursor_to(0, i)?;
+            term.clear_line()?;
+        }
+        let prompt = screen.len().saturating_sub(1);
+        term.move_cursor_to(0, prompt)?;
+        term.clear_line()?;
+        term.write_str("> ")?;
+        self.drawn = screen.to_vec();
+        // The command typed by the user is on the prompt line, it is always redrawn
+        self.drawn[prompt].clear();
+        Ok(())
+    }
+}
+
+/// Instructions before and after `pc`. The 6502 cannot be decoded backward, so the
+/// earliest start that falls back on `pc` is used for the previous instructions.
+pub fn disassemble_around(
+    disassembler: &Disassembler,
+    nes: &mut NesData,
+    pc: u16,
+    before: usize,
+    total: usize,
+) -> Vec<Decoded> {
+    for distance in (1..=(before as u16 * 3)).rev() {
+        let start = pc.wrapping_sub(distance);
+        let mut lines = Vec::new();
+        let mut addr = start;
+        while lines.len() < before * 3 && addr.wrapping_sub(start) < distance {
+            let decoded = disassembler.decode(nes, addr);
+            addr = addr.wrapping_add(decoded.bytes.len() as u16);
+            lines.push(decoded);
+        }
+        if addr == pc {
+            let skip = lines.len().saturating_sub(before);
+            let mut result: Vec<Decoded> = lines.into_iter().skip(skip).collect();
+            let after = total.saturating_sub(result.len());
+            result.extend(disassembler.disassemble(nes, pc, after));
+            return result;
+        }
+    }
+    disassembler.disassemble(nes, pc, total)
+}
+
+fn registers_pane(cpu: &CPU6502) -> Vec<String> {
+    let flags: String = "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match cpu.status & (0x80 >> i) != 0 {
+            true => c,
+            false => c.to_ascii_lowercase(),
+        })
+        .collect();
+    vec![
+        "Registers".to_string(),
+        format!("A ${:02X}  X ${:02X}  Y ${:02X}", cpu.a, cpu.x, cpu.y),
+        format!("S ${:02X}  PC ${:04X}", cpu.stkp, cpu.pc),
+        format!("P ${:02X}  {}", cpu.status, flags),
+        format!("Cycles left {}", cpu.cycles),
+    ]
+}
+
+fn stack_pane(cpu: &CPU6502, nes: &mut NesData) -> Vec<String> {
+    let mut result = vec!["Stack".to_string()];
+    for i in 1..=STACK_ROWS as u16 {
+        let addr = 0x0100 + cpu.stkp as u16 + i;
+        if addr > 0x01FF {
+            break;
+        }
+        result.push(format!("${:04X}  {:02X}", addr, nes.cpu_read(addr, true)));
+    }
+    result
+}
+
+/// Lines of 16 bytes with their address and ASCII text
+pub fn hex_dump(space: MemorySpace, addr: u16, count: usize, nes: &mut NesData) -> Vec<String> {
+    let mut result = Vec::new();
+    for row in (0..count).step_by(MEMORY_COLUMNS) {
+        let start = (addr as usize + row) % space.size();
+        let bytes: Vec<u8> = (0..MEMORY_COLUMNS.min(count - row))
+            .map(|i| space.read(nes, ((start + i) % space.size()) as u16))
+            .collect();
+        let hex: Vec<String> = bytes.iter().map(|x| format!("{:02X}", x)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|x| match x {
+                0x20..=0x7E => *x as char,
+                _ => '.',
+            })
+            .collect();
+        result.push(format!("{:04X}  {:<47}  {}", start, hex.join(" "), text));
+    }
+    result
+}
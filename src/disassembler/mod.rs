@@ -0,0 +1,173 @@
+use super::components::*;
+
+pub use self::symbols::*;
+
+mod symbols;
+
+/// Accumulator versions of the shifts, written `ASL A` instead of a bare `ASL`
+const ACCUMULATOR_OPCODES: [u8; 4] = [0x0A, 0x2A, 0x4A, 0x6A];
+
+/// An instruction decoded from memory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decoded {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    /// `???` for the opcodes missing from the table
+    pub mnemonic: String,
+    pub addr_mode: String,
+    /// Operand as written in the instruction, the branch target for relative jumps
+    pub operand: u16,
+}
+
+/// Decodes the instructions found on the cpu bus
+pub struct Disassembler {
+    lookup: Vec<INSTRUCTION>,
+    pub symbols: Symbols,
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Disassembler::new()
+    }
+}
+
+/// Size of the operand of an addressing mode, in bytes
+fn operand_size(addr_mode: &str) -> u16 {
+    match addr_mode {
+        "IMM" | "ZP0" | "ZPX" | "ZPY" | "REL" | "IDX" | "IZX" | "IDY" | "IZY" => 1,
+        "ABS" | "ABX" | "ABY" | "IND" => 2,
+        _ => 0,
+    }
+}
+
+impl Disassembler {
+    pub fn new() -> Disassembler {
+        Disassembler {
+            lookup: get_lookup_list(),
+            symbols: Symbols::new(),
+        }
+    }
+
+    /// Decode the instruction at `addr`, memory is read without side effects
+    pub fn decode(&self, bus: &mut dyn DataActions, addr: u16) -> Decoded {
+        let opcode = bus.cpu_read(addr, true);
+        let instruction = &self.lookup[opcode as usize];
+        let size = operand_size(&instruction.addr_mode);
+        let mut bytes = vec![opcode];
+        for i in 1..=size {
+            bytes.push(bus.cpu_read(addr.wrapping_add(i), true));
+        }
+        let mut operand = match size {
+            1 => bytes[1] as u16,
+            2 => bytes[1] as u16 | (bytes[2] as u16) << 8,
+            _ => 0,
+        };
+        if instruction.addr_mode == "REL" {
+            operand = addr.wrapping_add(2).wrapping_add(bytes[1] as i8 as u16);
+        }
+        Decoded {
+            addr,
+            bytes,
+            mnemonic: instruction.opcode.clone(),
+            addr_mode: instruction.addr_mode.clone(),
+            operand,
+        }
+    }
+
+    /// Decode `count` instructions following each other from `addr`
+    pub fn disassemble(&self, bus: &mut dyn DataActions, addr: u16, count: usize) -> Vec<Decoded> {
+        let mut result = Vec::with_capacity(count);
+        let mut addr = addr;
+        for _ in 0..count {
+            let decoded = self.decode(bus, addr);
+            addr = addr.wrapping_add(decoded.bytes.len() as u16);
+            result.push(decoded);
+        }
+        result
+    }
+
+    /// Address read or written by the instruction with the current registers,
+    /// None for the implied and immediate modes
+    pub fn effective_address(
+        &self,
+        decoded: &Decoded,
+        cpu: &CPU6502,
+        bus: &mut dyn DataActions,
+    ) -> Option<u16> {
+        let operand = decoded.operand;
+        let mut read_word =
+            |lo: u16, hi: u16| bus.cpu_read(lo, true) as u16 | (bus.cpu_read(hi, true) as u16) << 8;
+        match decoded.addr_mode.as_str() {
+            "ZP0" | "ABS" | "REL" => Some(operand),
+            "ZPX" => Some((operand + cpu.x as u16) & 0x00FF),
+            "ZPY" => Some((operand + cpu.y as u16) & 0x00FF),
+            "ABX" => Some(operand.wrapping_add(cpu.x as u16)),
+            "ABY" => Some(operand.wrapping_add(cpu.y as u16)),
+            // The pointer does not cross pages, $xxFF reads its high byte from $xx00
+            "IND" => Some(read_word(
+                operand,
+                (operand & 0xFF00) | (operand.wrapping_add(1) & 0x00FF),
+            )),
+            "IDX" | "IZX" => {
+                let pointer = (operand + cpu.x as u16) & 0x00FF;
+                Some(read_word(pointer, (pointer + 1) & 0x00FF))
+            }
+            "IDY" | "IZY" => {
+                Some(read_word(operand, (operand + 1) & 0x00FF).wrapping_add(cpu.y as u16))
+            }
+            _ => None,
+        }
+    }
+
+    /// Assembly text of an instruction, addresses are replaced by their labels
+    pub fn format(&self, decoded: &Decoded) -> String {
+        let byte = format!("${:02X}", decoded.operand);
+        let word = self.address(decoded.operand, 4);
+        let zero_page = self.address(decoded.operand, 2);
+        let operand = match decoded.addr_mode.as_str() {
+            "IMP" if ACCUMULATOR_OPCODES.contains(&decoded.bytes[0]) => "A".to_string(),
+            "IMM" => format!("#{}", byte),
+            "ZP0" => zero_page,
+            "ZPX" => format!("{},X", zero_page),
+            "ZPY" => format!("{},Y", zero_page),
+            "IDX" | "IZX" => format!("({},X)", zero_page),
+            "IDY" | "IZY" => format!("({}),Y", zero_page),
+            "ABS" | "REL" => word,
+            "ABX" => format!("{},X", word),
+            "ABY" => format!("{},Y", word),
+            "IND" => format!("({})", word),
+            _ => String::new(),
+        };
+        let mnemonic = match decoded.mnemonic.as_str() {
+            "???" => format!(".db ${:02X}", decoded.bytes[0]),
+            x => x.to_string(),
+        };
+        match operand.is_empty() {
+            true => mnemonic,
+            false => format!("{} {}", mnemonic, operand),
+        }
+    }
+
+    /// Listing line with the address, its label, the raw bytes and the instruction
+    pub fn format_line(&self, decoded: &Decoded) -> String {
+        let bytes: Vec<String> = decoded.bytes.iter().map(|x| format!("{:02X}", x)).collect();
+        let label = match self.symbols.labels.get(&decoded.addr) {
+            Some(label) => format!("{}:", label.name),
+            None => String::new(),
+        };
+        format!(
+            "{:04X}  {:<8}  {:<12}{}",
+            decoded.addr,
+            bytes.join(" "),
+            label,
+            self.format(decoded)
+        )
+    }
+
+    fn address(&self, addr: u16, digits: usize) -> String {
+        match self.symbols.name(addr) {
+            Some(name) => name,
+            None => format!("${:01$X}", addr, digits),
+        }
+    }
+}
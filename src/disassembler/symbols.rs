@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Named address, `size` bytes long for arrays
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    pub size: u16,
+}
+
+/// Labels of the cpu address space, loaded from FCEUX `.nl` or ca65 `.dbg` files
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    pub labels: BTreeMap<u16, Label>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+    pub fn insert(&mut self, addr: u16, name: &str, size: u16) {
+        let label = Label {
+            name: name.to_string(),
+            size: size.max(1),
+        };
+        self.labels.insert(addr, label);
+    }
+
+    /// Name of an address, `name+offset` when it falls inside an array
+    pub fn name(&self, addr: u16) -> Option<String> {
+        let (start, label) = self.labels.range(..=addr).next_back()?;
+        match addr - start {
+            0 => Some(label.name.clone()),
+            offset if offset < label.size => Some(format!("{}+{}", label.name, offset)),
+            _ => None,
+        }
+    }
+
+    /// Add the labels of a symbol file, the format is chosen from the extension
+    pub fn load(&mut self, path: &str) -> io::Result<()> {
+        let text = fs::read_to_string(path)?;
+        let result = match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("nl") => self.parse_nl(&text),
+            Some("dbg") => self.parse_dbg(&text),
+            _ => Err("unknown symbol file, expected .nl or .dbg".to_string()),
+        };
+        result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+    }
+
+    /// FCEUX name list, one `$ADDR[/SIZE]#name#comment` per line
+    pub fn parse_nl(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(3, '#');
+            let location = fields.next().unwrap_or("");
+            let name = fields.next().unwrap_or("").trim();
+            let mut location = location.splitn(2, '/');
+            let addr = location.next().unwrap_or("").trim_start_matches('$');
+            let addr = u16::from_str_radix(addr, 16)
+                .map_err(|_| format!("line {}: bad address", i + 1))?;
+            let size = match location.next() {
+                Some(x) => u16::from_str_radix(x.trim_start_matches('$'), 16)
+                    .map_err(|_| format!("line {}: bad size", i + 1))?,
+                None => 1,
+            };
+            if !name.is_empty() {
+                self.insert(addr, name, size);
+            }
+        }
+        Ok(())
+    }
+
+    /// ca65/ld65 debug information, only the `sym` lines of type `lab` are used
+    pub fn parse_dbg(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let fields = match line.strip_prefix("sym\t") {
+                Some(x) => x,
+                None => continue,
+            };
+            let mut name = None;
+            let mut value = None;
+            let mut size = 1;
+            let mut label = false;
+            for field in fields.split(',') {
+                let mut pair = field.splitn(2, '=');
+                let key = pair.next().unwrap_or("");
+                let data = pair.next().unwrap_or("");
+                match key {
+                    "name" => name = Some(data.trim_matches('"')),
+                    "val" => {
+                        value = Some(
+                            parse_dbg_number(data)
+                                .ok_or_else(|| format!("line {}: bad value", i + 1))?,
+                        )
+                    }
+                    "size" => {
+                        size = parse_dbg_number(data)
+                            .ok_or_else(|| format!("line {}: bad size", i + 1))?
+                    }
+                    "type" => label = data == "lab",
+                    _ => (),
+                }
+            }
+            if let (true, Some(name), Some(value)) = (label, name, value) {
+                self.insert(value as u16, name, size as u16);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Numbers of the debug files are decimal or `0x` hexadecimal
+fn parse_dbg_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(x) => u32::from_str_radix(x, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
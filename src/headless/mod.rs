@@ -0,0 +1,278 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::archive::*;
+use super::audio::*;
+use super::components::*;
+use super::movie::*;
+use super::patch::*;
+use super::screenshot::*;
+use super::utils::parse_number;
+
+/// Command line help of the `rustnes-headless` binary
+pub const USAGE: &str = "usage: rustnes-headless <rom.nes|rom.unf|disk.fds|archive.zip|rom.gz> [options]
+
+options:
+    --frames N              maximum number of frames to run (default 600)
+    --until-pc ADDR         stop when the cpu reaches ADDR
+    --until-ram ADDR=VALUE  stop when the byte at ADDR equals VALUE
+    --movie FILE            feed the controllers from a movie (.fm2 or native)
+    --screenshot FILE       write the last frame as a PNG (.png) or PPM picture
+    --crop-overscan         remove the 8 lines hidden at the top and bottom of the screenshot
+    --scale N               enlarge the screenshot N times
+    --palette FILE          colors of the screenshot, a .pal file or one of 2c02, ntsc, grayscale
+    --ram-dump FILE         write the 2KB of internal ram
+    --wav FILE              write the audio as mono WAV
+    --wav-format s16|f32    16 bits integer (default) or 32 bits float WAV samples
+    --sample-rate N         audio sample rate in Hz (default 44100)
+    --exit-code ADDR        exit with the byte found at ADDR
+    --region ntsc|pal|dendy timings of the console (default: from the header)
+    --fds-bios FILE         BIOS of the Famicom Disk System, needed by .fds disk images
+    --disk-save FILE        where the disk is written when modified (default: the image with .sav)
+    --member NAME           file to load from a zip archive (default: the first rom found)
+    --patch FILE            apply an IPS, UPS or BPS patch to the rom
+    --soft-patch            apply the patch next to the rom with the same name, if there is one
+
+numbers are decimal, or hexadecimal when prefixed with $ or 0x.
+exit codes: 0 when done, 1 on error, 2 when a --until condition was never met.
+with --exit-code the byte at ADDR replaces 0 and 2, errors still exit with 1:
+keep 1 and 2 out of the codes of the rom, or tell them apart with the message on stderr.";
+
+/// What to run and what to write once done
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeadlessOptions {
+    pub rom: String,
+    pub frames: u64,
+    pub until: Vec<Condition>,
+    pub movie: Option<String>,
+    pub screenshot: Option<String>,
+    pub screenshot_options: ScreenshotOptions,
+    pub palette: Option<String>,
+    pub ram_dump: Option<String>,
+    pub wav: Option<String>,
+    pub wav_format: SampleFormat,
+    pub sample_rate: Option<u32>,
+    pub exit_code: Option<u16>,
+    pub fds_bios: Option<String>,
+    pub disk_save: Option<String>,
+    /// Replaces the region given by the header
+    pub region: Option<Region>,
+    /// File taken from a zip archive
+    pub member: Option<String>,
+    pub patch: Option<String>,
+    /// Look for a patch named like the rom
+    pub soft_patch: bool,
+}
+
+/// Condition ending the run before the frame limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Pc(u16),
+    Ram(u16, u8),
+}
+
+/// Summary of a headless run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunResult {
+    pub frames: u64,
+    /// Whether one of the `until` conditions stopped the run
+    pub condition_met: bool,
+    /// 0, or 2 when an `until` condition was never met, replaced by the byte of `--exit-code`
+    pub exit_code: i32,
+}
+
+impl HeadlessOptions {
+    pub fn parse(args: &[String]) -> Result<HeadlessOptions, String> {
+        let mut options = HeadlessOptions { frames: 600, ..HeadlessOptions::default() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if !options.rom.is_empty() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+                options.rom = arg.clone();
+                continue;
+            }
+            if arg == "--crop-overscan" {
+                options.screenshot_options.crop_overscan = true;
+                continue;
+            }
+            if arg == "--soft-patch" {
+                options.soft_patch = true;
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("missing value after {}", arg))?;
+            match arg.as_str() {
+                "--frames" => options.frames = parse_number(value)?,
+                "--until-pc" => options.until.push(Condition::Pc(parse_addr(value)?)),
+                "--until-ram" => {
+                    let mut parts = value.splitn(2, '=');
+                    let addr = parse_addr(parts.next().unwrap_or(""))?;
+                    let data = parse_number(parts.next().unwrap_or(""))? as u8;
+                    options.until.push(Condition::Ram(addr, data));
+                }
+                "--movie" => options.movie = Some(value.clone()),
+                "--screenshot" => options.screenshot = Some(value.clone()),
+                "--scale" => options.screenshot_options.scale = parse_number(value)?.max(1) as usize,
+                "--palette" => options.palette = Some(value.clone()),
+                "--ram-dump" => options.ram_dump = Some(value.clone()),
+                "--wav" => options.wav = Some(value.clone()),
+                "--wav-format" => options.wav_format = SampleFormat::parse(value)?,
+                "--sample-rate" => options.sample_rate = Some(parse_number(value)?.max(1) as u32),
+                "--exit-code" => options.exit_code = Some(parse_addr(value)?),
+                "--fds-bios" => options.fds_bios = Some(value.clone()),
+                "--disk-save" => options.disk_save = Some(value.clone()),
+                "--region" => options.region = Some(Region::parse(value)?),
+                "--member" => options.member = Some(value.clone()),
+                "--patch" => options.patch = Some(value.clone()),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+        if options.rom.is_empty() {
+            return Err("no rom given".to_string());
+        }
+        Ok(options)
+    }
+
+    /// Save file of a disk image, the image itself is never modified
+    pub fn disk_save_path(&self) -> String {
+        match &self.disk_save {
+            Some(path) => path.clone(),
+            None => Path::new(&self.rom).with_extension("sav").to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Load the rom, run it and write the requested outputs
+pub fn run(options: &HeadlessOptions) -> Result<RunResult, String> {
+    let rom = fs::read(&options.rom).map_err(|e| format!("{}: {}", options.rom, e))?;
+    let rom = unpack_rom(rom, options.member.as_deref()).map_err(|e| format!("{}: {}", options.rom, e))?;
+    let rom = patch_rom(rom, options)?;
+    let movie = match &options.movie {
+        Some(path) => Some(Movie::load(path).map_err(|e| format!("{}: {}", path, e))?),
+        None => None,
+    };
+
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    if let Some(path) = &options.palette {
+        nes.ppu.palette = Palette::load(path).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(rate) = options.sample_rate {
+        nes.apu.sample_rate = rate;
+    }
+    if is_fds_image(&rom) {
+        load_disk(&mut nes, &rom, options)?;
+    } else if is_unif_image(&rom) {
+        nes.insert_unif(&rom).map_err(|e| format!("{}: {}", options.rom, e))?;
+    } else {
+        nes.insert_cartridge(rom).map_err(|e| format!("{}: {}", options.rom, e))?;
+    }
+    if let Some(region) = options.region {
+        nes.set_region(region);
+    }
+    cpu.power(&mut nes);
+    if let Some(movie) = &movie {
+        movie.rewind(&mut cpu, &mut nes).map_err(|e| format!("movie: {}", e))?;
+    }
+
+    let mut result = RunResult { frames: 0, condition_met: false, exit_code: 0 };
+    let mut audio = Vec::new();
+    while result.frames < options.frames && !result.condition_met {
+        if let Some(frame) = movie.as_ref().and_then(|m| m.frames.get(result.frames as usize)) {
+            apply_frame(frame, &mut cpu, &mut nes);
+        }
+        result.condition_met = run_frame_until(&mut cpu, &mut nes, &options.until);
+        result.frames += 1;
+        if options.wav.is_some() {
+            audio.append(&mut nes.apu.samples);
+        } else {
+            nes.apu.samples.clear();
+        }
+    }
+
+    if let Some(path) = &options.screenshot {
+        Image::from_frame(&nes.ppu.frame_buffer, &nes.ppu.palette, &options.screenshot_options)
+            .save(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = &options.ram_dump {
+        fs::write(path, &nes.ram[0..0x0800]).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = &options.wav {
+        fs::write(path, samples_to_wav(&audio, nes.apu.sample_rate, options.wav_format)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if let Some(disk) = nes.cartridge.disk.as_ref().filter(|disk| disk.is_modified()) {
+        let path = options.disk_save_path();
+        fs::write(&path, disk.to_fds()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    result.exit_code = match options.exit_code {
+        Some(addr) => nes.cpu_read(addr, true) as i32,
+        None if !options.until.is_empty() && !result.condition_met => 2,
+        None => 0,
+    };
+    Ok(result)
+}
+
+/// Apply the patch given by `--patch`, or the one found next to the rom with `--soft-patch`
+fn patch_rom(rom: Vec<u8>, options: &HeadlessOptions) -> Result<Vec<u8>, String> {
+    let path = match (&options.patch, options.soft_patch) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, true) => match find_soft_patch(Path::new(&options.rom)) {
+            Some(path) => path,
+            None => return Ok(rom),
+        },
+        (None, false) => return Ok(rom),
+    };
+    let name = path.to_string_lossy().into_owned();
+    let patch = fs::read(&path).map_err(|e| format!("{}: {}", name, e))?;
+    apply_patch(&rom, &patch).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// Insert a disk image with the BIOS, and what was saved of the disk by a previous run
+fn load_disk(nes: &mut NesData, image: &[u8], options: &HeadlessOptions) -> Result<(), String> {
+    let path = options.fds_bios.as_ref().ok_or("disk images need the FDS BIOS, given by --fds-bios")?;
+    let bios = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    nes.insert_disk(image, bios).map_err(|e| format!("{}: {}", options.rom, e))?;
+    let path = options.disk_save_path();
+    if let Ok(save) = fs::read(&path) {
+        if let Some(disk) = nes.cartridge.disk.as_mut() {
+            disk.load_save(&save).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Run one frame, stopping early if a condition is met
+fn run_frame_until(cpu: &mut CPU6502, nes: &mut NesData, until: &[Condition]) -> bool {
+    if until.is_empty() {
+        nes.run_frame(cpu);
+        return false;
+    }
+    while !nes.ppu.frame_complete {
+        nes.clock(cpu);
+        if cpu.cycles == 0 && condition_met(cpu, nes, until) {
+            return true;
+        }
+    }
+    nes.ppu.frame_complete = false;
+    condition_met(cpu, nes, until)
+}
+
+/// Cpu address of an option, larger values are refused instead of wrapping around
+fn parse_addr(text: &str) -> Result<u16, String> {
+    let addr = parse_number(text)?;
+    if addr > 0xFFFF {
+        return Err(format!("address '{}' is above $FFFF", text));
+    }
+    Ok(addr as u16)
+}
+
+fn condition_met(cpu: &CPU6502, nes: &mut NesData, until: &[Condition]) -> bool {
+    until.iter().any(|c| match *c {
+        Condition::Pc(addr) => cpu.pc == addr,
+        Condition::Ram(addr, data) => nes.cpu_read(addr, true) == data,
+    })
+}
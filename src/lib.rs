@@ -0,0 +1,15 @@
+pub mod archive;
+pub mod assembler;
+pub mod audio;
+pub mod components;
+pub mod debugger;
+pub mod disassembler;
+pub mod headless;
+pub mod movie;
+pub mod nsf;
+pub mod patch;
+pub mod screenshot;
+pub mod terminal;
+#[cfg(test)]
+mod test;
+pub mod utils;
@@ -1,8 +1,5 @@
-mod components;
-mod test;
-mod utils;
-use components::*;
-use test::*;
+use rustnes::components::*;
+use rustnes::debugger::*;
 
 fn main() {
     let mut nes = NesData::new();
@@ -0,0 +1,151 @@
+use std::io;
+
+use super::*;
+
+/// Button letters of a FM2 input field, from the highest bit to the lowest
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Import and export of the FCEUX text movie format.
+/// Only the standard controllers are supported, the zapper and the four score are rejected.
+impl Movie {
+    pub fn from_fm2(text: &str) -> io::Result<Movie> {
+        let mut movie = Movie::new(MovieStart::PowerOn);
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                movie.frames.push(parse_input(line)?);
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(x) => (&line[..x], line[x + 1..].trim()),
+                None => (line, ""),
+            };
+            match key {
+                "version" if value != "3" => return Err(invalid_fm2("only FM2 version 3 is supported")),
+                "binary" if value == "1" => return Err(invalid_fm2("binary FM2 movies are not supported")),
+                "fourscore" | "port2" if value != "0" && !value.is_empty() => {
+                    return Err(invalid_fm2("only standard controllers are supported"))
+                }
+                "port0" | "port1" if value == "2" => {
+                    return Err(invalid_fm2("only standard controllers are supported"))
+                }
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or(0),
+                "palFlag" => movie.pal = value == "1",
+                "romFilename" => movie.rom_name = value.to_string(),
+                "romChecksum" => movie.rom_checksum = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "savestate" => {
+                    let data = decode_base64(value.trim_start_matches("base64:"))
+                        .ok_or_else(|| invalid_fm2("savestate is not valid base64"))?;
+                    if !data.starts_with(b"RNSS") {
+                        return Err(invalid_fm2("movies starting from a FCEUX savestate are not supported"));
+                    }
+                    movie.start = MovieStart::SaveState(SaveState { data });
+                }
+                _ => (),
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut result = String::new();
+        result += "version 3\n";
+        result += "emuVersion 22020\n";
+        result += format!("rerecordCount {}\n", self.rerecord_count).as_str();
+        result += format!("palFlag {}\n", self.pal as u8).as_str();
+        result += format!("romFilename {}\n", self.rom_name).as_str();
+        result += format!("romChecksum {}\n", self.rom_checksum).as_str();
+        result += "guid 00000000-0000-0000-0000-000000000000\n";
+        result += "fourscore 0\nmicrophone 0\nport0 1\nport1 1\nport2 0\nFDS 0\nNewPPU 0\n";
+        for comment in self.comments.iter() {
+            result += format!("comment {}\n", comment).as_str();
+        }
+        if let MovieStart::SaveState(state) = &self.start {
+            result += format!("savestate base64:{}\n", encode_base64(&state.data)).as_str();
+        }
+        for frame in self.frames.iter() {
+            result += format!(
+                "|{}|{}|{}||\n",
+                frame.command,
+                format_buttons(frame.ports[0]),
+                format_buttons(frame.ports[1])
+            )
+            .as_str();
+        }
+        result
+    }
+}
+
+/// Parse a `|commands|port0|port1|port2|` input line
+fn parse_input(line: &str) -> io::Result<MovieFrame> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 4 {
+        return Err(invalid_fm2("malformed input line"));
+    }
+    Ok(MovieFrame {
+        command: fields[1].trim().parse().map_err(|_| invalid_fm2("malformed command field"))?,
+        ports: [parse_buttons(fields[2])?, parse_buttons(fields[3])?],
+    })
+}
+
+fn parse_buttons(field: &str) -> io::Result<u8> {
+    if field.is_empty() {
+        return Ok(0);
+    }
+    if field.len() != 8 {
+        return Err(invalid_fm2("malformed controller field"));
+    }
+    Ok(field
+        .bytes()
+        .enumerate()
+        .filter(|(_, x)| *x != b'.' && *x != b' ')
+        .fold(0u8, |buttons, (i, _)| buttons | (0x80 >> i)))
+}
+
+fn format_buttons(buttons: u8) -> String {
+    FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, x)| match buttons & (0x80 >> i) != 0 {
+            true => *x as char,
+            false => '.',
+        })
+        .collect()
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in data.chunks(3) {
+        let block = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            result.push(match i <= chunk.len() {
+                true => BASE64[(block >> (18 - 6 * i) & 0x3F) as usize] as char,
+                false => '=',
+            });
+        }
+    }
+    result
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut block = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|x| *x != b'=') {
+        block = (block << 6 | BASE64.iter().position(|x| *x == c)? as u32) & 0xFFFF;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((block >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+fn invalid_fm2(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,203 @@
+mod fm2;
+
+use std::fs;
+use std::io;
+
+use super::components::*;
+
+/// Magic bytes of the native movie files
+const MOVIE_MAGIC: &[u8; 4] = b"RNM\x1A";
+const MOVIE_VERSION: u8 = 1;
+
+/// Commands that can be issued at the start of a frame, same values as FM2
+pub enum Command {
+    SoftReset = 1 << 0,
+    HardReset = 1 << 1,
+}
+
+/// Input of a single frame
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    /// Reset commands applied before the frame is run
+    pub command: u8,
+    /// Buttons held on both controller ports
+    pub ports: [u8; 2],
+}
+
+/// Where the movie starts from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieStart {
+    PowerOn,
+    SaveState(SaveState),
+}
+
+/// A recording of the controller inputs, frame by frame.
+/// Replaying it on the same rom from the same start gives the exact same emulation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_name: String,
+    /// Checksum of the rom, kept as found in the FM2 file
+    pub rom_checksum: String,
+    pub rerecord_count: u32,
+    pub pal: bool,
+    pub comments: Vec<String>,
+    pub start: MovieStart,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(start: MovieStart) -> Movie {
+        Movie {
+            rom_name: String::new(),
+            rom_checksum: String::new(),
+            rerecord_count: 0,
+            pal: false,
+            comments: Vec::new(),
+            start,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Put the console in the state the movie starts from
+    pub fn rewind(&self, cpu: &mut CPU6502, nes: &mut NesData) -> io::Result<()> {
+        match &self.start {
+            MovieStart::PowerOn => {
+                nes.reset_memory();
+                cpu.power(nes);
+                Ok(())
+            }
+            MovieStart::SaveState(state) => state.restore(cpu, nes),
+        }
+    }
+
+    /// Record the input currently held on the controllers and run the frame
+    pub fn record_frame(&mut self, command: u8, cpu: &mut CPU6502, nes: &mut NesData) {
+        let frame = MovieFrame {
+            command,
+            ports: [nes.controllers[0].buttons, nes.controllers[1].buttons],
+        };
+        self.frames.push(frame);
+        apply_frame(&frame, cpu, nes);
+        nes.run_frame(cpu);
+    }
+
+    /// Feed the input of the given frame and run it, returns false once the movie is over
+    pub fn play_frame(&self, frame: usize, cpu: &mut CPU6502, nes: &mut NesData) -> bool {
+        match self.frames.get(frame) {
+            Some(x) => {
+                apply_frame(x, cpu, nes);
+                nes.run_frame(cpu);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Load a movie, FM2 files are detected by their extension
+    pub fn load(path: &str) -> io::Result<Movie> {
+        if path.to_lowercase().ends_with(".fm2") {
+            Movie::from_fm2(&fs::read_to_string(path)?)
+        } else {
+            Movie::from_bytes(&fs::read(path)?)
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = MOVIE_MAGIC.to_vec();
+        result.push(MOVIE_VERSION);
+        result.push(self.pal as u8);
+        result.extend_from_slice(&self.rerecord_count.to_le_bytes());
+        for text in [&self.rom_name, &self.rom_checksum].iter() {
+            push_block(&mut result, text.as_bytes());
+        }
+        push_block(&mut result, self.comments.join("\n").as_bytes());
+        match &self.start {
+            MovieStart::PowerOn => push_block(&mut result, &[]),
+            MovieStart::SaveState(state) => push_block(&mut result, &state.data),
+        }
+        result.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in self.frames.iter() {
+            result.extend_from_slice(&[frame.command, frame.ports[0], frame.ports[1]]);
+        }
+        result
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Movie> {
+        if data.len() < 10 || &data[0..4] != MOVIE_MAGIC {
+            return Err(invalid_movie("not a movie file"));
+        }
+        if data[4] != MOVIE_VERSION {
+            return Err(invalid_movie("unsupported movie version"));
+        }
+        let mut offset = 10;
+        let rom_name = String::from_utf8_lossy(read_block(data, &mut offset)?).to_string();
+        let rom_checksum = String::from_utf8_lossy(read_block(data, &mut offset)?).to_string();
+        let comments = String::from_utf8_lossy(read_block(data, &mut offset)?).to_string();
+        let start = match read_block(data, &mut offset)? {
+            [] => MovieStart::PowerOn,
+            x => MovieStart::SaveState(SaveState { data: x.to_vec() }),
+        };
+        let nb_frames = read_u32(data, &mut offset)? as usize;
+        let inputs = data
+            .get(offset..offset + nb_frames * 3)
+            .ok_or_else(|| invalid_movie("movie is truncated"))?;
+
+        Ok(Movie {
+            rom_name,
+            rom_checksum,
+            rerecord_count: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+            pal: data[5] != 0,
+            comments: match comments.is_empty() {
+                true => Vec::new(),
+                false => comments.lines().map(|x| x.to_string()).collect(),
+            },
+            start,
+            frames: inputs
+                .chunks(3)
+                .map(|x| MovieFrame { command: x[0], ports: [x[1], x[2]] })
+                .collect(),
+        })
+    }
+}
+
+/// Apply the commands then the controller input of a frame
+pub fn apply_frame(frame: &MovieFrame, cpu: &mut CPU6502, nes: &mut NesData) {
+    if frame.command & Command::HardReset as u8 != 0 {
+        nes.reset_memory();
+        cpu.power(nes);
+    } else if frame.command & Command::SoftReset as u8 != 0 {
+        cpu.reset(nes);
+    }
+    nes.controllers[0].buttons = frame.ports[0];
+    nes.controllers[1].buttons = frame.ports[1];
+}
+
+fn push_block(result: &mut Vec<u8>, block: &[u8]) {
+    result.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    result.extend_from_slice(block);
+}
+
+fn read_u32(data: &[u8], offset: &mut usize) -> io::Result<u32> {
+    let bytes = data
+        .get(*offset..*offset + 4)
+        .ok_or_else(|| invalid_movie("movie is truncated"))?;
+    *offset += 4;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_block<'a>(data: &'a [u8], offset: &mut usize) -> io::Result<&'a [u8]> {
+    let length = read_u32(data, offset)? as usize;
+    let block = data
+        .get(*offset..*offset + length)
+        .ok_or_else(|| invalid_movie("movie is truncated"))?;
+    *offset += length;
+    Ok(block)
+}
+
+fn invalid_movie(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,441 @@
+use std::fs;
+
+use super::archive::unpack_rom;
+use super::audio::*;
+use super::components::*;
+use super::utils::parse_number;
+
+/// Command line help of the `rustnes-nsf` binary
+pub const USAGE: &str = "usage: rustnes-nsf <music.nsf> [options]
+
+options:
+    --track N               track to play, from 1 (default: the starting track of the file)
+    --seconds N             length of the rendered audio (default 120)
+    --wav FILE              write the track as mono WAV, without it the file is only described
+    --wav-format s16|f32    16 bits integer (default) or 32 bits float WAV samples
+    --sample-rate N         audio sample rate in Hz (default 44100)
+
+NSF and NSFe files are supported.";
+
+/// The player calls INIT and PLAY as subroutines returning here, nothing is mapped there
+const RETURN_ADDR: u16 = 0x4100;
+/// Cycles INIT may take before it is considered stuck
+const INIT_CYCLES: u64 = CPU_FREQUENCY;
+/// Speed of the tunes which do not give one, in microseconds between PLAY calls
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+const DEFAULT_PAL_SPEED: u16 = 19997;
+
+/// A NSF or NSFe music file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Nsf {
+    pub version: u8,
+    pub songs: u8,
+    /// First song to play, from 0
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub name: String,
+    pub artist: String,
+    pub copyright: String,
+    /// Microseconds between two calls of PLAY on a NTSC console
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    /// Banks mapped from $8000 when the tune starts, all zeros when it does not switch banks
+    pub banks: [u8; 8],
+    /// Bit 0 is set for PAL tunes, bit 1 for tunes playing on both systems
+    pub region: u8,
+    /// Expansion audio chips: VRC6, VRC7, FDS, MMC5, Namco 163 and Sunsoft 5B from bit 0
+    pub expansion: u8,
+    /// Names of the tracks, only given by NSFe files
+    pub track_names: Vec<String>,
+    pub data: Vec<u8>,
+}
+
+/// Plays the tracks of a NSF on the cpu and the APU, without any PPU
+pub struct NsfPlayer {
+    pub nsf: Nsf,
+    pub cpu: CPU6502,
+    pub nes: NesData,
+    /// Track playing, from 0
+    pub track: u8,
+    /// Cpu cycles between two calls of PLAY
+    pub play_period: u64,
+    play_timer: u64,
+    /// Set while INIT or PLAY has not returned
+    busy: bool,
+}
+
+/// What the `rustnes-nsf` binary renders
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NsfOptions {
+    pub file: String,
+    /// From 0
+    pub track: Option<u8>,
+    pub seconds: u64,
+    pub wav: Option<String>,
+    pub wav_format: SampleFormat,
+    pub sample_rate: Option<u32>,
+}
+
+impl Nsf {
+    pub fn parse(data: &[u8]) -> Result<Nsf, String> {
+        if data.starts_with(b"NESM\x1A") {
+            Nsf::parse_nsf(data)
+        } else if data.starts_with(b"NSFE") {
+            Nsf::parse_nsfe(data)
+        } else {
+            Err("not a NSF file".to_string())
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Nsf, String> {
+        let data = fs::read(path).map_err(|e| e.to_string()).and_then(|x| unpack_rom(x, None));
+        let data = data.map_err(|e| format!("{}: {}", path, e))?;
+        Nsf::parse(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn parse_nsf(data: &[u8]) -> Result<Nsf, String> {
+        if data.len() < 0x80 {
+            return Err("truncated NSF header".to_string());
+        }
+        let word = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let mut banks = [0u8; 8];
+        banks.copy_from_slice(&data[0x70..0x78]);
+        Ok(Nsf {
+            version: data[0x05],
+            songs: data[0x06],
+            starting_song: data[0x07].saturating_sub(1),
+            load_addr: word(0x08),
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            name: text(&data[0x0E..0x2E]),
+            artist: text(&data[0x2E..0x4E]),
+            copyright: text(&data[0x4E..0x6E]),
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            banks,
+            region: data[0x7A] & 0x03,
+            expansion: data[0x7B],
+            track_names: Vec::new(),
+            data: data[0x80..].to_vec(),
+        })
+    }
+
+    /// Chunks of a length, a 4 characters name and the data, after the "NSFE" tag
+    fn parse_nsfe(data: &[u8]) -> Result<Nsf, String> {
+        let mut nsf = Nsf {
+            version: 1,
+            songs: 1,
+            ntsc_speed: DEFAULT_NTSC_SPEED,
+            pal_speed: DEFAULT_PAL_SPEED,
+            ..Nsf::default()
+        };
+        let mut info = false;
+        let mut offset = 4;
+        while offset + 8 <= data.len() {
+            let size = u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]) as usize;
+            let name = &data[offset + 4..offset + 8];
+            let chunk = data
+                .get(offset + 8..offset + 8 + size)
+                .ok_or_else(|| format!("truncated {} chunk", String::from_utf8_lossy(name)))?;
+            let word = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+            match name {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err("truncated INFO chunk".to_string());
+                    }
+                    nsf.load_addr = word(0);
+                    nsf.init_addr = word(2);
+                    nsf.play_addr = word(4);
+                    nsf.region = chunk[6] & 0x03;
+                    nsf.expansion = chunk[7];
+                    nsf.songs = chunk.get(8).copied().unwrap_or(1);
+                    nsf.starting_song = chunk.get(9).copied().unwrap_or(0);
+                    info = true;
+                }
+                b"DATA" => nsf.data = chunk.to_vec(),
+                b"BANK" => {
+                    for (bank, value) in nsf.banks.iter_mut().zip(chunk.iter()) {
+                        *bank = *value;
+                    }
+                }
+                b"RATE" if chunk.len() >= 2 => {
+                    nsf.ntsc_speed = word(0);
+                    if chunk.len() >= 4 {
+                        nsf.pal_speed = word(2);
+                    }
+                }
+                b"auth" => {
+                    let mut fields = chunk.split(|x| *x == 0).map(text);
+                    nsf.name = fields.next().unwrap_or_default();
+                    nsf.artist = fields.next().unwrap_or_default();
+                    nsf.copyright = fields.next().unwrap_or_default();
+                }
+                b"tlbl" => {
+                    nsf.track_names = chunk.split(|x| *x == 0).map(text).collect();
+                    nsf.track_names.truncate(nsf.songs as usize);
+                }
+                b"NEND" => break,
+                // Unknown chunks starting with an uppercase letter must be understood
+                x if x[0].is_ascii_uppercase() && x != b"RATE" => {
+                    return Err(format!(
+                        "unsupported NSFe chunk {}",
+                        String::from_utf8_lossy(x)
+                    ))
+                }
+                _ => (),
+            }
+            offset += 8 + size;
+        }
+        if !info {
+            return Err("NSFe file without INFO chunk".to_string());
+        }
+        Ok(nsf)
+    }
+
+    pub fn bankswitched(&self) -> bool {
+        self.banks.iter().any(|x| *x != 0)
+    }
+
+    /// Tune data laid out in 4KB banks, and the banks mapped from $8000 at start
+    pub fn prg(&self) -> (Vec<u8>, [u8; 8]) {
+        if self.bankswitched() {
+            let mut prg = vec![0u8; self.load_addr as usize & 0x0FFF];
+            prg.extend_from_slice(&self.data);
+            prg.resize((prg.len() + 0x0FFF) & !0x0FFF, 0);
+            (prg, self.banks)
+        } else {
+            let mut prg = vec![0u8; 0x8000];
+            let start = (self.load_addr as usize).saturating_sub(0x8000);
+            let end = (start + self.data.len()).min(0x8000);
+            prg[start..end].copy_from_slice(&self.data[..end - start]);
+            (prg, [0, 1, 2, 3, 4, 5, 6, 7])
+        }
+    }
+
+    /// Title of a track, from 0
+    pub fn track_name(&self, track: u8) -> String {
+        match self.track_names.get(track as usize) {
+            Some(x) if !x.is_empty() => x.clone(),
+            _ => format!("track {}", track + 1),
+        }
+    }
+
+    /// Only PAL consoles can play it
+    pub fn pal_only(&self) -> bool {
+        self.region & 0x03 == 0x01
+    }
+}
+
+impl NsfPlayer {
+    /// Initialize the starting track of the file, which may be out of range
+    pub fn new(nsf: Nsf) -> Result<NsfPlayer, String> {
+        let track = nsf.starting_song;
+        NsfPlayer::with_track(nsf, track)
+    }
+
+    /// Initialize a track, from 0, without running the starting track first
+    pub fn with_track(nsf: Nsf, track: u8) -> Result<NsfPlayer, String> {
+        let mut player = NsfPlayer {
+            nsf,
+            cpu: CPU6502::new(),
+            nes: NesData::new(),
+            track: 0,
+            play_period: 0,
+            play_timer: 0,
+            busy: false,
+        };
+        player.select_track(track)?;
+        Ok(player)
+    }
+
+    /// Restart the console and run INIT for a track, from 0
+    pub fn select_track(&mut self, track: u8) -> Result<(), String> {
+        if track >= self.nsf.songs {
+            return Err(format!(
+                "track {} does not exist, there are {} tracks",
+                track + 1,
+                self.nsf.songs
+            ));
+        }
+        let sample_rate = self.nes.apu.sample_rate;
+        self.nes = NesData::new();
+        self.nes.apu.sample_rate = sample_rate;
+        let (prg, banks) = self.nsf.prg();
+        self.nes.cartridge.load_nsf(prg, banks);
+        self.nes.cartridge.audio = ExpansionAudio::from_nsf_flags(self.nsf.expansion);
+        for addr in 0x4000..=0x4013 {
+            self.nes.cpu_write(addr, 0);
+        }
+        self.nes.cpu_write(0x4015, 0x00);
+        self.nes.cpu_write(0x4015, 0x0F);
+        self.nes.cpu_write(0x4017, 0x40);
+
+        let (region, speed) = match self.nsf.pal_only() {
+            true => (1, self.nsf.pal_speed),
+            false => (0, self.nsf.ntsc_speed),
+        };
+        let speed = match (speed, region) {
+            (0, 0) => DEFAULT_NTSC_SPEED,
+            (0, _) => DEFAULT_PAL_SPEED,
+            (x, _) => x,
+        };
+        if region == 1 {
+            self.nes.set_region(Region::Pal);
+        }
+        self.play_period = speed as u64 * self.nes.region.cpu_frequency() / 1_000_000;
+        self.track = track;
+
+        self.cpu = CPU6502::new();
+        self.cpu.a = track;
+        self.cpu.x = region;
+        self.cpu.stkp = 0xFD;
+        self.call(self.nsf.init_addr);
+        for _ in 0..INIT_CYCLES {
+            if !self.busy {
+                break;
+            }
+            self.clock_cpu();
+        }
+        self.busy = false;
+        self.play_timer = 0;
+        self.nes.apu.samples.clear();
+        Ok(())
+    }
+
+    /// Advance by one cpu cycle, PLAY is called every `play_period` cycles
+    pub fn clock(&mut self) {
+        self.play_timer += 1;
+        if self.play_timer >= self.play_period {
+            self.play_timer = 0;
+            // A PLAY still running is not interrupted, the call is skipped
+            if !self.busy {
+                self.call(self.nsf.play_addr);
+            }
+        }
+        self.clock_cpu();
+    }
+
+    /// Audio of the next `cycles` cpu cycles
+    pub fn render(&mut self, cycles: u64) -> Vec<f32> {
+        for _ in 0..cycles {
+            self.clock();
+        }
+        std::mem::take(&mut self.nes.apu.samples)
+    }
+
+    pub fn render_seconds(&mut self, seconds: u64) -> Vec<f32> {
+        self.render(seconds * self.nes.region.cpu_frequency())
+    }
+
+    /// Run a subroutine, it returns to `RETURN_ADDR`
+    fn call(&mut self, addr: u16) {
+        let ret = RETURN_ADDR - 1;
+        self.nes
+            .cpu_write(0x0100 + self.cpu.stkp as u16, (ret >> 8) as u8);
+        self.cpu.stkp = self.cpu.stkp.wrapping_sub(1);
+        self.nes.cpu_write(0x0100 + self.cpu.stkp as u16, ret as u8);
+        self.cpu.stkp = self.cpu.stkp.wrapping_sub(1);
+        self.cpu.pc = addr;
+        self.cpu.cycles = 0;
+        self.busy = true;
+    }
+
+    fn clock_cpu(&mut self) {
+        if self.busy && self.cpu.cycles == 0 && self.cpu.pc == RETURN_ADDR {
+            self.busy = false;
+        }
+        if self.busy {
+            self.cpu.clock(&mut self.nes);
+        }
+        self.nes.clock_apu();
+    }
+}
+
+impl NsfOptions {
+    pub fn parse(args: &[String]) -> Result<NsfOptions, String> {
+        let mut options = NsfOptions {
+            seconds: 120,
+            ..NsfOptions::default()
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if !options.file.is_empty() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+                options.file = arg.clone();
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value after {}", arg))?;
+            match arg.as_str() {
+                "--track" => match parse_number(value)? {
+                    x @ 1..=255 => options.track = Some((x - 1) as u8),
+                    _ => return Err("tracks are numbered from 1 to 255".to_string()),
+                },
+                "--seconds" => options.seconds = parse_number(value)?,
+                "--wav" => options.wav = Some(value.clone()),
+                "--wav-format" => options.wav_format = SampleFormat::parse(value)?,
+                "--sample-rate" => options.sample_rate = Some(parse_number(value)?.max(1) as u32),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+        if options.file.is_empty() {
+            return Err("no file given".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Describe the file and render the chosen track
+pub fn run(options: &NsfOptions) -> Result<String, String> {
+    let nsf = Nsf::load(&options.file)?;
+    let mut result = format!(
+        "{}\n{}\n{}\n{} tracks, starting with {}\n",
+        nsf.name,
+        nsf.artist,
+        nsf.copyright,
+        nsf.songs,
+        nsf.starting_song + 1
+    );
+    for track in 0..nsf.songs {
+        result += &format!("  {:3}  {}\n", track + 1, nsf.track_name(track));
+    }
+    let path = match &options.wav {
+        Some(x) => x,
+        None => return Ok(result),
+    };
+
+    let track = options.track.unwrap_or(nsf.starting_song);
+    let mut player = NsfPlayer::with_track(nsf, track)?;
+    if let Some(rate) = options.sample_rate {
+        player.nes.apu.sample_rate = rate;
+    }
+    let samples = player.render_seconds(options.seconds);
+    fs::write(
+        path,
+        samples_to_wav(&samples, player.nes.apu.sample_rate, options.wav_format),
+    )
+    .map_err(|e| format!("{}: {}", path, e))?;
+    result += &format!(
+        "{} written to {}, {} seconds",
+        player.nsf.track_name(track),
+        path,
+        options.seconds
+    );
+    Ok(result)
+}
+
+/// String of a zero padded field
+fn text(data: &[u8]) -> String {
+    let end = data.iter().position(|x| *x == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
@@ -0,0 +1,89 @@
+use super::{check_crc, read_checksums, read_number, read_size};
+
+/// BPS patch: the result is built by copying runs from the rom, from the patch or from
+/// what was already written. The rom and the result are checked against the CRC32 of the patch.
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"BPS1") {
+        return Err("not a BPS patch".to_string());
+    }
+    let [source_crc, target_crc, _] = read_checksums(patch)?;
+    let end = patch.len() - 12;
+    let mut offset = 4;
+    let source_size = read_size(patch, &mut offset, "rom")?;
+    let target_size = read_size(patch, &mut offset, "patched rom")?;
+    let metadata_size = read_size(patch, &mut offset, "metadata")?;
+    offset += metadata_size;
+    if offset > end {
+        return Err("the BPS patch is truncated".to_string());
+    }
+    if rom.len() != source_size {
+        return Err(format!(
+            "the patch expects a rom of {} bytes, not {}",
+            source_size,
+            rom.len()
+        ));
+    }
+    check_crc(rom, source_crc, "rom")?;
+
+    let invalid = || "the BPS patch reads outside of the roms".to_string();
+    // Every run is checked against the rest of the patch and of the roms before it is copied
+    let range = |start: usize, length: usize| start.checked_add(length).map(|end| start..end);
+    let mut result: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_relative: usize = 0;
+    let mut target_relative: usize = 0;
+    while offset < end {
+        let action = read_number(patch, &mut offset)?;
+        let length = (action >> 2) + 1;
+        if length > target_size - result.len() {
+            return Err("the BPS patch writes past the end of the rom".to_string());
+        }
+        match action & 0x03 {
+            // Source read, the rom at the same position
+            0 => {
+                let run = range(result.len(), length).ok_or_else(invalid)?;
+                result.extend_from_slice(rom.get(run).ok_or_else(invalid)?);
+            }
+            // Target read, bytes of the patch
+            1 => {
+                let data = patch[..end]
+                    .get(range(offset, length).ok_or_else(invalid)?)
+                    .ok_or_else(invalid)?;
+                result.extend_from_slice(data);
+                offset += length;
+            }
+            // Source copy, anywhere in the rom
+            2 => {
+                source_relative = move_relative(source_relative, read_number(patch, &mut offset)?)
+                    .ok_or_else(invalid)?;
+                let data = rom
+                    .get(range(source_relative, length).ok_or_else(invalid)?)
+                    .ok_or_else(invalid)?;
+                result.extend_from_slice(data);
+                source_relative += length;
+            }
+            // Target copy, from the result itself, one byte at a time as the run can overlap
+            _ => {
+                target_relative = move_relative(target_relative, read_number(patch, &mut offset)?)
+                    .ok_or_else(invalid)?;
+                for _ in 0..length {
+                    let byte = *result.get(target_relative).ok_or_else(invalid)?;
+                    result.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+    if result.len() != target_size {
+        return Err("the BPS patch is truncated".to_string());
+    }
+    check_crc(&result, target_crc, "patched rom")?;
+    Ok(result)
+}
+
+/// Signed move of a copy position, the sign is in the low bit
+fn move_relative(position: usize, data: usize) -> Option<usize> {
+    match data & 0x01 {
+        0 => position.checked_add(data >> 1),
+        _ => position.checked_sub(data >> 1),
+    }
+}
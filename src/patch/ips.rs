@@ -0,0 +1,50 @@
+/// Offset of a record made of the bytes "EOF", it ends the patch
+const IPS_EOF: usize = 0x454F46;
+
+/// IPS patch: records of bytes written at 24 bits offsets, a record of size 0 repeats a
+/// single byte. The rom grows when a record goes past its end, and the offset found after
+/// the end marker truncates it.
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"PATCH") {
+        return Err("not an IPS patch".to_string());
+    }
+    let mut result = rom.to_vec();
+    let mut offset = 5;
+    let bytes = |offset: usize, length: usize| {
+        patch
+            .get(offset..offset + length)
+            .ok_or_else(|| "the IPS patch is truncated".to_string())
+    };
+    loop {
+        let address = bytes(offset, 3)?;
+        let address =
+            (address[0] as usize) << 16 | (address[1] as usize) << 8 | address[2] as usize;
+        offset += 3;
+        if address == IPS_EOF {
+            break;
+        }
+        let size = bytes(offset, 2)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+        offset += 2;
+        let data = match size {
+            0 => {
+                let run = bytes(offset, 3)?;
+                offset += 3;
+                vec![run[2]; (run[0] as usize) << 8 | run[1] as usize]
+            }
+            _ => {
+                let data = bytes(offset, size)?.to_vec();
+                offset += size;
+                data
+            }
+        };
+        if result.len() < address + data.len() {
+            result.resize(address + data.len(), 0);
+        }
+        result[address..address + data.len()].copy_from_slice(&data);
+    }
+    if let Ok(size) = bytes(offset, 3) {
+        result.truncate((size[0] as usize) << 16 | (size[1] as usize) << 8 | size[2] as usize);
+    }
+    Ok(result)
+}
@@ -0,0 +1,97 @@
+mod bps;
+mod ips;
+mod ups;
+
+pub use self::bps::apply_bps;
+pub use self::ips::apply_ips;
+pub use self::ups::apply_ups;
+
+use std::path::{Path, PathBuf};
+
+/// Extensions of the patch files, in the order they are searched for a soft patch
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// Largest rom or metadata a UPS or BPS patch may ask for, checked before allocating
+pub const MAX_PATCHED_SIZE: usize = 16 << 20;
+
+/// Patched copy of a rom, the format is found from the magic bytes of the patch
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err("unknown patch format, expected IPS, UPS or BPS".to_string())
+    }
+}
+
+/// Patch next to a rom with the same name, like `game.ips` for `game.nes`
+pub fn find_soft_patch(rom: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+/// Variable length number of UPS and BPS, 7 bits per byte with the last one flagged
+fn read_number(patch: &[u8], offset: &mut usize) -> Result<usize, String> {
+    let mut result: usize = 0;
+    let mut shift: usize = 1;
+    loop {
+        let byte = *patch.get(*offset).ok_or("the patch is truncated")?;
+        *offset += 1;
+        result = (byte as usize & 0x7F)
+            .checked_mul(shift)
+            .and_then(|x| x.checked_add(result))
+            .ok_or("invalid number in the patch")?;
+        if byte & 0x80 != 0 {
+            return Ok(result);
+        }
+        shift = shift.checked_shl(7).ok_or("invalid number in the patch")?;
+        result = result
+            .checked_add(shift)
+            .ok_or("invalid number in the patch")?;
+    }
+}
+
+/// Size read from a UPS or BPS patch, refused above `MAX_PATCHED_SIZE`
+fn read_size(patch: &[u8], offset: &mut usize, what: &str) -> Result<usize, String> {
+    let size = read_number(patch, offset)?;
+    if size > MAX_PATCHED_SIZE {
+        return Err(format!(
+            "the patch asks for {} bytes of {}, more than {}",
+            size, what, MAX_PATCHED_SIZE
+        ));
+    }
+    Ok(size)
+}
+
+/// The 3 CRC32 ending UPS and BPS patches, of the source, the target and the patch itself
+fn read_checksums(patch: &[u8]) -> Result<[u32; 3], String> {
+    if patch.len() < 16 {
+        return Err("the patch is truncated".to_string());
+    }
+    let footer = &patch[patch.len() - 12..];
+    let crc =
+        |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+    let checksums = [crc(0), crc(4), crc(8)];
+    if crc32fast::hash(&patch[..patch.len() - 4]) != checksums[2] {
+        return Err("the patch is corrupted, its checksum does not match".to_string());
+    }
+    Ok(checksums)
+}
+
+/// Check the rom given to a UPS or BPS patch and the result
+fn check_crc(data: &[u8], expected: u32, what: &str) -> Result<(), String> {
+    match crc32fast::hash(data) == expected {
+        true => Ok(()),
+        false => Err(format!(
+            "the {} checksum does not match the patch ({:08X} instead of {:08X})",
+            what,
+            crc32fast::hash(data),
+            expected
+        )),
+    }
+}
@@ -0,0 +1,47 @@
+use super::{check_crc, read_checksums, read_number, read_size};
+
+/// UPS patch: the bytes to XOR with the rom, after runs of unchanged bytes.
+/// The rom and the result are checked against the CRC32 of the patch.
+pub fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"UPS1") {
+        return Err("not an UPS patch".to_string());
+    }
+    let [source_crc, target_crc, _] = read_checksums(patch)?;
+    let end = patch.len() - 12;
+    let mut offset = 4;
+    let source_size = read_size(patch, &mut offset, "rom")?;
+    let target_size = read_size(patch, &mut offset, "patched rom")?;
+    if rom.len() != source_size {
+        return Err(format!(
+            "the patch expects a rom of {} bytes, not {}",
+            source_size,
+            rom.len()
+        ));
+    }
+    check_crc(rom, source_crc, "rom")?;
+
+    let mut result = rom.to_vec();
+    result.resize(target_size, 0);
+    let mut position: usize = 0;
+    while offset < end {
+        position = position
+            .checked_add(read_number(patch, &mut offset)?)
+            .ok_or("the UPS patch skips past the end of the rom")?;
+        loop {
+            let byte = *patch[..end]
+                .get(offset)
+                .ok_or("the UPS patch is truncated")?;
+            offset += 1;
+            if byte == 0 {
+                position = position.saturating_add(1);
+                break;
+            }
+            if let Some(x) = result.get_mut(position) {
+                *x ^= byte;
+            }
+            position = position.saturating_add(1);
+        }
+    }
+    check_crc(&result, target_crc, "patched rom")?;
+    Ok(result)
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::io;
+
+use super::components::*;
+
+/// Lines hidden by most NTSC televisions at the top and at the bottom of the picture
+pub const OVERSCAN_LINES: usize = 8;
+
+/// File format of a screenshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+/// How the frame buffer is turned into a picture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenshotOptions {
+    /// Remove the lines of `OVERSCAN_LINES`
+    pub crop_overscan: bool,
+    /// Every pixel becomes a square of `scale` x `scale` pixels
+    pub scale: usize,
+}
+
+/// RGB picture, 3 bytes per pixel from the top left corner
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl ImageFormat {
+    /// `.png` files are PNG, anything else is PPM
+    pub fn from_path(path: &str) -> ImageFormat {
+        match path.to_lowercase().ends_with(".png") {
+            true => ImageFormat::Png,
+            false => ImageFormat::Ppm,
+        }
+    }
+}
+
+impl Default for ScreenshotOptions {
+    fn default() -> Self {
+        ScreenshotOptions {
+            crop_overscan: false,
+            scale: 1,
+        }
+    }
+}
+
+impl Image {
+    /// Convert a frame buffer of pallette indexes to RGB
+    pub fn from_frame(frame: &[u16], palette: &Palette, options: &ScreenshotOptions) -> Image {
+        let scale = options.scale.max(1);
+        let (top, bottom) = match options.crop_overscan {
+            true => (OVERSCAN_LINES, SCREEN_HEIGHT - OVERSCAN_LINES),
+            false => (0, SCREEN_HEIGHT),
+        };
+        let width = SCREEN_WIDTH * scale;
+        let height = (bottom - top) * scale;
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in top..bottom {
+            let mut line = Vec::with_capacity(width * 3);
+            for pixel in frame[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].iter() {
+                let (r, g, b) = palette.rgb(*pixel);
+                for _ in 0..scale {
+                    line.extend_from_slice(&[r, g, b]);
+                }
+            }
+            for _ in 0..scale {
+                rgb.extend_from_slice(&line);
+            }
+        }
+        Image { width, height, rgb }
+    }
+
+    /// Binary PPM file
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut result = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        result.extend_from_slice(&self.rgb);
+        result
+    }
+
+    /// 8 bits RGB PNG file
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut result = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Bit depth 8, truecolor, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        png_chunk(&mut result, b"IHDR", &header);
+
+        // Every line starts with its filter type, 0 is no filter
+        let mut lines = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for line in self.rgb.chunks(self.width * 3) {
+            lines.push(0);
+            lines.extend_from_slice(line);
+        }
+        png_chunk(
+            &mut result,
+            b"IDAT",
+            &miniz_oxide::deflate::compress_to_vec_zlib(&lines, 6),
+        );
+        png_chunk(&mut result, b"IEND", &[]);
+        result
+    }
+
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Png => self.to_png(),
+            ImageFormat::Ppm => self.to_ppm(),
+        }
+    }
+
+    /// Write the picture, the format comes from the extension of `path`
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.encode(ImageFormat::from_path(path)))
+    }
+}
+
+/// Length, type, data and CRC of the type and data
+fn png_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    output.extend_from_slice(&crc.finalize().to_be_bytes());
+}
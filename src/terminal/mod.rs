@@ -0,0 +1,168 @@
+use std::fmt::Write;
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use console::{Key, Term};
+
+use super::components::*;
+
+/// Command line help of the `rustnes-term` binary
+pub const USAGE: &str = "usage: rustnes-term <rom.nes> [--scale N]
+
+keys:
+    arrows or w a s d    directional pad
+    x / z                A / B
+    enter / c            start / select
+    escape or q          quit
+
+--scale N shows one pixel out of N, by default the picture is fitted to the terminal.";
+
+/// Terminals only send key presses, a button stays held for this many frames after
+/// its last press. The key repeat of the terminal keeps it held longer.
+const HOLD_FRAMES: u8 = 10;
+/// Upper half block, its foreground is the top pixel and its background the bottom one
+const HALF_BLOCK: char = '\u{2580}';
+
+/// Action bound to a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    Press(u8),
+    Quit,
+}
+
+pub fn key_action(key: &Key) -> Option<KeyAction> {
+    let button = match key {
+        Key::ArrowUp | Key::Char('w') => Button::Up,
+        Key::ArrowDown | Key::Char('s') => Button::Down,
+        Key::ArrowLeft | Key::Char('a') => Button::Left,
+        Key::ArrowRight | Key::Char('d') => Button::Right,
+        Key::Char('x') => Button::A,
+        Key::Char('z') => Button::B,
+        Key::Enter => Button::Start,
+        Key::Char('c') => Button::Select,
+        Key::Escape | Key::Char('q') => return Some(KeyAction::Quit),
+        _ => return None,
+    };
+    Some(KeyAction::Press(button as u8))
+}
+
+/// Smallest scale showing the whole picture in a terminal of `columns` x `rows`,
+/// a character holds two pixels stacked vertically
+pub fn fit_scale(columns: usize, rows: usize) -> usize {
+    let mut scale = 1;
+    while SCREEN_WIDTH / scale > columns || SCREEN_HEIGHT / 2 / scale > rows {
+        scale += 1;
+    }
+    scale
+}
+
+/// Draw the frame with truecolor escape codes, one pixel out of `scale` is kept
+pub fn render_frame(frame: &[u16], palette: &Palette, scale: usize) -> String {
+    let width = SCREEN_WIDTH / scale;
+    let height = SCREEN_HEIGHT / 2 / scale;
+    let mut result = String::with_capacity(width * height * 20);
+    for row in 0..height {
+        let mut colors = None;
+        for column in 0..width {
+            let x = column * scale;
+            let top = frame[(row * 2 * scale) * SCREEN_WIDTH + x];
+            let bottom = frame[((row * 2 + 1) * scale) * SCREEN_WIDTH + x];
+            // Colors are only sent when they change, it divides the output by 5 or more
+            if colors != Some((top, bottom)) {
+                let (r, g, b) = palette.rgb(top);
+                let (r2, g2, b2) = palette.rgb(bottom);
+                let _ = write!(
+                    result,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    r, g, b, r2, g2, b2
+                );
+                colors = Some((top, bottom));
+            }
+            result.push(HALF_BLOCK);
+        }
+        result.push_str("\x1b[0m\r\n");
+    }
+    result
+}
+
+/// Play a rom in the terminal until escape is pressed
+pub fn run(rom: Vec<u8>, scale: Option<usize>) -> io::Result<()> {
+    let term = Term::buffered_stdout();
+    let (rows, columns) = term.size();
+    let scale = scale
+        .unwrap_or_else(|| fit_scale(columns as usize, rows.saturating_sub(1) as usize))
+        .max(1);
+
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    if is_unif_image(&rom) {
+        nes.insert_unif(&rom).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    } else {
+        nes.insert_cartridge(rom).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    cpu.power(&mut nes);
+
+    // Reading a key blocks, so the keyboard is read by its own thread
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || {
+        let term = Term::stdout();
+        while let Ok(key) = term.read_key() {
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    term.hide_cursor()?;
+    term.clear_screen()?;
+    let mut held = [0u8; 8];
+    let mut next_frame = Instant::now();
+    let mut last_second = (Instant::now(), nes.ppu.frame_count);
+    let mut fps = 0;
+    'running: loop {
+        for key in keys.try_iter() {
+            match key_action(&key) {
+                Some(KeyAction::Quit) => break 'running,
+                Some(KeyAction::Press(mask)) => held[mask.trailing_zeros() as usize] = HOLD_FRAMES,
+                None => (),
+            }
+        }
+        nes.controllers[0].buttons = 0;
+        for (bit, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                nes.controllers[0].buttons |= 1 << bit;
+                *frames -= 1;
+            }
+        }
+
+        nes.run_frame(&mut cpu);
+        // No audio output here, the samples would pile up
+        nes.apu.samples.clear();
+
+        if last_second.0.elapsed() >= Duration::from_secs(1) {
+            fps = nes.ppu.frame_count - last_second.1;
+            last_second = (Instant::now(), nes.ppu.frame_count);
+        }
+        term.move_cursor_to(0, 0)?;
+        term.write_str(&render_frame(&nes.ppu.frame_buffer, &nes.ppu.palette, scale))?;
+        term.write_str(&format!(
+            "frame {}  {} fps  escape to quit",
+            nes.ppu.frame_count, fps
+        ))?;
+        term.flush()?;
+
+        next_frame += nes.region.frame_duration();
+        let now = Instant::now();
+        match next_frame > now {
+            true => thread::sleep(next_frame - now),
+            // Too slow to keep up, do not try to catch up
+            false => next_frame = now,
+        }
+    }
+    term.write_str("\x1b[0m")?;
+    term.clear_screen()?;
+    term.show_cursor()?;
+    term.flush()
+}
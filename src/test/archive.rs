@@ -0,0 +1,153 @@
+use std::env;
+use std::fs;
+
+use super::super::archive::*;
+use super::super::headless::*;
+use super::mapper::mapper_image;
+use super::*;
+
+/// Zip file of `files`, deflated or stored
+fn zip_archive(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut directory = Vec::new();
+    for (name, content, deflated) in files.iter() {
+        let compressed = match deflated {
+            true => miniz_oxide::deflate::compress_to_vec(content, 6),
+            false => content.to_vec(),
+        };
+        let method: u16 = if *deflated { 8 } else { 0 };
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&[0; 4]);
+        fields.extend_from_slice(&crc32fast::hash(content).to_le_bytes());
+        fields.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        directory.extend_from_slice(&0x0201_4B50u32.to_le_bytes());
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        directory.extend_from_slice(&fields);
+        directory.extend_from_slice(&[0; 10]);
+        directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+
+        data.extend_from_slice(&0x0403_4B50u32.to_le_bytes());
+        data.extend_from_slice(&fields);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&compressed);
+    }
+    let start = data.len() as u32;
+    data.extend_from_slice(&directory);
+    data.extend_from_slice(&0x0605_4B50u32.to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    data.extend_from_slice(&start.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data
+}
+
+/// Gzip file with a file name
+fn gzip_archive(name: &str, content: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x1F, 0x8B, 8, 0x08, 0, 0, 0, 0, 0, 3];
+    data.extend_from_slice(name.as_bytes());
+    data.push(0);
+    data.extend(miniz_oxide::deflate::compress_to_vec(content, 6));
+    data.extend_from_slice(&crc32fast::hash(content).to_le_bytes());
+    data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    data
+}
+
+#[test]
+pub fn archive_zip_members() {
+    let rom = nrom_image(&[0x4C, 0x00, 0x80]);
+    let other = mapper_image(2, 2, 0);
+    let zip = zip_archive(&[
+        ("readme.txt", b"not a rom", false),
+        ("roms/game.nes", &rom, true),
+        ("roms/other.NES", &other, false),
+    ]);
+    assert!(is_archive(&zip));
+    assert_eq!(unpack_rom(zip.clone(), None).unwrap(), rom);
+    assert_eq!(unpack_rom(zip.clone(), Some("other.NES")).unwrap(), other);
+    assert_eq!(unpack_rom(zip.clone(), Some("roms/game.nes")).unwrap(), rom);
+    assert_eq!(
+        unpack_rom(zip.clone(), Some("missing.nes")),
+        Err("the archive has no file named 'missing.nes'".to_string())
+    );
+    assert!(unpack_rom(zip_archive(&[("readme.txt", b"text", true)]), None).is_err());
+
+    // A damaged entry fails its checksum
+    let mut damaged = zip_archive(&[("game.nes", &rom, false)]);
+    damaged[40] ^= 0xFF;
+    assert!(unpack_rom(damaged, None).unwrap_err().contains("checksum"));
+
+    // Files that are not archives are kept
+    assert_eq!(unpack_rom(rom.clone(), None).unwrap(), rom);
+}
+
+#[test]
+pub fn archive_unsupported_and_garbage() {
+    let mut seven_zip = vec![0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C];
+    seven_zip.resize(64, 0);
+    assert!(unpack_rom(seven_zip.clone(), None)
+        .unwrap_err()
+        .contains("unsupported archive"));
+
+    // Short, garbage or truncated roms are refused instead of panicking
+    let mut nes = NesData::new();
+    assert!(nes.insert_cartridge(vec![0x4E, 0x45, 0x53]).is_err());
+    assert!(nes.insert_cartridge(seven_zip).is_err());
+    let mut truncated = mapper_image(0, 2, 1);
+    truncated.truncate(0x5000);
+    assert_eq!(
+        nes.insert_cartridge(truncated),
+        Err("the rom is truncated, 20480 bytes instead of 40976".to_string())
+    );
+    assert!(nes.insert_cartridge(mapper_image(0, 0, 1)).is_err());
+    assert!(nes.insert_cartridge(mapper_image(0, 2, 1)).is_ok());
+}
+
+#[test]
+pub fn archive_gzip() {
+    let rom = mapper_image(3, 2, 2);
+    let gz = gzip_archive("game.nes", &rom);
+    assert!(is_archive(&gz));
+    assert_eq!(unpack_rom(gz.clone(), None).unwrap(), rom);
+    let mut truncated = gz.clone();
+    truncated.truncate(gz.len() / 2);
+    assert!(unpack_rom(truncated, None).is_err());
+}
+
+#[test]
+pub fn archive_in_headless() {
+    // Program writing $42 to $0300, zipped with another rom before it
+    let program = vec![0xA9, 0x42, 0x8D, 0x00, 0x03, 0x4C, 0x05, 0x80];
+    let path = env::temp_dir().join("rustnes_archive.zip");
+    let zip = zip_archive(&[
+        ("first.nes", &nrom_image(&[0x4C, 0x00, 0x80]), true),
+        ("second.nes", &nrom_image(&program), true),
+    ]);
+    fs::write(&path, zip).unwrap();
+    let args: Vec<String> = [
+        path.to_str().unwrap(),
+        "--frames",
+        "2",
+        "--exit-code",
+        "$0300",
+    ]
+    .iter()
+    .map(|x| x.to_string())
+    .collect();
+    let options = HeadlessOptions::parse(&args).unwrap();
+    assert_eq!(run(&options).unwrap().exit_code, 0);
+    let options = HeadlessOptions {
+        member: Some("second.nes".to_string()),
+        ..options
+    };
+    assert_eq!(run(&options).unwrap().exit_code, 0x42);
+}
@@ -0,0 +1,77 @@
+use std::env;
+use std::fs;
+
+use super::super::assembler::*;
+use super::*;
+
+#[test]
+pub fn assemble_matches_asm6() {
+    // test.nes was produced by ASM6F from test.asm
+    let mut expected = Vec::new();
+    File::open("src/test/test.nes").unwrap().read_to_end(&mut expected).unwrap();
+    assert_eq!(test_code(), expected);
+}
+
+#[test]
+pub fn assemble_labels_and_modes() {
+    let program = assemble(
+        "
+        PTR = $10
+        .org $8000
+reset:  LDA #<data          ; forward reference
+        STA PTR
+        LDA #>data
+        STA PTR+1
+        LDY #0
+loop:   LDA (PTR),Y
+        STA $0300,Y
+        INY
+        CPY #size
+        BNE loop
+        JMP (vector)
+        ASL A
+data:   .byte 1, 2, \"AB\"
+size = * - data
+vector: .word reset
+        ",
+    )
+    .unwrap();
+    assert_eq!(program.labels["size"], 4);
+    assert_eq!(program.labels["loop"], 0x800A);
+    assert_eq!(
+        program.bytes(),
+        vec![
+            0xA9, 0x18, 0x85, 0x10, 0xA9, 0x80, 0x85, 0x11, 0xA0, 0x00, // pointer setup
+            0xB1, 0x10, 0x99, 0x00, 0x03, 0xC8, 0xC0, 0x04, 0xD0, 0xF6, // copy loop
+            0x6C, 0x1C, 0x80, 0x0A, // JMP (vector), ASL A
+            0x01, 0x02, 0x41, 0x42, 0x00, 0x80, // data and vector
+        ]
+    );
+
+    assert!(assemble("LDA missing").unwrap_err().contains("undefined label 'missing'"));
+    assert!(assemble("STA #1").unwrap_err().contains("addressing mode"));
+    assert!(assemble("a: NOP\na: NOP").unwrap_err().contains("defined twice"));
+}
+
+#[test]
+pub fn assemble_ines_image_runs() {
+    let dir = env::temp_dir().join("rustnes_assembler");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("store.inc"), "store: LDA #$42\n STA $0300\n").unwrap();
+    fs::write(
+        dir.join("main.asm"),
+        "  .inesprg 1\n  .ineschr 1\n  .org $C000\n  .include \"store.inc\"\nend: JMP end\n  .org $FFFC\n  .word store\n",
+    )
+    .unwrap();
+    let program = assemble_file(dir.join("main.asm").to_str().unwrap()).unwrap();
+    let rom = program.to_ines();
+    assert_eq!(rom.len(), 16 + 0x4000 + 0x2000);
+
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(rom).unwrap();
+    cpu.power(&mut nes);
+    nes.run_frame(&mut cpu);
+    assert_eq!(cpu.pc, program.labels["end"] as u16);
+    assert_eq!(nes.ram[0x0300], 0x42);
+}
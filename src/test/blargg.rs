@@ -0,0 +1,155 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+/// Signature written at $6001 by the test roms once $6000 holds a valid status
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+/// Status while the test is still running
+const STATUS_RUNNING: u8 = 0x80;
+/// Status asking for a press on the reset button
+const STATUS_RESET: u8 = 0x81;
+/// The roms ask to wait at least 100ms before pressing reset
+const RESET_DELAY: u64 = 6;
+
+/// Outcome of a test rom following the $6000 protocol of blargg
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlarggResult {
+    /// Result code found at $6000, 0 means passed. None if the rom never finished
+    pub code: Option<u8>,
+    /// Text written from $6004
+    pub text: String,
+    pub frames: u64,
+}
+
+impl BlarggResult {
+    pub fn passed(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Run a test rom until it reports a result or `max_frames` are elapsed,
+/// pressing reset whenever the rom asks for it
+pub fn run_blargg(rom: Vec<u8>, max_frames: u64) -> Result<BlarggResult, String> {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(rom)?;
+    cpu.power(&mut nes);
+
+    let mut result = BlarggResult { code: None, text: String::new(), frames: 0 };
+    let mut reset_at = None;
+    while result.frames < max_frames {
+        nes.run_frame(&mut cpu);
+        result.frames += 1;
+        // The status is read through the bus like the cpu would, whatever the mapper does with $6000
+        let signature: Vec<u8> = (0x6001..0x6004).map(|addr| nes.cpu_read(addr, true)).collect();
+        if signature != BLARGG_SIGNATURE {
+            continue;
+        }
+        match nes.cpu_read(0x6000, true) {
+            STATUS_RUNNING => reset_at = None,
+            STATUS_RESET => match reset_at {
+                None => reset_at = Some(result.frames + RESET_DELAY),
+                Some(frame) if frame <= result.frames => {
+                    cpu.reset(&mut nes);
+                    reset_at = None;
+                }
+                Some(_) => (),
+            },
+            code => {
+                result.code = Some(code);
+                break;
+            }
+        }
+    }
+    result.text = blargg_text(&mut nes);
+    Ok(result)
+}
+
+/// NUL terminated text starting at $6004
+fn blargg_text(nes: &mut NesData) -> String {
+    let text: Vec<u8> = (0x6004..0x8000)
+        .map(|addr| nes.cpu_read(addr, true))
+        .take_while(|x| *x != 0)
+        .collect();
+    String::from_utf8_lossy(&text).trim().to_string()
+}
+
+/// Writes the signature, the text and the status to $6000, then loops forever
+fn report_program(status: u8, text: &str) -> Vec<u8> {
+    let mut program = Vec::new();
+    let mut store = |data: u8, addr: u16| {
+        program.extend_from_slice(&[0xA9, data, 0x8D, addr as u8, (addr >> 8) as u8]);
+    };
+    for (i, x) in BLARGG_SIGNATURE.iter().enumerate() {
+        store(*x, 0x6001 + i as u16);
+    }
+    for (i, x) in text.bytes().chain(Some(0)).enumerate() {
+        store(x, 0x6004 + i as u16);
+    }
+    store(status, 0x6000);
+    let end = 0x8000 + program.len() as u16;
+    program.extend_from_slice(&[0x4C, end as u8, (end >> 8) as u8]);
+    program
+}
+
+#[test]
+pub fn blargg_protocol_passed() {
+    let result = run_blargg(nrom_image(&report_program(0, "Passed\n")), 10).unwrap();
+    assert!(result.passed());
+    assert_eq!(result.text, "Passed");
+    assert_eq!(result.frames, 1);
+}
+
+#[test]
+pub fn blargg_protocol_failed() {
+    let result = run_blargg(nrom_image(&report_program(3, "Failed #3")), 10).unwrap();
+    assert!(!result.passed());
+    assert_eq!(result.code, Some(3));
+    assert_eq!(result.text, "Failed #3");
+
+    let result = run_blargg(nrom_image(&report_program(STATUS_RUNNING, "")), 10).unwrap();
+    assert_eq!(result.code, None);
+    assert_eq!(result.frames, 10);
+
+    // Only the status decides, whatever the text says
+    let result = run_blargg(nrom_image(&report_program(1, "Passed")), 10).unwrap();
+    assert!(!result.passed());
+    assert!(run_blargg(vec![0x4E, 0x45, 0x53, 0x1A], 10).is_err());
+}
+
+/// Run every rom of `roms/blargg` (or of the `RUSTNES_BLARGG_ROMS` directory),
+/// skipped when the directory does not exist
+#[test]
+pub fn blargg_test_roms() {
+    let dir = env::var("RUSTNES_BLARGG_ROMS").unwrap_or_else(|_| "roms/blargg".to_string());
+    let mut roms = Vec::new();
+    collect_roms(Path::new(&dir), &mut roms);
+    roms.sort();
+
+    let mut failures = Vec::new();
+    for path in roms.iter() {
+        // The status at $6000 decides, the text tells why a rom failed
+        match run_blargg(fs::read(path).unwrap(), 60 * 60) {
+            Ok(result) if result.passed() => (),
+            Ok(result) => failures.push(format!("{}: {}", path.display(), result.text)),
+            Err(e) => failures.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    assert!(failures.is_empty(), "{}/{} test roms failed: {:?}", failures.len(), roms.len(), failures);
+}
+
+fn collect_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            collect_roms(&path, roms);
+        } else if path.extension().is_some_and(|x| x.eq_ignore_ascii_case("nes")) {
+            roms.push(path);
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use super::*;
+
+/// Runs the instruction at the program counter up to its last cycle
+fn step(cpu: &mut CPU6502, nes: &mut NesData) {
+    cpu.clock(nes);
+    while cpu.cycles > 0 {
+        cpu.clock(nes);
+    }
+}
+
+#[test]
+pub fn jsr_rts_return_address() {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    // JSR $0210 at $0200, RTS at $0210
+    nes.ram[0x0200..0x0203].copy_from_slice(&[0x20, 0x10, 0x02]);
+    nes.ram[0x0210] = 0x60;
+    cpu.pc = 0x0200;
+    cpu.stkp = 0xFD;
+
+    step(&mut cpu, &mut nes);
+    assert_eq!((cpu.pc, cpu.stkp), (0x0210, 0xFB));
+    // The address of the last byte of JSR is pushed, high byte first
+    assert_eq!((nes.ram[0x01FD], nes.ram[0x01FC]), (0x02, 0x02));
+
+    step(&mut cpu, &mut nes);
+    assert_eq!((cpu.pc, cpu.stkp), (0x0203, 0xFD));
+}
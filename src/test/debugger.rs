@@ -0,0 +1,89 @@
+use super::super::debugger::*;
+use super::*;
+
+/// Writes $01 to $0300 then $02 to $0301 and loops forever at $800A
+fn store_program() -> Vec<u8> {
+    vec![
+        0xA9, 0x01, 0x8D, 0x00, 0x03, // $0300 = $01
+        0xA9, 0x02, 0x8D, 0x01, 0x03, // $0301 = $02
+        0x4C, 0x0A, 0x80, // loop forever
+    ]
+}
+
+fn powered_nes() -> (CPU6502, NesData) {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(nrom_image(&store_program())).unwrap();
+    cpu.power(&mut nes);
+    (cpu, nes)
+}
+
+#[test]
+pub fn debugger_step_and_breakpoint() {
+    let (mut cpu, mut nes) = powered_nes();
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.step_into(&mut cpu, &mut nes), StopReason::Step);
+    assert_eq!(cpu.pc, 0x8000);
+    assert_eq!(debugger.step_over(&mut cpu, &mut nes), StopReason::Step);
+    assert_eq!(cpu.pc, 0x8002);
+
+    debugger.add_breakpoint(Breakpoint::new(0x8007));
+    assert_eq!(
+        debugger.resume(&mut cpu, &mut nes),
+        StopReason::Breakpoint(0)
+    );
+    assert_eq!((cpu.pc, cpu.a), (0x8007, 0x02));
+    assert_eq!(nes.ram[0x0301], 0x00);
+
+    // A false condition never stops the cpu
+    debugger.breakpoints[0].condition = Some(Expression::parse("[$0301] == 2 && A != 2").unwrap());
+    debugger.clock_limit = 1000;
+    assert_eq!(debugger.resume(&mut cpu, &mut nes), StopReason::ClockLimit);
+    assert_eq!(nes.ram[0x0301], 0x02);
+}
+
+#[test]
+pub fn debugger_watchpoints() {
+    let (mut cpu, mut nes) = powered_nes();
+    let mut debugger = Debugger::new();
+    debugger
+        .command("w w $0300-$03FF if VALUE == 2", &mut cpu, &mut nes)
+        .unwrap();
+    let access = BusAccess {
+        space: AddressSpace::Cpu,
+        kind: AccessKind::Write,
+        addr: 0x0301,
+        data: 0x02,
+    };
+    assert_eq!(
+        debugger.resume(&mut cpu, &mut nes),
+        StopReason::Watchpoint(0, access)
+    );
+
+    debugger
+        .command("w x cpu $800A", &mut cpu, &mut nes)
+        .unwrap();
+    assert_eq!(
+        debugger.command("c", &mut cpu, &mut nes).unwrap(),
+        "watchpoint 1 hit by Execute of $4C at Cpu $800A at $800A"
+    );
+    assert!(debugger.command("w q $0300", &mut cpu, &mut nes).is_err());
+}
+
+#[test]
+pub fn debugger_run_to_scanline() {
+    let (mut cpu, mut nes) = powered_nes();
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.run_to_scanline(&mut cpu, &mut nes, 100),
+        StopReason::Scanline(100)
+    );
+    assert_eq!(nes.ppu.scanline, 100);
+    assert_eq!(
+        Expression::parse("SCANLINE + 1")
+            .unwrap()
+            .eval(&cpu, &mut nes, AccessContext::default()),
+        101
+    );
+    assert!(Expression::parse("A ==").is_err());
+}
@@ -0,0 +1,85 @@
+use super::super::disassembler::*;
+use super::*;
+
+fn program_nes(program: &[u8]) -> NesData {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(nrom_image(program)).unwrap();
+    nes
+}
+
+#[test]
+pub fn disassemble_addressing_modes() {
+    let mut nes = program_nes(&[
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x00, 0x20, // STA $2000
+        0xB5, 0x10, // LDA $10,X
+        0xB1, 0x20, // LDA ($20),Y
+        0x0A, // ASL A
+        0xD0, 0xF4, // BNE $8000
+        0x6C, 0xFC, 0xFF, // JMP ($FFFC)
+        0x02, // illegal
+    ]);
+    let disassembler = Disassembler::new();
+    let lines: Vec<String> = disassembler
+        .disassemble(&mut nes, 0x8000, 8)
+        .iter()
+        .map(|x| disassembler.format(x))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            "LDA #$01",
+            "STA $2000",
+            "LDA $10,X",
+            "LDA ($20),Y",
+            "ASL A",
+            "BNE $8000",
+            "JMP ($FFFC)",
+            ".db $02"
+        ]
+    );
+
+    let mut cpu = CPU6502::new();
+    cpu.x = 0xF5;
+    cpu.y = 0x04;
+    nes.ram[0x20] = 0x00;
+    nes.ram[0x21] = 0x03;
+    let decoded = disassembler.decode(&mut nes, 0x8005);
+    assert_eq!(
+        disassembler.effective_address(&decoded, &cpu, &mut nes),
+        Some(0x0005)
+    );
+    let decoded = disassembler.decode(&mut nes, 0x8007);
+    assert_eq!(
+        disassembler.effective_address(&decoded, &cpu, &mut nes),
+        Some(0x0304)
+    );
+    let decoded = disassembler.decode(&mut nes, 0x800C);
+    assert_eq!(
+        disassembler.effective_address(&decoded, &cpu, &mut nes),
+        Some(0x8000)
+    );
+}
+
+#[test]
+pub fn disassemble_with_symbols() {
+    let mut nes = program_nes(&[0x8D, 0x00, 0x20, 0x85, 0x12, 0x4C, 0x00, 0x80]);
+    let mut disassembler = Disassembler::new();
+    disassembler
+        .symbols
+        .parse_nl("$8000#Reset#entry point\n$0010/4#buffer#\n")
+        .unwrap();
+    disassembler
+        .symbols
+        .parse_dbg("version\tmajor=2,minor=0\nsym\tid=0,name=\"PPUCTRL\",addrsize=absolute,val=0x2000,type=lab\nsym\tid=1,name=\"SIZE\",val=0x10,type=equ\n")
+        .unwrap();
+    let lines: Vec<String> = disassembler
+        .disassemble(&mut nes, 0x8000, 3)
+        .iter()
+        .map(|x| disassembler.format_line(x))
+        .collect();
+    assert_eq!(lines[0], "8000  8D 00 20  Reset:      STA PPUCTRL");
+    assert_eq!(lines[1], "8003  85 12                 STA buffer+2");
+    assert_eq!(lines[2], "8005  4C 00 80              JMP Reset");
+    assert_eq!(disassembler.symbols.name(0x0014), None);
+}
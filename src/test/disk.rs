@@ -0,0 +1,151 @@
+use super::*;
+
+/// Side with the disk info, one file of 4 bytes at $6000 and nothing after it
+pub fn test_side() -> Vec<u8> {
+    let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+    side.resize(56, 0);
+    side.extend_from_slice(&[0x02, 0x01]);
+    side.extend_from_slice(&[0x03, 0x00, 0x00]);
+    side.extend_from_slice(b"TESTFILE");
+    side.extend_from_slice(&[0x00, 0x60, 0x04, 0x00, 0x00]);
+    side.extend_from_slice(&[0x04, 0xDE, 0xAD, 0xBE, 0xEF]);
+    side.resize(FDS_SIDE_SIZE, 0);
+    side
+}
+
+/// Write a byte to $4024 and wait for the drive to take it, or read the next one
+fn transfer(disk: &mut DiskSystem, data: u8) -> u8 {
+    disk.write(0x4024, data);
+    while !disk.transfer_complete {
+        disk.clock();
+    }
+    let result = disk.read(0x4031).unwrap();
+    disk.acknowledge(0x4031);
+    result
+}
+
+#[test]
+pub fn disk_image_formats() {
+    let side = test_side();
+    let mut image = side.clone();
+    image.extend(side.iter());
+    assert_eq!(parse_fds(&image).unwrap().len(), 2);
+    let with_header = sides_to_fds(&[side.clone(), side.clone()]);
+    assert_eq!(&with_header[..5], b"FDS\x1A\x02");
+    assert_eq!(
+        parse_fds(&with_header).unwrap(),
+        vec![side.clone(), side.clone()]
+    );
+    assert!(is_fds_image(&image) && is_fds_image(&with_header));
+    assert!(parse_fds(&image[..1000]).is_err());
+    assert!(parse_fds(&vec![0u8; FDS_SIDE_SIZE])
+        .unwrap_err()
+        .contains("side 1"));
+
+    // The gaps, marks and CRCs added for the drive are removed on the way back
+    let disk = DiskSystem::new(std::slice::from_ref(&side));
+    assert!(!disk.is_modified());
+    assert_eq!(parse_fds(&disk.to_fds()).unwrap(), vec![side]);
+
+    let mut nes = NesData::new();
+    assert!(nes
+        .insert_disk(&image, vec![0; 100])
+        .unwrap_err()
+        .contains("BIOS"));
+    nes.insert_disk(&image, vec![0; FDS_BIOS_SIZE]).unwrap();
+    // 32KB of RAM and the read only BIOS
+    nes.cpu_write(0x6000, 0x12);
+    nes.cpu_write(0xDFFF, 0x34);
+    nes.cpu_write(0xE000, 0x56);
+    assert_eq!(
+        (
+            nes.cpu_read(0x6000, true),
+            nes.cpu_read(0xDFFF, true),
+            nes.cpu_read(0xE000, true)
+        ),
+        (0x12, 0x34, 0x00)
+    );
+}
+
+#[test]
+pub fn disk_timer_irq_and_side_switch() {
+    let mut nes = NesData::new();
+    nes.insert_disk(&test_side(), vec![0; FDS_BIOS_SIZE])
+        .unwrap();
+    nes.cpu_write(0x4023, 0x01);
+    nes.cpu_write(0x4020, 100);
+    nes.cpu_write(0x4021, 0);
+    nes.cpu_write(0x4022, 0x02);
+    for _ in 0..100 {
+        nes.cartridge.clock();
+    }
+    assert!(!nes.cartridge.irq());
+    nes.cartridge.clock();
+    assert!(nes.cartridge.irq());
+    // Reading the status acknowledges the IRQ
+    assert_eq!(nes.cpu_read(0x4030, false) & 0x01, 0x01);
+    assert!(!nes.cartridge.irq());
+
+    let disk = nes.cartridge.disk.as_mut().unwrap();
+    assert_eq!(disk.read(0x4032).unwrap() & 0x01, 0x00);
+    disk.insert(None).unwrap();
+    assert_eq!(disk.read(0x4032).unwrap() & 0x07, 0x07);
+    assert!(disk.insert(Some(1)).is_err());
+    disk.insert(Some(0)).unwrap();
+    // The disk takes a moment to be seen as inserted
+    assert_eq!(disk.read(0x4032).unwrap() & 0x01, 0x01);
+}
+
+#[test]
+pub fn disk_runs_as_ntsc() {
+    // A PAL cartridge inserted before does not change the timings of the disk
+    let mut rom = nrom_image(&[0x4C, 0x00, 0x80]);
+    rom[9] = 1;
+    let mut nes = NesData::new();
+    nes.insert_cartridge(rom).unwrap();
+    assert_eq!(nes.region, Region::Pal);
+    nes.insert_disk(&test_side(), vec![0; FDS_BIOS_SIZE]).unwrap();
+    assert_eq!(
+        (nes.region, nes.ppu.region, nes.apu.region),
+        (Region::Ntsc, Region::Ntsc, Region::Ntsc)
+    );
+}
+
+#[test]
+pub fn disk_read_and_write_back() {
+    let side = test_side();
+    let mut disk = DiskSystem::new(std::slice::from_ref(&side));
+    disk.write(0x4023, 0x01);
+    // Motor on, read mode, transfer enabled: the gap is skipped up to the block mark
+    disk.write(0x4025, 0x65);
+    let header: Vec<u8> = (0..16).map(|_| transfer(&mut disk, 0)).collect();
+    assert_eq!(header[0], 0x80);
+    assert_eq!(header[1..], side[..15]);
+    assert!(!disk.is_modified());
+
+    // Rewrite the first block from the start of the disk, with another game name
+    disk.write(0x4025, 0x00);
+    disk.clock();
+    disk.write(0x4025, 0x61);
+    for _ in 0..28300 / 8 {
+        transfer(&mut disk, 0);
+    }
+    let mut block = side[..56].to_vec();
+    block[16..19].copy_from_slice(b"NEW");
+    for data in [0x80].iter().chain(block.iter()) {
+        transfer(&mut disk, *data);
+    }
+    // The drive writes the CRC on its own
+    disk.write(0x4025, 0x71);
+    for _ in 0..300 {
+        disk.clock();
+    }
+    assert!(disk.is_modified());
+
+    let saved = parse_fds(&disk.to_fds()).unwrap();
+    assert_eq!(&saved[0][..56], &block[..]);
+    assert_eq!(&saved[0][56..], &side[56..]);
+    let mut reloaded = DiskSystem::new(&[side]);
+    reloaded.load_save(&disk.to_fds()).unwrap();
+    assert_eq!(reloaded.to_fds(), disk.to_fds());
+}
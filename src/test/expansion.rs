@@ -0,0 +1,134 @@
+use super::super::nsf::*;
+use super::*;
+
+/// NSF using the VRC6: INIT starts its first pulse at volume 15, PLAY does nothing
+fn vrc6_nsf() -> Vec<u8> {
+    let code = assemble(
+        "
+        .org $8000
+init:   LDA #$7F
+        STA $9000
+        LDA #$FD
+        STA $9001
+        LDA #$80
+        STA $9002
+play:   RTS
+        ",
+    )
+    .unwrap();
+    let mut header = vec![0u8; 0x80];
+    header[..5].copy_from_slice(b"NESM\x1A");
+    header[5] = 1;
+    header[6] = 1;
+    header[7] = 1;
+    header[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, code.labels["play"] as u8, 0x80]);
+    header[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+    header[0x7B] = 0x01;
+    header.extend(code.bytes());
+    header
+}
+
+/// Highest output of the chips over `cycles` cpu cycles
+fn peak(audio: &mut ExpansionAudio, cycles: u32) -> f32 {
+    let mut result = 0f32;
+    for _ in 0..cycles {
+        audio.clock();
+        result = result.max(audio.output());
+    }
+    result
+}
+
+#[test]
+pub fn expansion_vrc6_nsf_is_mixed() {
+    let mut player = NsfPlayer::new(Nsf::parse(&vrc6_nsf()).unwrap()).unwrap();
+    assert!(player.nes.cartridge.audio.vrc6.is_some());
+    assert!(player.nes.cartridge.audio.n163.is_none());
+    let samples = player.render_seconds(1);
+    // The APU is silent, the pulse of the VRC6 is heard
+    assert_eq!(player.nes.apu.channels()[..2], [0, 0]);
+    assert!(samples.iter().any(|x| x.abs() > 0.05));
+}
+
+#[test]
+pub fn expansion_relative_levels() {
+    let mut apu = APU::new();
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0xBF);
+    apu.cpu_write(0x4002, 0xFD);
+    apu.cpu_write(0x4003, 0x00);
+    // The silent triangle outputs 7, it is removed from the level of the pulse
+    let rest = APU::new().output();
+    let mut apu_peak = 0f32;
+    for _ in 0..4000 {
+        apu.clock();
+        apu_peak = apu_peak.max(apu.output() - rest);
+    }
+    assert!((apu_peak - PULSE_FULL).abs() < 0.001);
+
+    let mut vrc6 = ExpansionAudio::for_mapper(24);
+    vrc6.cpu_write(0x9000, 0x7F);
+    vrc6.cpu_write(0x9001, 0xFD);
+    vrc6.cpu_write(0x9002, 0x80);
+    let mut mmc5 = ExpansionAudio::for_mapper(5);
+    mmc5.cpu_write(0x5015, 0x01);
+    mmc5.cpu_write(0x5000, 0xBF);
+    mmc5.cpu_write(0x5002, 0xFD);
+    mmc5.cpu_write(0x5003, 0x00);
+    // A pulse at full volume is as loud on the three chips
+    for chip in [&mut vrc6, &mut mmc5] {
+        assert!((peak(chip, 4000) - apu_peak).abs() < 0.002);
+    }
+
+    let mut sunsoft = ExpansionAudio::for_mapper(69);
+    for (reg, data) in [(0, 0xFF), (7, 0x3E), (8, 0x0F)] {
+        sunsoft.cpu_write(0xC000, reg);
+        sunsoft.cpu_write(0xE000, data);
+    }
+    let level = peak(&mut sunsoft, 10000);
+    assert!((level / PULSE_FULL - 1.5).abs() < 0.01);
+}
+
+#[test]
+pub fn expansion_n163_ram_port() {
+    let mut nes = NesData::new();
+    nes.cartridge.audio = ExpansionAudio::from_nsf_flags(0x10);
+    // Auto-increment from $7E, the address wraps around
+    nes.cpu_write(0xF800, 0xFE);
+    nes.cpu_write(0x4800, 0x12);
+    nes.cpu_write(0x4800, 0x34);
+    nes.cpu_write(0x4800, 0x56);
+    nes.cpu_write(0xF800, 0xFE);
+    let read: Vec<u8> = (0..3).map(|_| nes.cpu_read(0x4800, false)).collect();
+    assert_eq!(read, vec![0x12, 0x34, 0x56]);
+    let n163 = nes.cartridge.audio.n163.as_ref().unwrap();
+    assert_eq!((n163.ram[0x7F], n163.ram[0x00]), (0x34, 0x56));
+    assert_eq!(n163.active_channels(), 4);
+    // Without auto-increment, the address stays
+    nes.cpu_write(0xF800, 0x10);
+    nes.cpu_read(0x4800, false);
+    assert_eq!(nes.cartridge.audio.n163.as_ref().unwrap().address, 0x10);
+}
+
+#[test]
+pub fn expansion_fds_and_vrc7_produce_sound() {
+    let mut fds = ExpansionAudio::from_nsf_flags(0x04);
+    fds.cpu_write(0x4089, 0x80);
+    for i in 0..64 {
+        fds.cpu_write(0x4040 + i, if i < 32 { 63 } else { 0 });
+    }
+    fds.cpu_write(0x4089, 0x00);
+    fds.cpu_write(0x4080, 0xA0);
+    fds.cpu_write(0x4082, 0x00);
+    fds.cpu_write(0x4083, 0x04);
+    assert_eq!(fds.cpu_read(0x4090), Some(0x60));
+    assert!((peak(&mut fds, 4000) / PULSE_FULL - 2.4).abs() < 0.01);
+
+    let mut vrc7 = ExpansionAudio::for_mapper(85);
+    // Instrument 3 at full volume, key on at about 440Hz
+    for (reg, data) in [(0x30, 0x30), (0x10, 0x20), (0x20, 0x19)] {
+        vrc7.cpu_write(0x9010, reg);
+        vrc7.cpu_write(0x9030, data);
+    }
+    let level = peak(&mut vrc7, 20000);
+    assert!(level > 0.1 * PULSE_FULL && level <= PULSE_FULL);
+}
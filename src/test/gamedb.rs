@@ -0,0 +1,119 @@
+use std::fs;
+
+use super::mapper::mapper_image;
+use super::*;
+use crate::utils::Sha1;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+#[test]
+pub fn sha1_vectors() {
+    let digest = |data: &[u8]| {
+        let mut sha1 = Sha1::new();
+        sha1.update(data);
+        hex(&sha1.finalize())
+    };
+    assert_eq!(digest(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    assert_eq!(digest(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    assert_eq!(
+        digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+    );
+    // Split updates give the same digest
+    let mut sha1 = Sha1::new();
+    for chunk in vec![b'a'; 1000].chunks(37) {
+        sha1.update(chunk);
+    }
+    assert_eq!(hex(&sha1.finalize()), digest(&[b'a'; 1000]));
+}
+
+#[test]
+pub fn gamedb_fixes_ines_header() {
+    // CNROM dumped with the mapper of NROM and a horizontal mirroring
+    let image = mapper_image(0, 2, 2);
+    let rom = &image[16..];
+    let mut sha1 = Sha1::new();
+    sha1.update(rom);
+    let sha1 = hex(&sha1.finalize()).to_uppercase();
+    let xml = format!(
+        r#"<nes20db>
+<game>
+<!-- Games\Test Game (USA).nes -->
+<prgrom size="32768" crc32="00000000"/>
+<rom size="49152" crc32="{:08X}" sha1="{}"/>
+<pcb mapper="3" submapper="2" mirroring="V" battery="1"/>
+<prgnvram size="8192"/>
+<console type="0" region="1"/>
+</game>
+</nes20db>"#,
+        crc32fast::hash(rom),
+        sha1
+    );
+    let db = GameDb::parse(&xml);
+    assert_eq!(db.games.len(), 1);
+    let game = db.find(&rom[..0x8000], &rom[0x8000..]).unwrap();
+    assert_eq!(game.title, "Test Game (USA)");
+
+    let mut header = Header::new(&image[..16]);
+    game.apply(&mut header);
+    assert!(header.is_nes2());
+    assert_eq!((header.mapper_id(), header.submapper()), (3, 2));
+    assert_eq!(header.flags_6 & 0x0B, 0x03);
+    assert_eq!(header.prg_ram_size(), Some(0x2000));
+    assert_eq!(Region::from_header(&header), Region::Pal);
+    let text = header.to_string();
+    assert!(text.contains("title : Test Game (USA)"));
+    assert!(text.contains("board : CNROM"));
+
+    // Only the title and the board are taken for a NES 2.0 header
+    let mut header = Header::new(&image[..16]);
+    header.flags_7 |= 0x08;
+    game.apply(&mut header);
+    assert_eq!(header.mapper_id(), 0);
+    assert_eq!(header.board.as_deref(), Some("CNROM"));
+
+    // The SHA-1 must match too
+    let db = GameDb::parse(&xml.replace(&sha1, &"0".repeat(40)));
+    assert!(db.find(&rom[..0x8000], &rom[0x8000..]).is_none());
+
+    // Loaded as a cartridge, the CNROM register switches the CHR banks. The board has
+    // bus conflicts, the value is written where the ROM holds it.
+    let mut cartridge = Cartridge::new();
+    cartridge
+        .load_with_db(image.clone(), &GameDb::parse(&xml))
+        .unwrap();
+    assert_eq!(cartridge.header.title.as_deref(), Some("Test Game (USA)"));
+    assert_eq!(cartridge.mirror(), Mirror::Vertical);
+    assert_eq!(cartridge.prg_ram.len(), 0x2000);
+    cartridge.cpu_write(0x8400, 0x01);
+    assert_eq!(cartridge.ppu_read(0x0000), 8);
+}
+
+/// Super Mario Bros. with a broken iNES header, through the embedded database.
+/// The dump is not part of the repository, the load is skipped without `roms/Super Mario Bros.nes`.
+#[test]
+pub fn gamedb_embedded_fixes_mario() {
+    let game = GameDb::embedded()
+        .games
+        .iter()
+        .find(|game| game.crc32 == 0x3337EC46)
+        .unwrap();
+    assert_eq!(game.title, "Super Mario Bros. (World)");
+    assert_eq!((game.mapper, game.mirroring), (0, Some('V')));
+    assert!(game.sha1.is_some());
+
+    let mut rom = match fs::read("roms/Super Mario Bros.nes") {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    // Mapper 1 and a horizontal mirroring, like the bad dumps
+    rom[6] = 0x10;
+    rom[7] = 0x00;
+    let mut cartridge = Cartridge::new();
+    cartridge.load(rom).unwrap();
+    assert_eq!(cartridge.header.title.as_deref(), Some("Super Mario Bros. (World)"));
+    assert_eq!(cartridge.header.mapper_id(), 0);
+    assert_eq!(cartridge.mirror(), Mirror::Vertical);
+}
@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+
+use super::super::headless::*;
+use super::super::movie::*;
+use super::*;
+
+/// Sets the backdrop color to $21, enables the background and writes $42 to $0300
+fn backdrop_program() -> Vec<u8> {
+    vec![
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, // $2006 = $3F00
+        0xA9, 0x21, 0x8D, 0x07, 0x20, // $2007 = $21
+        0xA9, 0x08, 0x8D, 0x01, 0x20, // $2001 = background on
+        0xA9, 0x42, 0x8D, 0x00, 0x03, // $0300 = $42
+        0x4C, 0x19, 0x80, // loop forever
+    ]
+}
+
+fn temp_path(name: &str) -> String {
+    env::temp_dir().join(name).to_string_lossy().to_string()
+}
+
+#[test]
+pub fn headless_run_until_ram() {
+    let rom = temp_path("rustnes_headless_until.nes");
+    let dump = temp_path("rustnes_headless_until.ram");
+    fs::write(&rom, nrom_image(&backdrop_program())).unwrap();
+    let args: Vec<String> = [&rom, "--until-ram", "$0300=$42", "--exit-code", "0x0300", "--ram-dump", &dump]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    let options = HeadlessOptions::parse(&args).unwrap();
+    let result = run(&options).unwrap();
+    assert!(result.condition_met);
+    assert_eq!(result.frames, 1);
+    assert_eq!(result.exit_code, 0x42);
+    assert_eq!(fs::read(&dump).unwrap()[0x0300], 0x42);
+
+    let options = HeadlessOptions { until: vec![Condition::Ram(0x0300, 0x43)], frames: 3, ..options };
+    assert_eq!(run(&HeadlessOptions { exit_code: None, ..options }).unwrap().exit_code, 2);
+}
+
+#[test]
+pub fn headless_addresses_in_range() {
+    for option in ["--exit-code", "--until-pc"].iter() {
+        let args: Vec<String> = ["game.nes", option, "0x10300"].iter().map(|x| x.to_string()).collect();
+        assert!(HeadlessOptions::parse(&args).is_err());
+    }
+    let args: Vec<String> = ["game.nes", "--exit-code", "$FFFF"].iter().map(|x| x.to_string()).collect();
+    assert_eq!(HeadlessOptions::parse(&args).unwrap().exit_code, Some(0xFFFF));
+}
+
+#[test]
+pub fn headless_screenshot_backdrop() {
+    let rom = temp_path("rustnes_headless_backdrop.nes");
+    let picture = temp_path("rustnes_headless_backdrop.ppm");
+    fs::write(&rom, nrom_image(&backdrop_program())).unwrap();
+    let options = HeadlessOptions {
+        rom,
+        frames: 2,
+        screenshot: Some(picture.clone()),
+        ..HeadlessOptions::default()
+    };
+    run(&options).unwrap();
+    let ppm = fs::read(&picture).unwrap();
+    assert!(ppm.starts_with(b"P6\n256 240\n255\n"));
+    // Color $21 of the pallette on every pixel
+    assert_eq!(&ppm[15..18], &[76, 154, 236]);
+    assert_eq!(&ppm[ppm.len() - 3..], &[76, 154, 236]);
+}
+
+#[test]
+pub fn headless_png_screenshot_options() {
+    let args: Vec<String> = ["game.nes", "--screenshot", "shot.png", "--crop-overscan", "--scale", "3"]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    let options = HeadlessOptions::parse(&args).unwrap();
+    assert_eq!(options.screenshot.as_deref(), Some("shot.png"));
+    assert!(options.screenshot_options.crop_overscan);
+    assert_eq!(options.screenshot_options.scale, 3);
+}
+
+#[test]
+pub fn headless_disk_needs_bios() {
+    let disk = temp_path("rustnes_headless_disk.fds");
+    let bios_path = temp_path("rustnes_headless_disk.rom");
+    let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+    side.resize(FDS_SIDE_SIZE, 0);
+    fs::write(&disk, sides_to_fds(&[side])).unwrap();
+    // Loops at $E000, where the reset vector points
+    let mut bios = vec![0u8; FDS_BIOS_SIZE];
+    bios[..3].copy_from_slice(&[0x4C, 0x00, 0xE0]);
+    bios[0x1FFC..0x1FFE].copy_from_slice(&[0x00, 0xE0]);
+    fs::write(&bios_path, bios).unwrap();
+
+    let options = HeadlessOptions { rom: disk.clone(), frames: 1, ..HeadlessOptions::default() };
+    assert!(run(&options).unwrap_err().contains("--fds-bios"));
+    assert_eq!(options.disk_save_path(), temp_path("rustnes_headless_disk.sav"));
+    let options = HeadlessOptions { fds_bios: Some(bios_path), ..options };
+    assert_eq!(run(&options).unwrap().frames, 1);
+}
+
+#[test]
+pub fn headless_movie_resets() {
+    let rom = temp_path("rustnes_headless_reset.nes");
+    let path = temp_path("rustnes_headless_reset.movie");
+    // $0300 is incremented once after every reset
+    fs::write(&rom, nrom_image(&[0xEE, 0x00, 0x03, 0x4C, 0x03, 0x80])).unwrap();
+    let mut movie = Movie::new(MovieStart::PowerOn);
+    for command in [0, Command::SoftReset as u8, 0].iter() {
+        movie.frames.push(MovieFrame { command: *command, ports: [0, 0] });
+    }
+    movie.save(&path).unwrap();
+    let dump = temp_path("rustnes_headless_reset.ram");
+    let options = HeadlessOptions {
+        rom,
+        frames: 3,
+        movie: Some(path),
+        ram_dump: Some(dump.clone()),
+        ..HeadlessOptions::default()
+    };
+    run(&options).unwrap();
+    assert_eq!(fs::read(&dump).unwrap()[0x0300], 2);
+}
@@ -0,0 +1,775 @@
+use super::*;
+
+/// iNES image with `prg_banks` 16KB PRG banks and `chr_banks` 8KB CHR banks.
+/// Every KB of PRG and CHR is filled with its index, the bank mapped at an address can be read back.
+pub fn mapper_image(mapper: u16, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+    let flags_6 = (mapper as u8 & 0x0F) << 4;
+    let flags_7 = mapper as u8 & 0xF0;
+    let mut rom = vec![
+        0x4E, 0x45, 0x53, 0x1A, prg_banks, chr_banks, flags_6, flags_7,
+    ];
+    rom.resize(16, 0);
+    for kb in 0..(prg_banks as usize * 16 + chr_banks as usize * 8) {
+        let index = match kb < prg_banks as usize * 16 {
+            true => kb,
+            false => kb - prg_banks as usize * 16,
+        };
+        rom.extend(vec![index as u8; 0x400]);
+    }
+    rom
+}
+
+/// NES 2.0 image of a board variant, see `mapper_image`
+pub fn nes2_image(mapper: u16, submapper: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+    let mut rom = mapper_image(mapper, prg_banks, chr_banks);
+    rom[7] |= 0x08;
+    rom[8] = submapper << 4 | (mapper >> 8) as u8;
+    rom
+}
+
+/// Run the PPU and the board for a frame, without the cpu running the PRG filled with indices
+fn run_ppu_frame(nes: &mut NesData) {
+    while !nes.ppu.frame_complete {
+        nes.ppu.clock(&mut nes.cartridge);
+        if nes.clock_counter.is_multiple_of(3) {
+            nes.cartridge.clock();
+        }
+        nes.clock_counter += 1;
+    }
+    nes.ppu.frame_complete = false;
+}
+
+/// Fetch made by the PPU, seen by the mapper
+fn fetch(cartridge: &mut Cartridge, addr: u16) -> u8 {
+    let data = cartridge.ppu_read(addr);
+    cartridge.observe_ppu_read(addr);
+    data
+}
+
+#[test]
+pub fn mapper_trainer_is_skipped() {
+    // 512 bytes between the header and the PRG, loaded at $7000
+    let mut rom = mapper_image(0, 2, 1);
+    rom[6] |= 0x04;
+    let trainer: Vec<u8> = (0..512).map(|x| (x % 251) as u8).collect();
+    rom.splice(16..16, trainer.iter().cloned());
+    let mut cartridge = Cartridge::new();
+    cartridge.load(rom.clone()).unwrap();
+    assert!(cartridge.header.has_trainer());
+    assert_eq!(cartridge.cpu_read(0x8000), 0);
+    assert_eq!(cartridge.cpu_read(0xFFFF), 31);
+    assert_eq!(cartridge.ppu_read(0x1FFF), 7);
+    assert_eq!(cartridge.cpu_read(0x7001), 1);
+    assert_eq!(cartridge.cpu_read(0x71FF), trainer[511]);
+
+    // The size of the trainer is part of the size checked
+    rom.truncate(rom.len() - 1);
+    assert!(Cartridge::new().load(rom).is_err());
+}
+
+#[test]
+pub fn mapper_mmc2_banks_and_latches() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(9, 8, 16)).unwrap();
+    cartridge.cpu_write(0xA000, 2);
+    // 8KB switched at $8000, then the 3 last banks
+    let prg: Vec<u8> = [0x8000, 0xA000, 0xC000, 0xE000]
+        .iter()
+        .map(|addr| cartridge.cpu_read(*addr))
+        .collect();
+    assert_eq!(prg, vec![16, 104, 112, 120]);
+    // No register at $8000-$9FFF, the ROM is left alone
+    cartridge.cpu_write(0x8000, 0x55);
+    cartridge.cpu_write(0x9FFF, 0x55);
+    assert_eq!((cartridge.cpu_read(0x8000), cartridge.cpu_read(0x9FFF)), (16, 23));
+
+    for (i, addr) in [0xB000, 0xC000, 0xD000, 0xE000].iter().enumerate() {
+        cartridge.cpu_write(*addr, 4 + i as u8);
+    }
+    // The latches start on $FD, the new bank is used after the fetch of the tile
+    assert_eq!(fetch(&mut cartridge, 0x0FE8), 16 + 3);
+    assert_eq!(fetch(&mut cartridge, 0x0000), 20);
+    // Only the first row of $FD switches the first pattern table of MMC2
+    fetch(&mut cartridge, 0x0FD9);
+    assert_eq!(fetch(&mut cartridge, 0x0000), 20);
+    fetch(&mut cartridge, 0x0FD8);
+    assert_eq!(fetch(&mut cartridge, 0x0000), 16);
+    fetch(&mut cartridge, 0x1FEF);
+    assert_eq!(fetch(&mut cartridge, 0x1000), 28);
+    // Peeking at the pattern tables does not move the latches
+    assert_eq!(cartridge.ppu_read(0x1FDA), 31);
+    assert_eq!(fetch(&mut cartridge, 0x1000), 28);
+
+    assert_eq!(cartridge.mirror(), Mirror::Vertical);
+    cartridge.cpu_write(0xF000, 1);
+    assert_eq!(cartridge.mirror(), Mirror::Horizontal);
+}
+
+#[test]
+pub fn mapper_mmc4_banks_and_latches() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(10, 8, 16)).unwrap();
+    cartridge.cpu_write(0xA000, 3);
+    assert_eq!(
+        (
+            cartridge.cpu_read(0x8000),
+            cartridge.cpu_read(0xBFFF),
+            cartridge.cpu_read(0xC000)
+        ),
+        (48, 63, 112)
+    );
+    cartridge.cpu_write(0x9000, 0x55);
+    assert_eq!(cartridge.cpu_read(0x9000), 52);
+    cartridge.cpu_write(0xB000, 4);
+    cartridge.cpu_write(0xC000, 5);
+    fetch(&mut cartridge, 0x0FED);
+    assert_eq!(fetch(&mut cartridge, 0x0000), 20);
+    fetch(&mut cartridge, 0x0FDB);
+    assert_eq!(fetch(&mut cartridge, 0x0000), 16);
+    // PRG RAM at $6000
+    cartridge.cpu_write(0x6000, 0x42);
+    assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+}
+
+#[test]
+pub fn mapper_mmc2_sprite_fetches() {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(mapper_image(9, 8, 16)).unwrap();
+    nes.cartridge.mapper.banks[7] = 1;
+    // A single sprite of the tile $FD, in the pattern table at $1000
+    nes.ppu.oam = [0xFF; 256];
+    nes.ppu.oam[..4].copy_from_slice(&[0x10, 0xFD, 0x00, 0x00]);
+    nes.ppu.ctrl = 0x08;
+    nes.ppu.mask = 0x10;
+    run_ppu_frame(&mut nes);
+    assert_eq!(nes.cartridge.mapper.banks[7], 0);
+
+    // The empty slots of 8x16 sprites fetch $1FE0 and $1FE8, and latch $FE
+    nes.ppu.oam = [0xFF; 256];
+    nes.ppu.ctrl = 0x20;
+    run_ppu_frame(&mut nes);
+    assert_eq!(nes.cartridge.mapper.banks[7], 1);
+}
+
+#[test]
+pub fn mapper_mmc5_prg_and_ram() {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(mapper_image(5, 8, 16)).unwrap();
+    // 8KB banks at power up, $5117 is the last bank
+    assert_eq!(nes.cpu_read(0xE000, true), 120);
+    nes.cpu_write(0x5100, 0);
+    assert_eq!(nes.cpu_read(0x8000, true), 96);
+    nes.cpu_write(0x5100, 1);
+    nes.cpu_write(0x5115, 0x83);
+    assert_eq!(
+        (nes.cpu_read(0x8000, true), nes.cpu_read(0xC000, true)),
+        (16, 112)
+    );
+
+    // RAM at $A000 and $6000, only written once unprotected
+    nes.cpu_write(0x5100, 3);
+    nes.cpu_write(0x5114, 0x81);
+    nes.cpu_write(0x5115, 0x02);
+    nes.cpu_write(0x5113, 0x01);
+    nes.cpu_write(0xA000, 0x12);
+    assert_eq!(nes.cpu_read(0xA000, true), 0x00);
+    nes.cpu_write(0x5102, 0x02);
+    nes.cpu_write(0x5103, 0x01);
+    nes.cpu_write(0xA000, 0x12);
+    nes.cpu_write(0x6000, 0x34);
+    nes.cpu_write(0x8000, 0x56);
+    assert_eq!(nes.cpu_read(0xA000, true), 0x12);
+    assert_eq!(
+        (nes.cartridge.prg_ram[0x4000], nes.cartridge.prg_ram[0x2000]),
+        (0x12, 0x34)
+    );
+    assert_eq!(nes.cpu_read(0x8000, true), 8);
+
+    nes.cpu_write(0x5205, 200);
+    nes.cpu_write(0x5206, 100);
+    assert_eq!(
+        (nes.cpu_read(0x5205, true), nes.cpu_read(0x5206, true)),
+        (0x20, 0x4E)
+    );
+}
+
+#[test]
+pub fn mapper_mmc5_chr_modes() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(5, 8, 16)).unwrap();
+    cartridge.cpu_write(0x5101, 3);
+    for i in 0..8 {
+        cartridge.cpu_write(0x5120 + i, 10 + i as u8);
+    }
+    assert_eq!(
+        (cartridge.ppu_read(0x0400), cartridge.ppu_read(0x1C00)),
+        (11, 17)
+    );
+    // Outside of the frame, the last banks written are used
+    for i in 0..4 {
+        cartridge.cpu_write(0x5128 + i, 20 + i as u8);
+    }
+    assert_eq!(
+        (cartridge.ppu_read(0x0400), cartridge.ppu_read(0x1400)),
+        (21, 21)
+    );
+    cartridge.cpu_write(0x5101, 1);
+    assert_eq!(cartridge.ppu_read(0x1000), 23 * 4);
+    cartridge.cpu_write(0x5101, 0);
+    cartridge.cpu_write(0x5127, 3);
+    assert_eq!(cartridge.ppu_read(0x1C00), 31);
+}
+
+#[test]
+pub fn mapper_mmc5_nametables() {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(mapper_image(5, 8, 16)).unwrap();
+    // Vram pages 0 and 1, ExRAM, fill mode
+    nes.cpu_write(0x5105, 0b11_10_01_00);
+    nes.cpu_write(0x5106, 0x42);
+    nes.cpu_write(0x5107, 0x02);
+    nes.cpu_write(0x5104, 0x02);
+    nes.cpu_write(0x5C05, 0x99);
+    assert_eq!(nes.cpu_read(0x5C05, true), 0x99);
+    nes.cpu_write(0x5104, 0x00);
+    assert_eq!(nes.ppu_read(0x2805, true), 0x99);
+    assert_eq!(
+        (nes.ppu_read(0x2C10, true), nes.ppu_read(0x2FC0, true)),
+        (0x42, 0xAA)
+    );
+    nes.ppu_write(0x2400, 0x11);
+    nes.ppu_write(0x2806, 0x22);
+    assert_eq!(nes.ppu.names[1][0], 0x11);
+    assert_eq!(nes.cartridge.mapper.mmc5.as_ref().unwrap().exram[6], 0x22);
+
+    // The registers and ExRAM are part of the save states
+    let mut cpu = CPU6502::new();
+    let state = SaveState::capture(&cpu, &nes);
+    nes.cpu_write(0x5106, 0x00);
+    nes.ppu_write(0x2806, 0x00);
+    state.restore(&mut cpu, &mut nes).unwrap();
+    assert_eq!(
+        (nes.ppu_read(0x2C10, true), nes.ppu_read(0x2806, true)),
+        (0x42, 0x22)
+    );
+}
+
+#[test]
+pub fn mapper_mmc5_scanline_irq() {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(mapper_image(5, 8, 16)).unwrap();
+    nes.cpu_write(0x2001, 0x08);
+    nes.cpu_write(0x5203, 100);
+    run_ppu_frame(&mut nes);
+    // Pending but disabled during the first frame
+    assert!(!nes.cartridge.irq());
+    assert_eq!(nes.cpu_read(0x5204, false) & 0x80, 0x80);
+    nes.cpu_write(0x5204, 0x80);
+    while !nes.cartridge.irq() {
+        nes.ppu.clock(&mut nes.cartridge);
+        if nes.clock_counter.is_multiple_of(3) {
+            nes.cartridge.clock();
+        }
+        nes.clock_counter += 1;
+    }
+    assert_eq!(nes.ppu.scanline, 100);
+    // In frame and pending, acknowledged by the read
+    assert_eq!(nes.cpu_read(0x5204, false), 0xC0);
+    assert!(!nes.cartridge.irq());
+    // The frame ends with the vblank
+    while nes.ppu.scanline != 250 {
+        nes.ppu.clock(&mut nes.cartridge);
+        if nes.clock_counter.is_multiple_of(3) {
+            nes.cartridge.clock();
+        }
+        nes.clock_counter += 1;
+    }
+    assert_eq!(nes.cpu_read(0x5204, false), 0x00);
+}
+
+#[test]
+pub fn mapper_mmc5_extended_attributes_and_split() {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(mapper_image(5, 8, 16)).unwrap();
+    nes.cpu_write(0x2001, 0x0A);
+    // Palette 3 and the 4KB bank 5 for the first 64 tiles, written in the RAM mode
+    nes.cpu_write(0x5104, 0x02);
+    for i in 0..64 {
+        nes.cpu_write(0x5C00 + i, 0xC5);
+    }
+    nes.cpu_write(0x5104, 0x01);
+    nes.ppu.pallette[0x0F] = 0x2A;
+    run_ppu_frame(&mut nes);
+    run_ppu_frame(&mut nes);
+    // The rows of the tiles of bank 5 are 20, pixels 3 and 5 use the color 3
+    let line = &nes.ppu.frame_buffer[256..512];
+    assert_eq!((line[3] & 0x3F, line[4] & 0x3F), (0x2A, 0x00));
+
+    // Right side split from the 16th tile, with the tiles of ExRAM from the bank 5
+    nes.cpu_write(0x5104, 0x00);
+    nes.cpu_write(0x5200, 0xC0 | 16);
+    nes.cpu_write(0x5202, 5);
+    nes.ppu.pallette[0x03] = 0x11;
+    run_ppu_frame(&mut nes);
+    run_ppu_frame(&mut nes);
+    let line = &nes.ppu.frame_buffer[10 * 256..11 * 256];
+    assert_eq!(
+        (line[3] & 0x3F, line[128 + 3] & 0x3F, line[128 + 4] & 0x3F),
+        (0x00, 0x11, 0x00)
+    );
+}
+
+#[test]
+pub fn mapper_vrc4_banks_and_wiring() {
+    // VRC4a, the registers are selected by A1 and A2
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(21, 1, 8, 16)).unwrap();
+    cartridge.cpu_write(0x8000, 3);
+    cartridge.cpu_write(0xA000, 5);
+    let prg = |cartridge: &Cartridge| -> Vec<u8> {
+        [0x8000, 0xA000, 0xC000, 0xE000]
+            .iter()
+            .map(|addr| cartridge.cpu_read(*addr))
+            .collect()
+    };
+    assert_eq!(prg(&cartridge), vec![24, 40, 112, 120]);
+    cartridge.cpu_write(0x9004, 0x02);
+    assert_eq!(prg(&cartridge), vec![112, 40, 24, 120]);
+
+    // Low and high nibbles of the 1KB CHR banks
+    cartridge.cpu_write(0xB000, 0x05);
+    cartridge.cpu_write(0xB002, 0x02);
+    cartridge.cpu_write(0xE004, 0x03);
+    cartridge.cpu_write(0xE006, 0x01);
+    assert_eq!(cartridge.ppu_read(0x0000), 37);
+    assert_eq!(cartridge.ppu_read(0x1C00), 19);
+
+    cartridge.cpu_write(0x9000, 1);
+    assert_eq!(cartridge.mirror(), Mirror::Horizontal);
+    cartridge.cpu_write(0x9000, 3);
+    assert_eq!(cartridge.mirror(), Mirror::OneScreenHi);
+
+    // Without submapper the lines of both VRC4e and VRC4f select the registers
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(23, 8, 16)).unwrap();
+    cartridge.cpu_write(0xB000, 0x06);
+    cartridge.cpu_write(0xB004, 0x01);
+    cartridge.cpu_write(0xB002, 0x09);
+    assert_eq!(cartridge.ppu_read(0x0000), 22);
+    assert_eq!(cartridge.ppu_read(0x0400), 9);
+
+    // VRC2a swaps the lines and drops the low bit of the CHR banks
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(22, 8, 16)).unwrap();
+    cartridge.cpu_write(0xB000, 0x06);
+    cartridge.cpu_write(0xB001, 0x08);
+    assert_eq!(cartridge.ppu_read(0x0000), 3);
+    assert_eq!(cartridge.ppu_read(0x0400), 4);
+    cartridge.cpu_write(0x9000, 0x03);
+    assert_eq!(cartridge.mirror(), Mirror::Horizontal);
+}
+
+#[test]
+pub fn mapper_vrc6_banks() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(24, 8, 16)).unwrap();
+    cartridge.cpu_write(0x8000, 2);
+    cartridge.cpu_write(0xC000, 5);
+    let prg: Vec<u8> = [0x8000, 0xA000, 0xC000, 0xE000]
+        .iter()
+        .map(|addr| cartridge.cpu_read(*addr))
+        .collect();
+    assert_eq!(prg, vec![32, 40, 40, 120]);
+
+    cartridge.cpu_write(0xD000, 9);
+    cartridge.cpu_write(0xE003, 11);
+    assert_eq!(cartridge.ppu_read(0x0000), 9);
+    assert_eq!(cartridge.ppu_read(0x1C00), 11);
+    // 2KB banks with the registers in the order of the slots
+    cartridge.cpu_write(0xD001, 3);
+    cartridge.cpu_write(0xB003, 0x05);
+    assert_eq!(cartridge.ppu_read(0x0800), 6);
+    assert_eq!(cartridge.ppu_read(0x0C00), 7);
+    assert_eq!(cartridge.mirror(), Mirror::Horizontal);
+
+    // VRC6b swaps A0 and A1, the sound registers still reach the expansion audio
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(26, 8, 16)).unwrap();
+    cartridge.cpu_write(0xE001, 13);
+    assert_eq!(cartridge.ppu_read(0x1800), 13);
+}
+
+#[test]
+pub fn mapper_vrc7_banks_and_irq() {
+    // VRC7a, the registers are selected by A4
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(85, 2, 8, 16)).unwrap();
+    cartridge.cpu_write(0x8000, 3);
+    cartridge.cpu_write(0x8010, 5);
+    cartridge.cpu_write(0x9000, 7);
+    let prg = |cartridge: &Cartridge| -> Vec<u8> {
+        [0x8000, 0xA000, 0xC000, 0xE000]
+            .iter()
+            .map(|addr| cartridge.cpu_read(*addr))
+            .collect()
+    };
+    assert_eq!(prg(&cartridge), vec![24, 40, 56, 120]);
+    // The sound registers do not switch the bank at $C000
+    cartridge.cpu_write(0x9010, 0x30);
+    cartridge.cpu_write(0x9030, 0x30);
+    assert_eq!(prg(&cartridge), vec![24, 40, 56, 120]);
+
+    cartridge.cpu_write(0xA000, 9);
+    cartridge.cpu_write(0xD010, 11);
+    assert_eq!(cartridge.ppu_read(0x0000), 9);
+    assert_eq!(cartridge.ppu_read(0x1C00), 11);
+    cartridge.cpu_write(0xE000, 1);
+    assert_eq!(cartridge.mirror(), Mirror::Horizontal);
+
+    // Cycle mode IRQ, the latch is at $E010 and the control at $F000
+    cartridge.cpu_write(0xE010, 0xFE);
+    cartridge.cpu_write(0xF000, 0x07);
+    cartridge.clock();
+    cartridge.clock();
+    assert!(cartridge.irq());
+    cartridge.cpu_write(0xF010, 0);
+    assert!(!cartridge.irq());
+
+    // VRC7b selects them with A3
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(85, 1, 8, 16)).unwrap();
+    cartridge.cpu_write(0x8008, 5);
+    cartridge.cpu_write(0x9010, 6);
+    assert_eq!(prg(&cartridge), vec![0, 40, 0, 120]);
+}
+
+#[test]
+pub fn mapper_vrc_irq() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(24, 8, 16)).unwrap();
+    // Cycle mode, the counter overflows after 2 cycles and is reloaded
+    cartridge.cpu_write(0xF000, 0xFE);
+    cartridge.cpu_write(0xF001, 0x07);
+    cartridge.clock();
+    assert!(!cartridge.irq());
+    cartridge.clock();
+    assert!(cartridge.irq());
+    // The acknowledge restores the enable bit written with the control
+    cartridge.cpu_write(0xF002, 0);
+    assert!(!cartridge.irq());
+    cartridge.clock();
+    cartridge.clock();
+    assert!(cartridge.irq());
+
+    // Scanline mode, 3 scanlines of 113.67 cycles
+    cartridge.cpu_write(0xF000, 0xFD);
+    cartridge.cpu_write(0xF001, 0x02);
+    assert!(!cartridge.irq());
+    for _ in 0..340 {
+        cartridge.clock();
+    }
+    assert!(!cartridge.irq());
+    cartridge.clock();
+    assert!(cartridge.irq());
+    cartridge.cpu_write(0xF002, 0);
+    for _ in 0..400 {
+        cartridge.clock();
+    }
+    assert!(!cartridge.irq());
+
+    // The counter is saved with the banks
+    let mut nes = NesData::new();
+    nes.insert_cartridge(nes2_image(21, 2, 8, 16)).unwrap();
+    nes.cpu_write(0xF000, 0x0E);
+    nes.cpu_write(0xF040, 0x0F);
+    nes.cpu_write(0xF080, 0x06);
+    nes.cpu_write(0x8000, 4);
+    let mut cpu = CPU6502::new();
+    let state = SaveState::capture(&cpu, &nes);
+    nes.cpu_write(0x8000, 0);
+    nes.cartridge.clock();
+    nes.cartridge.clock();
+    assert!(nes.cartridge.irq());
+    state.restore(&mut cpu, &mut nes).unwrap();
+    assert!(!nes.cartridge.irq());
+    assert_eq!(nes.cpu_read(0x8000, true), 32);
+    nes.cartridge.clock();
+    assert!(!nes.cartridge.irq());
+    nes.cartridge.clock();
+    assert!(nes.cartridge.irq());
+}
+
+/// PRG mapped at $8000, $A000, $C000 and $E000
+fn prg_banks(cartridge: &Cartridge) -> Vec<u8> {
+    [0x8000, 0xA000, 0xC000, 0xE000]
+        .iter()
+        .map(|addr| cartridge.cpu_read(*addr))
+        .collect()
+}
+
+#[test]
+pub fn mapper_16kb_prg_boards() {
+    // UxROM switches $8000, UN1ROM $C000
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(2, 1, 8, 0)).unwrap();
+    cartridge.cpu_write(0x8000, 3);
+    assert_eq!(prg_banks(&cartridge), vec![48, 56, 112, 120]);
+    cartridge.ppu_write(0x1234, 0x56);
+    assert_eq!(cartridge.ppu_read(0x1234), 0x56);
+
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(180, 1, 8, 0)).unwrap();
+    cartridge.cpu_write(0x8C00, 3);
+    assert_eq!(prg_banks(&cartridge), vec![0, 8, 48, 56]);
+
+    // Camerica, the Fire Hawk board also selects the screen
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(71, 1, 8, 0)).unwrap();
+    cartridge.cpu_write(0xC000, 5);
+    cartridge.cpu_write(0x9000, 0x10);
+    assert_eq!(prg_banks(&cartridge), vec![80, 88, 112, 120]);
+    assert_eq!(cartridge.mirror(), Mirror::OneScreenHi);
+    cartridge.cpu_write(0x9000, 0x00);
+    assert_eq!(cartridge.mirror(), Mirror::OneScreenLo);
+    cartridge.cpu_write(0xA000, 0xFF);
+    assert_eq!(prg_banks(&cartridge), vec![80, 88, 112, 120]);
+}
+
+#[test]
+pub fn mapper_chr_boards() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(3, 1, 2, 4)).unwrap();
+    cartridge.cpu_write(0x8000, 2);
+    assert_eq!(prg_banks(&cartridge), vec![0, 8, 16, 24]);
+    assert_eq!(cartridge.ppu_read(0x0C00), 19);
+
+    // Mapper 87 reverses the bits of the bank, from $6000
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(87, 1, 4)).unwrap();
+    cartridge.cpu_write(0x6000, 0x01);
+    assert_eq!(prg_banks(&cartridge), vec![0, 8, 0, 8]);
+    assert_eq!(cartridge.ppu_read(0x0000), 16);
+}
+
+#[test]
+pub fn mapper_32kb_prg_boards() {
+    // Register address, value, mapped PRG banks and first CHR KB. The boards with bus
+    // conflicts are written where the ROM holds the same value.
+    let boards: [(u16, u16, u8, [u8; 4], u8); 5] = [
+        (7, 0x8000, 0x12, [64, 72, 80, 88], 0),
+        (11, 0xC400, 0x11, [32, 40, 48, 56], 8),
+        (66, 0xC400, 0x11, [32, 40, 48, 56], 8),
+        (79, 0x4100, 0x0D, [32, 40, 48, 56], 40),
+        (140, 0x6000, 0x35, [96, 104, 112, 120], 40),
+    ];
+    for (mapper, addr, data, prg, chr) in boards.iter() {
+        let mut cartridge = Cartridge::new();
+        let chr_banks = if *mapper == 7 { 0 } else { 16 };
+        cartridge.load(nes2_image(*mapper, 1, 8, chr_banks)).unwrap();
+        cartridge.cpu_write(*addr, *data);
+        assert_eq!(prg_banks(&cartridge), prg.to_vec(), "mapper {}", mapper);
+        assert_eq!(cartridge.ppu_read(0x0000), *chr, "mapper {}", mapper);
+    }
+
+    // The boards with their registers below $8000 leave the ROM alone
+    for mapper in [79, 87, 140].iter() {
+        let mut cartridge = Cartridge::new();
+        cartridge.load(mapper_image(*mapper, 2, 4)).unwrap();
+        cartridge.cpu_write(0x8000, 0x55);
+        cartridge.cpu_write(0xFFFF, 0x55);
+        let rom = (cartridge.cpu_read(0x8000), cartridge.cpu_read(0xFFFF));
+        assert_eq!(rom, (0, 31), "mapper {}", mapper);
+    }
+
+    // NINA-03/06 only decodes the addresses with A8 set
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(79, 2, 4)).unwrap();
+    cartridge.cpu_write(0x4000 | 0x0200, 0x01);
+    assert_eq!(cartridge.ppu_read(0x0000), 0);
+    cartridge.cpu_write(0x5100, 0x01);
+    assert_eq!(cartridge.ppu_read(0x0000), 8);
+
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(7, 8, 0)).unwrap();
+    cartridge.cpu_write(0x8000, 0x10);
+    assert_eq!(cartridge.mirror(), Mirror::OneScreenHi);
+}
+
+#[test]
+pub fn mapper_34_boards() {
+    // BNROM switches 32KB, CHR RAM
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(34, 2, 8, 0)).unwrap();
+    cartridge.cpu_write(0x8000, 0xFF);
+    assert_eq!(prg_banks(&cartridge), vec![0, 8, 16, 24]);
+    cartridge.cpu_write(0x8C00, 0x03);
+    assert_eq!(prg_banks(&cartridge), vec![96, 104, 112, 120]);
+
+    // NINA-001 has its registers in the PRG RAM and two 4KB CHR banks
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(34, 4, 4)).unwrap();
+    cartridge.cpu_write(0x7FFD, 1);
+    cartridge.cpu_write(0x7FFE, 3);
+    cartridge.cpu_write(0x7FFF, 6);
+    assert_eq!(prg_banks(&cartridge), vec![32, 40, 48, 56]);
+    assert_eq!(cartridge.ppu_read(0x0000), 12);
+    assert_eq!(cartridge.ppu_read(0x1000), 24);
+    assert_eq!(cartridge.cpu_read(0x7FFF), 6);
+}
+
+#[test]
+pub fn mapper_bus_conflicts() {
+    // The byte of the ROM at the address written is ANDed with the value
+    for (submapper, bank) in [(0, 16), (2, 16), (1, 48)].iter() {
+        let mut cartridge = Cartridge::new();
+        cartridge.load(nes2_image(2, *submapper, 8, 0)).unwrap();
+        cartridge.cpu_write(0xC400, 0x03);
+        assert_eq!(cartridge.cpu_read(0x8000), *bank, "submapper {}", submapper);
+    }
+
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(66, 4, 4)).unwrap();
+    cartridge.cpu_write(0x8800, 0x13);
+    assert_eq!(cartridge.cpu_read(0x8000), 0);
+    assert_eq!(cartridge.ppu_read(0x0000), 16);
+
+    // AxROM only has them on AMROM
+    let mut cartridge = Cartridge::new();
+    cartridge.load(nes2_image(7, 2, 8, 0)).unwrap();
+    cartridge.cpu_write(0x8C00, 0x13);
+    assert_eq!(cartridge.cpu_read(0x8000), 96);
+    assert_eq!(cartridge.mirror(), Mirror::OneScreenLo);
+}
+
+#[test]
+pub fn mapper_fme7_banks_and_ram() {
+    // 32KB of PRG RAM
+    let mut nes = NesData::new();
+    let mut rom = nes2_image(69, 0, 8, 16);
+    rom[10] = 0x09;
+    nes.insert_cartridge(rom).unwrap();
+    let command = |nes: &mut NesData, command: u8, data: u8| {
+        nes.cpu_write(0x8000, command);
+        nes.cpu_write(0xA000, data);
+    };
+    command(&mut nes, 0x09, 3);
+    command(&mut nes, 0x0A, 4);
+    command(&mut nes, 0x0B, 5);
+    command(&mut nes, 0x00, 7);
+    command(&mut nes, 0x07, 9);
+    let prg: Vec<u8> = [0x8000, 0xA000, 0xC000, 0xE000]
+        .iter()
+        .map(|addr| nes.cpu_read(*addr, true))
+        .collect();
+    assert_eq!(prg, vec![24, 32, 40, 120]);
+    assert_eq!(nes.ppu_read(0x0000, true), 7);
+    assert_eq!(nes.ppu_read(0x1C00, true), 9);
+    command(&mut nes, 0x0C, 2);
+    assert_eq!(nes.cartridge.mirror(), Mirror::OneScreenLo);
+
+    // ROM at $6000, the writes do not reach it
+    command(&mut nes, 0x08, 6);
+    nes.cpu_write(0x6000, 0x99);
+    assert_eq!(nes.cpu_read(0x6000, true), 48);
+    // RAM banks
+    command(&mut nes, 0x08, 0xC1);
+    nes.cpu_write(0x6000, 0x11);
+    command(&mut nes, 0x08, 0xC2);
+    nes.cpu_write(0x6000, 0x22);
+    assert_eq!(nes.cpu_read(0x6000, true), 0x22);
+    command(&mut nes, 0x08, 0xC5);
+    assert_eq!(nes.cpu_read(0x6000, true), 0x11);
+    assert_eq!(nes.cartridge.prg_ram.len(), 0x8000);
+    // Disabled RAM
+    command(&mut nes, 0x08, 0x41);
+    nes.cpu_write(0x6000, 0x33);
+    command(&mut nes, 0x08, 0xC1);
+    assert_eq!(nes.cpu_read(0x6000, true), 0x11);
+}
+
+#[test]
+pub fn mapper_fme7_irq() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(69, 8, 16)).unwrap();
+    for (command, data) in [(0x0E, 0x02), (0x0F, 0x00), (0x0D, 0x81)].iter() {
+        cartridge.cpu_write(0x8000, *command);
+        cartridge.cpu_write(0xA000, *data);
+    }
+    // 2, 1, 0, then $FFFF
+    for _ in 0..2 {
+        cartridge.clock();
+    }
+    assert!(!cartridge.irq());
+    cartridge.clock();
+    assert!(cartridge.irq());
+    // Writing the control acknowledges it, the counter keeps running without IRQ
+    cartridge.cpu_write(0x8000, 0x0D);
+    cartridge.cpu_write(0xA000, 0x80);
+    assert!(!cartridge.irq());
+    assert_eq!(cartridge.mapper.fme7.as_ref().unwrap().counter, 0xFFFF);
+    cartridge.clock();
+    assert_eq!(cartridge.mapper.fme7.as_ref().unwrap().counter, 0xFFFE);
+}
+
+#[test]
+pub fn mapper_namco163_banks_and_nametables() {
+    let mut nes = NesData::new();
+    nes.insert_cartridge(mapper_image(19, 8, 16)).unwrap();
+    nes.cpu_write(0xE000, 2);
+    nes.cpu_write(0xE800, 3);
+    nes.cpu_write(0xF000, 4);
+    let prg: Vec<u8> = [0x8000, 0xA000, 0xC000, 0xE000]
+        .iter()
+        .map(|addr| nes.cpu_read(*addr, true))
+        .collect();
+    assert_eq!(prg, vec![16, 24, 32, 120]);
+    nes.cpu_write(0x8000, 5);
+    nes.cpu_write(0xB800, 100);
+    assert_eq!(nes.ppu_read(0x0000, true), 5);
+    assert_eq!(nes.ppu_read(0x1C00, true), 100);
+
+    // Vertical mirroring on the vram at power, then a nametable from the CHR ROM
+    nes.ppu_write(0x2400, 0x11);
+    assert_eq!(nes.ppu_read(0x2C00, true), 0x11);
+    nes.cpu_write(0xC000, 0xE1);
+    nes.cpu_write(0xD800, 42);
+    assert_eq!(nes.ppu_read(0x2000, true), 0x11);
+    assert_eq!(nes.ppu_read(0x2C05, true), 42);
+    nes.ppu_write(0x2C05, 0);
+    assert_eq!(nes.ppu_read(0x2C05, true), 42);
+
+    // The PRG RAM is protected until $F800 holds $4x
+    nes.cpu_write(0x6000, 0x12);
+    assert_eq!(nes.cpu_read(0x6000, true), 0);
+    nes.cpu_write(0xF800, 0x42);
+    nes.cpu_write(0x6000, 0x12);
+    nes.cpu_write(0x6800, 0x34);
+    assert_eq!(
+        (nes.cpu_read(0x6000, true), nes.cpu_read(0x6800, true)),
+        (0x12, 0)
+    );
+    // $F800 is also the address of the sound RAM
+    nes.cpu_write(0x4800, 0x56);
+    assert_eq!(nes.cartridge.audio.n163.as_ref().unwrap().ram[0x42], 0x56);
+}
+
+#[test]
+pub fn mapper_namco163_irq() {
+    let mut cartridge = Cartridge::new();
+    cartridge.load(mapper_image(19, 8, 16)).unwrap();
+    cartridge.cpu_write(0x5000, 0xFD);
+    cartridge.cpu_write(0x5800, 0xFF);
+    assert_eq!(cartridge.cpu_read(0x5800), 0xFF);
+    cartridge.clock();
+    assert!(!cartridge.irq());
+    cartridge.clock();
+    assert!(cartridge.irq());
+    // The counter stops at $7FFF
+    cartridge.clock();
+    assert_eq!(
+        (cartridge.cpu_read(0x5000), cartridge.cpu_read(0x5800)),
+        (0xFF, 0xFF)
+    );
+    cartridge.cpu_write(0x5800, 0x7F);
+    assert!(!cartridge.irq());
+}
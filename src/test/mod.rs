@@ -1,99 +1,57 @@
-use console::Term;
 use std::fs::File;
-use std::io;
 use std::io::prelude::*;
 
-use super::*;
-
-
-pub fn test_cpu(cpu: &mut CPU6502, nes: &mut NesData, depth: Option<usize>) {
-    let dpth = match depth {
-        Some(x) => x,
-        None => 8,
-    };
-    let mut input = String::new();
-
-    let term = Term::stdout();
-    // Set the reset vector to the cartridge memory address
-    nes.cpu_write(0xFFFC, 0x20);
-    nes.cpu_write(0xFFFD, 0x40);
-
-    // get the Assembly code
-    let code: Vec<u8> = test_code();
-    // Writes the code in the ram with offset 0x8000
-    for i in 0..code.len() {
-        nes.cpu_write(0x4020 + i as u16, code[i]);
-    }
-    cpu.power(nes);
-    while input.trim() != "quit" {
-        cpu.clock(nes);
-        println!(
-            "{}\n{}\n\n\n\nCode :\n\n{}",
-            display_registers(cpu),
-            display_ram(nes.ram.to_vec(), 0x0000, 16, dpth),
-            display_code(nes.cartridge.to_vec(), 16, dpth)
-        );
-        io::stdin().read_line(&mut input).unwrap();
-        term.clear_screen().unwrap();
-    }
-}
-
-/// Display ram on a length * depth
-fn display_ram(ram: Vec<u8>, start: usize, length: usize, depth: usize) -> String {
-    let mut result = String::new();
-
-    for i in start..start + depth * length {
-        if i % length == 0 {
-            result += format!("\n{1:00$X}  --  ", 4, i).as_str();
-        }
-        result += format!(" {1:00$X}", 2, ram[i]).as_str();
-    }
-    result
-}
-
-fn display_code(cartridge: Vec<u8>, length: usize, depth: usize) -> String {
-    let mut result = String::new();
-
-    for i in 0..depth * length {
-        if i % length == 0 {
-            result += format!("\n{1:00$X}  --  ", 4, i+0x4020).as_str();
-        }
-        result += format!(" {1:00$X}", 2, cartridge[i]).as_str();
-    }
-    result
-}
-
-fn display_registers(cpu: &CPU6502) -> String {
-    let mut result = String::new();
-
-    result += format!(
-        "{:012} : {:03}\n",
-        "Applying", cpu.lookup[cpu.curr_opcode as usize].opcode
-    )
-    .as_str();
-    result += format!(
-        "{:012} : {:03}\n\n",
-        "Adressing", cpu.lookup[cpu.curr_opcode as usize].addr_mode
-    )
-    .as_str();
-    result += format!("{:012} : {1:02$X}\n", "Accumulator", cpu.a, 2).as_str();
-    result += format!("{:012} : {1:02$X}\n", "X-Register", cpu.x, 2).as_str();
-    result += format!("{:012} : {1:02$X}\n", "Y-Register", cpu.y, 2).as_str();
-    result += format!("{:012} : {1:02$X}\n", "StackPointer", cpu.stkp, 2).as_str();
-    result += format!("{:012} : {1:02$X}\n", "Status", cpu.status, 2).as_str();
-    result += format!("{:012} : {1:02$X}\n", "PC", cpu.pc, 2).as_str();
-    result += format!("{:012} : {1:02}\n", "Cycles left", cpu.cycles).as_str();
-    result
-}
-
-/// This functions returns a compiled assembly code  that
-/// loads some data in the ram and executes some shift left
-fn test_code() -> Vec<u8> {
-    let mut file = File::open("src/test/test.nes").unwrap();
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents).unwrap();
-    contents
-}
+use super::components::*;
+use super::assembler::*;
+use super::audio::*;
+use super::debugger::*;
+
+#[cfg(test)]
+mod archive;
+#[cfg(test)]
+mod assembler;
+#[cfg(test)]
+mod audio;
+#[cfg(test)]
+mod blargg;
+#[cfg(test)]
+mod cpu;
+#[cfg(test)]
+mod debugger;
+#[cfg(test)]
+mod disassembler;
+#[cfg(test)]
+mod disk;
+#[cfg(test)]
+mod expansion;
+#[cfg(test)]
+mod gamedb;
+#[cfg(test)]
+mod headless;
+#[cfg(test)]
+mod mapper;
+#[cfg(test)]
+mod movie;
+#[cfg(test)]
+mod nsf;
+#[cfg(test)]
+mod palette;
+#[cfg(test)]
+mod patch;
+#[cfg(test)]
+mod region;
+#[cfg(test)]
+mod resampler;
+#[cfg(test)]
+mod screenshot;
+#[cfg(test)]
+mod state;
+#[cfg(test)]
+mod terminal;
+#[cfg(test)]
+mod tui;
+#[cfg(test)]
+mod unif;
 
 fn load_mario() -> Vec<u8> {
     let mut file = File::open("roms/Super Mario Bros.nes").unwrap();
@@ -111,7 +69,7 @@ fn load_metroid() -> Vec<u8> {
 #[test]
 pub fn metroid_header(){ 
     let mut cartridge = Cartridge::new();
-    cartridge.load(load_mario());
+    cartridge.load(load_mario()).unwrap();
     println!("{}",cartridge.header);
 }
 
@@ -119,10 +77,32 @@ pub fn metroid_header(){
 #[test]
 pub fn test_mapper1_read(){
     let mut cartridge = Cartridge::new();
-    cartridge.load(load_mario());
+    cartridge.load(load_mario()).unwrap();
     assert_eq!(cartridge.prg_memory[0], cartridge.cpu_read(0x8000));
     assert_eq!(cartridge.prg_memory[0x0032], cartridge.cpu_read(0x8000 + 0x0032));
     assert_eq!(cartridge.chr_memory[0], cartridge.ppu_read(0x0000));
     assert_eq!(cartridge.chr_memory[0x0032], cartridge.ppu_read(0x0032));
 }
 
+
+/// Hash of the audio of the first `frames` frames of a rom, to compare with a known good capture
+pub fn rom_audio_hash(rom: Vec<u8>, frames: u64) -> u64 {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(rom).unwrap();
+    cpu.power(&mut nes);
+    hash_samples(&record_audio(&mut cpu, &mut nes, frames))
+}
+
+/// Builds an iNES image with a single 16KB PRG bank holding the program at $8000,
+/// the reset vector points to the start of the program
+pub fn nrom_image(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0xEAu8; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3FFC] = 0x00;
+    prg[0x3FFD] = 0x80;
+    rom.extend(prg);
+    rom.extend(vec![0u8; 0x2000]);
+    rom
+}
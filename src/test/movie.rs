@@ -0,0 +1,80 @@
+use super::super::movie::*;
+use super::*;
+
+/// Strobes the first pad and copies the 8 buttons to $0200-$0207, forever
+fn controller_program() -> Vec<u8> {
+    let mut code = vec![0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40];
+    for i in 0..8 {
+        code.extend_from_slice(&[0xAD, 0x16, 0x40, 0x8D, i, 0x02]);
+    }
+    code.extend_from_slice(&[0x4C, 0x00, 0x80]);
+    code
+}
+
+fn power_on() -> (CPU6502, NesData) {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(nrom_image(&controller_program())).unwrap();
+    cpu.power(&mut nes);
+    (cpu, nes)
+}
+
+#[test]
+pub fn movie_playback_is_deterministic() {
+    let (mut cpu, mut nes) = power_on();
+    let mut movie = Movie::new(MovieStart::PowerOn);
+    let mut states = Vec::new();
+    for buttons in [0x00u8, 0x01, 0x81, 0x00, 0x5A].iter() {
+        nes.controllers[0].buttons = *buttons;
+        movie.record_frame(0, &mut cpu, &mut nes);
+        states.push(SaveState::capture(&cpu, &nes));
+    }
+    assert_eq!(&nes.ram[0x0200..0x0208], &[0, 1, 0, 1, 1, 0, 1, 0]);
+
+    let (mut cpu, mut nes) = power_on();
+    movie.rewind(&mut cpu, &mut nes).unwrap();
+    let mut frame = 0;
+    while movie.play_frame(frame, &mut cpu, &mut nes) {
+        assert!(SaveState::capture(&cpu, &nes) == states[frame]);
+        frame += 1;
+    }
+    assert_eq!(frame, 5);
+}
+
+#[test]
+pub fn movie_from_save_state() {
+    let (mut cpu, mut nes) = power_on();
+    nes.run_frame(&mut cpu);
+    let mut movie = Movie::new(MovieStart::SaveState(SaveState::capture(&cpu, &nes)));
+    nes.controllers[0].buttons = 0x80;
+    movie.record_frame(0, &mut cpu, &mut nes);
+    let end = SaveState::capture(&cpu, &nes);
+
+    let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    nes.reset_memory();
+    movie.rewind(&mut cpu, &mut nes).unwrap();
+    assert!(movie.play_frame(0, &mut cpu, &mut nes));
+    assert!(SaveState::capture(&cpu, &nes) == end);
+}
+
+#[test]
+pub fn fm2_import_export() {
+    let text = "version 3\nemuVersion 22020\nrerecordCount 12\npalFlag 0\n\
+                romFilename test\nguid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n\
+                fourscore 0\nport0 1\nport1 1\nport2 0\ncomment author someone\n\
+                |1|........|........||\n|0|R......A|........||\n|0|...UT...|.L......||\n";
+    let movie = Movie::from_fm2(text).unwrap();
+    assert_eq!(movie.rerecord_count, 12);
+    assert_eq!(movie.rom_name, "test");
+    assert_eq!(movie.comments, vec!["author someone".to_string()]);
+    assert_eq!(
+        movie.frames,
+        vec![
+            MovieFrame { command: 1, ports: [0x00, 0x00] },
+            MovieFrame { command: 0, ports: [0x81, 0x00] },
+            MovieFrame { command: 0, ports: [0x18, 0x40] },
+        ]
+    );
+    assert!(Movie::from_fm2(&movie.to_fm2()).unwrap() == movie);
+    assert!(Movie::from_fm2("version 3\nport0 2\n").is_err());
+}
@@ -0,0 +1,126 @@
+use super::super::nsf::*;
+use super::*;
+
+/// Two 4KB banks: the code, then a bank starting with $42 switched in at $A000 by INIT
+fn test_nsf() -> Vec<u8> {
+    let code = assemble(
+        "
+        .org $8000
+init:   STA $0300
+        JSR setup
+        LDA #1
+        STA $5FFA
+        LDA $A000
+        STA $0302
+        RTS
+setup:  LDA #$BF
+        STA $4000
+        LDA #$FD
+        STA $4002
+        LDA #$00
+        STA $4003
+        RTS
+play:   LDX $0301
+        INX
+        STX $0301
+        RTS
+        ",
+    )
+    .unwrap();
+    let mut header = vec![0u8; 0x80];
+    header[..5].copy_from_slice(b"NESM\x1A");
+    header[5] = 1;
+    header[6] = 3;
+    header[7] = 2;
+    header[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, code.labels["play"] as u8, 0x80]);
+    header[0x0E..0x12].copy_from_slice(b"Test");
+    header[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+    header[0x70..0x78].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    let mut data = code.bytes();
+    data.resize(0x1000, 0);
+    data.push(0x42);
+    header.extend(data);
+    header
+}
+
+#[test]
+pub fn nsf_init_play_and_bankswitch() {
+    let nsf = Nsf::parse(&test_nsf()).unwrap();
+    assert_eq!(
+        (nsf.songs, nsf.starting_song, nsf.name.as_str()),
+        (3, 1, "Test")
+    );
+    assert!(nsf.bankswitched());
+    let mut player = NsfPlayer::new(nsf.clone()).unwrap();
+    assert_eq!(player.nes.ram[0x0300], 1);
+    // INIT returned from its subroutine and read the switched bank
+    assert_eq!(player.nes.ram[0x0302], 0x42);
+    assert_eq!(player.nes.cartridge.mapper.banks[2], 1);
+
+    let samples = player.render_seconds(1);
+    assert!((44099..=44101).contains(&samples.len()));
+    assert!(samples.iter().any(|x| x.abs() > 0.05));
+    // PLAY runs 60 times a second
+    assert!((59..=61).contains(&player.nes.ram[0x0301]));
+
+    player.select_track(2).unwrap();
+    assert_eq!((player.nes.ram[0x0300], player.nes.ram[0x0301]), (2, 0));
+    assert!(player.select_track(3).is_err());
+
+    // A starting track out of range is an error, not a silent player
+    let nsf = Nsf { starting_song: 3, ..nsf };
+    assert!(NsfPlayer::new(nsf.clone()).is_err());
+    // Another track still plays, the starting one is never initialized
+    let player = NsfPlayer::with_track(nsf.clone(), 2).unwrap();
+    assert_eq!((player.track, player.nes.ram[0x0300]), (2, 2));
+    assert!(NsfPlayer::new(Nsf { songs: 0, starting_song: 0, ..nsf }).is_err());
+}
+
+#[test]
+pub fn nsf_track_option_range() {
+    let parse = |track: &str| {
+        let args: Vec<String> = ["music.nsf", "--track", track].iter().map(|x| x.to_string()).collect();
+        NsfOptions::parse(&args).map(|options| options.track)
+    };
+    assert_eq!(parse("1"), Ok(Some(0)));
+    assert_eq!(parse("255"), Ok(Some(254)));
+    assert!(parse("0").is_err());
+    assert!(parse("256").is_err());
+    assert!(parse("300").is_err());
+}
+
+#[test]
+pub fn nsf_nsfe_chunks() {
+    let chunk = |name: &[u8], data: &[u8]| {
+        let mut result = (data.len() as u32).to_le_bytes().to_vec();
+        result.extend_from_slice(name);
+        result.extend_from_slice(data);
+        result
+    };
+    let mut file = b"NSFE".to_vec();
+    file.extend(chunk(
+        b"INFO",
+        &[0x00, 0x80, 0x00, 0x80, 0x03, 0x80, 0, 0x01, 2, 1],
+    ));
+    file.extend(chunk(b"DATA", &[0x60]));
+    file.extend(chunk(b"auth", b"Game\0Artist\0Year\0Ripper\0"));
+    file.extend(chunk(b"tlbl", b"Title\0Ending\0"));
+    file.extend(chunk(b"RATE", &10000u16.to_le_bytes()));
+    file.extend(chunk(b"NEND", &[]));
+    let nsf = Nsf::parse(&file).unwrap();
+    assert_eq!(
+        (nsf.songs, nsf.starting_song, nsf.play_addr),
+        (2, 1, 0x8003)
+    );
+    assert_eq!(
+        (nsf.artist.as_str(), nsf.track_name(1)),
+        ("Artist", "Ending".to_string())
+    );
+    assert_eq!((nsf.expansion, nsf.ntsc_speed), (0x01, 10000));
+    assert_eq!(nsf.prg().0[0], 0x60);
+
+    let mut unknown = file[..file.len() - 8].to_vec();
+    unknown.extend(chunk(b"ZZZZ", &[]));
+    assert!(Nsf::parse(&unknown).unwrap_err().contains("ZZZZ"));
+    assert!(Nsf::parse(b"NESM").is_err());
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::fs;
+
+use super::*;
+
+#[test]
+pub fn palette_pal_files() {
+    let mut data: Vec<u8> = (0..192).map(|x| x as u8).collect();
+    let palette = Palette::from_pal(&data).unwrap();
+    assert_eq!(palette.rgb(0x21), (99, 100, 101));
+    // Red emphasis dims the green and the blue
+    let (r, g, b) = palette.rgb(0x21 | 0x40);
+    assert_eq!(r, 99);
+    assert!(g < 100 && b < 101);
+
+    data = (0..1536).map(|x| (x / 3) as u8).collect();
+    let palette = Palette::from_pal(&data).unwrap();
+    assert_eq!(palette.rgb(0x1FF), (255, 255, 255));
+    assert_eq!(palette.to_pal(), data);
+    assert!(Palette::from_pal(&[0; 100]).unwrap_err().contains("100"));
+
+    let path = env::temp_dir().join("rustnes_palette.pal");
+    fs::write(&path, palette.to_pal()).unwrap();
+    assert_eq!(
+        Palette::load(path.to_str().unwrap()).unwrap().colors[..],
+        palette.colors[..]
+    );
+    assert!(Palette::load("2c02").is_ok());
+}
+
+#[test]
+pub fn palette_ntsc_generation() {
+    let palette = Palette::generate(&NtscParameters::default());
+    let (r, g, b) = palette.rgb(0x21);
+    assert!(b > r && b > g, "$21 is light blue {:?}", (r, g, b));
+    let (r, g, b) = palette.rgb(0x16);
+    assert!(r > 2 * g && r > 2 * b, "$16 is red {:?}", (r, g, b));
+    assert_eq!(palette.rgb(0x0F), (0, 0, 0));
+    assert_eq!(palette.rgb(0x30), (255, 255, 255));
+    let (r, g, b) = palette.rgb(0x00);
+    assert!(r == g && g == b);
+
+    // The 3 emphasis bits darken every channel
+    let light_blue = palette.rgb(0x21);
+    let darker = palette.rgb(0x21 | 0x1C0);
+    assert!(darker.0 < light_blue.0 && darker.1 < light_blue.1 && darker.2 < light_blue.2);
+
+    let gray = Palette::generate(&NtscParameters {
+        saturation: 0.0,
+        ..NtscParameters::default()
+    });
+    let (r, g, b) = gray.rgb(0x16);
+    assert!(r == g && g == b);
+    // Grayscale keeps the column 0 of the row
+    assert_eq!(
+        palette.color(0x16, PpuMask::Grayscale as u8),
+        palette.rgb(0x10)
+    );
+}
@@ -0,0 +1,177 @@
+use std::env;
+use std::fs;
+
+use super::super::headless::*;
+use super::super::patch::*;
+use super::*;
+
+/// Number of UPS and BPS patches
+fn encode_number(mut value: usize) -> Vec<u8> {
+    let mut result = Vec::new();
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            result.push(byte | 0x80);
+            return result;
+        }
+        result.push(byte);
+        value -= 1;
+    }
+}
+
+/// Append the checksums of the source, the target and the patch
+fn with_checksums(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let crc = crc32fast::hash(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+    patch
+}
+
+#[test]
+pub fn patch_ips() {
+    let rom: Vec<u8> = (0..16).collect();
+    let mut patch = b"PATCH".to_vec();
+    // 2 bytes at 3, 4 bytes of $EE at 8 with RLE, 2 bytes past the end
+    patch.extend_from_slice(&[0x00, 0x00, 0x03, 0x00, 0x02, 0xAA, 0xBB]);
+    patch.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0xEE]);
+    patch.extend_from_slice(&[0x00, 0x00, 0x11, 0x00, 0x02, 0x01, 0x02]);
+    patch.extend_from_slice(b"EOF");
+    let result = apply_patch(&rom, &patch).unwrap();
+    assert_eq!(result.len(), 19);
+    assert_eq!(&result[2..6], &[2, 0xAA, 0xBB, 5]);
+    assert_eq!(&result[7..13], &[7, 0xEE, 0xEE, 0xEE, 0xEE, 12]);
+    assert_eq!(&result[15..], &[15, 0, 1, 2]);
+
+    // Truncation extension after the end marker
+    patch.extend_from_slice(&[0x00, 0x00, 0x0A]);
+    assert_eq!(apply_patch(&rom, &patch).unwrap().len(), 10);
+
+    assert!(apply_ips(&rom, b"PATCH\x00\x00\x01\x00\x05\x01").is_err());
+    assert!(apply_patch(&rom, b"NOTAPATCH").is_err());
+}
+
+#[test]
+pub fn patch_ups() {
+    let source: Vec<u8> = (0..32).collect();
+    let mut target = source.clone();
+    target[4] = 0x44;
+    target[5] = 0x55;
+    target.extend_from_slice(&[0x99, 0x98]);
+
+    let mut patch = b"UPS1".to_vec();
+    patch.extend(encode_number(source.len()));
+    patch.extend(encode_number(target.len()));
+    patch.extend(encode_number(4));
+    patch.extend_from_slice(&[4 ^ 0x44, 5 ^ 0x55, 0]);
+    patch.extend(encode_number(25));
+    patch.extend_from_slice(&[0x99, 0x98, 0]);
+    let patch = with_checksums(patch, &source, &target);
+    assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+
+    // Another rom, or a damaged patch
+    let mut other = source.clone();
+    other[0] = 1;
+    assert!(apply_ups(&other, &patch)
+        .unwrap_err()
+        .contains("rom checksum"));
+    let mut damaged = patch.clone();
+    damaged[6] ^= 1;
+    assert!(apply_ups(&source, &damaged)
+        .unwrap_err()
+        .contains("corrupted"));
+}
+
+#[test]
+pub fn patch_bps() {
+    let source: Vec<u8> = (0..16).collect();
+    // 4 bytes of the rom, 2 new ones, 3 bytes from the rom at 10, then a copy of the
+    // result overlapping itself
+    let mut target: Vec<u8> = vec![0, 1, 2, 3, 0xA0, 0xA1, 10, 11, 12];
+    target.extend_from_slice(&[11, 12, 11, 12, 11]);
+
+    let mut patch = b"BPS1".to_vec();
+    patch.extend(encode_number(source.len()));
+    patch.extend(encode_number(target.len()));
+    patch.extend(encode_number(3));
+    patch.extend_from_slice(b"abc");
+    patch.extend(encode_number((4 - 1) << 2));
+    patch.extend(encode_number((2 - 1) << 2 | 1));
+    patch.extend_from_slice(&[0xA0, 0xA1]);
+    patch.extend(encode_number((3 - 1) << 2 | 2));
+    patch.extend(encode_number(10 << 1));
+    patch.extend(encode_number((5 - 1) << 2 | 3));
+    patch.extend(encode_number(7 << 1));
+    let patch = with_checksums(patch, &source, &target);
+    assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+
+    let mut other = source.clone();
+    other.push(0);
+    assert!(apply_bps(&other, &patch).is_err());
+}
+
+#[test]
+pub fn patch_sizes_are_bounded() {
+    let source: Vec<u8> = (0..16).collect();
+    let header = |magic: &[u8], numbers: &[usize]| {
+        let mut patch = magic.to_vec();
+        for x in numbers {
+            patch.extend(encode_number(*x));
+        }
+        patch
+    };
+    // Sizes far above the limit are refused before anything is allocated
+    for magic in [&b"UPS1"[..], &b"BPS1"[..]].iter() {
+        let patch = header(magic, &[16, usize::MAX >> 8, 0]);
+        let error = apply_patch(&source, &with_checksums(patch, &source, &[])).unwrap_err();
+        assert!(error.contains("bytes of patched rom"), "{}", error);
+    }
+    let patch = header(b"BPS1", &[16, 16, MAX_PATCHED_SIZE + 1]);
+    assert!(apply_bps(&source, &with_checksums(patch, &source, &[])).unwrap_err().contains("metadata"));
+    let patch = header(b"BPS1", &[16, 16, 1000]);
+    assert!(apply_bps(&source, &with_checksums(patch, &source, &[])).is_err());
+
+    // A run longer than the rest of the result, or wrapping around the offsets
+    let mut patch = header(b"BPS1", &[16, 16, 0, 0, (usize::MAX >> 3) << 2 | 3]);
+    patch.extend(encode_number(0));
+    assert!(apply_bps(&source, &with_checksums(patch, &source, &[])).is_err());
+    let patch = header(b"BPS1", &[16, 16, 0, 2, usize::MAX >> 1 << 1, 2, usize::MAX >> 1 << 1]);
+    assert!(apply_bps(&source, &with_checksums(patch, &source, &[])).is_err());
+    let patch = header(b"UPS1", &[16, 16, usize::MAX - 1, 4, 0]);
+    assert!(apply_ups(&source, &with_checksums(patch, &source, &[])).is_err());
+}
+
+#[test]
+pub fn patch_soft_patch_in_headless() {
+    // Program writing $42 to $0300, the patch makes it write $43
+    let program = vec![0xA9, 0x42, 0x8D, 0x00, 0x03, 0x4C, 0x05, 0x80];
+    let rom = env::temp_dir().join("rustnes_soft_patch.nes");
+    let patch = env::temp_dir().join("rustnes_soft_patch.ips");
+    fs::write(&rom, nrom_image(&program)).unwrap();
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x00, 0x11, 0x00, 0x01, 0x43]);
+    ips.extend_from_slice(b"EOF");
+    fs::write(&patch, ips).unwrap();
+    assert_eq!(find_soft_patch(&rom), Some(patch.clone()));
+
+    let args: Vec<String> = [
+        rom.to_str().unwrap(),
+        "--frames",
+        "2",
+        "--exit-code",
+        "$0300",
+    ]
+    .iter()
+    .map(|x| x.to_string())
+    .collect();
+    let options = HeadlessOptions::parse(&args).unwrap();
+    assert_eq!(run(&options).unwrap().exit_code, 0x42);
+    let options = HeadlessOptions {
+        soft_patch: true,
+        ..options
+    };
+    assert_eq!(run(&options).unwrap().exit_code, 0x43);
+    fs::remove_file(&patch).unwrap();
+    assert_eq!(run(&options).unwrap().exit_code, 0x42);
+}
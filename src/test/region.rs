@@ -0,0 +1,159 @@
+use super::super::headless::*;
+use super::*;
+
+/// Console running a rom looping at $8000, with the given header bytes 9, 10 and 12
+fn console(flags_7: u8, flags_9: u8, flags_10: u8, timing: u8) -> (CPU6502, NesData) {
+    let mut rom = nrom_image(&[0x4C, 0x00, 0x80]);
+    rom[7] = flags_7;
+    rom[9] = flags_9;
+    rom[10] = flags_10;
+    rom[12] = timing;
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(rom).unwrap();
+    cpu.power(&mut nes);
+    (cpu, nes)
+}
+
+#[test]
+pub fn region_from_header() {
+    assert_eq!(console(0, 0, 0, 0).1.region, Region::Ntsc);
+    assert_eq!(console(0, 1, 0, 0).1.region, Region::Pal);
+    assert_eq!(console(0, 0, 2, 0).1.region, Region::Pal);
+    // The timing byte of NES 2.0 replaces the flags, multi-region games run as NTSC
+    assert_eq!(console(0x08, 1, 0, 0).1.region, Region::Ntsc);
+    assert_eq!(console(0x08, 0, 0, 1).1.region, Region::Pal);
+    assert_eq!(console(0x08, 0, 0, 2).1.region, Region::Ntsc);
+    let (_, nes) = console(0x08, 0, 0, 3);
+    assert_eq!(
+        (nes.region, nes.ppu.region, nes.apu.region),
+        (Region::Dendy, Region::Dendy, Region::Dendy)
+    );
+
+    assert_eq!(Region::parse("PAL"), Ok(Region::Pal));
+    assert!(Region::parse("secam").is_err());
+    let args: Vec<String> = vec![
+        "game.nes".to_string(),
+        "--region".to_string(),
+        "dendy".to_string(),
+    ];
+    assert_eq!(
+        HeadlessOptions::parse(&args).unwrap().region,
+        Some(Region::Dendy)
+    );
+}
+
+#[test]
+pub fn region_frame_timings() {
+    // Scanlines, vblank start and cpu cycles of two frames, the rendering is off.
+    // NTSC has 3 dots per cycle and 341 * 262 is not a multiple of 3, PAL 3.2.
+    let expected = [
+        (Region::Ntsc, 262, 241, 59562),
+        (Region::Pal, 312, 241, 66495),
+        (Region::Dendy, 312, 291, 70928),
+    ];
+    for (region, scanlines, vblank, cycles) in expected.iter() {
+        let (mut cpu, mut nes) = console(0, 0, 0, 0);
+        nes.set_region(*region);
+        nes.run_frame(&mut cpu);
+        let start = (nes.clock_counter, nes.apu.cycle);
+        let mut vblank_line = None;
+        for _ in 0..2 {
+            while !nes.ppu.frame_complete {
+                let was_vblank = nes.ppu.vblank();
+                nes.clock(&mut cpu);
+                if vblank_line.is_none() && !was_vblank && nes.ppu.vblank() {
+                    vblank_line = Some(nes.ppu.scanline);
+                }
+            }
+            nes.ppu.frame_complete = false;
+        }
+        assert_eq!(
+            nes.clock_counter - start.0,
+            341 * scanlines * 2,
+            "{:?}",
+            region
+        );
+        assert_eq!(nes.apu.cycle - start.1, *cycles, "{:?}", region);
+        assert_eq!(vblank_line, Some(*vblank), "{:?}", region);
+    }
+}
+
+#[test]
+pub fn region_apu_timings() {
+    let mut irq_cycles = Vec::new();
+    for region in [Region::Ntsc, Region::Pal, Region::Dendy].iter() {
+        let mut apu = APU::new();
+        apu.set_region(*region);
+        apu.cpu_write(0x400E, 0x0F);
+        apu.cpu_write(0x4010, 0x00);
+        let periods = (apu.noise.timer_period, apu.dmc.timer_period);
+        let mut cycles = 0;
+        while !apu.irq() {
+            apu.clock();
+            cycles += 1;
+        }
+        irq_cycles.push((cycles, periods));
+    }
+    assert_eq!(
+        irq_cycles,
+        vec![
+            (29829, (4068, 428)),
+            (33252, (3778, 398)),
+            (29829, (4068, 428))
+        ]
+    );
+
+    // The samples keep their rate, the clock of the resampler follows the cpu
+    let mut apu = APU::new();
+    apu.set_region(Region::Pal);
+    for _ in 0..Region::Pal.cpu_frequency() {
+        apu.clock();
+    }
+    assert!((apu.samples.len() as i64 - 44100).abs() <= 1);
+}
+
+#[test]
+pub fn region_noise_shift_rate() {
+    // The noise periods are in cpu cycles, the shift register steps once per period
+    for (region, index, period) in [
+        (Region::Ntsc, 0x00, 4),
+        (Region::Ntsc, 0x05, 96),
+        (Region::Pal, 0x02, 14),
+        (Region::Pal, 0x0F, 3778),
+    ]
+    .iter()
+    {
+        let mut apu = APU::new();
+        apu.set_region(*region);
+        apu.cpu_write(0x400E, *index);
+        let mut shifts = 0;
+        for _ in 0..period * 100 {
+            let shift = apu.noise.shift;
+            apu.clock();
+            if apu.noise.shift != shift {
+                shifts += 1;
+            }
+        }
+        assert_eq!(shifts, 100, "{:?} period {}", region, period);
+    }
+}
+
+#[test]
+pub fn region_pal_swaps_emphasis() {
+    for (region, emphasis) in [
+        (Region::Ntsc, 0x01),
+        (Region::Pal, 0x02),
+        (Region::Dendy, 0x02),
+    ]
+    .iter()
+    {
+        let (mut cpu, mut nes) = console(0, 0, 0, 0);
+        nes.set_region(*region);
+        // Red emphasis on NTSC, green on PAL: the frame buffer is in the NTSC order
+        nes.ppu.mask = 0x20;
+        nes.run_frame(&mut cpu);
+        nes.run_frame(&mut cpu);
+        assert_eq!(nes.ppu.frame_buffer[0] >> 6, *emphasis, "{:?}", region);
+    }
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+/// One second of a square wave of the given frequency, between 0 and 1
+fn square(resampler: &mut Resampler, frequency: u64) -> Vec<f32> {
+    let mut output = Vec::new();
+    let half_period = CPU_FREQUENCY / frequency / 2;
+    for clock in 0..CPU_FREQUENCY {
+        let amplitude = ((clock / half_period) % 2) as f32;
+        resampler.clock(amplitude, &mut output);
+    }
+    output
+}
+
+#[test]
+pub fn resampler_rate_and_filters() {
+    let mut resampler = Resampler::new(CPU_FREQUENCY, 48000);
+    let output = square(&mut resampler, 440);
+    assert!((47999..=48001).contains(&output.len()), "{}", output.len());
+    // The high-passes remove the DC offset, the band-limited steps barely ring
+    let last = &output[24000..];
+    let mean = last.iter().sum::<f32>() / last.len() as f32;
+    assert!(mean.abs() < 0.01, "{}", mean);
+    assert!(last.iter().all(|x| x.abs() < 1.2));
+    assert!(last.iter().any(|x| x.abs() > 0.4));
+
+    resampler.adjust_rate(1.01);
+    assert_eq!(square(&mut resampler, 440).len(), 48480);
+    resampler.adjust_rate(2.0);
+    assert_eq!(resampler.rate_adjustment, 1.05);
+
+    resampler.set_sample_rate(44100);
+    resampler.adjust_rate(1.0);
+    assert!((44099..=44101).contains(&square(&mut resampler, 440).len()));
+}
@@ -0,0 +1,51 @@
+use super::super::screenshot::*;
+use super::*;
+
+/// Color $21 everywhere but on the first line, which is $0F
+fn test_frame() -> Vec<u16> {
+    let mut frame = vec![0x21u16; SCREEN_WIDTH * SCREEN_HEIGHT];
+    frame[..SCREEN_WIDTH].iter_mut().for_each(|x| *x = 0x0F);
+    frame
+}
+
+#[test]
+pub fn screenshot_crop_and_scale() {
+    let frame = test_frame();
+    let image = Image::from_frame(&frame, &Palette::default(), &ScreenshotOptions::default());
+    assert_eq!((image.width, image.height), (256, 240));
+    assert_eq!(&image.rgb[..3], &[0, 0, 0]);
+
+    let options = ScreenshotOptions {
+        crop_overscan: true,
+        scale: 2,
+    };
+    let image = Image::from_frame(&frame, &Palette::default(), &options);
+    assert_eq!((image.width, image.height), (512, 448));
+    assert_eq!(image.rgb.len(), 512 * 448 * 3);
+    // The black line is cropped
+    assert!(image.rgb.chunks(3).all(|x| x == [76, 154, 236]));
+    assert!(image.to_ppm().starts_with(b"P6\n512 448\n255\n"));
+}
+
+#[test]
+pub fn screenshot_png_encoding() {
+    let image = Image::from_frame(&test_frame(), &Palette::default(), &ScreenshotOptions::default());
+    let png = image.to_png();
+    assert_eq!(ImageFormat::from_path("shot.PNG"), ImageFormat::Png);
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"));
+    assert_eq!(&png[16..26], &[0, 0, 1, 0, 0, 0, 0, 240, 8, 2]);
+    assert_eq!(
+        &png[png.len() - 12..],
+        b"\x00\x00\x00\x00IEND\xae\x42\x60\x82"
+    );
+
+    // The only IDAT chunk follows the header
+    let length = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+    assert_eq!(&png[37..41], b"IDAT");
+    let lines = miniz_oxide::inflate::decompress_to_vec_zlib(&png[41..41 + length]).unwrap();
+    assert_eq!(lines.len(), 240 * (256 * 3 + 1));
+    for (y, line) in lines.chunks(256 * 3 + 1).enumerate() {
+        assert_eq!(line[0], 0);
+        assert_eq!(&line[1..], &image.rgb[y * 256 * 3..(y + 1) * 256 * 3]);
+    }
+}
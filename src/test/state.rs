@@ -0,0 +1,127 @@
+use super::disk::test_side;
+use super::mapper::mapper_image;
+use super::*;
+
+#[test]
+pub fn state_saves_only_cartridge_ram() {
+    // 128KB of PRG ROM and 8KB of CHR RAM
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(mapper_image(2, 8, 0)).unwrap();
+    cpu.power(&mut nes);
+    nes.ppu_write(0x0010, 0x55);
+    let state = SaveState::capture(&cpu, &nes);
+    assert!(state.data.len() < 0x8000);
+
+    nes.ppu_write(0x0010, 0xAA);
+    state.restore(&mut cpu, &mut nes).unwrap();
+    assert_eq!(nes.ppu_read(0x0010, true), 0x55);
+
+    // The sizes of the memories still tell the cartridges apart
+    let mut other = NesData::new();
+    other.insert_cartridge(mapper_image(2, 4, 0)).unwrap();
+    assert!(state.restore(&mut cpu, &mut other).is_err());
+}
+
+#[test]
+pub fn state_restores_audio() {
+    let program = [
+        0xA9, 0x01, 0x8D, 0x15, 0x40, // $4015 = pulse 1 on
+        0xA9, 0xBF, 0x8D, 0x00, 0x40, // $4000 = duty 50%, constant volume 15
+        0xA9, 0x80, 0x8D, 0x02, 0x40, // $4002 = timer low
+        0xA9, 0x00, 0x8D, 0x03, 0x40, // $4003 = timer high
+        0x4C, 0x14, 0x80, // loop forever
+    ];
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(nrom_image(&program)).unwrap();
+    cpu.power(&mut nes);
+    let run = |cpu: &mut CPU6502, nes: &mut NesData| {
+        nes.apu.samples.clear();
+        for _ in 0..2 {
+            nes.run_frame(cpu);
+        }
+        nes.apu.samples.clone()
+    };
+    run(&mut cpu, &mut nes);
+    let state = SaveState::capture(&cpu, &nes);
+    let samples = run(&mut cpu, &mut nes);
+    assert!(samples.iter().any(|x| x.abs() > 0.1));
+
+    state.restore(&mut cpu, &mut nes).unwrap();
+    assert_eq!(run(&mut cpu, &mut nes), samples);
+}
+
+#[test]
+pub fn state_restores_expansion_audio() {
+    // Namco 163, its RAM is written through $F800 and $4800
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(mapper_image(19, 8, 8)).unwrap();
+    cpu.power(&mut nes);
+    nes.cpu_write(0xF800, 0x80);
+    for i in 0..0x80 {
+        nes.cpu_write(0x4800, i);
+    }
+    let state = SaveState::capture(&cpu, &nes);
+
+    nes.cpu_write(0xF800, 0x80);
+    for _ in 0..0x80 {
+        nes.cpu_write(0x4800, 0xFF);
+    }
+    state.restore(&mut cpu, &mut nes).unwrap();
+    let chip = nes.cartridge.audio.n163.as_ref().unwrap();
+    assert!(chip.ram.iter().enumerate().all(|(i, x)| *x == i as u8));
+    assert!(chip.auto_increment);
+}
+
+#[test]
+pub fn state_restores_disk_system() {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_disk(&test_side(), vec![0; FDS_BIOS_SIZE]).unwrap();
+    cpu.power(&mut nes);
+    nes.cpu_write(0x4023, 0x01);
+    nes.cpu_write(0x4020, 100);
+    nes.cpu_write(0x4022, 0x02);
+    nes.cpu_write(0x4025, 0x2D);
+    let disk = nes.cartridge.disk.as_mut().unwrap();
+    disk.sides[0][5000] = 0x42;
+    disk.modified[0] = true;
+    disk.position = 1234;
+    let state = SaveState::capture(&cpu, &nes);
+
+    // Another console with the same disk, the written side comes from the state
+    let mut other = NesData::new();
+    other.insert_disk(&test_side(), vec![0; FDS_BIOS_SIZE]).unwrap();
+    state.restore(&mut cpu, &mut other).unwrap();
+    let disk = other.cartridge.disk.as_ref().unwrap();
+    assert_eq!(disk.sides[0][5000], 0x42);
+    assert!(disk.is_modified());
+    assert_eq!(disk.position, 1234);
+    assert_eq!((disk.timer_reload, disk.timer_counter), (100, 100));
+    assert!(disk.timer_enabled && disk.motor_on && disk.read_mode);
+    assert_eq!(disk.mirror, Mirror::Horizontal);
+
+    // A disk with another number of sides is refused
+    let mut two_sides = test_side();
+    two_sides.extend(test_side());
+    other.insert_disk(&two_sides, vec![0; FDS_BIOS_SIZE]).unwrap();
+    assert!(state.restore(&mut cpu, &mut other).is_err());
+}
+
+#[test]
+pub fn state_restores_region() {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    nes.insert_cartridge(nrom_image(&[0x4C, 0x00, 0x80])).unwrap();
+    nes.set_region(Region::Pal);
+    cpu.power(&mut nes);
+    nes.run_frame(&mut cpu);
+    let state = SaveState::capture(&cpu, &nes);
+
+    // The timings of the console come back with the region
+    nes.set_region(Region::Ntsc);
+    state.restore(&mut cpu, &mut nes).unwrap();
+    assert_eq!((nes.region, nes.ppu.region, nes.apu.region), (Region::Pal, Region::Pal, Region::Pal));
+}
@@ -0,0 +1,36 @@
+use console::Key;
+
+use super::super::terminal::*;
+use super::*;
+
+#[test]
+pub fn terminal_render_half_blocks() {
+    let mut frame = vec![0x21u16; SCREEN_WIDTH * SCREEN_HEIGHT];
+    frame[SCREEN_WIDTH] = 0x0F;
+    let text = render_frame(&frame, &Palette::default(), 1);
+    let lines: Vec<&str> = text.split("\r\n").collect();
+    assert_eq!(lines.len(), SCREEN_HEIGHT / 2 + 1);
+    // Top pixel $21, bottom pixel $0F then the colors change back once
+    assert!(lines[0].starts_with("\x1b[38;2;76;154;236;48;2;0;0;0m\u{2580}\x1b[38;2;76;154;236;48;2;76;154;236m\u{2580}\u{2580}"));
+    assert_eq!(lines[1].matches('\u{2580}').count(), SCREEN_WIDTH);
+    assert_eq!(
+        render_frame(&frame, &Palette::default(), 4).split("\r\n").count(),
+        SCREEN_HEIGHT / 8 + 1
+    );
+}
+
+#[test]
+pub fn terminal_fit_and_keys() {
+    assert_eq!(fit_scale(300, 130), 1);
+    assert_eq!(fit_scale(200, 50), 3);
+    assert_eq!(
+        key_action(&Key::Char('x')),
+        Some(KeyAction::Press(Button::A as u8))
+    );
+    assert_eq!(
+        key_action(&Key::ArrowLeft),
+        Some(KeyAction::Press(Button::Left as u8))
+    );
+    assert_eq!(key_action(&Key::Escape), Some(KeyAction::Quit));
+    assert_eq!(key_action(&Key::Char('p')), None);
+}
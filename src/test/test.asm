@@ -1,5 +1,5 @@
 LDA #01
 ADC #02
 ADC #03
-STA #0000
+STA $00
 BRK
@@ -0,0 +1,67 @@
+use super::super::debugger::*;
+use super::*;
+
+fn powered_nes() -> (CPU6502, NesData) {
+    let mut nes = NesData::new();
+    let mut cpu = CPU6502::new();
+    // LDA #$01, STA $0300, JMP $8005
+    nes.insert_cartridge(nrom_image(&[
+        0xA9, 0x01, 0x8D, 0x00, 0x03, 0x4C, 0x05, 0x80,
+    ]))
+    .unwrap();
+    cpu.power(&mut nes);
+    (cpu, nes)
+}
+
+#[test]
+pub fn tui_panes_follow_the_cpu() {
+    let (mut cpu, mut nes) = powered_nes();
+    let mut ui = DebuggerUi::new();
+    assert!(ui.execute("s", &mut cpu, &mut nes));
+    assert!(ui.execute("", &mut cpu, &mut nes));
+    assert_eq!(cpu.pc, 0x8002);
+
+    let screen = ui.render(&cpu, &mut nes).join("\n");
+    assert!(screen.contains("> 8002  8D 00 03"), "{}", screen);
+    // The instruction before the program counter is found again
+    assert!(screen.contains("8000"), "{}", screen);
+    assert!(screen.contains("A $01"), "{}", screen);
+
+    ui.execute("b $8005", &mut cpu, &mut nes);
+    assert!(ui
+        .render(&cpu, &mut nes)
+        .iter()
+        .any(|x| x.contains("breakpoint 0 : $8005")));
+    assert!(!ui.execute("quit", &mut cpu, &mut nes));
+}
+
+#[test]
+pub fn tui_memory_commands() {
+    let (mut cpu, mut nes) = powered_nes();
+    let mut ui = DebuggerUi::new();
+    ui.execute("e $0200 $41 $42 3", &mut cpu, &mut nes);
+    assert_eq!(&nes.ram[0x0200..0x0203], &[0x41, 0x42, 0x03]);
+    assert_eq!(
+        hex_dump(MemorySpace::Cpu, 0x0200, 4, &mut nes),
+        vec![format!("0200  {:<47}  AB..", "41 42 03 00")]
+    );
+
+    ui.execute("x/16 $0200", &mut cpu, &mut nes);
+    let screen = ui.render(&cpu, &mut nes);
+    assert!(
+        screen
+            .iter()
+            .filter(|x| x.starts_with("0200  41 42 03"))
+            .count()
+            == 1
+    );
+
+    ui.execute("e oam 4 $FF", &mut cpu, &mut nes);
+    assert_eq!(nes.ppu.oam[4], 0xFF);
+    ui.execute("m ppu $3F00", &mut cpu, &mut nes);
+    ui.execute("m +", &mut cpu, &mut nes);
+    assert_eq!(
+        (ui.memory_space, ui.memory_addr),
+        (MemorySpace::Ppu, 0x3F80)
+    );
+}
@@ -0,0 +1,92 @@
+use super::*;
+
+/// UNIF file made of `chunks`
+fn unif_image(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let mut data = b"UNIF".to_vec();
+    data.extend_from_slice(&7u32.to_le_bytes());
+    data.resize(32, 0);
+    for (id, chunk) in chunks.iter() {
+        data.extend_from_slice(*id);
+        data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        data.extend_from_slice(chunk);
+    }
+    data
+}
+
+#[test]
+pub fn unif_loads_as_ines() {
+    // The PRG chunks are put in order of their number, not of the file
+    let image = unif_image(&[
+        (b"MAPR", b"NES-CNROM\0".to_vec()),
+        (b"NAME", b"Test Game\0".to_vec()),
+        (b"PRG1", vec![0x22; 0x4000]),
+        (b"PRG0", vec![0x11; 0x4000]),
+        (b"CHR0", vec![0x33; 0x4000]),
+        (b"MIRR", vec![1]),
+        (b"BATR", vec![1]),
+        (b"TVCI", vec![1]),
+        (b"DINF", vec![0; 204]),
+    ]);
+    assert!(is_unif_image(&image));
+    let mut nes = NesData::new();
+    nes.insert_unif(&image).unwrap();
+    let cartridge = &nes.cartridge;
+    assert_eq!(
+        (cartridge.header.mapper_id(), cartridge.header.submapper()),
+        (3, 2)
+    );
+    assert_eq!(
+        (cartridge.cpu_read(0x8000), cartridge.cpu_read(0xC000)),
+        (0x11, 0x22)
+    );
+    assert_eq!(cartridge.chr_memory.len(), 0x4000);
+    assert_eq!(cartridge.mirror(), Mirror::Vertical);
+    assert_eq!(cartridge.header.flags_6 & 0x02, 0x02);
+    assert_eq!(nes.region, Region::Pal);
+    assert_eq!(cartridge.header.title.as_deref(), Some("Test Game"));
+    assert_eq!(cartridge.header.board.as_deref(), Some("CNROM"));
+    assert!(cartridge.header.to_string().contains("board : CNROM"));
+
+    // 8KB of PRG is mirrored to fill the 16KB bank, no CHR is CHR RAM
+    let image = unif_image(&[
+        (b"MAPR", b"UNL-AOROM".to_vec()),
+        (b"PRG0", (0..0x2000).map(|x| (x >> 8) as u8).collect()),
+    ]);
+    let mut cartridge = Cartridge::new();
+    cartridge.load_unif(&image).unwrap();
+    assert_eq!(cartridge.header.mapper_id(), 7);
+    assert_eq!(cartridge.cpu_read(0xA100), 0x01);
+    assert_eq!(cartridge.chr_memory.len(), 0x2000);
+    assert_eq!(cartridge.header.title, None);
+}
+
+#[test]
+pub fn unif_errors() {
+    let mut cartridge = Cartridge::new();
+    let board = |name: &str| {
+        unif_image(&[
+            (b"MAPR", name.as_bytes().to_vec()),
+            (b"PRG0", vec![0; 0x4000]),
+        ])
+    };
+    assert_eq!(
+        cartridge.load_unif(&board("UNL-SOMETHING")),
+        Err("unsupported UNIF board 'SOMETHING'".to_string())
+    );
+    // MMC1 and MMC3 boards are not emulated yet
+    assert_eq!(
+        cartridge.load_unif(&board("NES-SNROM")),
+        Err("unsupported UNIF board 'SNROM'".to_string())
+    );
+    assert!(cartridge.load_unif(&board("NES-TLROM")).is_err());
+    assert!(cartridge
+        .load_unif(&unif_image(&[(b"PRG0", vec![0; 0x4000])]))
+        .is_err());
+    assert!(cartridge
+        .load_unif(&unif_image(&[(b"MAPR", b"NES-NROM".to_vec())]))
+        .is_err());
+    let mut truncated = board("NES-NROM");
+    truncated.truncate(truncated.len() - 1);
+    assert!(parse_unif(&truncated).is_err());
+    assert!(parse_unif(b"NES\x1A").is_err());
+}
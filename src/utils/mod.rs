@@ -1,4 +1,8 @@
 pub use self::byte_types::{BaseByte, Convert};
+pub use self::number::parse_number;
+pub use self::sha1::Sha1;
 pub use self::traits::*;
 mod byte_types;
+mod number;
+mod sha1;
 mod traits;
@@ -0,0 +1,12 @@
+/// Parse a decimal number, or an hexadecimal one prefixed with `$` or `0x`.
+/// Used by the command line options and the debugger commands.
+pub fn parse_number(text: &str) -> Result<u64, String> {
+    let result = if let Some(x) = text.strip_prefix('$') {
+        u64::from_str_radix(x, 16)
+    } else if let Some(x) = text.strip_prefix("0x") {
+        u64::from_str_radix(x, 16)
+    } else {
+        text.parse()
+    };
+    result.map_err(|_| format!("'{}' is not a number", text))
+}